@@ -38,6 +38,12 @@ pub fn hash_ast(program: &Program) -> Hash {
     Hash(hash_value(&payload))
 }
 
+/// Hashes an arbitrary JSON document (e.g. an execution plan) with sorted keys,
+/// so maps that serialize in varying order hash identically.
+pub fn hash_json(value: &Value) -> Hash {
+    Hash(hash_value(value))
+}
+
 fn hash_value(value: &Value) -> String {
     let canonical = canonical_json(value);
     let digest = Sha3_256::digest(canonical.as_bytes());
@@ -114,5 +120,9 @@ fn item_to_value(item: &Item) -> Value {
             ("kind", Value::String("Trait".to_string())),
             ("name", Value::String(t.name.clone())),
         ]),
+        Item::TypeAlias(t) => object(vec![
+            ("kind", Value::String("TypeAlias".to_string())),
+            ("name", Value::String(t.name.clone())),
+        ]),
     }
 }
@@ -0,0 +1,112 @@
+/*
+ * tupa.h - C ABI for embedding the Tupã runtime.
+ *
+ * Link against libtupa (crates/tupa-capi). Plans, inputs, outputs and
+ * reports cross the boundary as NUL-terminated UTF-8 JSON.
+ *
+ * Ownership rules:
+ *   - Handles (TupaRuntime, TupaPlan) are created by this library and freed
+ *     with their tupa_*_free function, exactly once.
+ *   - Strings passed in are borrowed for the duration of the call only; the
+ *     library copies what it keeps.
+ *   - Strings returned through an out-parameter are owned by the caller and
+ *     freed with tupa_string_free, never with free().
+ *   - tupa_version() and tupa_last_error() return library-owned strings:
+ *     never free them. tupa_last_error() is valid until the next call into
+ *     the library on the same thread.
+ *   - Out-parameters are set to NULL first and only filled on TUPA_OK.
+ *
+ * Threads:
+ *   - Errors are kept per thread.
+ *   - A runtime may be shared between threads once its steps are
+ *     registered; register steps before the first run.
+ *   - Step callbacks may run on any of the runtime's threads, and
+ *     concurrently when runs overlap. Do not call back into
+ *     tupa_runtime_run from a step.
+ */
+#ifndef TUPA_H
+#define TUPA_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum TupaStatus {
+    TUPA_OK = 0,
+    /* A required pointer was NULL or a string was not UTF-8. */
+    TUPA_INVALID_ARGUMENT = 1,
+    /* A plan, input or step output was not valid JSON. */
+    TUPA_INVALID_JSON = 2,
+    /* The pipeline, or one of its steps, failed. */
+    TUPA_RUN_FAILED = 3,
+    /* The runtime panicked; handles stay usable. */
+    TUPA_PANIC = 4
+} TupaStatus;
+
+typedef struct TupaRuntime TupaRuntime;
+typedef struct TupaPlan TupaPlan;
+typedef struct TupaStepResult TupaStepResult;
+
+/*
+ * Step implemented by the host. `input_json` is the pipeline state, borrowed
+ * for the call. Report the output with tupa_step_result_set_output and
+ * return 0, or return non-zero, optionally after
+ * tupa_step_result_set_error. `result` is only valid during the call.
+ */
+typedef int (*TupaStepFn)(void *user_data, const char *input_json, TupaStepResult *result);
+
+/* Library version, e.g. "0.8.1". */
+const char *tupa_version(void);
+
+/* Message of the last failed call on this thread, or NULL. */
+const char *tupa_last_error(void);
+
+/* Frees a string returned by this library. NULL is ignored. */
+void tupa_string_free(char *s);
+
+/* Creates a runtime with no registered steps; NULL on failure. */
+TupaRuntime *tupa_runtime_new(void);
+
+/* Frees a runtime and its registered steps. NULL is ignored. */
+void tupa_runtime_free(TupaRuntime *runtime);
+
+/* Loads an execution plan, as written by `tupa codegen --plan-only`. */
+TupaStatus tupa_plan_load(const char *plan_json, TupaPlan **plan_out);
+
+/* Frees a plan. NULL is ignored. */
+void tupa_plan_free(TupaPlan *plan);
+
+/*
+ * Implements the steps whose `function_ref` in the plan is `name` (e.g.
+ * "main::step_score") with `func`. `func` and `user_data` must stay valid
+ * until the runtime is freed; the library never frees `user_data`.
+ */
+TupaStatus tupa_runtime_register_step(TupaRuntime *runtime, const char *name, TupaStepFn func,
+                                      void *user_data);
+
+/*
+ * Runs `plan` on `input_json`, blocking until it finishes, and returns the
+ * final state in `*output_out`.
+ */
+TupaStatus tupa_runtime_run(TupaRuntime *runtime, const TupaPlan *plan, const char *input_json,
+                            char **output_out);
+
+/*
+ * Evaluates the plan's constraints against `state_json` and returns the
+ * report (`success`, `constraints`, `warnings`, `decision`) in
+ * `*report_out`.
+ */
+TupaStatus tupa_runtime_evaluate_constraints(TupaRuntime *runtime, const TupaPlan *plan,
+                                             const char *state_json, char **report_out);
+
+/* Sets a step's output from JSON; the string is copied. */
+TupaStatus tupa_step_result_set_output(TupaStepResult *result, const char *output_json);
+
+/* Sets the message of a failing step; the string is copied. */
+TupaStatus tupa_step_result_set_error(TupaStepResult *result, const char *message);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TUPA_H */
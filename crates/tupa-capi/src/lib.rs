@@ -0,0 +1,356 @@
+//! C ABI for embedding the Tupã runtime in hosts written in C, C++, Go or
+//! Java. `include/tupa.h` declares these functions and documents who owns
+//! each pointer; everything crossing the boundary is UTF-8 JSON.
+//!
+//! Every call catches panics, reports failures as a `TupaStatus` and leaves
+//! the message in a thread-local slot read with `tupa_last_error`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tupa_codegen::execution_plan::ExecutionPlan;
+use tupa_runtime::Runtime;
+
+/// Drives the async runtime; `tupa_runtime_run` blocks the calling thread
+/// on it.
+static EXECUTOR: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start the Tupã runtime")
+});
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TupaStatus {
+    Ok = 0,
+    /// A required pointer was NULL or a string was not UTF-8.
+    InvalidArgument = 1,
+    /// A plan, input or step output was not valid JSON.
+    InvalidJson = 2,
+    /// The pipeline, or one of its steps, failed.
+    RunFailed = 3,
+    /// The runtime panicked; the handle stays usable.
+    Panic = 4,
+}
+
+/// Runtime with its registered steps.
+pub struct TupaRuntime {
+    runtime: Runtime,
+}
+
+/// Execution plan loaded from JSON.
+pub struct TupaPlan {
+    plan: ExecutionPlan,
+}
+
+/// Where a step callback leaves its output or error.
+#[derive(Default)]
+pub struct TupaStepResult {
+    output: Option<Value>,
+    error: Option<String>,
+}
+
+/// Step implemented by the host: gets the pipeline state as JSON and
+/// reports its output through `result`, returning 0 on success.
+pub type TupaStepFn = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    input_json: *const c_char,
+    result: *mut TupaStepResult,
+) -> c_int;
+
+struct Failure {
+    status: TupaStatus,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: TupaStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, turning its failure or panic into a status and the last error.
+fn ffi_call(f: impl FnOnce() -> Result<(), Failure>) -> TupaStatus {
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = None);
+    let failure = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return TupaStatus::Ok,
+        Ok(Err(failure)) => failure,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Failure::new(TupaStatus::Panic, format!("panic: {message}"))
+        }
+    };
+    set_last_error(&failure.message);
+    failure.status
+}
+
+unsafe fn arg<'a, T>(ptr: *const T, name: &str) -> Result<&'a T, Failure> {
+    ptr.as_ref()
+        .ok_or_else(|| Failure::new(TupaStatus::InvalidArgument, format!("{name} is NULL")))
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err(Failure::new(
+            TupaStatus::InvalidArgument,
+            format!("{name} is NULL"),
+        ));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| Failure::new(TupaStatus::InvalidArgument, format!("{name} is not UTF-8")))
+}
+
+unsafe fn json_arg(ptr: *const c_char, name: &str) -> Result<Value, Failure> {
+    serde_json::from_str(str_arg(ptr, name)?)
+        .map_err(|e| Failure::new(TupaStatus::InvalidJson, format!("{name}: {e}")))
+}
+
+unsafe fn out_arg<'a, T>(out: *mut *mut T, name: &str) -> Result<&'a mut *mut T, Failure> {
+    let out = out
+        .as_mut()
+        .ok_or_else(|| Failure::new(TupaStatus::InvalidArgument, format!("{name} is NULL")))?;
+    *out = ptr::null_mut();
+    Ok(out)
+}
+
+fn json_string(value: &Value) -> *mut c_char {
+    CString::new(value.to_string())
+        .expect("JSON escapes NUL bytes")
+        .into_raw()
+}
+
+/// Version of the library, e.g. `"0.8.1"`. Static; never freed.
+#[no_mangle]
+pub extern "C" fn tupa_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Message of the last failed call on this thread, or NULL.
+#[no_mangle]
+pub extern "C" fn tupa_last_error() -> *const c_char {
+    LAST_ERROR.with(|slot| slot.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Frees a string returned by this library.
+///
+/// # Safety
+/// `s` must be NULL or a string returned by this library, freed once.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Creates a runtime with no registered steps.
+#[no_mangle]
+pub extern "C" fn tupa_runtime_new() -> *mut TupaRuntime {
+    let mut runtime = ptr::null_mut();
+    ffi_call(|| {
+        runtime = Box::into_raw(Box::new(TupaRuntime {
+            runtime: Runtime::new(),
+        }));
+        Ok(())
+    });
+    runtime
+}
+
+/// Frees a runtime and the steps registered on it.
+///
+/// # Safety
+/// `runtime` must be NULL or a handle from `tupa_runtime_new`, freed once
+/// and not in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_runtime_free(runtime: *mut TupaRuntime) {
+    if !runtime.is_null() {
+        drop(Box::from_raw(runtime));
+    }
+}
+
+/// Loads an execution plan, as written by `tupa codegen --plan-only`.
+///
+/// # Safety
+/// `plan_json` must be a NUL-terminated string and `plan_out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_plan_load(
+    plan_json: *const c_char,
+    plan_out: *mut *mut TupaPlan,
+) -> TupaStatus {
+    ffi_call(|| {
+        let out = out_arg(plan_out, "plan_out")?;
+        let plan: ExecutionPlan = serde_json::from_str(str_arg(plan_json, "plan_json")?)
+            .map_err(|e| Failure::new(TupaStatus::InvalidJson, format!("plan_json: {e}")))?;
+        *out = Box::into_raw(Box::new(TupaPlan { plan }));
+        Ok(())
+    })
+}
+
+/// Frees a plan.
+///
+/// # Safety
+/// `plan` must be NULL or a handle from `tupa_plan_load`, freed once.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_plan_free(plan: *mut TupaPlan) {
+    if !plan.is_null() {
+        drop(Box::from_raw(plan));
+    }
+}
+
+/// Calls a host callback; the runtime may do so from any of its threads.
+struct HostStep {
+    func: TupaStepFn,
+    user_data: *mut c_void,
+}
+
+// The header requires callbacks and their user data to be thread-safe.
+unsafe impl Send for HostStep {}
+unsafe impl Sync for HostStep {}
+
+impl HostStep {
+    fn call(&self, input: Value) -> Result<Value, String> {
+        let input = CString::new(input.to_string()).map_err(|e| e.to_string())?;
+        let mut result = TupaStepResult::default();
+        let code = unsafe { (self.func)(self.user_data, input.as_ptr(), &mut result) };
+        if code != 0 {
+            return Err(result
+                .error
+                .unwrap_or_else(|| format!("step callback returned {code}")));
+        }
+        result
+            .output
+            .ok_or_else(|| "step callback set no output".to_string())
+    }
+}
+
+/// Implements the step whose `function_ref` is `name` with `func`.
+///
+/// # Safety
+/// `runtime` must be a live handle and `name` a NUL-terminated string.
+/// `func` and `user_data` must stay valid, and be safe to call from any
+/// thread, until the runtime is freed.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_runtime_register_step(
+    runtime: *mut TupaRuntime,
+    name: *const c_char,
+    func: Option<TupaStepFn>,
+    user_data: *mut c_void,
+) -> TupaStatus {
+    ffi_call(|| {
+        let runtime = arg(runtime, "runtime")?;
+        let name = str_arg(name, "name")?;
+        let func = func.ok_or_else(|| Failure::new(TupaStatus::InvalidArgument, "func is NULL"))?;
+        let step = HostStep { func, user_data };
+        runtime
+            .runtime
+            .register_step(name, move |input| step.call(input));
+        Ok(())
+    })
+}
+
+/// Runs `plan` on `input_json` and returns the final state.
+///
+/// # Safety
+/// `runtime` and `plan` must be live handles, `input_json` a NUL-terminated
+/// string and `output_out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_runtime_run(
+    runtime: *mut TupaRuntime,
+    plan: *const TupaPlan,
+    input_json: *const c_char,
+    output_out: *mut *mut c_char,
+) -> TupaStatus {
+    ffi_call(|| {
+        let out = out_arg(output_out, "output_out")?;
+        let runtime = arg(runtime, "runtime")?;
+        let plan = arg(plan, "plan")?;
+        let input = json_arg(input_json, "input_json")?;
+        let output = EXECUTOR
+            .block_on(runtime.runtime.run_pipeline_async(&plan.plan, input))
+            .map_err(|e| Failure::new(TupaStatus::RunFailed, e.to_string()))?;
+        *out = json_string(&output);
+        Ok(())
+    })
+}
+
+/// Evaluates `plan`'s constraints against `state_json` and returns the
+/// report: `success`, `constraints`, `warnings` and, if any, `decision`.
+///
+/// # Safety
+/// As for `tupa_runtime_run`.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_runtime_evaluate_constraints(
+    runtime: *mut TupaRuntime,
+    plan: *const TupaPlan,
+    state_json: *const c_char,
+    report_out: *mut *mut c_char,
+) -> TupaStatus {
+    ffi_call(|| {
+        let out = out_arg(report_out, "report_out")?;
+        let runtime = arg(runtime, "runtime")?;
+        let plan = arg(plan, "plan")?;
+        let state = json_arg(state_json, "state_json")?;
+        *out = json_string(&runtime.runtime.evaluate_constraints(&plan.plan, &state));
+        Ok(())
+    })
+}
+
+/// Sets a step's output; the JSON is copied.
+///
+/// # Safety
+/// `result` must be the pointer passed to the running callback and
+/// `output_json` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_step_result_set_output(
+    result: *mut TupaStepResult,
+    output_json: *const c_char,
+) -> TupaStatus {
+    ffi_call(|| {
+        let output = json_arg(output_json, "output_json")?;
+        let result = result
+            .as_mut()
+            .ok_or_else(|| Failure::new(TupaStatus::InvalidArgument, "result is NULL"))?;
+        result.output = Some(output);
+        Ok(())
+    })
+}
+
+/// Sets the message a failing step reports; the string is copied.
+///
+/// # Safety
+/// As for `tupa_step_result_set_output`.
+#[no_mangle]
+pub unsafe extern "C" fn tupa_step_result_set_error(
+    result: *mut TupaStepResult,
+    message: *const c_char,
+) -> TupaStatus {
+    ffi_call(|| {
+        let message = str_arg(message, "message")?;
+        let result = result
+            .as_mut()
+            .ok_or_else(|| Failure::new(TupaStatus::InvalidArgument, "result is NULL"))?;
+        result.error = Some(message.to_string());
+        Ok(())
+    })
+}
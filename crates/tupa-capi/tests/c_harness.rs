@@ -0,0 +1,82 @@
+//! Compiles `tests/harness.c` against `include/tupa.h` and the built
+//! library, then runs it on a plan compiled from Tupã source.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tupa_codegen::execution_plan::codegen_pipeline;
+use tupa_parser::{parse_program, Item};
+
+const SOURCE: &str = r#"
+pipeline Scoring {
+    input: { x: f64 },
+    constraints: [ { metric: "score", le: 10.0 } ],
+    steps: [ step("score") { input.x * 2.0 } ]
+}
+"#;
+
+/// Builds `libtupa` (integration tests only get the rlib) and returns its
+/// directory: the parent of the test binary's `deps`.
+fn library_dir() -> PathBuf {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut build = Command::new(cargo);
+    build.args(["build", "--lib", "-p", "tupa-capi"]);
+    if !cfg!(debug_assertions) {
+        build.arg("--release");
+    }
+    assert!(build.status().unwrap().success(), "building libtupa failed");
+    let exe = std::env::current_exe().unwrap();
+    exe.parent().unwrap().parent().unwrap().to_path_buf()
+}
+
+fn write_plan(dir: &Path) -> PathBuf {
+    let program = parse_program(SOURCE).unwrap();
+    let pipeline = program
+        .items
+        .iter()
+        .find_map(|item| match item {
+            Item::Pipeline(p) => Some(p),
+            _ => None,
+        })
+        .unwrap();
+    let path = dir.join("plan.json");
+    std::fs::write(&path, codegen_pipeline("main", pipeline, &program).unwrap()).unwrap();
+    path
+}
+
+#[test]
+fn test_c_harness_runs_pipelines_through_the_header() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("tupa-capi-harness");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let lib_dir = library_dir();
+    let harness = out_dir.join("harness");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg(crate_dir.join("tests/harness.c"))
+        .arg("-o")
+        .arg(&harness)
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-ltupa")
+        .status()
+        .unwrap_or_else(|e| panic!("failed to run {cc}: {e}"));
+    assert!(status.success(), "compiling harness.c failed");
+
+    let output = Command::new(&harness)
+        .arg(write_plan(&out_dir))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "harness failed:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
@@ -0,0 +1,113 @@
+/* Exercises tupa.h from C; run by tests/c_harness.rs with a plan path. */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "tupa.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                                    \
+    do {                                                                               \
+        if (!(cond)) {                                                                 \
+            const char *error = tupa_last_error();                                     \
+            fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n", __FILE__,    \
+                    __LINE__, #cond, error ? error : "none");                         \
+            failures++;                                                                \
+        }                                                                              \
+    } while (0)
+
+/* Doubles `x`, counting calls in `user_data`; fails on negative input. */
+static int score(void *user_data, const char *input_json, TupaStepResult *result) {
+    int *calls = user_data;
+    double x = 0;
+    (*calls)++;
+    const char *field = strstr(input_json, "\"x\":");
+    if (field == NULL || sscanf(field + 4, "%lf", &x) != 1) {
+        tupa_step_result_set_error(result, "input has no x");
+        return 1;
+    }
+    if (x < 0) {
+        tupa_step_result_set_error(result, "negative x");
+        return 1;
+    }
+    char output[64];
+    snprintf(output, sizeof output, "%g", x * 2);
+    return tupa_step_result_set_output(result, output) == TUPA_OK ? 0 : 1;
+}
+
+static char *read_file(const char *path) {
+    FILE *file = fopen(path, "rb");
+    if (file == NULL) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    long size = ftell(file);
+    rewind(file);
+    char *buffer = malloc(size + 1);
+    size_t read = fread(buffer, 1, size, file);
+    buffer[read] = '\0';
+    fclose(file);
+    return buffer;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s PLAN_JSON\n", argv[0]);
+        return 2;
+    }
+    char *plan_json = read_file(argv[1]);
+    if (plan_json == NULL) {
+        fprintf(stderr, "cannot read %s\n", argv[1]);
+        return 2;
+    }
+
+    CHECK(strlen(tupa_version()) > 0);
+
+    TupaPlan *plan = NULL;
+    CHECK(tupa_plan_load("{", &plan) == TUPA_INVALID_JSON);
+    CHECK(plan == NULL && tupa_last_error() != NULL);
+    CHECK(tupa_plan_load(NULL, &plan) == TUPA_INVALID_ARGUMENT);
+    CHECK(tupa_plan_load(plan_json, &plan) == TUPA_OK && plan != NULL);
+    CHECK(tupa_last_error() == NULL);
+    free(plan_json);
+
+    TupaRuntime *runtime = tupa_runtime_new();
+    CHECK(runtime != NULL);
+    int calls = 0;
+    CHECK(tupa_runtime_register_step(runtime, "main::step_score", NULL, NULL) ==
+          TUPA_INVALID_ARGUMENT);
+    CHECK(tupa_runtime_register_step(runtime, "main::step_score", score, &calls) == TUPA_OK);
+
+    char *output = NULL;
+    CHECK(tupa_runtime_run(runtime, plan, "{\"x\": 3.0}", &output) == TUPA_OK);
+    CHECK(output != NULL && strstr(output, "\"score\":6") != NULL);
+    CHECK(calls == 1);
+    tupa_string_free(output);
+
+    CHECK(tupa_runtime_run(runtime, plan, "{\"x\": -1.0}", &output) == TUPA_RUN_FAILED);
+    CHECK(output == NULL);
+    CHECK(tupa_last_error() != NULL && strstr(tupa_last_error(), "negative x") != NULL);
+    CHECK(tupa_runtime_run(runtime, plan, "not json", &output) == TUPA_INVALID_JSON);
+
+    char *report = NULL;
+    CHECK(tupa_runtime_evaluate_constraints(runtime, plan, "{\"score\": 6.0}", &report) ==
+          TUPA_OK);
+    CHECK(report != NULL && strstr(report, "\"success\":true") != NULL);
+    tupa_string_free(report);
+    CHECK(tupa_runtime_evaluate_constraints(runtime, plan, "{\"score\": 12.0}", &report) ==
+          TUPA_OK);
+    CHECK(report != NULL && strstr(report, "\"success\":false") != NULL);
+    tupa_string_free(report);
+
+    tupa_plan_free(plan);
+    tupa_runtime_free(runtime);
+    tupa_string_free(NULL);
+
+    if (failures > 0) {
+        fprintf(stderr, "%d check(s) failed\n", failures);
+        return 1;
+    }
+    printf("ok\n");
+    return 0;
+}
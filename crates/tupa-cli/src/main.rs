@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::process;
 
 mod run;
@@ -27,6 +27,52 @@ pub enum Commands {
         /// Execute a pre-compiled plan file
         #[arg(long)]
         plan: Option<String>,
+        /// Write runtime metrics (Prometheus text format) to this file
+        #[arg(long)]
+        metrics_out: Option<String>,
+        /// Append the run's trace (OTLP-JSON) to this file
+        #[arg(long)]
+        trace_out: Option<String>,
+        /// Post the run's trace to an OTLP/HTTP collector (e.g. http://localhost:4318/v1/traces)
+        #[arg(long)]
+        trace_endpoint: Option<String>,
+        /// W3C traceparent header of the calling trace
+        #[arg(long)]
+        traceparent: Option<String>,
+        /// Load and commit temporal pipeline state in this JSON file
+        #[arg(long)]
+        state_file: Option<String>,
+        /// Print why each step produced its output (guards, reasons, and the branches and bindings steps report)
+        #[arg(long, conflicts_with_all = ["trace_out", "trace_endpoint", "traceparent"])]
+        explain: bool,
+        #[command(flatten)]
+        python: PythonArgs,
+    },
+    /// Run a pipeline over a labeled dataset and check its constraints
+    Validate {
+        /// Input file (optional if --plan is used)
+        #[arg(required_unless_present = "plan")]
+        file: Option<String>,
+        /// Labeled records, one JSON object per line
+        #[arg(long)]
+        dataset: String,
+        /// Pipeline to validate (optional)
+        #[arg(long)]
+        pipeline: Option<String>,
+        /// Validate a pre-compiled plan file
+        #[arg(long)]
+        plan: Option<String>,
+        /// Record field holding the expected class
+        #[arg(long, default_value = "label")]
+        label: String,
+        /// Output path of the predicted class (default: last step's output)
+        #[arg(long)]
+        prediction: Option<String>,
+        /// Output format (text/json)
+        #[arg(long, default_value = "text")]
+        format: String,
+        #[command(flatten)]
+        python: PythonArgs,
     },
     /// Check syntax and types
     Check {
@@ -46,6 +92,8 @@ pub enum Commands {
         /// Output format
         #[arg(long, default_value = "text")]
         format: String,
+        #[command(flatten)]
+        python: PythonArgs,
     },
     /// Parse and show AST
     Parse {
@@ -67,7 +115,7 @@ pub enum Commands {
     Codegen {
         /// Input file
         file: String,
-        /// Output format
+        /// Output format (text/json/rust-bindings/python-types/pydantic)
         #[arg(long, default_value = "text")]
         format: String,
         /// Generate execution plan only (JSON)
@@ -84,6 +132,24 @@ pub enum Commands {
     },
 }
 
+/// Python environment of `py:` steps; flags take precedence over the
+/// `TUPA_PYTHON*` variables.
+#[derive(Args)]
+pub struct PythonArgs {
+    /// Virtualenv whose packages Python steps import (default: $TUPA_PYTHON_VENV or $VIRTUAL_ENV)
+    #[arg(long)]
+    python_venv: Option<String>,
+    /// Python interpreter whose packages Python steps import (default: $TUPA_PYTHON)
+    #[arg(long)]
+    python: Option<String>,
+    /// Directory added to sys.path, relative to the .tp or plan file; repeatable (also $TUPA_PYTHON_PATH)
+    #[arg(long)]
+    python_path: Vec<String>,
+    /// Python module imported before the run; repeatable (also $TUPA_PYTHON_PRELOAD)
+    #[arg(long)]
+    python_preload: Vec<String>,
+}
+
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
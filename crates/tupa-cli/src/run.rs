@@ -1,11 +1,19 @@
-use crate::Commands;
+use crate::{Commands, PythonArgs};
 use serde_json::json;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tupa_codegen::execution_plan::{codegen_pipeline, ExecutionPlan};
 use tupa_codegen::generate_stub_with_types;
+use tupa_codegen::python_bindings::{generate_python_types, PythonTypesStyle};
+use tupa_codegen::rust_bindings::generate_rust_bindings;
 use tupa_lexer::LexerError;
+use tupa_parser::module::{load_program, LoadedProgram, ModuleError};
 use tupa_parser::{parse_program, Expr, ExprKind, Item, ParserError, Span};
-use tupa_runtime::Runtime;
+use tupa_runtime::dataset::DatasetOptions;
+use tupa_runtime::state::FileStateStore;
+use tupa_runtime::trace::{FileExporter, HttpExporter, TraceContext, TraceExporter};
+use tupa_runtime::{PythonEnv, Runtime};
 use tupa_typecheck::{analyze_effects, typecheck_program_with_warnings, TypeError};
 
 pub async fn run(command: Commands) -> Result<(), String> {
@@ -15,13 +23,51 @@ pub async fn run(command: Commands) -> Result<(), String> {
             pipeline,
             input,
             plan,
-        } => run_pipeline(file, pipeline, input, plan).await,
+            metrics_out,
+            trace_out,
+            trace_endpoint,
+            traceparent,
+            state_file,
+            explain,
+            python,
+        } => {
+            let tracing = TraceOptions {
+                out: trace_out,
+                endpoint: trace_endpoint,
+                traceparent,
+            };
+            let options = RunOptions {
+                metrics_out,
+                state_file,
+                explain,
+                python: python_env(python, plan.as_deref().or(file.as_deref())),
+            };
+            run_pipeline(file, pipeline, input, plan, options, tracing).await
+        }
+        Commands::Validate {
+            file,
+            dataset,
+            pipeline,
+            plan,
+            label,
+            prediction,
+            format,
+            python,
+        } => {
+            let env = python_env(python, plan.as_deref().or(file.as_deref()));
+            let options = DatasetOptions { label, prediction };
+            run_validate(file, pipeline, plan, dataset, options, format, env).await
+        }
         Commands::Check { file, format } => run_check(file, format).await,
         Commands::Audit {
             file,
             format,
             input,
-        } => run_audit(file, format, input).await,
+            python,
+        } => {
+            let env = python_env(python, Some(&file));
+            run_audit(file, format, input, env).await
+        }
         Commands::Parse { file, format } => run_parse(file, format).await,
         Commands::Lex { file, format } => run_lex(file, format).await,
         Commands::Codegen {
@@ -33,13 +79,69 @@ pub async fn run(command: Commands) -> Result<(), String> {
     }
 }
 
+struct RunOptions {
+    metrics_out: Option<String>,
+    state_file: Option<String>,
+    explain: bool,
+    python: PythonEnv,
+}
+
+/// Python environment from the `--python*` flags over the `TUPA_PYTHON*`
+/// variables; `sys.path` entries are relative to `source`, the `.tp` or
+/// plan file.
+fn python_env(args: PythonArgs, source: Option<&str>) -> PythonEnv {
+    let mut env = PythonEnv::from_env();
+    if let Some(venv) = args.python_venv {
+        env.venv = Some(venv.into());
+        env.interpreter = None;
+    }
+    if let Some(python) = args.python {
+        env.interpreter = Some(python.into());
+    }
+    env.paths
+        .splice(0..0, args.python_path.into_iter().map(PathBuf::from));
+    env.preload.splice(0..0, args.python_preload);
+    let base = source
+        .and_then(|source| Path::new(source).parent())
+        .unwrap_or(Path::new(""));
+    env.paths_relative_to(base)
+}
+
+struct TraceOptions {
+    out: Option<String>,
+    endpoint: Option<String>,
+    traceparent: Option<String>,
+}
+
+impl TraceOptions {
+    fn enabled(&self) -> bool {
+        self.out.is_some() || self.endpoint.is_some() || self.traceparent.is_some()
+    }
+}
+
 async fn run_pipeline(
     file: Option<String>,
     pipeline_name: Option<String>,
     input_file: Option<String>,
     plan_file: Option<String>,
+    options: RunOptions,
+    tracing: TraceOptions,
 ) -> Result<(), String> {
     let runtime = Runtime::new();
+    if !options.python.is_empty() {
+        runtime
+            .configure_python(&options.python)
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(path) = options.state_file {
+        runtime.set_state_store(Arc::new(FileStateStore::new(path)));
+    }
+    let parent = tracing
+        .traceparent
+        .as_deref()
+        .map(TraceContext::parse_traceparent)
+        .transpose()
+        .map_err(|e| e.to_string())?;
 
     // Read input JSON
     let input = if let Some(path) = input_file {
@@ -49,14 +151,67 @@ async fn run_pipeline(
         serde_json::Value::Null
     };
 
-    let plan: ExecutionPlan = if let Some(path) = plan_file {
+    let plan = load_plan(file, pipeline_name, plan_file)?;
+
+    // Execute
+    let mut explanation = None;
+    let result = if options.explain {
+        let (result, explained) = runtime.run_pipeline_explained(&plan, input).await;
+        explanation = Some(explained);
+        result
+    } else if tracing.enabled() {
+        let (result, trace) = runtime.run_pipeline_traced(&plan, input, parent).await;
+        if let Some(path) = &tracing.out {
+            FileExporter::new(path)
+                .export(&trace)
+                .map_err(|e| e.to_string())?;
+        }
+        if let Some(endpoint) = &tracing.endpoint {
+            HttpExporter::new(endpoint.as_str())
+                .export(&trace)
+                .map_err(|e| e.to_string())?;
+        }
+        result
+    } else {
+        runtime.run_pipeline_async(&plan, input).await
+    };
+
+    if let Some(path) = options.metrics_out {
+        std::fs::write(&path, runtime.render_metrics())
+            .map_err(|e| format!("Failed to write metrics to {}: {}", path, e))?;
+    }
+
+    match result {
+        Ok(output) => {
+            println!("{}", serde_json::to_string_pretty(&output).unwrap());
+            if let Some(explanation) = explanation {
+                print!("{}", explanation.render_text());
+            }
+            Ok(())
+        }
+        Err(e) => {
+            if let Some(explanation) = explanation {
+                print!("{}", explanation.render_text());
+            }
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Loads `--plan`, or compiles the named (or first) pipeline of `file`.
+fn load_plan(
+    file: Option<String>,
+    pipeline_name: Option<String>,
+    plan_file: Option<String>,
+) -> Result<ExecutionPlan, String> {
+    let plan = if let Some(path) = plan_file {
         // Load pre-compiled plan
         let content = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
         serde_json::from_str(&content).map_err(|e| format!("Invalid plan JSON: {}", e))?
     } else if let Some(path) = file {
-        // Compile from source
-        let content = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
-        let program = parse_program(&content).map_err(|e| format!("{:?}", e))?;
+        // Compile from source, with the modules it imports
+        let loaded = load_source(&path, "text")?;
+        let program = loaded.program;
         typecheck_program_with_warnings(&program).map_err(|e| format!("{:?}", e))?;
 
         // Find pipeline
@@ -97,107 +252,167 @@ async fn run_pipeline(
     } else {
         return Err("Either --plan or file argument must be provided".to_string());
     };
+    Ok(plan)
+}
 
-    // Execute
-    let result = runtime.run_pipeline_async(&plan, input).await;
+async fn run_validate(
+    file: Option<String>,
+    pipeline_name: Option<String>,
+    plan_file: Option<String>,
+    dataset_file: String,
+    options: DatasetOptions,
+    format: String,
+    python: PythonEnv,
+) -> Result<(), String> {
+    let plan = load_plan(file, pipeline_name, plan_file)?;
+    let content = std::fs::read_to_string(&dataset_file).map_err(|e| e.to_string())?;
+    let dataset = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("Invalid dataset JSON at line {}: {}", i + 1, e))
+        })
+        .collect::<Result<Vec<serde_json::Value>, String>>()?;
 
-    match result {
-        Ok(output) => {
-            println!("{}", serde_json::to_string_pretty(&output).unwrap());
-            Ok(())
-        }
-        Err(e) => Err(e.to_string()),
+    let runtime = Runtime::new();
+    if !python.is_empty() {
+        runtime
+            .configure_python(&python)
+            .map_err(|e| e.to_string())?;
     }
-}
+    let report = runtime
+        .validate_dataset(&plan, dataset, &options)
+        .await
+        .map_err(|e| e.to_string())?;
 
-async fn run_check(file: String, format: String) -> Result<(), String> {
-    let content = std::fs::read_to_string(&file).map_err(|e| e.to_string())?;
+    if format == "json" {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print!("{}", render_validation_report(&report));
+    }
+    if report["success"].as_bool().unwrap_or(false) {
+        Ok(())
+    } else {
+        Err(format!("Validation failed for pipeline '{}'", plan.name))
+    }
+}
 
-    match parse_program(&content) {
-        Ok(program) => match typecheck_program_with_warnings(&program) {
-            Ok(_) => {
-                if format == "json" {
-                    println!("{}", json!({ "status": "ok" }));
-                } else {
-                    println!("OK");
-                }
-                Ok(())
-            }
-            Err(e) => {
-                let span = match &e {
-                    TypeError::UnknownType { .. } | TypeError::InvalidTypeArity { .. } => {
-                        Span { start: 0, end: 0 }
-                    }
+fn render_validation_report(report: &serde_json::Value) -> String {
+    let metrics = &report["metrics"];
+    let mut out = format!(
+        "pipeline {}: {} records, {} errors, {} unlabeled\n",
+        report["pipeline"].as_str().unwrap_or_default(),
+        report["records"],
+        metrics["errors"],
+        metrics["unlabeled"]
+    );
+    for constraint in report["constraints"].as_array().into_iter().flatten() {
+        let status = constraint["status"]
+            .as_str()
+            .unwrap_or("fail")
+            .to_uppercase();
+        out.push_str(&format!(
+            "  {status:<4} {} {} {} (value {})\n",
+            constraint["metric"].as_str().unwrap_or_default(),
+            constraint["comparator"].as_str().unwrap_or_default(),
+            constraint["threshold"],
+            constraint["value"]
+        ));
+    }
+    for metric in report["unmeasured"].as_array().into_iter().flatten() {
+        out.push_str(&format!(
+            "  note: '{}' was not measured on the dataset, so its constraint fails\n",
+            metric.as_str().unwrap_or_default()
+        ));
+    }
+    for problem in report["problems"].as_array().into_iter().flatten() {
+        out.push_str(&format!(
+            "  problem: {}\n",
+            problem.as_str().unwrap_or_default()
+        ));
+    }
+    let verdict = if report["success"].as_bool().unwrap_or(false) {
+        "PASS"
+    } else {
+        "FAIL"
+    };
+    out.push_str(&format!("result: {verdict}\n"));
+    out
+}
 
-                    TypeError::UnknownVar { span, .. }
-                    | TypeError::UnknownFunction { span, .. }
-                    | TypeError::UnknownVariant { span, .. }
-                    | TypeError::Mismatch { span, .. }
-                    | TypeError::ArityMismatch { span, .. }
-                    | TypeError::InvalidBinary { span, .. }
-                    | TypeError::InvalidUnary { span, .. }
-                    | TypeError::InvalidCallTarget { span, .. }
-                    | TypeError::UnknownField { span, .. }
-                    | TypeError::ReturnMismatch { span, .. }
-                    | TypeError::MissingReturn { span }
-                    | TypeError::InvalidConstraint { span, .. }
-                    | TypeError::UnprovenConstraint { span, .. }
-                    | TypeError::BreakOutsideLoop { span }
-                    | TypeError::ContinueOutsideLoop { span }
-                    | TypeError::NonExhaustiveMatch { span } => {
-                        (*span).unwrap_or(Span { start: 0, end: 0 })
-                    }
+async fn run_check(file: String, format: String) -> Result<(), String> {
+    let loaded = load_source(&file, &format)?;
 
-                    TypeError::ImpureInDeterministic { span, .. }
-                    | TypeError::UndefinedMetric { span, .. } => *span,
-                };
-                let code = get_error_code(&e);
-                if format == "json" {
-                    Err(format_json_error(
-                        e.to_string(),
-                        span,
-                        &file,
-                        &content,
-                        code,
-                    ))
-                } else {
-                    Err(format_text_error(
-                        e.to_string(),
-                        span,
-                        &file,
-                        &content,
-                        code,
-                    ))
-                }
-            }
-        },
-        Err(e) => {
-            let span = match &e {
-                ParserError::Unexpected(_, s) => *s,
-                ParserError::Eof(pos) => Span {
-                    start: *pos,
-                    end: *pos,
-                },
-                ParserError::Lexer(tupa_lexer::LexerError::Unexpected(_, pos)) => Span {
-                    start: *pos,
-                    end: *pos + 1,
-                },
-                ParserError::MissingSemicolon(s) => *s,
-            };
-            let msg = match &e {
-                ParserError::Unexpected(tok, _) => format!("unexpected token {:?}", tok),
-                _ => e.to_string(),
-            };
+    match typecheck_program_with_warnings(&loaded.program) {
+        Ok(_) => {
             if format == "json" {
-                Err(format_json_error(msg, span, &file, &content, None))
+                println!("{}", json!({ "status": "ok" }));
             } else {
-                Err(format_text_error(msg, span, &file, &content, None))
+                println!("OK");
             }
+            Ok(())
+        }
+        Err(e) => {
+            let span = e.span().unwrap_or(Span { start: 0, end: 0 });
+            Err(format_located_error(
+                e.to_string(),
+                span,
+                &loaded,
+                Some(e.code()),
+                &format,
+            ))
         }
     }
 }
 
-async fn run_audit(file: String, format: String, input: Option<String>) -> Result<(), String> {
+/// Loads `file` and the modules it imports, reporting parse and import
+/// errors against the file they occur in.
+fn load_source(file: &str, format: &str) -> Result<LoadedProgram, String> {
+    load_program(file).map_err(|e| {
+        let Some(at) = e.location() else {
+            return e.to_string();
+        };
+        let msg = match &e {
+            ModuleError::Parse {
+                error: ParserError::Unexpected(tok, _),
+                ..
+            } => format!("unexpected token {:?}", tok),
+            _ => e.to_string(),
+        };
+        let path = at.path.display().to_string();
+        if format == "json" {
+            format_json_error(msg, at.span, &path, &at.source, None)
+        } else {
+            format_text_error(msg, at.span, &path, &at.source, None)
+        }
+    })
+}
+
+/// Formats an error at a span of a loaded program, in the file it falls in.
+fn format_located_error(
+    message: String,
+    span: Span,
+    loaded: &LoadedProgram,
+    code: Option<&str>,
+    format: &str,
+) -> String {
+    let (file, span) = loaded.locate(span);
+    let path = file.path.display().to_string();
+    if format == "json" {
+        format_json_error(message, span, &path, &file.source, code)
+    } else {
+        format_text_error(message, span, &path, &file.source, code)
+    }
+}
+
+async fn run_audit(
+    file: String,
+    format: String,
+    input: Option<String>,
+    python: PythonEnv,
+) -> Result<(), String> {
     let content = std::fs::read_to_string(&file).map_err(|e| e.to_string())?;
     let program = parse_program(&content).map_err(|e| format!("{:?}", e))?;
 
@@ -220,10 +435,41 @@ async fn run_audit(file: String, format: String, input: Option<String>) -> Resul
         tupa_audit::hash_execution(&program, &inputs)
     };
 
+    // Programs with Python externals also record the environment they ran
+    // against.
+    let uses_python = program.items.iter().any(|item| {
+        matches!(item, Item::Function(f)
+            if f.external_spec.as_ref().is_some_and(|spec| spec.python.is_some()))
+    });
+    let python_env = if uses_python {
+        if !python.is_empty() {
+            tupa_pyffi::configure_python(&python)?;
+        }
+        let report = tupa_pyffi::python_report()?;
+        let hash = tupa_audit::hash_json(&report);
+        Some((report, hash))
+    } else {
+        None
+    };
+
     if format == "json" {
-        println!("{}", json!({ "ast_hash": hash.as_str() }));
+        let mut out = json!({ "ast_hash": hash.as_str() });
+        if let Some((report, env_hash)) = &python_env {
+            out["python"] = report.clone();
+            out["python_env_hash"] = json!(env_hash.as_str());
+        }
+        println!("{}", out);
     } else {
         println!("AST Hash: {}", hash);
+        if let Some((report, env_hash)) = &python_env {
+            println!(
+                "Python: {} {} ({} packages)",
+                report["implementation"].as_str().unwrap_or_default(),
+                report["version"].as_str().unwrap_or_default(),
+                report["packages"].as_object().map_or(0, |p| p.len())
+            );
+            println!("Python Env Hash: {}", env_hash);
+        }
     }
     Ok(())
 }
@@ -309,31 +555,6 @@ fn format_text_error(
     )
 }
 
-fn get_error_code(e: &TypeError) -> Option<&'static str> {
-    match e {
-        TypeError::UnknownType { .. } => Some("E1001"),
-        TypeError::UnknownVar { .. } => Some("E1002"),
-        TypeError::UnknownFunction { .. } => Some("E1003"),
-        TypeError::UnknownVariant { .. } => Some("E1004"),
-        TypeError::Mismatch { .. } => Some("E2001"),
-        TypeError::ArityMismatch { .. } => Some("E2002"),
-        TypeError::InvalidTypeArity { .. } => Some("E2002"),
-        TypeError::InvalidBinary { .. } => Some("E2003"),
-        TypeError::InvalidUnary { .. } => Some("E2004"),
-        TypeError::InvalidCallTarget { .. } => Some("E2005"),
-        TypeError::ReturnMismatch { .. } => Some("E2006"),
-        TypeError::MissingReturn { .. } => Some("E2007"),
-        TypeError::UnknownField { .. } => Some("E2008"),
-        TypeError::InvalidConstraint { .. } => Some("E3001"),
-        TypeError::UnprovenConstraint { .. } => Some("E3002"),
-        TypeError::BreakOutsideLoop { .. } => Some("E4001"),
-        TypeError::ContinueOutsideLoop { .. } => Some("E4002"),
-        TypeError::NonExhaustiveMatch { .. } => Some("E5001"),
-        TypeError::ImpureInDeterministic { .. } => Some("E2005"),
-        TypeError::UndefinedMetric { .. } => Some("E2006"),
-    }
-}
-
 async fn run_parse(file: String, format: String) -> Result<(), String> {
     let content = std::fs::read_to_string(&file).map_err(|e| e.to_string())?;
 
@@ -347,18 +568,7 @@ async fn run_parse(file: String, format: String) -> Result<(), String> {
             Ok(())
         }
         Err(e) => {
-            let span = match &e {
-                ParserError::Unexpected(_, s) => *s,
-                ParserError::Eof(pos) => Span {
-                    start: *pos,
-                    end: *pos,
-                },
-                ParserError::Lexer(tupa_lexer::LexerError::Unexpected(_, pos)) => Span {
-                    start: *pos,
-                    end: *pos + 1,
-                },
-                ParserError::MissingSemicolon(s) => *s,
-            };
+            let span = e.span();
             let msg = match &e {
                 ParserError::Unexpected(tok, _) => format!("unexpected token {:?}", tok),
                 _ => e.to_string(),
@@ -404,247 +614,206 @@ async fn run_lex(file: String, format: String) -> Result<(), String> {
 }
 
 async fn run_codegen(file: String, format: String, plan_only: bool) -> Result<(), String> {
-    let content = std::fs::read_to_string(&file).map_err(|e| e.to_string())?;
-
-    match parse_program(&content) {
-        Ok(program) => {
-            match typecheck_program_with_warnings(&program) {
-                Ok(_) => {
-                    if format == "json" || plan_only {
-                        // Generate execution plans for all pipelines
-                        let mut plans = Vec::new();
-                        let pipelines: Vec<_> = program
-                            .items
-                            .iter()
-                            .filter_map(|item| {
-                                if let Item::Pipeline(p) = item {
-                                    Some(p)
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect();
-
-                        if pipelines.is_empty() && plan_only {
-                            return Err("No pipelines found to generate plan".to_string());
+    let loaded = load_source(&file, &format)?;
+    let program = &loaded.program;
+
+    match typecheck_program_with_warnings(program) {
+        Ok(_) => {
+            if format == "json" || plan_only {
+                // Generate execution plans for all pipelines
+                let mut plans = Vec::new();
+                let pipelines: Vec<_> = program
+                    .items
+                    .iter()
+                    .filter_map(|item| {
+                        if let Item::Pipeline(p) = item {
+                            Some(p)
+                        } else {
+                            None
                         }
+                    })
+                    .collect();
 
-                        for p in &pipelines {
-                            let plan_json =
-                                codegen_pipeline("main", p, &program).map_err(|e| e.to_string())?;
-                            let plan: serde_json::Value = serde_json::from_str(&plan_json).unwrap();
-                            plans.push(plan);
-                        }
+                if pipelines.is_empty() && plan_only {
+                    return Err("No pipelines found to generate plan".to_string());
+                }
 
-                        if plan_only {
-                            // Write to file
-                            // Use input filename stem
-                            let path = std::path::Path::new(&file);
-                            let stem = path.file_stem().unwrap().to_string_lossy();
-                            let output_path = format!("{}.plan.json", stem);
-
-                            if !plans.is_empty() {
-                                // Write single object (first pipeline) to be compatible with `run --plan`
-                                // If there are multiple, this might be ambiguous, but fits the simple test case.
-                                let plan_json = serde_json::to_string_pretty(&plans[0]).unwrap();
-                                std::fs::write(&output_path, plan_json)
-                                    .map_err(|e| e.to_string())?;
-                            }
-                        } else {
-                            println!("{}", serde_json::to_string_pretty(&plans).unwrap());
-                        }
-                    } else {
-                        // Text format (Rust stub)
-                        let stub = generate_stub_with_types(&program);
-                        println!("{}", stub);
-                    }
-                    Ok(())
+                for p in &pipelines {
+                    let plan_json =
+                        codegen_pipeline("main", p, program).map_err(|e| e.to_string())?;
+                    let plan: serde_json::Value = serde_json::from_str(&plan_json).unwrap();
+                    plans.push(plan);
                 }
-                Err(e) => {
-                    if format == "json" {
-                        let span = match &e {
-                            TypeError::UnknownType { .. } | TypeError::InvalidTypeArity { .. } => {
-                                Span { start: 0, end: 0 }
-                            }
-
-                            TypeError::UnknownVar { span, .. }
-                            | TypeError::UnknownFunction { span, .. }
-                            | TypeError::UnknownVariant { span, .. }
-                            | TypeError::Mismatch { span, .. }
-                            | TypeError::ArityMismatch { span, .. }
-                            | TypeError::InvalidBinary { span, .. }
-                            | TypeError::InvalidUnary { span, .. }
-                            | TypeError::InvalidCallTarget { span, .. }
-                            | TypeError::UnknownField { span, .. }
-                            | TypeError::ReturnMismatch { span, .. }
-                            | TypeError::MissingReturn { span }
-                            | TypeError::InvalidConstraint { span, .. }
-                            | TypeError::UnprovenConstraint { span, .. }
-                            | TypeError::BreakOutsideLoop { span }
-                            | TypeError::ContinueOutsideLoop { span }
-                            | TypeError::NonExhaustiveMatch { span } => {
-                                (*span).unwrap_or(Span { start: 0, end: 0 })
-                            }
-
-                            TypeError::ImpureInDeterministic { span, .. }
-                            | TypeError::UndefinedMetric { span, .. } => *span,
-                        };
-                        let code = get_error_code(&e);
-                        Err(format_json_error(
-                            e.to_string(),
-                            span,
-                            &file,
-                            &content,
-                            code,
-                        ))
-                    } else {
-                        Err(e.to_string())
+
+                if plan_only {
+                    // Write to file
+                    // Use input filename stem
+                    let path = std::path::Path::new(&file);
+                    let stem = path.file_stem().unwrap().to_string_lossy();
+                    let output_path = format!("{}.plan.json", stem);
+
+                    if !plans.is_empty() {
+                        // Write single object (first pipeline) to be compatible with `run --plan`
+                        // If there are multiple, this might be ambiguous, but fits the simple test case.
+                        let plan_json = serde_json::to_string_pretty(&plans[0]).unwrap();
+                        std::fs::write(&output_path, plan_json).map_err(|e| e.to_string())?;
                     }
+                } else {
+                    println!("{}", serde_json::to_string_pretty(&plans).unwrap());
                 }
+            } else if format == "rust-bindings" {
+                let bindings =
+                    generate_rust_bindings("main", program).map_err(|e| e.to_string())?;
+                print!("{}", bindings);
+            } else if format == "python-types" || format == "pydantic" {
+                let style = if format == "pydantic" {
+                    PythonTypesStyle::Pydantic
+                } else {
+                    PythonTypesStyle::TypedDict
+                };
+                print!("{}", generate_python_types(program, style)?);
+            } else {
+                // Text format (Rust stub)
+                let stub = generate_stub_with_types(program);
+                println!("{}", stub);
             }
+            Ok(())
         }
         Err(e) => {
-            let span = match &e {
-                ParserError::Unexpected(_, s) => *s,
-                ParserError::Eof(pos) => Span {
-                    start: *pos,
-                    end: *pos,
-                },
-                ParserError::Lexer(tupa_lexer::LexerError::Unexpected(_, pos)) => Span {
-                    start: *pos,
-                    end: *pos + 1,
-                },
-                ParserError::MissingSemicolon(s) => *s,
-            };
-            let msg = match &e {
-                ParserError::Unexpected(tok, _) => format!("unexpected token {:?}", tok),
-                _ => e.to_string(),
-            };
             if format == "json" {
-                Err(format_json_error(msg, span, &file, &content, None))
+                let span = match &e {
+                    TypeError::UnknownType { .. }
+                    | TypeError::RecursiveAlias { .. }
+                    | TypeError::InvalidTypeArity { .. } => Span { start: 0, end: 0 },
+
+                    TypeError::UnknownVar { span, .. }
+                    | TypeError::UnknownFunction { span, .. }
+                    | TypeError::UnknownVariant { span, .. }
+                    | TypeError::Mismatch { span, .. }
+                    | TypeError::ArityMismatch { span, .. }
+                    | TypeError::InvalidBinary { span, .. }
+                    | TypeError::InvalidUnary { span, .. }
+                    | TypeError::InvalidCallTarget { span, .. }
+                    | TypeError::UnknownField { span, .. }
+                    | TypeError::InvalidTry { span, .. }
+                    | TypeError::ReturnMismatch { span, .. }
+                    | TypeError::MissingReturn { span }
+                    | TypeError::InvalidConstraint { span, .. }
+                    | TypeError::UnprovenConstraint { span, .. }
+                    | TypeError::BreakOutsideLoop { span }
+                    | TypeError::ContinueOutsideLoop { span }
+                    | TypeError::NonExhaustiveMatch { span } => {
+                        (*span).unwrap_or(Span { start: 0, end: 0 })
+                    }
+
+                    TypeError::ImpureInDeterministic { span, .. }
+                    | TypeError::UndefinedMetric { span, .. }
+                    | TypeError::InvalidTemporalState { span, .. } => *span,
+                };
+                Err(format_located_error(
+                    e.to_string(),
+                    span,
+                    &loaded,
+                    Some(e.code()),
+                    &format,
+                ))
             } else {
-                Err(format_text_error(msg, span, &file, &content, None))
+                Err(e.to_string())
             }
         }
     }
 }
 
 async fn run_effects(file: String, format: String) -> Result<(), String> {
-    let content = std::fs::read_to_string(&file).map_err(|e| e.to_string())?;
-
-    match parse_program(&content) {
-        Ok(program) => {
-            match typecheck_program_with_warnings(&program) {
-                Ok(_) => {
-                    let mut effects_map = HashMap::new();
-
-                    for item in &program.items {
-                        match item {
-                            Item::Function(f) => {
-                                // Wrap body in a block expr for analysis
-                                let body_expr = Expr {
-                                    kind: ExprKind::Block(f.body.clone()),
-                                    span: Span { start: 0, end: 0 }, // Dummy span
-                                };
-                                let effs = analyze_effects(&body_expr, &HashMap::new());
-                                effects_map.insert(f.name.clone(), effs);
-                            }
-                            Item::Pipeline(p) => {
-                                for step in &p.steps {
-                                    let effs = analyze_effects(&step.body, &HashMap::new());
-                                    effects_map
-                                        .insert(format!("pipeline:{}:{}", p.name, step.name), effs);
-                                }
-                            }
-                            _ => {}
-                        }
+    let loaded = load_source(&file, &format)?;
+    let program = &loaded.program;
+
+    match typecheck_program_with_warnings(program) {
+        Ok(_) => {
+            let mut effects_map = HashMap::new();
+
+            for item in &program.items {
+                match item {
+                    Item::Function(f) => {
+                        // Wrap body in a block expr for analysis
+                        let body_expr = Expr {
+                            kind: ExprKind::Block(f.body.clone()),
+                            span: Span { start: 0, end: 0 }, // Dummy span
+                        };
+                        let effs = analyze_effects(&body_expr, &HashMap::new());
+                        effects_map.insert(f.name.clone(), effs);
                     }
-
-                    if format == "json" {
-                        let mut serializable_map = HashMap::new();
-                        for (k, v) in effects_map {
-                            serializable_map.insert(k, v.to_names());
-                        }
-                        println!(
-                            "{}",
-                            serde_json::to_string_pretty(&serializable_map).unwrap()
-                        );
-                    } else {
-                        // Sort keys for deterministic output
-                        let mut sorted_keys: Vec<_> = effects_map.keys().collect();
-                        sorted_keys.sort();
-
-                        for name in sorted_keys {
-                            let effs = effects_map.get(name).unwrap();
-                            println!("{}: {:?}", name, effs.to_names());
+                    Item::Pipeline(p) => {
+                        for step in &p.steps {
+                            let effs = analyze_effects(&step.body, &HashMap::new());
+                            effects_map.insert(format!("pipeline:{}:{}", p.name, step.name), effs);
                         }
                     }
-                    Ok(())
+                    _ => {}
                 }
-                Err(e) => {
-                    if format == "json" {
-                        let span = match &e {
-                            TypeError::UnknownType { .. } | TypeError::InvalidTypeArity { .. } => {
-                                Span { start: 0, end: 0 }
-                            }
-
-                            TypeError::UnknownVar { span, .. }
-                            | TypeError::UnknownFunction { span, .. }
-                            | TypeError::UnknownVariant { span, .. }
-                            | TypeError::Mismatch { span, .. }
-                            | TypeError::ArityMismatch { span, .. }
-                            | TypeError::InvalidBinary { span, .. }
-                            | TypeError::InvalidUnary { span, .. }
-                            | TypeError::InvalidCallTarget { span, .. }
-                            | TypeError::UnknownField { span, .. }
-                            | TypeError::ReturnMismatch { span, .. }
-                            | TypeError::MissingReturn { span }
-                            | TypeError::InvalidConstraint { span, .. }
-                            | TypeError::UnprovenConstraint { span, .. }
-                            | TypeError::BreakOutsideLoop { span }
-                            | TypeError::ContinueOutsideLoop { span }
-                            | TypeError::NonExhaustiveMatch { span } => {
-                                (*span).unwrap_or(Span { start: 0, end: 0 })
-                            }
-
-                            TypeError::ImpureInDeterministic { span, .. }
-                            | TypeError::UndefinedMetric { span, .. } => *span,
-                        };
-                        let code = get_error_code(&e);
-                        Err(format_json_error(
-                            e.to_string(),
-                            span,
-                            &file,
-                            &content,
-                            code,
-                        ))
-                    } else {
-                        Err(e.to_string())
-                    }
+            }
+
+            if format == "json" {
+                let mut serializable_map = HashMap::new();
+                for (k, v) in effects_map {
+                    serializable_map.insert(k, v.to_names());
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serializable_map).unwrap()
+                );
+            } else {
+                // Sort keys for deterministic output
+                let mut sorted_keys: Vec<_> = effects_map.keys().collect();
+                sorted_keys.sort();
+
+                for name in sorted_keys {
+                    let effs = effects_map.get(name).unwrap();
+                    println!("{}: {:?}", name, effs.to_names());
                 }
             }
+            Ok(())
         }
         Err(e) => {
-            let span = match &e {
-                ParserError::Unexpected(_, s) => *s,
-                ParserError::Eof(pos) => Span {
-                    start: *pos,
-                    end: *pos,
-                },
-                ParserError::Lexer(tupa_lexer::LexerError::Unexpected(_, pos)) => Span {
-                    start: *pos,
-                    end: *pos + 1,
-                },
-                ParserError::MissingSemicolon(s) => *s,
-            };
-            let msg = match &e {
-                ParserError::Unexpected(tok, _) => format!("unexpected token {:?}", tok),
-                _ => e.to_string(),
-            };
             if format == "json" {
-                Err(format_json_error(msg, span, &file, &content, None))
+                let span = match &e {
+                    TypeError::UnknownType { .. }
+                    | TypeError::RecursiveAlias { .. }
+                    | TypeError::InvalidTypeArity { .. } => Span { start: 0, end: 0 },
+
+                    TypeError::UnknownVar { span, .. }
+                    | TypeError::UnknownFunction { span, .. }
+                    | TypeError::UnknownVariant { span, .. }
+                    | TypeError::Mismatch { span, .. }
+                    | TypeError::ArityMismatch { span, .. }
+                    | TypeError::InvalidBinary { span, .. }
+                    | TypeError::InvalidUnary { span, .. }
+                    | TypeError::InvalidCallTarget { span, .. }
+                    | TypeError::UnknownField { span, .. }
+                    | TypeError::InvalidTry { span, .. }
+                    | TypeError::ReturnMismatch { span, .. }
+                    | TypeError::MissingReturn { span }
+                    | TypeError::InvalidConstraint { span, .. }
+                    | TypeError::UnprovenConstraint { span, .. }
+                    | TypeError::BreakOutsideLoop { span }
+                    | TypeError::ContinueOutsideLoop { span }
+                    | TypeError::NonExhaustiveMatch { span } => {
+                        (*span).unwrap_or(Span { start: 0, end: 0 })
+                    }
+
+                    TypeError::ImpureInDeterministic { span, .. }
+                    | TypeError::UndefinedMetric { span, .. }
+                    | TypeError::InvalidTemporalState { span, .. } => *span,
+                };
+                Err(format_located_error(
+                    e.to_string(),
+                    span,
+                    &loaded,
+                    Some(e.code()),
+                    &format,
+                ))
             } else {
-                Err(format_text_error(msg, span, &file, &content, None))
+                Err(e.to_string())
             }
         }
     }
@@ -80,3 +80,560 @@ fn audit_rejects_invalid_inputs() {
         .failure()
         .stderr(contains("expected a JSON array"));
 }
+
+#[test]
+fn codegen_rust_bindings_outputs_typed_wrapper() {
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.current_dir(repo_root())
+        .args([
+            "codegen",
+            "--format",
+            "rust-bindings",
+            "examples/pipeline/config_driven_strategy.tp",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("pub struct ConfigDrivenStrategyInput {"))
+        .stdout(contains("pub async fn run_config_driven_strategy("));
+}
+
+#[test]
+fn codegen_python_types_outputs_typed_dicts() {
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.current_dir(repo_root())
+        .args([
+            "codegen",
+            "--format",
+            "python-types",
+            "examples/pipeline/config_driven_strategy.tp",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("class ConfigDrivenStrategyInput(TypedDict):"));
+}
+
+#[test]
+fn run_writes_metrics_even_when_pipeline_fails() {
+    let dir = std::env::temp_dir().join(format!("tupa-metrics-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("bad_tx.json");
+    let metrics = dir.join("metrics.prom");
+    std::fs::write(&input, r#"{ "amount": 100 }"#).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.current_dir(repo_root())
+        .args([
+            "run",
+            "--pipeline",
+            "FraudDetection",
+            "--input",
+            input.to_str().unwrap(),
+            "--metrics-out",
+            metrics.to_str().unwrap(),
+            "examples/pipeline/fraud_complete.tp",
+        ])
+        .assert()
+        .failure();
+
+    let text = std::fs::read_to_string(&metrics).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(text.contains("# TYPE tupa_pipeline_runs_total counter"));
+    assert!(
+        text.contains("tupa_pipeline_runs_total{pipeline=\"FraudDetection\",status=\"failure\"} 1")
+    );
+}
+
+#[test]
+fn run_exports_trace_under_caller_traceparent() {
+    let dir = std::env::temp_dir().join(format!("tupa-trace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("bad_tx.json");
+    let trace = dir.join("trace.jsonl");
+    std::fs::write(&input, r#"{ "amount": 100 }"#).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.current_dir(repo_root())
+        .args([
+            "run",
+            "--pipeline",
+            "FraudDetection",
+            "--input",
+            input.to_str().unwrap(),
+            "--trace-out",
+            trace.to_str().unwrap(),
+            "--traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "examples/pipeline/fraud_complete.tp",
+        ])
+        .assert()
+        .failure();
+
+    let text = std::fs::read_to_string(&trace).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    let otlp: serde_json::Value = serde_json::from_str(text.trim()).unwrap();
+    let root = &otlp["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+    assert_eq!(root["name"], "pipeline FraudDetection");
+    assert_eq!(root["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(root["parentSpanId"], "00f067aa0ba902b7");
+    assert_eq!(root["status"]["code"], 2);
+}
+
+#[test]
+fn check_structured_decision_example() {
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.current_dir(repo_root())
+        .args(["check", "examples/pipeline/structured_decision.tp"])
+        .assert()
+        .success()
+        .stdout(contains("OK"));
+}
+
+#[test]
+fn check_risk_limits_example() {
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.current_dir(repo_root())
+        .args(["check", "examples/pipeline/risk_limits.tp"])
+        .assert()
+        .success()
+        .stdout(contains("OK"));
+}
+
+#[test]
+fn check_temporal_state_example() {
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.current_dir(repo_root())
+        .args(["check", "examples/pipeline/temporal_state.tp"])
+        .assert()
+        .success()
+        .stdout(contains("OK"));
+}
+
+#[test]
+fn run_persists_temporal_state_between_runs() {
+    let dir = std::env::temp_dir().join(format!("tupa-state-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let plan = dir.join("plan.json");
+    let state = dir.join("state.json");
+    std::fs::write(
+        &plan,
+        r#"{
+  "name": "Guard", "version": "0.8.1", "seed": null,
+  "input_schema": { "kind": "object", "elem": null, "fields": null, "len": null, "name": null, "tensor_shape": null, "tensor_dtype": null },
+  "output_schema": null, "steps": [], "constraints": [], "metrics": {}, "metric_plans": [],
+  "temporal": [{ "key": "stop_loss", "kind": "cooldown", "threshold": 3, "source": "stop" }]
+}"#,
+    )
+    .unwrap();
+
+    for (stop, remaining) in [(true, 3), (false, 2)] {
+        let input = dir.join("input.json");
+        std::fs::write(&input, format!(r#"{{ "stop": {stop} }}"#)).unwrap();
+        let mut cmd = cargo_bin_cmd!("tupa");
+        cmd.args([
+            "run",
+            "--plan",
+            plan.to_str().unwrap(),
+            "--input",
+            input.to_str().unwrap(),
+            "--state-file",
+            state.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains(format!("\"remaining_ticks\": {remaining}")));
+    }
+
+    let stored: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&state).unwrap()).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(stored["Guard/stop_loss"]["stage"], "active");
+}
+
+#[test]
+fn run_explain_reports_failed_step() {
+    let dir = std::env::temp_dir().join(format!("tupa-explain-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let plan = dir.join("plan.json");
+    std::fs::write(
+        &plan,
+        r#"{
+  "name": "Exit", "version": "0.8.1", "seed": null,
+  "input_schema": { "kind": "object", "elem": null, "fields": null, "len": null, "name": null, "tensor_shape": null, "tensor_dtype": null },
+  "output_schema": null, "constraints": [], "metrics": {}, "metric_plans": [],
+  "steps": [{ "name": "signal", "function_ref": "py:math.sqrt", "effects": [] }]
+}"#,
+    )
+    .unwrap();
+
+    let input = dir.join("input.json");
+    std::fs::write(&input, r#"{ "price": 101.5 }"#).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.args([
+        "run",
+        "--plan",
+        plan.to_str().unwrap(),
+        "--input",
+        input.to_str().unwrap(),
+        "--explain",
+    ])
+    .assert()
+    .failure()
+    .stdout(contains("pipeline Exit"))
+    .stdout(contains("step signal [failure]"))
+    .stdout(contains("error: TypeError: must be real number, not dict"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn run_explain_reports_unresolved_step() {
+    let dir = std::env::temp_dir().join(format!("tupa-explain-unresolved-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let plan = dir.join("plan.json");
+    std::fs::write(
+        &plan,
+        r#"{
+  "name": "Exit", "version": "0.8.1", "seed": null,
+  "input_schema": { "kind": "object", "elem": null, "fields": null, "len": null, "name": null, "tensor_shape": null, "tensor_dtype": null },
+  "output_schema": null, "constraints": [], "metrics": {}, "metric_plans": [],
+  "steps": [{ "name": "signal", "function_ref": "missing_fn", "effects": [] }]
+}"#,
+    )
+    .unwrap();
+
+    let input = dir.join("input.json");
+    std::fs::write(&input, r#"{ "price": 101.5 }"#).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.args([
+        "run",
+        "--plan",
+        plan.to_str().unwrap(),
+        "--input",
+        input.to_str().unwrap(),
+        "--explain",
+    ])
+    .assert()
+    .failure()
+    .stdout(contains("pipeline Exit"))
+    .stdout(contains("step signal [failure]"))
+    .stdout(contains(
+        "error: Function not found: step 'signal' (missing_fn): not registered",
+    ))
+    .stderr(contains("Function not found"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn run_explain_includes_events_reported_by_python_steps() {
+    let dir = std::env::temp_dir().join(format!("tupa-explain-python-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("tupa_cli_signal.py"),
+        "import tupa_explain\n\n\
+         def signal(state):\n    \
+             price = state[\"price\"]\n    \
+             tupa_explain.bind(\"price\", price)\n    \
+             tupa_explain.branch(\"price > 100\", \"then\" if price > 100 else \"else\")\n    \
+             return {\"passed\": price > 100, \"reason\": \"price_above_threshold\"}\n",
+    )
+    .unwrap();
+    let plan = dir.join("plan.json");
+    std::fs::write(
+        &plan,
+        r#"{
+  "name": "Exit", "version": "0.8.1", "seed": null,
+  "input_schema": { "kind": "object", "elem": null, "fields": null, "len": null, "name": null, "tensor_shape": null, "tensor_dtype": null },
+  "output_schema": null, "constraints": [], "metrics": {}, "metric_plans": [],
+  "steps": [{ "name": "signal", "function_ref": "py:tupa_cli_signal.signal", "effects": [] }]
+}"#,
+    )
+    .unwrap();
+    let input = dir.join("input.json");
+    std::fs::write(&input, r#"{ "price": 101.5 }"#).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.args([
+        "run",
+        "--plan",
+        plan.to_str().unwrap(),
+        "--input",
+        input.to_str().unwrap(),
+        "--python-path",
+        ".",
+        "--explain",
+    ])
+    .assert()
+    .success()
+    .stdout(contains(
+        "  step signal [success]\n    let price = 101.5\n    branch price > 100 -> then\n    \
+         guard output: pass (price_above_threshold)\n",
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn run_imports_python_steps_from_paths_next_to_the_plan() {
+    let dir = std::env::temp_dir().join(format!("tupa-python-env-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("tupa_cli_scoring.py"),
+        "def double(state):\n    return state[\"x\"] * 2\n",
+    )
+    .unwrap();
+    let plan = dir.join("plan.json");
+    std::fs::write(
+        &plan,
+        r#"{
+  "name": "Scoring", "version": "0.8.1", "seed": null,
+  "input_schema": { "kind": "object", "elem": null, "fields": null, "len": null, "name": null, "tensor_shape": null, "tensor_dtype": null },
+  "output_schema": null, "constraints": [], "metrics": {}, "metric_plans": [],
+  "steps": [{ "name": "double", "function_ref": "py:tupa_cli_scoring.double", "effects": [] }]
+}"#,
+    )
+    .unwrap();
+    let input = dir.join("input.json");
+    std::fs::write(&input, r#"{ "x": 4 }"#).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.args([
+        "run",
+        "--plan",
+        plan.to_str().unwrap(),
+        "--input",
+        input.to_str().unwrap(),
+        "--python-path",
+        ".",
+        "--python-preload",
+        "tupa_cli_scoring",
+    ])
+    .assert()
+    .success()
+    .stdout(contains("\"double\": 8"));
+
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.args([
+        "run",
+        "--plan",
+        plan.to_str().unwrap(),
+        "--input",
+        input.to_str().unwrap(),
+        "--python-preload",
+        "tupa_cli_missing_module",
+    ])
+    .assert()
+    .failure()
+    .stderr(contains(
+        "Failed to preload Python module 'tupa_cli_missing_module'",
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn audit_reports_python_environment_of_externals() {
+    let dir = std::env::temp_dir().join(format!("tupa-audit-python-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("score.tp");
+    std::fs::write(
+        &source,
+        r#"@external(python="tupa_cli_scoring.double", effects=[])
+fn double(state: { x: f64 }): f64 { return 0.0; }
+"#,
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.args(["audit", source.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(contains("Python: CPython 3."))
+        .stdout(contains("Python Env Hash: "));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn validate_gates_on_dataset_metrics() {
+    let dir = std::env::temp_dir().join(format!("tupa-validate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dataset = dir.join("labeled.jsonl");
+    std::fs::write(
+        &dataset,
+        concat!(
+            "{ \"flagged\": true, \"is_fraud\": true }\n",
+            "{ \"flagged\": true, \"is_fraud\": false }\n",
+            "\n",
+            "{ \"flagged\": false, \"is_fraud\": false }\n",
+            "{ \"flagged\": false, \"is_fraud\": false }\n",
+        ),
+    )
+    .unwrap();
+    let write_plan = |threshold: f64| {
+        let plan = dir.join(format!("plan-{threshold}.json"));
+        std::fs::write(
+            &plan,
+            format!(
+                r#"{{
+  "name": "Fraud", "version": "0.8.1", "seed": null,
+  "input_schema": {{ "kind": "object", "elem": null, "fields": null, "len": null, "name": null, "tensor_shape": null, "tensor_dtype": null }},
+  "output_schema": null, "steps": [], "metrics": {{}}, "metric_plans": [],
+  "constraints": [{{ "metric": "false_positive_rate", "comparator": "le", "threshold": {threshold} }}]
+}}"#
+            ),
+        )
+        .unwrap();
+        plan
+    };
+    let validate = |plan: &std::path::Path| {
+        let mut cmd = cargo_bin_cmd!("tupa");
+        cmd.args([
+            "validate",
+            "--plan",
+            plan.to_str().unwrap(),
+            "--dataset",
+            dataset.to_str().unwrap(),
+            "--label",
+            "is_fraud",
+            "--prediction",
+            "flagged",
+        ]);
+        cmd
+    };
+
+    validate(&write_plan(0.5))
+        .assert()
+        .success()
+        .stdout(contains("pipeline Fraud: 4 records, 0 errors, 0 unlabeled"))
+        .stdout(contains("PASS false_positive_rate le 0.5"))
+        .stdout(contains("result: PASS"));
+    validate(&write_plan(0.2))
+        .assert()
+        .failure()
+        .stdout(contains("FAIL false_positive_rate le 0.2"))
+        .stderr(contains("Validation failed for pipeline 'Fraud'"));
+
+    // A literal in the validation block does not stand in for a metric the
+    // dataset does not produce.
+    let unmeasured = dir.join("plan-unmeasured.json");
+    std::fs::write(
+        &unmeasured,
+        r#"{
+  "name": "Fraud", "version": "0.8.1", "seed": null,
+  "input_schema": { "kind": "object", "elem": null, "fields": null, "len": null, "name": null, "tensor_shape": null, "tensor_dtype": null },
+  "output_schema": null, "steps": [], "metrics": { "custom_score": 0.9 }, "metric_plans": [],
+  "constraints": [{ "metric": "custom_score", "comparator": "ge", "threshold": 0.5 }]
+}"#,
+    )
+    .unwrap();
+    validate(&unmeasured)
+        .assert()
+        .failure()
+        .stdout(contains("UNMEASURED custom_score ge 0.5 (value null)"))
+        .stdout(contains(
+            "note: 'custom_score' was not measured on the dataset",
+        ))
+        .stdout(contains("result: FAIL"))
+        .stderr(contains("Validation failed for pipeline 'Fraud'"));
+
+    // A label no record carries measures no rate, so nothing passes.
+    let mut cmd = cargo_bin_cmd!("tupa");
+    cmd.args([
+        "validate",
+        "--plan",
+        write_plan(0.5).to_str().unwrap(),
+        "--dataset",
+        dataset.to_str().unwrap(),
+        "--label",
+        "is_fraudulent",
+        "--prediction",
+        "flagged",
+    ])
+    .assert()
+    .failure()
+    .stdout(contains("pipeline Fraud: 4 records, 0 errors, 4 unlabeled"))
+    .stdout(contains(
+        "UNMEASURED false_positive_rate le 0.5 (value null)",
+    ))
+    .stdout(contains(
+        "problem: no record has a usable 'is_fraudulent' label and prediction",
+    ))
+    .stdout(contains("result: FAIL"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn check_and_codegen_load_imported_modules() {
+    cargo_bin_cmd!("tupa")
+        .current_dir(repo_root())
+        .args(["check", "examples/pipeline/quote_spread.tp"])
+        .assert()
+        .success()
+        .stdout(contains("OK"));
+    cargo_bin_cmd!("tupa")
+        .current_dir(repo_root())
+        .args([
+            "codegen",
+            "--format=json",
+            "examples/pipeline/quote_spread.tp",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("\"name\": \"QuoteSpread\""))
+        .stdout(contains("\"function_ref\": \"main::step_mid\""));
+
+    // Errors point into the module they occur in.
+    let dir = std::env::temp_dir().join(format!("tupa-modules-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("main.tp"),
+        "import \"lib/sizing\" as sizing;\nfn main() { print(sizing.lots(2)); }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib/sizing.tp"),
+        "export fn lots(n: i64): i64 {\n  return n + \"x\";\n}\n",
+    )
+    .unwrap();
+    cargo_bin_cmd!("tupa")
+        .args(["check", dir.join("main.tp").to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(contains("invalid operand types for Add"))
+        .stderr(contains("lib/sizing.tp:2:10"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn run_executes_python_externals_of_imported_modules() {
+    let dir = std::env::temp_dir().join(format!("tupa-modules-run-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("lib/pymath.tp"),
+        "@external(python=\"math.sqrt\", effects=[])\nexport fn sqrt(x: f64): f64 {}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("main.tp"),
+        r#"import "lib/pymath" as m;
+
+pipeline Root {
+  input: f64,
+  steps: [
+    step("root") { m.sqrt(input) }
+  ]
+}
+"#,
+    )
+    .unwrap();
+    std::fs::write(dir.join("input.json"), "16.0").unwrap();
+    cargo_bin_cmd!("tupa")
+        .args([
+            "run",
+            "--input",
+            dir.join("input.json").to_str().unwrap(),
+            dir.join("main.tp").to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("\"root\": 4.0"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tupa_parser::{Comparator, Expr, ExprKind, Item, PipelineDecl, Program, Stmt, Type};
-use tupa_typecheck::analyze_effects;
+use tupa_parser::{
+    Comparator, EnumDef, Expr, ExprKind, Function, Item, PipelineDecl, Program, Severity, Stmt,
+    TemporalKind, Type, TypeAlias,
+};
+use tupa_typecheck::{analyze_effects, builtin_enums, external_spec_effects, pipeline_types};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ExecutionPlan {
     pub name: String,
     pub version: String,
@@ -14,23 +17,115 @@ pub struct ExecutionPlan {
     pub constraints: Vec<ConstraintPlan>,
     pub metrics: HashMap<String, f64>,
     pub metric_plans: Vec<MetricPlan>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub temporal: Vec<TemporalPlan>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct StepPlan {
     pub name: String,
     pub function_ref: String,
     pub effects: Vec<String>,
+    /// Declared signature when the step calls an `@external` function; the
+    /// runtime checks the argument and the returned value against it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external: Option<ExternalSignature>,
+    /// Whether the step returns `Result`: the runtime fails the step on
+    /// `{"Err": e}` and passes the `Ok` payload on.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub returns_result: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalSignature {
+    /// Name of the Tupã function declaring the external.
+    pub function: String,
+    /// Schema of its parameter, which receives the pipeline input.
+    pub param: Option<TypeSchema>,
+    pub returns: Option<TypeSchema>,
+    /// Effects the external declares; the runtime blocks any others.
+    #[serde(default)]
+    pub effects: Vec<String>,
+}
+
+impl ExternalSignature {
+    fn of(func: &Function, program: &Program) -> Self {
+        Self {
+            function: func.name.clone(),
+            param: func.params.first().map(|p| type_to_schema(&p.ty, program)),
+            returns: func
+                .return_type
+                .as_ref()
+                .map(|ty| type_to_schema(ty, program)),
+            effects: func
+                .external_spec
+                .as_ref()
+                .map(|spec| external_spec_effects(spec).to_names())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Signature of the last `fn` in `declaration`, Tupã source such as
+    /// `fn fetch(req: { symbol: string }): f64`; the body may be omitted,
+    /// and enums or `type` aliases the types use are declared before it.
+    pub fn from_declaration(declaration: &str) -> Result<Self, String> {
+        let declaration = declaration.trim().trim_end_matches(';');
+        let program = tupa_parser::parse_program(declaration)
+            .or_else(|e| tupa_parser::parse_program(&format!("{declaration} {{}}")).map_err(|_| e))
+            .map_err(|e| e.to_string())?;
+        program
+            .items
+            .iter()
+            .rev()
+            .find_map(|item| match item {
+                Item::Function(func) => Some(Self::of(func, &program)),
+                _ => None,
+            })
+            .ok_or_else(|| "declaration has no function".to_string())
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConstraintPlan {
+    /// Metric path; empty for `any`/`all` groups.
     pub metric: String,
+    /// `lt`, `le`, `eq`, `ne`, `ge`, `gt`, `between`, `in`, `any` or `all`.
     pub comparator: String,
+    /// Literal threshold of a comparison without `operands`.
     pub threshold: f64,
+    /// `between` bounds, `in` members, or a referenced comparison threshold.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub operands: Vec<ConstraintOperand>,
+    /// Nested constraints of `any`/`all`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub group: Vec<ConstraintPlan>,
+    #[serde(default, skip_serializing_if = "ConstraintSeverity::is_block")]
+    pub severity: ConstraintSeverity,
+}
+
+/// A literal threshold, or a metric/input path resolved at evaluation time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConstraintOperand {
+    Number(f64),
+    Ref(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConstraintSeverity {
+    #[default]
+    Block,
+    Warn,
+}
+
+impl ConstraintSeverity {
+    pub fn is_block(&self) -> bool {
+        *self == ConstraintSeverity::Block
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct TypeSchema {
     pub kind: String,
     pub elem: Option<Box<TypeSchema>>,
@@ -40,129 +135,326 @@ pub struct TypeSchema {
     pub name: Option<String>,
     pub tensor_shape: Option<Vec<Option<usize>>>,
     pub tensor_dtype: Option<String>,
+    /// Element schemas of a `tuple`, in order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Vec<TypeSchema>>,
+    /// Variants of an `enum`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variants: Option<Vec<VariantSchema>>,
+    /// Accepts `null`, and a missing field when used as a record field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nullable: Option<bool>,
+    /// `Safe` constraints on the value, without the leading `!`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraints: Option<Vec<String>>,
+}
+
+impl TypeSchema {
+    /// Schema of `kind` with no further detail, e.g. `TypeSchema::new("f64")`.
+    pub fn new(kind: &str) -> Self {
+        schema(kind)
+    }
+}
+
+/// One enum variant. Unit variants are encoded as the variant name, payload
+/// variants as `{"Variant": payload}` (an array when there are several).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariantSchema {
+    pub name: String,
+    pub payload: Vec<TypeSchema>,
+}
+
+/// Keyed temporal state the runtime loads before a run and advances after it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemporalPlan {
+    pub key: String,
+    /// `confirm` or `cooldown`.
+    pub kind: String,
+    pub threshold: i64,
+    /// Dotted path to the boolean step result that advances the state.
+    pub source: String,
+    /// Dotted input path whose value selects the entity the state belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MetricPlan {
     pub name: String,
     pub function_ref: String,
     pub args: serde_json::Value,
 }
 
-pub fn type_to_schema(ty: &Type) -> TypeSchema {
+/// Builds the runtime schema of `ty`, resolving enums and `type` aliases
+/// declared in `program`.
+///
+/// `Option<T>` (an enum shaped `Some(T) | None`) becomes the schema of `T`
+/// marked nullable. Recursive enum references stay `ident`. An alias takes
+/// the schema of the type it stands for, named after the alias.
+pub fn type_to_schema(ty: &Type, program: &Program) -> TypeSchema {
+    schema_of(
+        ty,
+        &TypeDecls::new(program),
+        &HashMap::new(),
+        &mut Vec::new(),
+    )
+}
+
+/// Enums, including `type` unions and the built-in `Option`/`Result`, and
+/// aliases declared in a program.
+pub(crate) struct TypeDecls<'a> {
+    pub(crate) enums: HashMap<&'a str, &'a EnumDef>,
+    pub(crate) aliases: HashMap<&'a str, &'a TypeAlias>,
+}
+
+impl<'a> TypeDecls<'a> {
+    pub(crate) fn new(program: &'a Program) -> Self {
+        let mut decls = Self {
+            enums: builtin_enums()
+                .iter()
+                .map(|def| (def.name.as_str(), def))
+                .collect(),
+            aliases: HashMap::new(),
+        };
+        for item in &program.items {
+            match item {
+                Item::Enum(def) => {
+                    decls.enums.insert(def.name.as_str(), def);
+                }
+                Item::TypeAlias(alias) => match alias.as_enum() {
+                    Some(def) => {
+                        decls.enums.insert(def.name.as_str(), def);
+                    }
+                    None => {
+                        decls.aliases.insert(alias.name.as_str(), alias);
+                    }
+                },
+                _ => {}
+            }
+        }
+        decls
+    }
+
+    /// The type `name<args>` stands for, if `name` is an alias.
+    pub(crate) fn expand(&self, name: &str, args: &[Type]) -> Option<Type> {
+        self.aliases.get(name)?.expand(args)
+    }
+}
+
+/// Whether `def` has the shape of `Option<T>`: `Some(T)` and `None`.
+pub(crate) fn is_option_enum(def: &EnumDef) -> bool {
+    def.name == "Option"
+        && def.generics.len() == 1
+        && def.variants.len() == 2
+        && def
+            .variants
+            .iter()
+            .any(|v| v.name == "Some" && v.args.len() == 1)
+        && def
+            .variants
+            .iter()
+            .any(|v| v.name == "None" && v.args.is_empty())
+}
+
+/// Whether `def` has the shape of `Result<T, E>`: `Ok(T)` and `Err(E)`.
+pub(crate) fn is_result_enum(def: &EnumDef) -> bool {
+    def.name == "Result"
+        && def.generics.len() == 2
+        && def.variants.len() == 2
+        && def
+            .variants
+            .iter()
+            .any(|v| v.name == "Ok" && v.args.len() == 1)
+        && def
+            .variants
+            .iter()
+            .any(|v| v.name == "Err" && v.args.len() == 1)
+}
+
+fn schema(kind: &str) -> TypeSchema {
+    TypeSchema {
+        kind: kind.into(),
+        ..Default::default()
+    }
+}
+
+fn schema_of(
+    ty: &Type,
+    decls: &TypeDecls,
+    generics: &HashMap<&str, TypeSchema>,
+    expanding: &mut Vec<String>,
+) -> TypeSchema {
     match ty {
         Type::Tensor(t) => TypeSchema {
-            kind: "tensor".into(),
-            elem: None,
-            fields: None,
-            len: None,
-            name: None,
             tensor_shape: Some(t.shape.iter().map(|&x| x.map(|n| n as usize)).collect()),
-            tensor_dtype: Some(format!("{:?}", t.dtype)),
+            tensor_dtype: Some(t.dtype.clone()),
+            ..schema("tensor")
         },
         Type::Array { elem, len } => TypeSchema {
-            kind: "array".into(),
-            elem: Some(Box::new(type_to_schema(elem))),
-            fields: None,
+            elem: Some(Box::new(schema_of(elem, decls, generics, expanding))),
             len: Some(*len),
-            name: None,
-            tensor_shape: None,
-            tensor_dtype: None,
+            ..schema("array")
         },
         Type::Slice { elem } => TypeSchema {
-            kind: "slice".into(),
-            elem: Some(Box::new(type_to_schema(elem))),
-            fields: None,
-            len: None,
-            name: None,
-            tensor_shape: None,
-            tensor_dtype: None,
+            elem: Some(Box::new(schema_of(elem, decls, generics, expanding))),
+            ..schema("slice")
         },
         Type::Record(fields) => TypeSchema {
-            kind: "object".into(),
-            elem: None,
             fields: Some(
                 fields
                     .iter()
-                    .map(|(name, ty)| (name.clone(), type_to_schema(ty)))
+                    .map(|(name, ty)| (name.clone(), schema_of(ty, decls, generics, expanding)))
                     .collect(),
             ),
-            len: None,
-            name: None,
-            tensor_shape: None,
-            tensor_dtype: None,
+            ..schema("object")
         },
-        Type::Safe { base, .. } => type_to_schema(base),
+        Type::Tuple(items) => TypeSchema {
+            items: Some(
+                items
+                    .iter()
+                    .map(|ty| schema_of(ty, decls, generics, expanding))
+                    .collect(),
+            ),
+            ..schema("tuple")
+        },
+        Type::Safe { base, constraints } => {
+            let mut base = schema_of(base, decls, generics, expanding);
+            base.constraints
+                .get_or_insert_with(Vec::new)
+                .extend(constraints.iter().cloned());
+            base
+        }
         Type::Ident(name) => match name.as_str() {
-            "i64" => TypeSchema {
-                kind: "i64".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
-            },
-            "f64" => TypeSchema {
-                kind: "f64".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
-            },
-            "bool" => TypeSchema {
-                kind: "bool".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
-            },
-            "string" => TypeSchema {
-                kind: "string".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
-            },
-            _ => TypeSchema {
-                kind: "ident".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: Some(name.clone()),
-                tensor_shape: None,
-                tensor_dtype: None,
-            },
+            "i64" | "f64" | "bool" | "string" | "null" => schema(name),
+            _ => {
+                if let Some(bound) = generics.get(name.as_str()) {
+                    return bound.clone();
+                }
+                if let Some(aliased) = decls.expand(name, &[]) {
+                    return TypeSchema {
+                        name: Some(name.clone()),
+                        ..schema_of(&aliased, decls, generics, expanding)
+                    };
+                }
+                match decls.enums.get(name.as_str()) {
+                    Some(def) => enum_schema(def, &[], decls, generics, expanding),
+                    None => match tupa_typecheck::builtin_type(name) {
+                        Some(builtin) => TypeSchema {
+                            name: Some(name.clone()),
+                            ..schema_of(&builtin, decls, generics, expanding)
+                        },
+                        None => TypeSchema {
+                            name: Some(name.clone()),
+                            ..schema("ident")
+                        },
+                    },
+                }
+            }
         },
-        _ => TypeSchema {
-            kind: "unknown".into(),
-            elem: None,
-            fields: None,
-            len: None,
-            name: None,
-            tensor_shape: None,
-            tensor_dtype: None,
+        Type::Generic { name, args } => match decls.enums.get(name.as_str()) {
+            Some(def) => enum_schema(def, args, decls, generics, expanding),
+            None => match decls.expand(name, args) {
+                Some(aliased) => TypeSchema {
+                    name: Some(name.clone()),
+                    ..schema_of(&aliased, decls, generics, expanding)
+                },
+                None => TypeSchema {
+                    name: Some(name.clone()),
+                    ..schema("ident")
+                },
+            },
         },
+        _ => schema("unknown"),
+    }
+}
+
+fn enum_schema(
+    def: &EnumDef,
+    args: &[Type],
+    decls: &TypeDecls,
+    generics: &HashMap<&str, TypeSchema>,
+    expanding: &mut Vec<String>,
+) -> TypeSchema {
+    let args: Vec<TypeSchema> = args
+        .iter()
+        .map(|arg| schema_of(arg, decls, generics, expanding))
+        .collect();
+    if is_option_enum(def) {
+        if let Some(inner) = args.into_iter().next() {
+            return TypeSchema {
+                nullable: Some(true),
+                ..inner
+            };
+        }
+        return schema("unknown");
+    }
+    if expanding.contains(&def.name) {
+        return TypeSchema {
+            name: Some(def.name.clone()),
+            ..schema("ident")
+        };
+    }
+    expanding.push(def.name.clone());
+    let bound: HashMap<&str, TypeSchema> =
+        def.generics.iter().map(String::as_str).zip(args).collect();
+    let variants = def
+        .variants
+        .iter()
+        .map(|variant| VariantSchema {
+            name: variant.name.clone(),
+            payload: variant
+                .args
+                .iter()
+                .map(|arg| schema_of(arg, decls, &bound, expanding))
+                .collect(),
+        })
+        .collect();
+    expanding.pop();
+    TypeSchema {
+        name: Some(def.name.clone()),
+        variants: Some(variants),
+        ..schema("enum")
     }
 }
 
 fn constraint_to_plan(c: &tupa_parser::Constraint) -> ConstraintPlan {
+    let mut operands: Vec<ConstraintOperand> = c
+        .operands
+        .iter()
+        .map(|operand| match operand {
+            tupa_parser::ConstraintOperand::Number(v) => ConstraintOperand::Number(*v),
+            tupa_parser::ConstraintOperand::Ref(path) => ConstraintOperand::Ref(path.clone()),
+        })
+        .collect();
+    // A literal comparison threshold is written as `threshold`.
+    let mut threshold = 0.0;
+    if let [ConstraintOperand::Number(value)] = operands.as_slice() {
+        if !matches!(c.comparator, Comparator::In) {
+            threshold = *value;
+            operands.clear();
+        }
+    }
     ConstraintPlan {
         metric: c.metric.clone(),
         comparator: match c.comparator {
             Comparator::Lt => "lt".into(),
             Comparator::Le => "le".into(),
             Comparator::Eq => "eq".into(),
+            Comparator::Ne => "ne".into(),
             Comparator::Ge => "ge".into(),
             Comparator::Gt => "gt".into(),
+            Comparator::Between => "between".into(),
+            Comparator::In => "in".into(),
+            Comparator::Any => "any".into(),
+            Comparator::All => "all".into(),
+        },
+        threshold,
+        operands,
+        group: c.group.iter().map(constraint_to_plan).collect(),
+        severity: match c.severity {
+            Severity::Block => ConstraintSeverity::Block,
+            Severity::Warn => ConstraintSeverity::Warn,
         },
-        threshold: c.threshold,
     }
 }
 
@@ -243,12 +535,18 @@ pub fn codegen_pipeline(
     pipeline: &PipelineDecl,
     program: &Program,
 ) -> serde_json::Result<String> {
+    // Programs are typechecked before codegen; one that does not typecheck
+    // gets no `returns_result` markers.
+    let fallible = pipeline_types(program, pipeline)
+        .map(|types| types.fallible)
+        .unwrap_or_default();
     let steps: Vec<StepPlan> = pipeline
         .steps
         .iter()
         .map(|step| {
             let effects = analyze_effects(&step.body, &HashMap::new()).to_names();
             let mut function_ref = format!("{module_name}::step_{}", step.name);
+            let mut external = None;
 
             // Check if body is a direct call to an external function
             if let ExprKind::Call { callee, args } = &step.body.kind {
@@ -263,8 +561,14 @@ pub fn codegen_pipeline(
                             if let Item::Function(f) = item {
                                 if &f.name == func_name {
                                     if let Some(spec) = &f.external_spec {
-                                        if let Some(py_target) = &spec.python {
-                                            function_ref = format!("py:{}", py_target);
+                                        let target = match (&spec.rust, &spec.python) {
+                                            (Some(rust), _) => Some(format!("rust:{rust}")),
+                                            (None, Some(py)) => Some(format!("py:{py}")),
+                                            (None, None) => None,
+                                        };
+                                        if let Some(target) = target {
+                                            function_ref = target;
+                                            external = Some(ExternalSignature::of(f, program));
                                         }
                                     }
                                     break;
@@ -279,6 +583,8 @@ pub fn codegen_pipeline(
                 name: step.name.clone(),
                 function_ref,
                 effects,
+                external,
+                returns_result: fallible.contains(&step.name),
             }
         })
         .collect();
@@ -286,8 +592,11 @@ pub fn codegen_pipeline(
         name: pipeline.name.clone(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         seed: pipeline.seed,
-        input_schema: type_to_schema(&pipeline.input_ty),
-        output_schema: pipeline.output_ty.as_ref().map(type_to_schema),
+        input_schema: type_to_schema(&pipeline.input_ty, program),
+        output_schema: pipeline
+            .output_ty
+            .as_ref()
+            .map(|ty| type_to_schema(ty, program)),
         steps,
         constraints: pipeline
             .constraints
@@ -296,6 +605,251 @@ pub fn codegen_pipeline(
             .collect(),
         metrics: extract_metrics(pipeline),
         metric_plans: extract_metric_plans(module_name, pipeline),
+        temporal: pipeline
+            .temporal
+            .iter()
+            .map(|decl| TemporalPlan {
+                key: decl.key.clone(),
+                kind: match decl.kind {
+                    TemporalKind::Confirm => "confirm",
+                    TemporalKind::Cooldown => "cooldown",
+                }
+                .to_string(),
+                threshold: decl.threshold,
+                source: decl.source.clone(),
+                per: decl.per.clone(),
+            })
+            .collect(),
     };
     serde_json::to_string_pretty(&plan)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tupa_parser::parse_program;
+
+    fn input_schema(src: &str) -> TypeSchema {
+        let program = parse_program(src).unwrap();
+        let pipeline = program
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Pipeline(p) => Some(p),
+                _ => None,
+            })
+            .unwrap();
+        type_to_schema(&pipeline.input_ty, &program)
+    }
+
+    #[test]
+    fn test_schema_describes_enum_variants_and_tuples() {
+        let schema = input_schema(
+            r#"
+            enum Side { Buy, Sell(f64, (i64, string)) }
+            pipeline P { input: { side: Side, ratio: Safe<f64, !nan, !inf> }, steps: [] }
+            "#,
+        );
+        let fields = schema.fields.unwrap();
+
+        let side = &fields["side"];
+        assert_eq!(side.kind, "enum");
+        assert_eq!(side.name.as_deref(), Some("Side"));
+        let variants = side.variants.as_ref().unwrap();
+        assert_eq!(variants[0].name, "Buy");
+        assert!(variants[0].payload.is_empty());
+        assert_eq!(variants[1].payload[0].kind, "f64");
+        let pair = &variants[1].payload[1];
+        assert_eq!(pair.kind, "tuple");
+        let kinds: Vec<_> = pair
+            .items
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|s| s.kind.as_str())
+            .collect();
+        assert_eq!(kinds, ["i64", "string"]);
+
+        let ratio = &fields["ratio"];
+        assert_eq!(ratio.kind, "f64");
+        assert_eq!(
+            ratio.constraints.as_deref(),
+            Some(&["nan".to_string(), "inf".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn test_schema_maps_option_to_nullable_and_stops_recursion() {
+        let schema = input_schema(
+            r#"
+            enum Option<T> { Some(T), None }
+            enum Chain { Link(i64, Chain), End }
+            pipeline P { input: { note: Option<string>, chain: Chain }, steps: [] }
+            "#,
+        );
+        let fields = schema.fields.unwrap();
+
+        assert_eq!(fields["note"].kind, "string");
+        assert_eq!(fields["note"].nullable, Some(true));
+
+        let chain = &fields["chain"];
+        assert_eq!(chain.kind, "enum");
+        let link = &chain.variants.as_ref().unwrap()[0];
+        assert_eq!(link.payload[1].kind, "ident");
+        assert_eq!(link.payload[1].name.as_deref(), Some("Chain"));
+    }
+
+    #[test]
+    fn test_schema_describes_builtin_option_and_result() {
+        let schema = input_schema(
+            "pipeline P { input: { note: Option<string>, quote: Result<f64, string> }, steps: [] }",
+        );
+        let fields = schema.fields.unwrap();
+        assert_eq!(fields["note"].kind, "string");
+        assert_eq!(fields["note"].nullable, Some(true));
+
+        let quote = &fields["quote"];
+        assert_eq!(
+            (quote.kind.as_str(), quote.name.as_deref()),
+            ("enum", Some("Result"))
+        );
+        let variants = quote.variants.as_ref().unwrap();
+        assert_eq!(variants[0].name, "Ok");
+        assert_eq!(variants[0].payload[0].kind, "f64");
+        assert_eq!(variants[1].name, "Err");
+        assert_eq!(variants[1].payload[0].kind, "string");
+    }
+
+    #[test]
+    fn test_schema_expands_builtin_decision() {
+        let schema = input_schema("pipeline P { input: { prior: Decision }, steps: [] }");
+        let prior = &schema.fields.unwrap()["prior"];
+        assert_eq!(prior.kind, "object");
+        assert_eq!(prior.name.as_deref(), Some("Decision"));
+        let fields = prior.fields.as_ref().unwrap();
+        assert_eq!(fields["flags"].kind, "slice");
+        let component = fields["components"].elem.as_ref().unwrap();
+        assert_eq!(component.name.as_deref(), Some("DecisionComponent"));
+        assert_eq!(component.fields.as_ref().unwrap()["weight"].kind, "f64");
+    }
+
+    #[test]
+    fn test_schema_names_type_aliases() {
+        let schema = input_schema(
+            "type Quote { bid: f64, ask: f64 }\n\
+             type Window<T> = [T; 3];\n\
+             type Signal = Buy(f64) | Hold;\n\
+             pipeline P { input: { quote: Quote, prices: Window<f64>, signal: Signal }, steps: [] }",
+        );
+        let fields = schema.fields.unwrap();
+        let quote = &fields["quote"];
+        assert_eq!(
+            (quote.kind.as_str(), quote.name.as_deref()),
+            ("object", Some("Quote"))
+        );
+        assert_eq!(quote.fields.as_ref().unwrap()["ask"].kind, "f64");
+        let prices = &fields["prices"];
+        assert_eq!((prices.kind.as_str(), prices.len), ("array", Some(3)));
+        assert_eq!(prices.name.as_deref(), Some("Window"));
+        assert_eq!(prices.elem.as_ref().unwrap().kind, "f64");
+        let signal = &fields["signal"];
+        assert_eq!(
+            (signal.kind.as_str(), signal.name.as_deref()),
+            ("enum", Some("Signal"))
+        );
+        assert_eq!(signal.variants.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_external_step_carries_declared_signature() {
+        let program = tupa_parser::parse_program(
+            r#"
+            @external(python="models.fraud.score", effects=[Time])
+            fn score(tx: { amount: f64 }): f64 { return 0.0; }
+            pipeline P { input: { amount: f64 }, steps: [ step("risk") { score(input) } ] }
+            "#,
+        )
+        .unwrap();
+        let pipeline = program
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Pipeline(p) => Some(p),
+                _ => None,
+            })
+            .unwrap();
+        let plan: ExecutionPlan =
+            serde_json::from_str(&codegen_pipeline("main", pipeline, &program).unwrap()).unwrap();
+
+        let step = &plan.steps[0];
+        assert_eq!(step.function_ref, "py:models.fraud.score");
+        let external = step.external.as_ref().unwrap();
+        assert_eq!(external.function, "score");
+        let param = external.param.as_ref().unwrap();
+        assert_eq!(param.fields.as_ref().unwrap()["amount"].kind, "f64");
+        assert_eq!(external.returns.as_ref().unwrap().kind, "f64");
+        assert_eq!(external.effects, ["time"]);
+    }
+
+    #[test]
+    fn test_steps_returning_result_are_marked() {
+        let program = tupa_parser::parse_program(
+            r#"
+            fn parse(raw: string): Result<f64, string> { return Ok(1.0); }
+            fn size(raw: string): i64 { return 1; }
+            pipeline P {
+                input: string,
+                steps: [ step("value") { parse(input) }, step("size") { size(input) } ]
+            }
+            "#,
+        )
+        .unwrap();
+        let pipeline = program
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Pipeline(p) => Some(p),
+                _ => None,
+            })
+            .unwrap();
+        let plan: ExecutionPlan =
+            serde_json::from_str(&codegen_pipeline("main", pipeline, &program).unwrap()).unwrap();
+
+        assert_eq!(plan.steps[0].function_ref, "main::step_value");
+        assert!(plan.steps[0].returns_result);
+        assert!(!plan.steps[1].returns_result);
+    }
+
+    #[test]
+    fn test_rust_external_matches_host_declaration() {
+        let program = tupa_parser::parse_program(
+            r#"
+            @external(rust="viper::fetch_market_data", effects=[IO])
+            fn fetch(req: { symbol: string }): f64 { return 0.0; }
+            pipeline P { input: { symbol: string }, steps: [ step("price") { fetch(input) } ] }
+            "#,
+        )
+        .unwrap();
+        let pipeline = program
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Pipeline(p) => Some(p),
+                _ => None,
+            })
+            .unwrap();
+        let plan: ExecutionPlan =
+            serde_json::from_str(&codegen_pipeline("main", pipeline, &program).unwrap()).unwrap();
+
+        let step = &plan.steps[0];
+        assert_eq!(step.function_ref, "rust:viper::fetch_market_data");
+        let external = step.external.as_ref().unwrap();
+        let declared = ExternalSignature::from_declaration(
+            "fn fetch_market_data(req: { symbol: string }): f64",
+        )
+        .unwrap();
+        assert_eq!(declared.param, external.param);
+        assert_eq!(declared.returns, external.returns);
+        assert!(ExternalSignature::from_declaration("pipeline").is_err());
+    }
+}
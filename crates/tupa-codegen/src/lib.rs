@@ -2,6 +2,9 @@ use std::collections::HashMap;
 
 use tupa_parser::{Expr, ExprKind, Function, Item, Program, Stmt, Type};
 pub mod execution_plan;
+mod naming;
+pub mod python_bindings;
+pub mod rust_bindings;
 #[allow(unused_imports)]
 use tupa_typecheck::{typecheck_program_with_warnings, Ty};
 
@@ -202,6 +205,7 @@ impl Codegen {
                 Item::Function(func) => func,
                 Item::Enum(_) => continue,  // enums don't have functions
                 Item::Trait(_) => continue, // traits don't have functions
+                Item::TypeAlias(_) => continue, // nor do type declarations
                 Item::Pipeline(_) => continue, // pipelines don't have functions
             };
             let params = func
@@ -219,9 +223,10 @@ impl Codegen {
         for item in &program.items {
             match item {
                 Item::Function(func) => self.emit_function(func),
-                Item::Enum(_) => {}     // enums don't emit code yet
-                Item::Trait(_) => {}    // traits don't emit code yet
-                Item::Pipeline(_) => {} // pipelines don't emit code
+                Item::Enum(_) => {}      // enums don't emit code yet
+                Item::Trait(_) => {}     // traits don't emit code yet
+                Item::TypeAlias(_) => {} // types are resolved by the checker
+                Item::Pipeline(_) => {}  // pipelines don't emit code
             }
         }
     }
@@ -508,7 +513,7 @@ impl Codegen {
                 self.collect_used_in_expr(index, env);
                 self.collect_used_in_expr(value, env);
             }
-            ExprKind::Await(expr) => {
+            ExprKind::Await(expr) | ExprKind::Try(expr) => {
                 self.collect_used_in_expr(expr, env);
             }
             ExprKind::Match {
@@ -2766,9 +2771,11 @@ mod tests {
     #[test]
     fn test_empty_function_codegen() {
         let program = Program {
+            imports: vec![],
             items: vec![Item::Function(Function {
                 attrs: vec![],
                 external_spec: None,
+                exported: false,
                 name: "main".to_string(),
                 params: vec![],
                 return_type: None,
@@ -2784,9 +2791,11 @@ mod tests {
     #[test]
     fn test_function_with_return_codegen() {
         let program = Program {
+            imports: vec![],
             items: vec![Item::Function(Function {
                 attrs: vec![],
                 external_spec: None,
+                exported: false,
                 name: "test".to_string(),
                 params: vec![],
                 return_type: Some(Type::Ident("i64".to_string())),
@@ -2804,9 +2813,11 @@ mod tests {
     #[test]
     fn test_string_literal_codegen() {
         let program = Program {
+            imports: vec![],
             items: vec![Item::Function(Function {
                 attrs: vec![],
                 external_spec: None,
+                exported: false,
                 name: "test".to_string(),
                 params: vec![],
                 return_type: None,
@@ -2824,9 +2835,11 @@ mod tests {
     #[test]
     fn test_f64_pow_codegen() {
         let program = Program {
+            imports: vec![],
             items: vec![Item::Function(Function {
                 attrs: vec![],
                 external_spec: None,
+                exported: false,
                 name: "powf".to_string(),
                 params: vec![Param {
                     name: "x".to_string(),
@@ -2857,9 +2870,11 @@ mod tests {
     #[test]
     fn test_f64_pow_folding() {
         let program = Program {
+            imports: vec![],
             items: vec![Item::Function(Function {
                 attrs: vec![],
                 external_spec: None,
+                exported: false,
                 name: "powf_const".to_string(),
                 params: vec![],
                 return_type: Some(Type::Ident("f64".to_string())),
@@ -2887,9 +2902,11 @@ mod tests {
     #[test]
     fn test_i64_pow_folding() {
         let program = Program {
+            imports: vec![],
             items: vec![Item::Function(Function {
                 attrs: vec![],
                 external_spec: None,
+                exported: false,
                 name: "powi_const".to_string(),
                 params: vec![],
                 return_type: Some(Type::Ident("i64".to_string())),
@@ -2916,9 +2933,11 @@ mod tests {
     #[test]
     fn test_lambda_captures_only_used_vars() {
         let program = Program {
+            imports: vec![],
             items: vec![Item::Function(Function {
                 attrs: vec![],
                 external_spec: None,
+                exported: false,
                 name: "main".to_string(),
                 params: vec![],
                 return_type: None,
@@ -0,0 +1,570 @@
+//! Python type hints for pipelines and Python externals.
+//!
+//! `generate_python_types` emits a Python module describing the JSON values
+//! exchanged with `@external(python=...)` functions: every pipeline input and
+//! every external function's argument and return type, either as
+//! `TypedDict`s or as pydantic models. The output is valid both as a `.py`
+//! module and as a `.pyi` stub, so the Python side can be checked against the
+//! Tupã contract with mypy.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use tupa_parser::{EnumDef, Function, Item, PipelineDecl, Program, Type, TypeAlias};
+
+use crate::execution_plan::{is_option_enum, TypeDecls};
+use crate::naming::{pascal_case, type_ident};
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+/// Shape of the generated record types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PythonTypesStyle {
+    /// `typing.TypedDict` classes; the values stay plain dicts.
+    TypedDict,
+    /// `pydantic.BaseModel` classes that validate the dicts on construction.
+    Pydantic,
+}
+
+/// Generates a Python module with type hints for `program`'s pipelines and
+/// Python externals.
+///
+/// Enums follow the JSON encoding used by the runtime: unit variants are
+/// their name as a string and payload variants a single-key object, except
+/// `Option<T>`, which is a nullable `T`.
+///
+/// Fails on a Python external that does not declare exactly one parameter:
+/// the runtime calls externals with one argument, the pipeline input.
+pub fn generate_python_types(program: &Program, style: PythonTypesStyle) -> Result<String, String> {
+    let mut gen = PythonGen::new(program, style);
+    for item in &program.items {
+        match item {
+            Item::Pipeline(pipeline) => gen.emit_pipeline(pipeline),
+            Item::Function(func) => {
+                if let Some(target) = func.external_spec.as_ref().and_then(|s| s.python.as_ref()) {
+                    gen.emit_external(func, target)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(gen.finish())
+}
+
+struct PythonGen<'a> {
+    style: PythonTypesStyle,
+    enums: HashMap<&'a str, &'a EnumDef>,
+    aliases: HashMap<&'a str, &'a TypeAlias>,
+    emitted_enums: HashSet<String>,
+    pending_enums: HashSet<String>,
+    type_names: HashSet<String>,
+    typing_imports: BTreeSet<&'static str>,
+    uses_field: bool,
+    items: Vec<String>,
+}
+
+impl<'a> PythonGen<'a> {
+    fn new(program: &'a Program, style: PythonTypesStyle) -> Self {
+        let TypeDecls { enums, aliases } = TypeDecls::new(program);
+        Self {
+            style,
+            enums,
+            aliases,
+            emitted_enums: HashSet::new(),
+            pending_enums: HashSet::new(),
+            type_names: HashSet::new(),
+            typing_imports: BTreeSet::new(),
+            uses_field: false,
+            items: Vec::new(),
+        }
+    }
+
+    fn emit_pipeline(&mut self, pipeline: &PipelineDecl) {
+        let name = format!("{}Input", pascal_case(&pipeline.name));
+        let doc = format!("Input of pipeline `{}`.", pipeline.name);
+        self.emit_named(&name, &pipeline.input_ty, &doc);
+    }
+
+    fn emit_external(&mut self, func: &Function, target: &str) -> Result<(), String> {
+        let [param] = func.params.as_slice() else {
+            return Err(format!(
+                "external '{}' ({target}) declares {} parameters, but externals are called with one argument, the pipeline input",
+                func.name,
+                func.params.len()
+            ));
+        };
+        let base = pascal_case(&func.name);
+        let doc = format!(
+            "Argument of external `{}`: the pipeline input, as parameter `{}`.",
+            func.name, param.name
+        );
+        let arg = self.named_type(&format!("{base}Input"), &param.ty, &doc);
+        let ret = match &func.return_type {
+            Some(ty) => {
+                let doc = format!("Result of external `{}`.", func.name);
+                self.named_type(&format!("{base}Output"), ty, &doc)
+            }
+            None => "None".to_string(),
+        };
+        self.typing_imports.insert("Callable");
+        self.items.push(format!(
+            "# Signature of `{target}` (external `{}`).\n{base}Signature = Callable[[{arg}], {ret}]",
+            func.name,
+        ));
+        Ok(())
+    }
+
+    /// Emits `name` as a class for records and as a type alias otherwise.
+    fn emit_named(&mut self, name: &str, ty: &Type, doc: &str) {
+        if let Type::Record(fields) = strip_safe(ty) {
+            self.emit_record(name, fields, &HashMap::new(), doc);
+        } else {
+            let py = self.py_type(ty, name, &HashMap::new());
+            self.type_names.insert(name.to_string());
+            self.items.push(format!("# {doc}\n{name} = {py}"));
+        }
+    }
+
+    /// Like `emit_named`, but inlines non-record types instead of aliasing them.
+    fn named_type(&mut self, name: &str, ty: &Type, doc: &str) -> String {
+        match strip_safe(ty) {
+            Type::Record(fields) => self.emit_record(name, fields, &HashMap::new(), doc),
+            _ => self.py_type(ty, name, &HashMap::new()),
+        }
+    }
+
+    fn emit_record(
+        &mut self,
+        hint: &str,
+        fields: &[(String, Type)],
+        subst: &HashMap<&str, String>,
+        doc: &str,
+    ) -> String {
+        let name = self.unique_name(hint);
+        self.type_names.insert(name.clone());
+        let fields = fields
+            .iter()
+            .map(|(field, ty)| {
+                let nested = format!("{name}{}", pascal_case(field));
+                (field.clone(), self.py_type(ty, &nested, subst))
+            })
+            .collect::<Vec<_>>();
+        self.emit_class(&name, doc, &fields);
+        name
+    }
+
+    fn emit_class(&mut self, name: &str, doc: &str, fields: &[(String, String)]) {
+        let mut out = String::new();
+        match self.style {
+            PythonTypesStyle::TypedDict => {
+                self.typing_imports.insert("TypedDict");
+                if fields.iter().any(|(field, _)| !is_python_ident(field)) {
+                    // Keys that are not identifiers need the functional syntax.
+                    if !doc.is_empty() {
+                        out.push_str(&format!("# {doc}\n"));
+                    }
+                    let entries = fields
+                        .iter()
+                        .map(|(field, ty)| format!("\"{field}\": {ty}"))
+                        .collect::<Vec<_>>();
+                    out.push_str(&format!(
+                        "{name} = TypedDict(\"{name}\", {{{}}})",
+                        entries.join(", ")
+                    ));
+                    self.items.push(out);
+                    return;
+                }
+                out.push_str(&format!("class {name}(TypedDict):\n"));
+            }
+            PythonTypesStyle::Pydantic => {
+                out.push_str(&format!("class {name}(BaseModel):\n"));
+            }
+        }
+        if !doc.is_empty() {
+            out.push_str(&format!("    \"\"\"{doc}\"\"\"\n\n"));
+        }
+        for (field, ty) in fields {
+            if is_python_ident(field) {
+                out.push_str(&format!("    {field}: {ty}\n"));
+            } else {
+                self.uses_field = true;
+                out.push_str(&format!(
+                    "    {}: {ty} = Field(alias=\"{field}\")\n",
+                    python_ident(field)
+                ));
+            }
+        }
+        if fields.is_empty() && doc.is_empty() {
+            out.push_str("    pass\n");
+        }
+        self.items.push(out.trim_end().to_string());
+    }
+
+    /// Emits the alias for `def` instantiated with `args` and returns its name.
+    fn emit_enum(&mut self, def: &EnumDef, args: Vec<String>) -> String {
+        let name = if args.is_empty() {
+            type_ident(&def.name)
+        } else {
+            format!("{}{}", type_ident(&def.name), pascal_case(&args.join(" ")))
+        };
+        if self.pending_enums.contains(&name) {
+            return format!("\"{name}\"");
+        }
+        if !self.emitted_enums.insert(name.clone()) {
+            return name;
+        }
+        self.pending_enums.insert(name.clone());
+        self.type_names.insert(name.clone());
+        let subst: HashMap<&str, String> =
+            def.generics.iter().map(String::as_str).zip(args).collect();
+
+        let mut units = Vec::new();
+        let mut members = Vec::new();
+        for variant in &def.variants {
+            if variant.args.is_empty() {
+                units.push(format!("\"{}\"", variant.name));
+                continue;
+            }
+            let hint = format!("{name}{}", variant.name);
+            let payload = if variant.args.len() == 1 {
+                self.py_type(&variant.args[0], &hint, &subst)
+            } else {
+                let items = variant
+                    .args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| self.py_type(arg, &format!("{hint}{i}"), &subst))
+                    .collect::<Vec<_>>();
+                format!("list[{}]", self.union(items))
+            };
+            let class = self.unique_name(&hint);
+            self.type_names.insert(class.clone());
+            self.emit_class(&class, "", &[(variant.name.clone(), payload)]);
+            members.push(class);
+        }
+        if !units.is_empty() {
+            self.typing_imports.insert("Literal");
+            members.insert(0, format!("Literal[{}]", units.join(", ")));
+        }
+        let alias = self.union(members);
+        self.items
+            .push(format!("# Enum `{}`.\n{name} = {alias}", def.name));
+        self.pending_enums.remove(&name);
+        name
+    }
+
+    /// Annotation of a `type` alias: a record alias without parameters is
+    /// emitted once as a class of the same name, anything else is inlined.
+    fn alias_type(
+        &mut self,
+        alias: &TypeAlias,
+        args: &[Type],
+        hint: &str,
+        subst: &HashMap<&str, String>,
+    ) -> String {
+        let Some(ty) = alias.expand(args) else {
+            return self.any();
+        };
+        let name = type_ident(&alias.name);
+        match strip_safe(&ty) {
+            Type::Record(fields) if args.is_empty() => {
+                if self.emitted_enums.insert(name.clone()) {
+                    self.emit_record(&name, fields, subst, &format!("Type `{}`.", alias.name))
+                } else {
+                    name
+                }
+            }
+            _ => self.py_type(&ty, hint, subst),
+        }
+    }
+
+    /// Maps a declared type to a Python annotation, emitting classes named
+    /// after `hint` for records. `subst` binds the generics of the enum being
+    /// instantiated.
+    fn py_type(&mut self, ty: &Type, hint: &str, subst: &HashMap<&str, String>) -> String {
+        match ty {
+            Type::Ident(name) => match name.as_str() {
+                "i64" => "int".into(),
+                "f64" => "float".into(),
+                "bool" => "bool".into(),
+                "string" => "str".into(),
+                "null" => "None".into(),
+                _ => {
+                    if let Some(bound) = subst.get(name.as_str()) {
+                        bound.clone()
+                    } else if let Some(def) = self.enums.get(name.as_str()).copied() {
+                        self.emit_enum(def, Vec::new())
+                    } else if let Some(alias) = self.aliases.get(name.as_str()).copied() {
+                        self.alias_type(alias, &[], hint, subst)
+                    } else if let Some(builtin) = tupa_typecheck::builtin_type(name) {
+                        // Built-in records such as `Decision` are emitted once, like enums.
+                        if self.emitted_enums.insert(name.clone()) {
+                            self.py_type(&builtin, name, subst)
+                        } else {
+                            name.clone()
+                        }
+                    } else {
+                        self.any()
+                    }
+                }
+            },
+            Type::Generic { name, args } => match self.enums.get(name.as_str()).copied() {
+                Some(def) if is_option_enum(def) && args.len() == 1 => {
+                    self.typing_imports.insert("Optional");
+                    format!("Optional[{}]", self.py_type(&args[0], hint, subst))
+                }
+                Some(def) => {
+                    let args = args
+                        .iter()
+                        .enumerate()
+                        .map(|(i, arg)| self.py_type(arg, &format!("{hint}{i}"), subst))
+                        .collect();
+                    self.emit_enum(def, args)
+                }
+                None => match self.aliases.get(name.as_str()).copied() {
+                    Some(alias) => self.alias_type(alias, args, hint, subst),
+                    None => self.any(),
+                },
+            },
+            Type::Record(fields) => self.emit_record(hint, fields, subst, ""),
+            Type::Tuple(items) => {
+                // Tuples cross the bridge as JSON arrays, i.e. Python lists.
+                let items = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| self.py_type(item, &format!("{hint}{i}"), subst))
+                    .collect::<Vec<_>>();
+                format!("list[{}]", self.union(items))
+            }
+            Type::Safe { base, .. } => self.py_type(base, hint, subst),
+            Type::Array { elem, .. } | Type::Slice { elem } => {
+                format!(
+                    "list[{}]",
+                    self.py_type(elem, &format!("{hint}Item"), subst)
+                )
+            }
+            Type::Tensor(t) => {
+                let mut out = match t.dtype.as_str() {
+                    "i32" | "i64" | "u8" => "int",
+                    "bool" => "bool",
+                    _ => "float",
+                }
+                .to_string();
+                for _ in 0..t.shape.len().max(1) {
+                    out = format!("list[{out}]");
+                }
+                out
+            }
+            Type::Unit => "None".into(),
+            Type::Func { .. } => self.any(),
+        }
+    }
+
+    fn union(&mut self, items: Vec<String>) -> String {
+        let mut unique = Vec::new();
+        for item in items {
+            if !unique.contains(&item) {
+                unique.push(item);
+            }
+        }
+        match unique.len() {
+            0 => self.any(),
+            1 => unique.remove(0),
+            _ => {
+                self.typing_imports.insert("Union");
+                format!("Union[{}]", unique.join(", "))
+            }
+        }
+    }
+
+    fn any(&mut self) -> String {
+        self.typing_imports.insert("Any");
+        "Any".into()
+    }
+
+    fn unique_name(&self, hint: &str) -> String {
+        if !self.type_names.contains(hint) {
+            return hint.to_string();
+        }
+        (2..)
+            .map(|n| format!("{hint}{n}"))
+            .find(|candidate| !self.type_names.contains(candidate))
+            .unwrap()
+    }
+
+    fn finish(self) -> String {
+        let format = match self.style {
+            PythonTypesStyle::TypedDict => "python-types",
+            PythonTypesStyle::Pydantic => "pydantic",
+        };
+        let mut out = format!(
+            "# Generated by `tupa codegen --format {format}`. Do not edit.\n\nfrom __future__ import annotations\n"
+        );
+        if !self.typing_imports.is_empty() {
+            let imports = self.typing_imports.into_iter().collect::<Vec<_>>();
+            out.push_str(&format!("\nfrom typing import {}\n", imports.join(", ")));
+        }
+        if self.style == PythonTypesStyle::Pydantic {
+            let imports = if self.uses_field {
+                "BaseModel, Field"
+            } else {
+                "BaseModel"
+            };
+            out.push_str(&format!("\nfrom pydantic import {imports}\n"));
+        }
+        for item in self.items {
+            out.push_str("\n\n");
+            out.push_str(&item);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn strip_safe(ty: &Type) -> &Type {
+    match ty {
+        Type::Safe { base, .. } => strip_safe(base),
+        _ => ty,
+    }
+}
+
+fn is_python_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_ok = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    starts_ok
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !PYTHON_KEYWORDS.contains(&name)
+}
+
+/// Attribute name for a pydantic field whose key is not an identifier.
+fn python_ident(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if PYTHON_KEYWORDS.contains(&ident.as_str()) || ident != name {
+        ident.push('_');
+    }
+    ident
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tupa_parser::parse_program;
+
+    const SRC: &str = r#"
+        enum Side { Buy, Hold, Sell(f64) }
+
+        @external(python="risk.score", effects=[IO])
+        fn risk_score(tx: { amount: f64, tags: [string], class: string }): { score: f64, side: Side } {
+            return { score: 0.0, side: Hold };
+        }
+
+        pipeline FraudCheck {
+            input: { amount: f64, account: { id: string, age_days: i64 } },
+            steps: [ step("risk") { risk_score(input) } ],
+        }
+    "#;
+
+    #[test]
+    fn test_typed_dict_types() {
+        let program = parse_program(SRC).unwrap();
+        let code = generate_python_types(&program, PythonTypesStyle::TypedDict).unwrap();
+
+        assert!(code.contains("from typing import Callable, Literal, TypedDict, Union\n"));
+        assert!(code
+            .contains("class FraudCheckInputAccount(TypedDict):\n    id: str\n    age_days: int"));
+        assert!(code.contains(
+            "class FraudCheckInput(TypedDict):\n    \"\"\"Input of pipeline `FraudCheck`.\"\"\"\n\n    amount: float\n    account: FraudCheckInputAccount"
+        ));
+        assert!(code.contains(
+            "RiskScoreInput = TypedDict(\"RiskScoreInput\", {\"amount\": float, \"tags\": list[str], \"class\": str})"
+        ));
+        assert!(code.contains("class SideSell(TypedDict):\n    Sell: float"));
+        assert!(code.contains("Side = Union[Literal[\"Buy\", \"Hold\"], SideSell]"));
+        assert!(code.contains("RiskScoreSignature = Callable[[RiskScoreInput], RiskScoreOutput]"));
+    }
+
+    #[test]
+    fn test_pydantic_models() {
+        let program = parse_program(SRC).unwrap();
+        let code = generate_python_types(&program, PythonTypesStyle::Pydantic).unwrap();
+
+        assert!(code.contains("from pydantic import BaseModel, Field\n"));
+        assert!(code.contains("class RiskScoreInput(BaseModel):"));
+        assert!(code.contains("    class_: str = Field(alias=\"class\")\n"));
+        assert!(code.contains("class RiskScoreOutput(BaseModel):"));
+        assert!(!code.contains("TypedDict"));
+    }
+
+    #[test]
+    fn test_generic_enum_instances_and_aliases() {
+        let src = r#"
+            enum Maybe<T> { Just(T), Nothing }
+            enum Option<T> { Some(T), None }
+
+            @external(python="model.predict")
+            fn predict(x: { m: Maybe<i64>, weights: [f64; 3], bias: Option<f64> }): f64 {
+                return 0.0;
+            }
+
+            pipeline Scoring {
+                input: i64,
+                steps: [],
+            }
+        "#;
+        let program = parse_program(src).unwrap();
+        let code = generate_python_types(&program, PythonTypesStyle::TypedDict).unwrap();
+
+        assert!(code.contains("# Input of pipeline `Scoring`.\nScoringInput = int"));
+        assert!(code.contains("class MaybeIntJust(TypedDict):\n    Just: int"));
+        assert!(code.contains("MaybeInt = Union[Literal[\"Nothing\"], MaybeIntJust]"));
+        assert!(
+            code.contains("    m: MaybeInt\n    weights: list[float]\n    bias: Optional[float]\n")
+        );
+        assert!(code.contains("PredictSignature = Callable[[PredictInput], float]"));
+        assert!(!code.contains("OptionFloat"));
+    }
+
+    #[test]
+    fn test_type_aliases_are_named_classes() {
+        let src = r#"
+            type Quote { bid: f64, ask: f64 }
+            type Window<T> = [T; 3];
+
+            @external(python="feeds.mid")
+            fn mid(req: { quote: Quote, prices: Window<f64> }): Quote { return req.quote; }
+        "#;
+        let program = parse_program(src).unwrap();
+        let code = generate_python_types(&program, PythonTypesStyle::TypedDict).unwrap();
+
+        assert!(code.contains("class Quote(TypedDict):\n    \"\"\"Type `Quote`.\"\"\""));
+        assert_eq!(code.matches("class Quote(").count(), 1);
+        assert!(code.contains("    quote: Quote\n    prices: list[float]\n"));
+        assert!(code.contains("MidSignature = Callable[[MidInput], Quote]"));
+    }
+
+    #[test]
+    fn test_externals_take_one_argument() {
+        let src = r#"
+            @external(python="model.blend")
+            fn blend(a: f64, b: f64): f64 { return a; }
+        "#;
+        let program = parse_program(src).unwrap();
+        let err = generate_python_types(&program, PythonTypesStyle::TypedDict).unwrap_err();
+        assert_eq!(
+            err,
+            "external 'blend' (model.blend) declares 2 parameters, but externals are called with one argument, the pipeline input"
+        );
+    }
+}
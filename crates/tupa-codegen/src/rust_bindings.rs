@@ -0,0 +1,499 @@
+//! Rust host bindings for pipelines.
+//!
+//! `generate_rust_bindings` emits a Rust module with `serde` types for every
+//! pipeline's input, step results and output, plus a typed
+//! `run_<pipeline>(&Runtime, Input)` wrapper over `Runtime::run_pipeline_async`.
+//! Host crates that include the module get a compile error instead of a
+//! runtime lookup failure when the `.tp` contract drifts.
+
+use std::collections::HashSet;
+
+use tupa_parser::{EnumDef, Item, PipelineDecl, Program, Type};
+use tupa_typecheck::{pipeline_types, Ty, TypeError};
+
+use crate::execution_plan::{codegen_pipeline, is_option_enum, is_result_enum, TypeDecls};
+use crate::naming::{pascal_case, snake_case, type_ident};
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+    "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Generates a Rust module binding every pipeline in `program`.
+///
+/// The module expects the host crate to depend on `serde` (with `derive`),
+/// `serde_json`, `tupa-codegen` and `tupa-runtime`.
+#[allow(clippy::result_large_err)]
+pub fn generate_rust_bindings(module_name: &str, program: &Program) -> Result<String, TypeError> {
+    let mut gen = BindingsGen::new(program);
+    for item in &program.items {
+        if let Item::Pipeline(pipeline) = item {
+            gen.emit_pipeline(module_name, pipeline, program)?;
+        }
+    }
+    Ok(gen.finish())
+}
+
+struct BindingsGen<'a> {
+    decls: TypeDecls<'a>,
+    emitted_enums: HashSet<String>,
+    type_names: HashSet<String>,
+    items: Vec<String>,
+}
+
+impl<'a> BindingsGen<'a> {
+    fn new(program: &'a Program) -> Self {
+        Self {
+            decls: TypeDecls::new(program),
+            emitted_enums: HashSet::new(),
+            type_names: HashSet::new(),
+            items: Vec::new(),
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn emit_pipeline(
+        &mut self,
+        module_name: &str,
+        pipeline: &PipelineDecl,
+        program: &Program,
+    ) -> Result<(), TypeError> {
+        let types = pipeline_types(program, pipeline)?;
+        let base = pascal_case(&pipeline.name);
+        let snake = snake_case(&pipeline.name);
+
+        let input_name = format!("{base}Input");
+        self.emit_named(&input_name, &types.input);
+
+        let steps_name = format!("{base}Steps");
+        if !types.steps.is_empty() {
+            let fields = types
+                .steps
+                .iter()
+                .map(|(name, ty)| {
+                    let ty_name = format!("{base}{}", pascal_case(name));
+                    (name.clone(), self.rust_type(ty, &ty_name))
+                })
+                .collect::<Vec<_>>();
+            self.emit_struct(
+                &steps_name,
+                &format!("Step results of pipeline `{}`.", pipeline.name),
+                &fields,
+            );
+        }
+
+        let output_name = format!("{base}Output");
+        match &types.output {
+            Some(output) => self.emit_named(&output_name, output),
+            None if types.steps.is_empty() => {
+                self.items
+                    .push(format!("pub type {output_name} = {input_name};"));
+            }
+            None => {
+                // Undeclared outputs are the pipeline state: record inputs are
+                // extended in place, other inputs are kept under `input`.
+                let input_field = if matches!(types.input, Ty::Record(_)) {
+                    "    #[serde(flatten)]\n    pub input: "
+                } else {
+                    "    pub input: "
+                };
+                self.type_names.insert(output_name.clone());
+                self.items.push(format!(
+                    "/// Final state of pipeline `{}`.\n{}\npub struct {output_name} {{\n{input_field}{input_name},\n    #[serde(flatten)]\n    pub steps: {steps_name},\n}}",
+                    pipeline.name,
+                    derive_line(),
+                ));
+            }
+        }
+
+        let plan_json =
+            codegen_pipeline(module_name, pipeline, program).expect("execution plan serializes");
+        let plan_const = format!("{}_PLAN", snake.to_uppercase());
+        let hashes = "#".repeat(raw_string_hashes(&plan_json));
+        self.items.push(format!(
+            "const {plan_const}: &str = r{hashes}\"{plan_json}\"{hashes};"
+        ));
+        self.items.push(format!(
+            "/// Execution plan for pipeline `{name}`.\npub fn {snake}_plan() -> ExecutionPlan {{\n    serde_json::from_str({plan_const}).expect(\"embedded plan for {name} is valid\")\n}}",
+            name = pipeline.name,
+        ));
+        self.items.push(format!(
+            "/// Runs pipeline `{name}` with typed input and output.\npub async fn run_{snake}(\n    runtime: &Runtime,\n    input: {input_name},\n) -> RuntimeResult<{output_name}> {{\n    let plan = {snake}_plan();\n    let input = serde_json::to_value(input)\n        .map_err(|e| RuntimeError::ValidationError(format!(\"input {{e}}\")))?;\n    let output = runtime.run_pipeline_async(&plan, input).await?;\n    serde_json::from_value(output)\n        .map_err(|e| RuntimeError::ValidationError(format!(\"output {{e}}\")))\n}}",
+            name = pipeline.name,
+        ));
+        Ok(())
+    }
+
+    /// Emits `name` as a struct for records and as a type alias otherwise.
+    fn emit_named(&mut self, name: &str, ty: &Ty) {
+        if let Ty::Record(fields) = ty {
+            self.emit_record(name, fields);
+        } else {
+            let rust = self.rust_type(ty, name);
+            self.type_names.insert(name.to_string());
+            self.items.push(format!("pub type {name} = {rust};"));
+        }
+    }
+
+    fn emit_record(&mut self, name: &str, fields: &[(String, Ty)]) -> String {
+        let name = self.unique_name(name);
+        let fields = fields
+            .iter()
+            .map(|(field, ty)| {
+                let nested = format!("{name}{}", pascal_case(field));
+                (field.clone(), self.rust_type(ty, &nested))
+            })
+            .collect::<Vec<_>>();
+        self.emit_struct(&name, "", &fields);
+        name
+    }
+
+    fn emit_struct(&mut self, name: &str, doc: &str, fields: &[(String, String)]) {
+        self.type_names.insert(name.to_string());
+        let mut out = String::new();
+        if !doc.is_empty() {
+            out.push_str(&format!("/// {doc}\n"));
+        }
+        out.push_str(&derive_line());
+        out.push_str(&format!("\npub struct {name} {{\n"));
+        for (field, ty) in fields {
+            let ident = rust_ident(field);
+            if ident.trim_start_matches("r#") != field {
+                out.push_str(&format!("    #[serde(rename = \"{field}\")]\n"));
+            }
+            out.push_str(&format!("    pub {ident}: {ty},\n"));
+        }
+        out.push('}');
+        self.items.push(out);
+    }
+
+    fn emit_enum(&mut self, def: &EnumDef) {
+        if !self.emitted_enums.insert(def.name.clone()) {
+            return;
+        }
+        let ident = type_ident(&def.name);
+        self.type_names.insert(ident.clone());
+        let generics: HashSet<&str> = def.generics.iter().map(String::as_str).collect();
+        let mut out = String::new();
+        out.push_str(&derive_line());
+        out.push_str(&format!("\npub enum {ident}"));
+        if !def.generics.is_empty() {
+            out.push_str(&format!("<{}>", def.generics.join(", ")));
+        }
+        out.push_str(" {\n");
+        for variant in &def.variants {
+            if variant.args.is_empty() {
+                out.push_str(&format!("    {},\n", variant.name));
+                continue;
+            }
+            let args = variant
+                .args
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| {
+                    let hint = format!("{ident}{}{i}", variant.name);
+                    self.rust_type_from_ast(arg, &hint, &generics)
+                })
+                .collect::<Vec<_>>();
+            out.push_str(&format!("    {}({}),\n", variant.name, args.join(", ")));
+        }
+        out.push('}');
+        self.items.push(out);
+    }
+
+    /// Maps a resolved type to Rust, emitting structs named after `hint` for records.
+    fn rust_type(&mut self, ty: &Ty, hint: &str) -> String {
+        match ty {
+            Ty::I64 => "i64".into(),
+            Ty::F64 => "f64".into(),
+            Ty::Bool => "bool".into(),
+            Ty::String => "String".into(),
+            Ty::Null | Ty::Unit => "()".into(),
+            Ty::Tensor(t) => tensor_type(&t.dtype, t.shape.len()),
+            Ty::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| self.rust_type(item, &format!("{hint}{i}")))
+                    .collect::<Vec<_>>();
+                format!("({},)", items.join(", "))
+            }
+            Ty::Record(fields) => self.emit_record(hint, fields),
+            Ty::Array { elem, .. } | Ty::Slice { elem } => {
+                format!("Vec<{}>", self.rust_type(elem, &format!("{hint}Item")))
+            }
+            Ty::Enum { name, args } => {
+                if let Some(def) = self.decls.enums.get(name.as_str()).copied() {
+                    if is_option_enum(def) && args.len() == 1 {
+                        // Options are encoded as nullable values, like serde's `Option`.
+                        return format!("Option<{}>", self.rust_type(args[0].ty(), hint));
+                    }
+                    if is_result_enum(def) && args.len() == 2 {
+                        // `{"Ok": v}`/`{"Err": e}` is serde's encoding of `Result`.
+                        return format!(
+                            "Result<{}, {}>",
+                            self.rust_type(args[0].ty(), &format!("{hint}Ok")),
+                            self.rust_type(args[1].ty(), &format!("{hint}Err"))
+                        );
+                    }
+                    self.emit_enum(def);
+                }
+                let name = type_ident(name);
+                if args.is_empty() {
+                    name
+                } else {
+                    let args = args
+                        .iter()
+                        .enumerate()
+                        .map(|(i, arg)| self.rust_type(arg.ty(), &format!("{hint}{i}")))
+                        .collect::<Vec<_>>();
+                    format!("{name}<{}>", args.join(", "))
+                }
+            }
+            Ty::Func { .. } | Ty::Closure { .. } | Ty::Trait(_) | Ty::Alias(_) | Ty::Unknown => {
+                "serde_json::Value".into()
+            }
+        }
+    }
+
+    /// Maps a declared type (enum payloads) to Rust; generic parameters stay symbolic.
+    fn rust_type_from_ast(&mut self, ty: &Type, hint: &str, generics: &HashSet<&str>) -> String {
+        match ty {
+            Type::Ident(name) => match name.as_str() {
+                "i64" => "i64".into(),
+                "f64" => "f64".into(),
+                "bool" => "bool".into(),
+                "string" => "String".into(),
+                "null" => "()".into(),
+                _ if generics.contains(name.as_str()) => name.clone(),
+                _ => {
+                    if let Some(def) = self.decls.enums.get(name.as_str()).copied() {
+                        self.emit_enum(def);
+                        type_ident(name)
+                    } else if let Some(aliased) = self.decls.expand(name, &[]) {
+                        self.rust_type_from_ast(&aliased, &type_ident(name), generics)
+                    } else if let Some(builtin) = tupa_typecheck::builtin_type(name) {
+                        self.rust_type_from_ast(&builtin, name, generics)
+                    } else {
+                        "serde_json::Value".into()
+                    }
+                }
+            },
+            Type::Generic { name, args } => {
+                if let Some(aliased) = self.decls.expand(name, args) {
+                    return self.rust_type_from_ast(&aliased, hint, generics);
+                }
+                if let Some(def) = self.decls.enums.get(name.as_str()).copied() {
+                    if is_option_enum(def) && args.len() == 1 {
+                        return format!(
+                            "Option<{}>",
+                            self.rust_type_from_ast(&args[0], hint, generics)
+                        );
+                    }
+                    if is_result_enum(def) && args.len() == 2 {
+                        return format!(
+                            "Result<{}, {}>",
+                            self.rust_type_from_ast(&args[0], &format!("{hint}Ok"), generics),
+                            self.rust_type_from_ast(&args[1], &format!("{hint}Err"), generics)
+                        );
+                    }
+                    self.emit_enum(def);
+                }
+                let args = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| self.rust_type_from_ast(arg, &format!("{hint}{i}"), generics))
+                    .collect::<Vec<_>>();
+                format!("{}<{}>", type_ident(name), args.join(", "))
+            }
+            Type::Record(fields) => {
+                let name = self.unique_name(hint);
+                let fields = fields
+                    .iter()
+                    .map(|(field, ty)| {
+                        let nested = format!("{name}{}", pascal_case(field));
+                        (
+                            field.clone(),
+                            self.rust_type_from_ast(ty, &nested, generics),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                self.emit_struct(&name, "", &fields);
+                name
+            }
+            Type::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| self.rust_type_from_ast(item, &format!("{hint}{i}"), generics))
+                    .collect::<Vec<_>>();
+                format!("({},)", items.join(", "))
+            }
+            Type::Safe { base, .. } => self.rust_type_from_ast(base, hint, generics),
+            Type::Array { elem, .. } | Type::Slice { elem } => {
+                format!(
+                    "Vec<{}>",
+                    self.rust_type_from_ast(elem, &format!("{hint}Item"), generics)
+                )
+            }
+            Type::Tensor(t) => tensor_type(&t.dtype, t.shape.len()),
+            Type::Unit => "()".into(),
+            Type::Func { .. } => "serde_json::Value".into(),
+        }
+    }
+
+    fn unique_name(&self, hint: &str) -> String {
+        if !self.type_names.contains(hint) {
+            return hint.to_string();
+        }
+        (2..)
+            .map(|n| format!("{hint}{n}"))
+            .find(|candidate| !self.type_names.contains(candidate))
+            .unwrap()
+    }
+
+    fn finish(self) -> String {
+        let mut out = String::from(
+            "// Generated by `tupa codegen --format rust-bindings`. Do not edit.\n\nuse serde::{Deserialize, Serialize};\nuse tupa_codegen::execution_plan::ExecutionPlan;\nuse tupa_runtime::{Runtime, RuntimeError, RuntimeResult};\n",
+        );
+        for item in self.items {
+            out.push('\n');
+            out.push_str(&item);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn derive_line() -> String {
+    "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]".to_string()
+}
+
+fn tensor_type(dtype: &str, rank: usize) -> String {
+    let scalar = match dtype {
+        "f32" | "i32" | "i64" | "u8" | "bool" | "f64" => dtype,
+        _ => "f64",
+    };
+    let mut out = scalar.to_string();
+    for _ in 0..rank.max(1) {
+        out = format!("Vec<{out}>");
+    }
+    out
+}
+
+/// Returns how many `#` a raw string literal needs to hold `content`.
+fn raw_string_hashes(content: &str) -> usize {
+    let mut needed = 1;
+    let bytes = content.as_bytes();
+    for (i, b) in bytes.iter().enumerate() {
+        if *b == b'"' {
+            let run = bytes[i + 1..].iter().take_while(|c| **c == b'#').count();
+            needed = needed.max(run + 1);
+        }
+    }
+    needed
+}
+
+fn rust_ident(name: &str) -> String {
+    let mut ident = snake_case(name);
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if RUST_KEYWORDS.contains(&ident.as_str()) {
+        format!("r#{ident}")
+    } else {
+        ident
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tupa_parser::parse_program;
+
+    #[test]
+    fn test_case_conversions() {
+        assert_eq!(snake_case("MNISTAudit"), "mnist_audit");
+        assert_eq!(snake_case("CreditDecision"), "credit_decision");
+        assert_eq!(pascal_case("viper::fetch_data"), "ViperFetchData");
+        assert_eq!(rust_ident("type"), "r#type");
+        assert_eq!(rust_ident("viper::analyze"), "viper_analyze");
+    }
+
+    #[test]
+    fn test_rust_bindings_for_record_pipeline() {
+        let src = r#"
+            enum Side { Buy, Sell(f64) }
+            fn decide(input: { symbol: string, price: f64 }): Side { return Buy; }
+            fn gate(input: { symbol: string, price: f64 }): { passed: bool, reason: string } {
+                return pass("ok");
+            }
+
+            pipeline EntryGate {
+                input: { symbol: string, price: f64 },
+                steps: [
+                    step("gate") { gate(input) },
+                    step("side") { decide(input) }
+                ],
+            }
+        "#;
+        let program = parse_program(src).unwrap();
+        let code = generate_rust_bindings("main", &program).unwrap();
+
+        assert!(code.contains(
+            "pub struct EntryGateInput {\n    pub symbol: String,\n    pub price: f64,\n}"
+        ));
+        assert!(code.contains("pub struct EntryGateGate {"));
+        assert!(code.contains("pub enum Side {\n    Buy,\n    Sell(f64),\n}"));
+        assert!(code.contains(
+            "pub struct EntryGateSteps {\n    pub gate: EntryGateGate,\n    pub side: Side,\n}"
+        ));
+        assert!(code.contains("#[serde(flatten)]\n    pub input: EntryGateInput,"));
+        assert!(code.contains("pub async fn run_entry_gate(\n    runtime: &Runtime,\n    input: EntryGateInput,\n) -> RuntimeResult<EntryGateOutput>"));
+        assert!(code.contains("const ENTRY_GATE_PLAN: &str = r#\"{"));
+    }
+
+    #[test]
+    fn test_rust_bindings_for_primitive_input_with_declared_output() {
+        let src = r#"
+            enum Option<T> { Some(T), None }
+            fn score(x: i64): f64 { return 1.0; }
+
+            pipeline Scoring {
+                input: i64,
+                steps: [ step("score") { score(input) } ],
+                output: { score: f64, note: Option<string> }
+            }
+        "#;
+        let program = parse_program(src).unwrap();
+        let code = generate_rust_bindings("main", &program).unwrap();
+
+        assert!(code.contains("pub type ScoringInput = i64;"));
+        assert!(code.contains("pub struct ScoringSteps {\n    pub score: f64,\n}"));
+        assert!(code.contains(
+            "pub struct ScoringOutput {\n    pub score: f64,\n    pub note: Option<String>,\n}"
+        ));
+        assert!(!code.contains("pub enum Option"));
+    }
+
+    #[test]
+    fn test_rust_bindings_use_std_result() {
+        let src = r#"
+            fn quote(x: f64): Result<f64, string> { return Ok(x); }
+
+            pipeline Quoting {
+                input: { price: f64, last: Result<f64, string> },
+                steps: [ step("quote") { quote(input.price) } ]
+            }
+        "#;
+        let program = parse_program(src).unwrap();
+        let code = generate_rust_bindings("main", &program).unwrap();
+
+        assert!(code.contains("pub last: Result<f64, String>,"));
+        assert!(code.contains("pub struct QuotingSteps {\n    pub quote: f64,\n}"));
+        assert!(!code.contains("pub enum Result"));
+    }
+}
@@ -38,16 +38,12 @@ pub fn format_source(source: &str) -> String {
                     in_string = true;
                     current_line.push(c);
                 }
-                '/' => {
-                    // Check for // comment
-                    if i + 1 < chars.len() && chars[i + 1] == '/' {
-                        in_comment = true;
-                        current_line.push(c);
-                        current_line.push(chars[i + 1]);
-                        i += 1; // Skip next /
-                    } else {
-                        current_line.push(c);
-                    }
+                // Check for // comment
+                '/' if i + 1 < chars.len() && chars[i + 1] == '/' => {
+                    in_comment = true;
+                    current_line.push(c);
+                    current_line.push(chars[i + 1]);
+                    i += 1; // Skip next /
                 }
                 '{' => {
                     current_line.push(c);
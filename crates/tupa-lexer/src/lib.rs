@@ -68,6 +68,7 @@ pub enum Token {
     Bang,
     Pipe,
     At,
+    Question,
     Percent,
     PercentEqual,
 }
@@ -255,8 +256,11 @@ fn symbol_one_char(input: &str) -> IResult<&str, Token> {
         value(Token::Dot, char('.')),
         value(Token::Bang, char('!')),
         value(Token::Pipe, char('|')),
-        value(Token::At, char('@')),
-        value(Token::Percent, char('%')),
+        alt((
+            value(Token::At, char('@')),
+            value(Token::Question, char('?')),
+            value(Token::Percent, char('%')),
+        )),
     ))(input)
 }
 
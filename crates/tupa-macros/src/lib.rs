@@ -0,0 +1,241 @@
+//! Procedural macros re-exported by `tupa-runtime`.
+//!
+//! `#[tupa_step]` turns a typed Rust function into a step the runtime can
+//! register with `Runtime::register_tupa_step`, and `#[derive(TupaType)]`
+//! describes a struct as a Tupã record so it can be a step's argument or
+//! return type.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::{
+    parse_macro_input, Data, DeriveInput, ExprArray, Fields, FnArg, GenericArgument, ItemFn,
+    LitStr, Pat, PathArguments, ReturnType, Type,
+};
+
+/// Makes a function taking one serde-deserializable argument, the pipeline
+/// state, and returning a serde-serializable value (or a `Result` whose
+/// error implements `Display`) registrable as an `@external(rust=...)`
+/// step.
+///
+/// The function stays callable as before; a braced struct of the same name
+/// implements `tupa_runtime::step::TupaStep` for it:
+///
+/// ```ignore
+/// #[tupa_step(name = "viper::fetch_market_data", effects = [IO])]
+/// fn fetch_market_data(req: MarketRequest) -> Result<f64, String> { ... }
+///
+/// runtime.register_tupa_step::<fetch_market_data>();
+/// println!("{}", fetch_market_data::declaration());
+/// ```
+///
+/// `name` defaults to the function's module path, e.g.
+/// `my_crate::steps::fetch_market_data`.
+#[proc_macro_attribute]
+pub fn tupa_step(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name: Option<LitStr> = None;
+    let mut effects: Vec<String> = Vec::new();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("effects") {
+            let list: ExprArray = meta.value()?.parse()?;
+            effects = list.elems.iter().map(effect_text).collect();
+            Ok(())
+        } else {
+            Err(meta.error("expected `name` or `effects`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let func = parse_macro_input!(item as ItemFn);
+    expand_step(name, effects, func)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `tupa_runtime::step::TupaType` for a struct with named
+/// fields, as the record `{ field: type, ... }`. `#[serde(rename = "...")]`
+/// on a field renames it in the record.
+#[proc_macro_derive(TupaType)]
+pub fn derive_tupa_type(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    expand_type(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Effect as written in `@external(effects=[...])`, e.g. `ExternalCall("x")`.
+fn effect_text(effect: &syn::Expr) -> String {
+    effect
+        .to_token_stream()
+        .to_string()
+        .replace(" (", "(")
+        .replace("( ", "(")
+        .replace(" )", ")")
+}
+
+fn expand_step(
+    name: Option<LitStr>,
+    effects: Vec<String>,
+    func: ItemFn,
+) -> syn::Result<TokenStream2> {
+    let sig = &func.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            "#[tupa_step] does not support async functions",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            "#[tupa_step] functions cannot be generic",
+        ));
+    }
+    let mut inputs = sig.inputs.iter();
+    let (param, param_ty) = match (inputs.next(), inputs.next()) {
+        (Some(FnArg::Typed(arg)), None) => {
+            let param = match &*arg.pat {
+                Pat::Ident(ident) => ident.ident.to_string(),
+                _ => "input".to_string(),
+            };
+            (param, &*arg.ty)
+        }
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &sig.inputs,
+                "#[tupa_step] functions take one argument, the pipeline state",
+            ))
+        }
+    };
+    let (output_ty, fallible) = match &sig.output {
+        ReturnType::Default => {
+            return Err(syn::Error::new_spanned(
+                sig,
+                "#[tupa_step] functions must return a value",
+            ))
+        }
+        ReturnType::Type(_, ty) => match result_ok_type(ty) {
+            Some(ok) => (ok, true),
+            None => (&**ty, false),
+        },
+    };
+
+    let ident = &sig.ident;
+    let vis = &func.vis;
+    let function = ident.to_string();
+    let name = match name {
+        Some(name) => quote!(#name),
+        None => quote!(concat!(module_path!(), "::", #function)),
+    };
+    let call = if fallible {
+        quote!(#ident(arg).map_err(|e| e.to_string())?)
+    } else {
+        quote!(#ident(arg))
+    };
+    let doc = format!("Step `{function}`, registered with `Runtime::register_tupa_step`.");
+    Ok(quote! {
+        #func
+
+        #[doc = #doc]
+        #[allow(non_camel_case_types)]
+        #vis struct #ident {}
+
+        impl ::tupa_runtime::step::TupaStep for #ident {
+            const NAME: &'static str = #name;
+            const FUNCTION: &'static str = #function;
+            const PARAM: &'static str = #param;
+            const EFFECTS: &'static [&'static str] = &[#(#effects),*];
+            type Param = #param_ty;
+            type Output = #output_ty;
+
+            fn call(
+                input: ::tupa_runtime::step::__private::Value,
+            ) -> ::std::result::Result<::tupa_runtime::step::__private::Value, ::std::string::String>
+            {
+                let arg: #param_ty = ::tupa_runtime::step::__private::from_value(input)
+                    .map_err(|e| format!("{}: invalid argument: {e}", Self::NAME))?;
+                let output: #output_ty = #call;
+                ::tupa_runtime::step::__private::to_value(output)
+                    .map_err(|e| format!("{}: invalid output: {e}", Self::NAME))
+            }
+        }
+    })
+}
+
+/// `T` when `ty` is `Result<T, E>`.
+fn result_ok_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match (args.args.first(), args.args.len()) {
+        (Some(GenericArgument::Type(ok)), 2) => Some(ok),
+        _ => None,
+    }
+}
+
+fn expand_type(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "derive(TupaType) supports structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "derive(TupaType) supports structs with named fields",
+            ))
+        }
+    };
+    let mut entries = Vec::new();
+    for field in fields {
+        let mut name = field
+            .ident
+            .as_ref()
+            .map(|i| i.to_string())
+            .unwrap_or_default();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("serde")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<syn::Expr>()?;
+                }
+                Ok(())
+            })?;
+        }
+        let ty = &field.ty;
+        entries.push(quote! {
+            format!("{}: {}", #name, <#ty as ::tupa_runtime::step::TupaType>::tupa_type())
+        });
+    }
+
+    for param in input.generics.type_params_mut() {
+        param
+            .bounds
+            .push(syn::parse_quote!(::tupa_runtime::step::TupaType));
+    }
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::tupa_runtime::step::TupaType for #ident #ty_generics #where_clause {
+            fn tupa_type() -> ::std::string::String {
+                let fields: ::std::vec::Vec<::std::string::String> = vec![#(#entries),*];
+                format!("{{ {} }}", fields.join(", "))
+            }
+        }
+    })
+}
@@ -1,17 +1,33 @@
+use std::collections::HashMap;
+
 use serde::Serialize;
 use thiserror::Error;
 pub use tupa_lexer::{lex_with_spans, LexerError, Span, Token, TokenSpan};
 
+pub mod module;
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Program {
+    pub imports: Vec<Import>,
     pub items: Vec<Item>,
 }
 
+/// `import "path" as alias;`, resolved by `module::load_program` relative to
+/// the importing file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Import {
+    pub path: String,
+    /// Name qualifying the module's exports; defaults to the file stem.
+    pub alias: Option<String>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Item {
     Function(Function),
     Enum(EnumDef),
     Trait(TraitDef),
+    TypeAlias(TypeAlias),
     Pipeline(PipelineDecl),
 }
 
@@ -24,6 +40,8 @@ pub struct Attribute {
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ExternalSpec {
     pub python: Option<String>,
+    /// Path of a Rust function the host registers, e.g. `viper::fetch`.
+    pub rust: Option<String>,
     pub effects: Vec<String>,
 }
 
@@ -32,6 +50,7 @@ pub struct EnumDef {
     pub name: String,
     pub generics: Vec<String>,
     pub variants: Vec<EnumVariant>,
+    pub exported: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -44,6 +63,48 @@ pub struct EnumVariant {
 pub struct TraitDef {
     pub name: String,
     pub methods: Vec<Function>,
+    pub exported: bool,
+}
+
+/// `type Name<T> ...`: a named record, alias or tagged union.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TypeAlias {
+    pub name: String,
+    pub generics: Vec<String>,
+    pub def: TypeDef,
+    pub exported: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum TypeDef {
+    /// `type Name { field: T, ... }` or `type Name = T;`.
+    Alias(Type),
+    /// `type Name = A(T) | B;`, checked like the enum it spells out.
+    Union(EnumDef),
+}
+
+impl TypeAlias {
+    /// The enum a union declares.
+    pub fn as_enum(&self) -> Option<&EnumDef> {
+        match &self.def {
+            TypeDef::Union(def) => Some(def),
+            TypeDef::Alias(_) => None,
+        }
+    }
+
+    /// The aliased type with the parameters bound to `args`; `None` for a
+    /// union or the wrong number of arguments.
+    pub fn expand(&self, args: &[Type]) -> Option<Type> {
+        let TypeDef::Alias(ty) = &self.def else {
+            return None;
+        };
+        if args.len() != self.generics.len() {
+            return None;
+        }
+        let bindings: HashMap<&str, &Type> =
+            self.generics.iter().map(String::as_str).zip(args).collect();
+        Some(ty.substitute(&bindings))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -55,6 +116,7 @@ pub struct PipelineDecl {
     pub output_ty: Option<Type>,
     pub constraints: Vec<Constraint>,
     pub steps: Vec<PipelineStep>,
+    pub temporal: Vec<TemporalDecl>,
     pub validation: Option<Block>,
     pub span: Span,
 }
@@ -64,18 +126,73 @@ pub enum Comparator {
     Lt,
     Le,
     Eq,
+    Ne,
     Ge,
     Gt,
+    /// `between: [low, high]`, inclusive.
+    Between,
+    /// `in: [a, b, ...]`.
+    In,
+    /// `any: [...]`: at least one nested constraint holds.
+    Any,
+    /// `all: [...]`: every nested constraint holds.
+    All,
+}
+
+/// A constraint threshold: a literal or the name of another metric or input
+/// field (`"max_drawdown"`, `"config.max_spread"`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ConstraintOperand {
+    Number(f64),
+    Ref(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    /// A failure fails the run's constraint report.
+    #[default]
+    Block,
+    /// A failure is reported but does not fail the report.
+    Warn,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Constraint {
+    /// Metric path; empty for `any`/`all` groups.
     pub metric: String,
     pub comparator: Comparator,
-    pub threshold: f64,
+    /// Thresholds: one for `lt`/`le`/`eq`/`ne`/`ge`/`gt`, the bounds of
+    /// `between` (low, high) and the members of `in`; none for groups.
+    pub operands: Vec<ConstraintOperand>,
+    /// Nested constraints of `any`/`all`.
+    pub group: Vec<Constraint>,
+    pub severity: Severity,
     pub span: Span,
 }
 
+/// Keyed temporal state declared in a pipeline's `state:` section, e.g.
+/// `{ key: "signal", confirm: 3, observe: "check.passed" }` or
+/// `{ key: "stop_loss", cooldown: 5, trigger: "exit.stop_loss", per: "symbol" }`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemporalDecl {
+    pub key: String,
+    pub kind: TemporalKind,
+    /// Required consecutive hits (`confirm`) or cooldown length in evaluations (`cooldown`).
+    pub threshold: i64,
+    /// Dotted path to the boolean step result that advances the state.
+    pub source: String,
+    /// Dotted input path whose value keeps separate state per entity, e.g.
+    /// per `symbol`; `None` keeps one state for the pipeline.
+    pub per: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TemporalKind {
+    Confirm,
+    Cooldown,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct PipelineStep {
     pub name: String,
@@ -91,6 +208,8 @@ pub struct Function {
     pub body: Vec<Stmt>,
     pub attrs: Vec<Attribute>,
     pub external_spec: Option<ExternalSpec>,
+    /// Declared with `export`, so importing modules can call it.
+    pub exported: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -158,6 +277,63 @@ pub enum Type {
     Unit,
 }
 
+impl Type {
+    /// Replaces the type names in `bindings`, e.g. generic parameters.
+    pub fn substitute(&self, bindings: &HashMap<&str, &Type>) -> Type {
+        let sub = |ty: &Type| ty.substitute(bindings);
+        match self {
+            Type::Ident(name) => bindings
+                .get(name.as_str())
+                .map_or_else(|| self.clone(), |&ty| ty.clone()),
+            Type::Generic { name, args } => Type::Generic {
+                name: name.clone(),
+                args: args.iter().map(sub).collect(),
+            },
+            Type::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), sub(ty)))
+                    .collect(),
+            ),
+            Type::Tuple(items) => Type::Tuple(items.iter().map(sub).collect()),
+            Type::Safe { base, constraints } => Type::Safe {
+                base: Box::new(sub(base)),
+                constraints: constraints.clone(),
+            },
+            Type::Array { elem, len } => Type::Array {
+                elem: Box::new(sub(elem)),
+                len: *len,
+            },
+            Type::Slice { elem } => Type::Slice {
+                elem: Box::new(sub(elem)),
+            },
+            Type::Func { params, ret } => Type::Func {
+                params: params.iter().map(sub).collect(),
+                ret: Box::new(sub(ret)),
+            },
+            Type::Tensor(_) | Type::Unit => self.clone(),
+        }
+    }
+
+    /// Whether the type names `name` anywhere, e.g. `{ next: Chain }`.
+    pub fn mentions(&self, name: &str) -> bool {
+        match self {
+            Type::Ident(ident) => ident == name,
+            Type::Generic { name: ident, args } => {
+                ident == name || args.iter().any(|arg| arg.mentions(name))
+            }
+            Type::Record(fields) => fields.iter().any(|(_, ty)| ty.mentions(name)),
+            Type::Tuple(items) => items.iter().any(|ty| ty.mentions(name)),
+            Type::Safe { base, .. } => base.mentions(name),
+            Type::Array { elem, .. } | Type::Slice { elem } => elem.mentions(name),
+            Type::Func { params, ret } => {
+                params.iter().any(|ty| ty.mentions(name)) || ret.mentions(name)
+            }
+            Type::Tensor(_) | Type::Unit => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Expr {
     pub kind: ExprKind,
@@ -201,6 +377,7 @@ pub enum ExprKind {
         index: Box<Expr>,
     },
     Await(Box<Expr>),
+    Try(Box<Expr>),
     Block(Block),
     If {
         condition: Box<Expr>,
@@ -292,6 +469,23 @@ pub enum ParserError {
     Eof(usize),
 }
 
+impl ParserError {
+    /// Source span the error points at.
+    pub fn span(&self) -> Span {
+        match self {
+            ParserError::Unexpected(_, span) | ParserError::MissingSemicolon(span) => *span,
+            ParserError::Eof(pos) => Span {
+                start: *pos,
+                end: *pos,
+            },
+            ParserError::Lexer(LexerError::Unexpected(_, pos)) => Span {
+                start: *pos,
+                end: *pos + 1,
+            },
+        }
+    }
+}
+
 impl Expr {
     fn new(kind: ExprKind, span: Span) -> Self {
         Self { kind, span }
@@ -361,6 +555,7 @@ fn token_to_string(token: &Token) -> String {
         Token::Bang => "!".to_string(),
         Token::Pipe => "|".to_string(),
         Token::At => "@".to_string(),
+        Token::Question => "?".to_string(),
         Token::Percent => "%".to_string(),
         Token::PercentEqual => "%=".to_string(),
     }
@@ -390,6 +585,7 @@ fn parse_external_effect_item(tokens: &[String]) -> Option<String> {
 fn parse_external_spec(args: &[String]) -> ExternalSpec {
     let mut spec = ExternalSpec {
         python: None,
+        rust: None,
         effects: Vec::new(),
     };
 
@@ -411,9 +607,12 @@ fn parse_external_spec(args: &[String]) -> ExternalSpec {
             continue;
         }
 
-        if key == "python" {
-            if let Some(value) = args.get(i + 2) {
-                spec.python = Some(value.clone());
+        if key == "python" || key == "rust" {
+            let value = args.get(i + 2).cloned();
+            if key == "python" {
+                spec.python = value;
+            } else {
+                spec.rust = value;
             }
             i += 3;
             continue;
@@ -483,23 +682,56 @@ fn parse_external_spec(args: &[String]) -> ExternalSpec {
     spec
 }
 pub fn parse_program(input: &str) -> Result<Program, ParserError> {
-    let tokens = lex_with_spans(input)?;
-    let mut parser = Parser::new(tokens, input.len());
+    parse_tokens(lex_with_spans(input)?, input.len())
+}
+
+/// Parses `input` as if it started at byte `offset`, so that the spans of
+/// modules merged into one program do not overlap. Errors point into
+/// `input`.
+pub(crate) fn parse_program_at(input: &str, offset: usize) -> Result<Program, ParserError> {
+    let tokens = lex_with_spans(input)?
+        .into_iter()
+        .map(|t| TokenSpan {
+            token: t.token,
+            span: Span {
+                start: t.span.start + offset,
+                end: t.span.end + offset,
+            },
+        })
+        .collect();
+    parse_tokens(tokens, offset + input.len()).map_err(|e| match e {
+        ParserError::Unexpected(token, span) => {
+            ParserError::Unexpected(token, shift_back(span, offset))
+        }
+        ParserError::MissingSemicolon(span) => {
+            ParserError::MissingSemicolon(shift_back(span, offset))
+        }
+        ParserError::Eof(pos) => ParserError::Eof(pos - offset),
+        e => e,
+    })
+}
+
+fn shift_back(span: Span, offset: usize) -> Span {
+    Span {
+        start: span.start - offset,
+        end: span.end - offset,
+    }
+}
+
+fn parse_tokens(tokens: Vec<TokenSpan>, eof_pos: usize) -> Result<Program, ParserError> {
+    let mut parser = Parser::new(tokens, eof_pos);
+    let mut imports = Vec::new();
     let mut items = Vec::new();
 
     while !parser.is_eof() {
-        if parser.is_type_decl_start() {
-            parser.skip_type_decl()?;
-            continue;
-        }
-        if parser.is_extern_decl_start() {
-            parser.skip_extern_decl()?;
+        if parser.is_keyword("import") {
+            imports.push(parser.parse_import()?);
             continue;
         }
         items.push(parser.parse_item()?);
     }
 
-    Ok(Program { items })
+    Ok(Program { imports, items })
 }
 
 struct Parser {
@@ -529,84 +761,48 @@ impl Parser {
         self.tokens.get(self.pos + 1).map(|t| &t.token)
     }
 
-    fn is_type_decl_start(&self) -> bool {
-        matches!(
-            (self.peek(), self.peek_next()),
-            (Some(Token::Ident(keyword)), Some(Token::Ident(_))) if keyword == "type"
-        )
+    /// Whether the next token is the contextual keyword `keyword`.
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(name)) if name == keyword)
     }
 
-    fn skip_type_decl(&mut self) -> Result<(), ParserError> {
-        match self.next() {
-            Some(TokenSpan {
-                token: Token::Ident(keyword),
-                ..
-            }) if keyword == "type" => {}
-            Some(TokenSpan { token, span }) => return Err(ParserError::Unexpected(token, span)),
-            None => return Err(ParserError::Eof(self.eof_pos)),
-        }
-
-        match self.next() {
+    fn parse_import(&mut self) -> Result<Import, ParserError> {
+        let start = self.next().ok_or(ParserError::Eof(self.eof_pos))?.span;
+        let (path, mut end) = match self.next() {
             Some(TokenSpan {
-                token: Token::Ident(_),
-                ..
-            }) => {}
+                token: Token::Str(path),
+                span,
+            }) => (path, span),
             Some(TokenSpan { token, span }) => return Err(ParserError::Unexpected(token, span)),
             None => return Err(ParserError::Eof(self.eof_pos)),
-        }
-
-        self.expect(Token::LBrace)?;
-        let mut depth = 1usize;
-        while depth > 0 {
+        };
+        let mut alias = None;
+        if self.is_keyword("as") {
+            self.next();
             match self.next() {
                 Some(TokenSpan {
-                    token: Token::LBrace,
-                    ..
-                }) => depth += 1,
-                Some(TokenSpan {
-                    token: Token::RBrace,
-                    ..
-                }) => depth = depth.saturating_sub(1),
-                Some(_) => {}
+                    token: Token::Ident(name),
+                    span,
+                }) => {
+                    alias = Some(name);
+                    end = span;
+                }
+                Some(TokenSpan { token, span }) => {
+                    return Err(ParserError::Unexpected(token, span))
+                }
                 None => return Err(ParserError::Eof(self.eof_pos)),
             }
         }
-
-        if matches!(self.peek(), Some(Token::Comma | Token::Semicolon)) {
-            self.next();
+        if matches!(self.peek(), Some(Token::Semicolon)) {
+            end = self.next().ok_or(ParserError::Eof(self.eof_pos))?.span;
         }
-
-        Ok(())
+        Ok(Import {
+            path,
+            alias,
+            span: merge_span(start, end),
+        })
     }
 
-    fn is_extern_decl_start(&self) -> bool {
-        matches!(
-            (self.peek(), self.peek_next()),
-            (Some(Token::Ident(_)), Some(Token::Fn))
-        )
-    }
-    fn skip_extern_decl(&mut self) -> Result<(), ParserError> {
-        match self.next() {
-            Some(TokenSpan {
-                token: Token::Ident(_),
-                ..
-            }) => {}
-            Some(TokenSpan { token, span }) => return Err(ParserError::Unexpected(token, span)),
-            None => return Err(ParserError::Eof(self.eof_pos)),
-        }
-        self.expect(Token::Fn)?;
-        loop {
-            match self.next() {
-                Some(TokenSpan {
-                    token: Token::Semicolon,
-                    ..
-                }) => break,
-                Some(_) => {}
-                None => return Err(ParserError::Eof(self.eof_pos)),
-            }
-        }
-        Ok(())
-    }
     fn is_index_assignment(&self) -> bool {
         if !matches!(self.peek(), Some(Token::Ident(_))) {
             return false;
@@ -658,15 +854,41 @@ impl Parser {
     }
 
     fn parse_item(&mut self) -> Result<Item, ParserError> {
+        // `export` may come before or after the attributes.
+        let mut exported = self.eat_keyword("export");
         let mut attrs = Vec::new();
         while matches!(self.peek(), Some(Token::At)) {
             attrs.push(self.parse_attribute()?);
         }
+        exported |= self.eat_keyword("export");
         match self.peek() {
-            Some(Token::Fn) => Ok(Item::Function(self.parse_function(attrs)?)),
-            Some(Token::Enum) => Ok(Item::Enum(self.parse_enum()?)),
-            Some(Token::Trait) => Ok(Item::Trait(self.parse_trait()?)),
-            Some(Token::Pipeline) => Ok(Item::Pipeline(self.parse_pipeline(attrs)?)),
+            Some(Token::Fn) => {
+                let mut func = self.parse_function(attrs)?;
+                func.exported = exported;
+                Ok(Item::Function(func))
+            }
+            Some(Token::Ident(keyword)) if keyword == "extern" => {
+                self.next();
+                let mut func = self.parse_extern_function(attrs)?;
+                func.exported = exported;
+                Ok(Item::Function(func))
+            }
+            Some(Token::Enum) => {
+                let mut def = self.parse_enum()?;
+                def.exported = exported;
+                Ok(Item::Enum(def))
+            }
+            Some(Token::Trait) => {
+                let mut def = self.parse_trait()?;
+                def.exported = exported;
+                Ok(Item::Trait(def))
+            }
+            Some(Token::Ident(keyword)) if keyword == "type" => {
+                let mut alias = self.parse_type_alias()?;
+                alias.exported = exported;
+                Ok(Item::TypeAlias(alias))
+            }
+            Some(Token::Pipeline) if !exported => Ok(Item::Pipeline(self.parse_pipeline(attrs)?)),
             Some(token) => {
                 let span = self.tokens.get(self.pos).map(|t| t.span).unwrap_or(Span {
                     start: self.eof_pos,
@@ -678,6 +900,14 @@ impl Parser {
         }
     }
 
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.next();
+        }
+        found
+    }
+
     fn parse_attribute(&mut self) -> Result<Attribute, ParserError> {
         self.expect(Token::At)?;
         let name = match self.next() {
@@ -729,6 +959,26 @@ impl Parser {
     }
 
     fn parse_function(&mut self, attrs: Vec<Attribute>) -> Result<Function, ParserError> {
+        let mut func = self.parse_function_signature(attrs)?;
+        func.body = self.parse_block()?;
+        Ok(func)
+    }
+
+    /// Parses `extern fn name(params): T;`, a function the host provides.
+    /// It has no body and is an external without a Python or Rust target.
+    fn parse_extern_function(&mut self, attrs: Vec<Attribute>) -> Result<Function, ParserError> {
+        let mut func = self.parse_function_signature(attrs)?;
+        self.expect(Token::Semicolon)?;
+        func.external_spec.get_or_insert(ExternalSpec {
+            python: None,
+            rust: None,
+            effects: Vec::new(),
+        });
+        Ok(func)
+    }
+
+    /// Parses `fn name(params): T`, leaving the body empty.
+    fn parse_function_signature(&mut self, attrs: Vec<Attribute>) -> Result<Function, ParserError> {
         self.expect(Token::Fn)?;
         let name = match self.next() {
             Some(TokenSpan {
@@ -747,7 +997,6 @@ impl Parser {
         } else {
             None
         };
-        let body = self.parse_block()?;
         let external_spec = attrs
             .iter()
             .find(|attr| attr.name == "external")
@@ -757,9 +1006,10 @@ impl Parser {
             name,
             params,
             return_type,
-            body,
+            body: Vec::new(),
             attrs,
             external_spec,
+            exported: false,
         })
     }
 
@@ -773,83 +1023,165 @@ impl Parser {
             Some(TokenSpan { token, span }) => return Err(ParserError::Unexpected(token, span)),
             None => return Err(ParserError::Eof(self.eof_pos)),
         };
+        let generics = self.parse_generic_params()?;
+        self.expect(Token::LBrace)?;
+        let mut variants = Vec::new();
+        while let Some(tok) = self.peek() {
+            if *tok == Token::RBrace {
+                break;
+            }
+            match self.next() {
+                Some(TokenSpan {
+                    token: Token::Ident(variant),
+                    ..
+                }) => variants.push(EnumVariant {
+                    name: variant,
+                    args: self.parse_variant_args()?,
+                }),
+                Some(TokenSpan { token, span }) => {
+                    return Err(ParserError::Unexpected(token, span))
+                }
+                None => return Err(ParserError::Eof(self.eof_pos)),
+            }
+            if let Some(Token::Comma) = self.peek() {
+                self.next();
+            }
+        }
+        self.expect(Token::RBrace)?;
+        Ok(EnumDef {
+            name,
+            generics,
+            variants,
+            exported: false,
+        })
+    }
+
+    /// `<T, U>` after a declared name, if present.
+    fn parse_generic_params(&mut self) -> Result<Vec<String>, ParserError> {
         let mut generics = Vec::new();
-        if matches!(self.peek(), Some(Token::Less)) {
-            self.next();
+        if !matches!(self.peek(), Some(Token::Less)) {
+            return Ok(generics);
+        }
+        self.next();
+        loop {
+            let param = match self.next() {
+                Some(TokenSpan {
+                    token: Token::Ident(name),
+                    ..
+                }) => name,
+                Some(TokenSpan { token, span }) => {
+                    return Err(ParserError::Unexpected(token, span))
+                }
+                None => return Err(ParserError::Eof(self.eof_pos)),
+            };
+            generics.push(param);
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+                if matches!(self.peek(), Some(Token::Greater)) {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+        self.expect(Token::Greater)?;
+        Ok(generics)
+    }
+
+    /// `(T, U)` after a variant name, if present.
+    fn parse_variant_args(&mut self) -> Result<Vec<Type>, ParserError> {
+        let mut args = Vec::new();
+        if !matches!(self.peek(), Some(Token::LParen)) {
+            return Ok(args);
+        }
+        self.next();
+        if !matches!(self.peek(), Some(Token::RParen)) {
             loop {
-                let param = match self.next() {
-                    Some(TokenSpan {
-                        token: Token::Ident(name),
-                        ..
-                    }) => name,
-                    Some(TokenSpan { token, span }) => {
-                        return Err(ParserError::Unexpected(token, span))
-                    }
-                    None => return Err(ParserError::Eof(self.eof_pos)),
-                };
-                generics.push(param);
+                args.push(self.parse_type()?);
                 if matches!(self.peek(), Some(Token::Comma)) {
                     self.next();
-                    if matches!(self.peek(), Some(Token::Greater)) {
+                    if matches!(self.peek(), Some(Token::RParen)) {
                         break;
                     }
                 } else {
                     break;
                 }
             }
-            self.expect(Token::Greater)?;
         }
-        self.expect(Token::LBrace)?;
-        let mut variants = Vec::new();
-        while let Some(tok) = self.peek() {
-            if *tok == Token::RBrace {
-                break;
+        self.expect(Token::RParen)?;
+        Ok(args)
+    }
+
+    fn parse_type_alias(&mut self) -> Result<TypeAlias, ParserError> {
+        self.next();
+        let name = match self.next() {
+            Some(TokenSpan {
+                token: Token::Ident(name),
+                ..
+            }) => name,
+            Some(TokenSpan { token, span }) => return Err(ParserError::Unexpected(token, span)),
+            None => return Err(ParserError::Eof(self.eof_pos)),
+        };
+        let generics = self.parse_generic_params()?;
+        let def = if matches!(self.peek(), Some(Token::LBrace)) {
+            TypeDef::Alias(self.parse_type()?)
+        } else {
+            self.expect(Token::Equal)?;
+            if self.is_union_start() {
+                TypeDef::Union(self.parse_union(&name, &generics)?)
+            } else {
+                TypeDef::Alias(self.parse_type()?)
             }
+        };
+        if matches!(self.peek(), Some(Token::Comma | Token::Semicolon)) {
+            self.next();
+        }
+        Ok(TypeAlias {
+            name,
+            generics,
+            def,
+            exported: false,
+        })
+    }
+
+    /// Whether a type definition spells out variants: `A(T) | B`.
+    fn is_union_start(&self) -> bool {
+        matches!(self.peek(), Some(Token::Pipe))
+            || matches!(
+                (self.peek(), self.peek_next()),
+                (Some(Token::Ident(_)), Some(Token::LParen | Token::Pipe))
+            )
+    }
+
+    fn parse_union(&mut self, name: &str, generics: &[String]) -> Result<EnumDef, ParserError> {
+        if matches!(self.peek(), Some(Token::Pipe)) {
+            self.next();
+        }
+        let mut variants = Vec::new();
+        loop {
             match self.next() {
                 Some(TokenSpan {
                     token: Token::Ident(variant),
                     ..
-                }) => {
-                    let args = if matches!(self.peek(), Some(Token::LParen)) {
-                        self.next();
-                        let mut args = Vec::new();
-                        if !matches!(self.peek(), Some(Token::RParen)) {
-                            loop {
-                                args.push(self.parse_type()?);
-                                if matches!(self.peek(), Some(Token::Comma)) {
-                                    self.next();
-                                    if matches!(self.peek(), Some(Token::RParen)) {
-                                        break;
-                                    }
-                                } else {
-                                    break;
-                                }
-                            }
-                        }
-                        self.expect(Token::RParen)?;
-                        args
-                    } else {
-                        Vec::new()
-                    };
-                    variants.push(EnumVariant {
-                        name: variant,
-                        args,
-                    });
-                }
+                }) => variants.push(EnumVariant {
+                    name: variant,
+                    args: self.parse_variant_args()?,
+                }),
                 Some(TokenSpan { token, span }) => {
                     return Err(ParserError::Unexpected(token, span))
                 }
                 None => return Err(ParserError::Eof(self.eof_pos)),
             }
-            if let Some(Token::Comma) = self.peek() {
-                self.next();
+            if !matches!(self.peek(), Some(Token::Pipe)) {
+                break;
             }
+            self.next();
         }
-        self.expect(Token::RBrace)?;
         Ok(EnumDef {
-            name,
-            generics,
+            name: name.to_string(),
+            generics: generics.to_vec(),
             variants,
+            exported: false,
         })
     }
 
@@ -886,7 +1218,11 @@ impl Parser {
             }
         }
         self.expect(Token::RBrace)?;
-        Ok(TraitDef { name, methods })
+        Ok(TraitDef {
+            name,
+            methods,
+            exported: false,
+        })
     }
 
     fn parse_pipeline(&mut self, mut attrs: Vec<Attribute>) -> Result<PipelineDecl, ParserError> {
@@ -931,6 +1267,7 @@ impl Parser {
         let mut output_ty = None;
         let mut constraints = Vec::new();
         let mut steps = Vec::new();
+        let mut temporal = Vec::new();
         let mut validation = None;
 
         while !matches!(self.peek(), Some(Token::RBrace)) {
@@ -956,106 +1293,7 @@ impl Parser {
                     output_ty = Some(self.parse_type()?);
                 }
                 "constraints" => {
-                    self.expect(Token::LBracket)?;
-                    while !matches!(self.peek(), Some(Token::RBracket)) {
-                        let start = if let Some(ts) = self.peek() {
-                            match ts {
-                                Token::LBrace => self.expect_span(Token::LBrace)?,
-                                _ => {
-                                    let span =
-                                        self.tokens.get(self.pos).map(|t| t.span).unwrap_or(Span {
-                                            start: self.eof_pos,
-                                            end: self.eof_pos,
-                                        });
-                                    return Err(ParserError::Unexpected(ts.clone(), span));
-                                }
-                            }
-                        } else {
-                            return Err(ParserError::Eof(self.eof_pos));
-                        };
-
-                        // metric: "..."
-                        match self.next() {
-                            Some(TokenSpan {
-                                token: Token::Ident(name),
-                                ..
-                            }) if name == "metric" => {}
-                            Some(TokenSpan { token, span }) => {
-                                return Err(ParserError::Unexpected(token, span))
-                            }
-                            None => return Err(ParserError::Eof(self.eof_pos)),
-                        }
-                        self.expect(Token::Colon)?;
-                        let metric = match self.next() {
-                            Some(TokenSpan {
-                                token: Token::Str(value),
-                                ..
-                            }) => value,
-                            Some(TokenSpan { token, span }) => {
-                                return Err(ParserError::Unexpected(token, span))
-                            }
-                            None => return Err(ParserError::Eof(self.eof_pos)),
-                        };
-
-                        if matches!(self.peek(), Some(Token::Comma)) {
-                            self.next();
-                        }
-
-                        // comparator: threshold
-                        let (comparator, threshold) = match self.next() {
-                            Some(TokenSpan {
-                                token: Token::Ident(key),
-                                span,
-                            }) => {
-                                self.expect(Token::Colon)?;
-                                let value = match self.next() {
-                                    Some(TokenSpan {
-                                        token: Token::Float(v),
-                                        ..
-                                    }) => v.parse::<f64>().unwrap_or(0.0),
-                                    Some(TokenSpan {
-                                        token: Token::Int(v),
-                                        ..
-                                    }) => v.parse::<f64>().unwrap_or(0.0),
-                                    Some(TokenSpan { token, span }) => {
-                                        return Err(ParserError::Unexpected(token, span))
-                                    }
-                                    None => return Err(ParserError::Eof(self.eof_pos)),
-                                };
-                                let cmp = match key.as_str() {
-                                    "lt" => Comparator::Lt,
-                                    "le" => Comparator::Le,
-                                    "eq" => Comparator::Eq,
-                                    "ge" => Comparator::Ge,
-                                    "gt" => Comparator::Gt,
-                                    _ => {
-                                        return Err(ParserError::Unexpected(
-                                            Token::Ident(key),
-                                            span,
-                                        ))
-                                    }
-                                };
-                                (cmp, value)
-                            }
-                            Some(TokenSpan { token, span }) => {
-                                return Err(ParserError::Unexpected(token, span))
-                            }
-                            None => return Err(ParserError::Eof(self.eof_pos)),
-                        };
-
-                        self.expect(Token::RBrace)?;
-                        constraints.push(Constraint {
-                            metric,
-                            comparator,
-                            threshold,
-                            span: start, // simplified span
-                        });
-
-                        if matches!(self.peek(), Some(Token::Comma)) {
-                            self.next();
-                        }
-                    }
-                    self.expect(Token::RBracket)?;
+                    constraints = self.parse_constraint_list()?;
                 }
                 "steps" => {
                     self.expect(Token::LBracket)?;
@@ -1106,6 +1344,16 @@ impl Parser {
                     }
                     self.expect(Token::RBracket)?;
                 }
+                "state" => {
+                    self.expect(Token::LBracket)?;
+                    while !matches!(self.peek(), Some(Token::RBracket)) {
+                        temporal.push(self.parse_temporal_decl()?);
+                        if matches!(self.peek(), Some(Token::Comma)) {
+                            self.next();
+                        }
+                    }
+                    self.expect(Token::RBracket)?;
+                }
                 "validation" => {
                     validation = Some(self.parse_block()?);
                 }
@@ -1133,11 +1381,237 @@ impl Parser {
             output_ty,
             constraints,
             steps,
+            temporal,
             validation,
             span: merge_span(start_span, end_span),
         })
     }
 
+    fn parse_constraint_list(&mut self) -> Result<Vec<Constraint>, ParserError> {
+        self.expect(Token::LBracket)?;
+        let mut constraints = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBracket)) {
+            constraints.push(self.parse_constraint()?);
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+            }
+        }
+        self.expect(Token::RBracket)?;
+        Ok(constraints)
+    }
+
+    /// Parses `{ metric: "m", <comparator>: <threshold>, severity: "warn" }`
+    /// or `{ any|all: [ ... ], severity: ... }`.
+    fn parse_constraint(&mut self) -> Result<Constraint, ParserError> {
+        let start = self.expect_span(Token::LBrace)?;
+        let mut metric = None;
+        let mut check = None;
+        let mut severity = Severity::Block;
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let (field, field_span) = match self.next() {
+                Some(TokenSpan {
+                    token: Token::Ident(name),
+                    span,
+                }) => (name, span),
+                Some(TokenSpan {
+                    token: Token::In,
+                    span,
+                }) => ("in".to_string(), span),
+                Some(TokenSpan { token, span }) => {
+                    return Err(ParserError::Unexpected(token, span))
+                }
+                None => return Err(ParserError::Eof(self.eof_pos)),
+            };
+            self.expect(Token::Colon)?;
+            let comparator = match field.as_str() {
+                "metric" => {
+                    metric = Some(self.parse_constraint_str()?.0);
+                    None
+                }
+                "severity" => {
+                    severity = match self.parse_constraint_str()? {
+                        (value, _) if value == "block" => Severity::Block,
+                        (value, _) if value == "warn" => Severity::Warn,
+                        (value, span) => {
+                            return Err(ParserError::Unexpected(Token::Str(value), span))
+                        }
+                    };
+                    None
+                }
+                "lt" => Some(Comparator::Lt),
+                "le" => Some(Comparator::Le),
+                "eq" => Some(Comparator::Eq),
+                "ne" => Some(Comparator::Ne),
+                "ge" => Some(Comparator::Ge),
+                "gt" => Some(Comparator::Gt),
+                "between" => Some(Comparator::Between),
+                "in" => Some(Comparator::In),
+                "any" => Some(Comparator::Any),
+                "all" => Some(Comparator::All),
+                _ => return Err(ParserError::Unexpected(Token::Ident(field), field_span)),
+            };
+            if let Some(comparator) = comparator {
+                if check.is_some() {
+                    return Err(ParserError::Unexpected(Token::Ident(field), field_span));
+                }
+                check = Some(match comparator {
+                    Comparator::Any | Comparator::All => {
+                        (comparator, Vec::new(), self.parse_constraint_list()?)
+                    }
+                    Comparator::Between | Comparator::In => {
+                        let list_span = self.tokens.get(self.pos).map(|t| t.span);
+                        let operands = self.parse_constraint_operands()?;
+                        let arity_ok = match comparator {
+                            Comparator::Between => operands.len() == 2,
+                            _ => !operands.is_empty(),
+                        };
+                        if !arity_ok {
+                            let span = list_span.unwrap_or(field_span);
+                            return Err(ParserError::Unexpected(Token::RBracket, span));
+                        }
+                        (comparator, operands, Vec::new())
+                    }
+                    _ => (
+                        comparator,
+                        vec![self.parse_constraint_operand()?],
+                        Vec::new(),
+                    ),
+                });
+            }
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+            }
+        }
+        let end = self.expect_span(Token::RBrace)?;
+        let Some((comparator, operands, group)) = check else {
+            return Err(ParserError::Unexpected(Token::RBrace, end));
+        };
+        let is_group = matches!(comparator, Comparator::Any | Comparator::All);
+        if is_group == metric.is_some() {
+            return Err(ParserError::Unexpected(Token::RBrace, end));
+        }
+        Ok(Constraint {
+            metric: metric.unwrap_or_default(),
+            comparator,
+            operands,
+            group,
+            severity,
+            span: start, // diagnostics point at the opening brace
+        })
+    }
+
+    fn parse_constraint_str(&mut self) -> Result<(String, Span), ParserError> {
+        match self.next() {
+            Some(TokenSpan {
+                token: Token::Str(value),
+                span,
+            }) => Ok((value, span)),
+            Some(TokenSpan { token, span }) => Err(ParserError::Unexpected(token, span)),
+            None => Err(ParserError::Eof(self.eof_pos)),
+        }
+    }
+
+    fn parse_constraint_operands(&mut self) -> Result<Vec<ConstraintOperand>, ParserError> {
+        self.expect(Token::LBracket)?;
+        let mut operands = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBracket)) {
+            operands.push(self.parse_constraint_operand()?);
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+            }
+        }
+        self.expect(Token::RBracket)?;
+        Ok(operands)
+    }
+
+    fn parse_constraint_operand(&mut self) -> Result<ConstraintOperand, ParserError> {
+        let negative = matches!(self.peek(), Some(Token::Minus));
+        if negative {
+            self.next();
+        }
+        let value = match self.next() {
+            Some(TokenSpan {
+                token: Token::Float(v) | Token::Int(v),
+                ..
+            }) => v.parse::<f64>().unwrap_or(0.0),
+            Some(TokenSpan {
+                token: Token::Str(path),
+                ..
+            }) if !negative => return Ok(ConstraintOperand::Ref(path)),
+            Some(TokenSpan { token, span }) => return Err(ParserError::Unexpected(token, span)),
+            None => return Err(ParserError::Eof(self.eof_pos)),
+        };
+        Ok(ConstraintOperand::Number(if negative {
+            -value
+        } else {
+            value
+        }))
+    }
+
+    fn parse_temporal_decl(&mut self) -> Result<TemporalDecl, ParserError> {
+        let start = self.expect_span(Token::LBrace)?;
+        let mut key = None;
+        let mut kind = None;
+        let mut source = None;
+        let mut per = None;
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let (field, field_span) = match self.next() {
+                Some(TokenSpan {
+                    token: Token::Ident(name),
+                    span,
+                }) => (name, span),
+                Some(TokenSpan { token, span }) => {
+                    return Err(ParserError::Unexpected(token, span))
+                }
+                None => return Err(ParserError::Eof(self.eof_pos)),
+            };
+            self.expect(Token::Colon)?;
+            let value = self.next().ok_or(ParserError::Eof(self.eof_pos))?;
+            match (field.as_str(), value.token) {
+                ("key", Token::Str(v)) => key = Some(v),
+                ("confirm", Token::Int(v)) | ("cooldown", Token::Int(v)) => {
+                    let threshold = v
+                        .parse::<i64>()
+                        .map_err(|_| ParserError::Unexpected(Token::Int(v.clone()), value.span))?;
+                    let temporal_kind = if field == "confirm" {
+                        TemporalKind::Confirm
+                    } else {
+                        TemporalKind::Cooldown
+                    };
+                    kind = Some((temporal_kind, threshold));
+                }
+                ("observe", Token::Str(v)) | ("trigger", Token::Str(v)) => {
+                    source = Some((field, v));
+                }
+                ("per", Token::Str(v)) => per = Some(v),
+                ("key" | "confirm" | "cooldown" | "observe" | "trigger" | "per", token) => {
+                    return Err(ParserError::Unexpected(token, value.span))
+                }
+                _ => return Err(ParserError::Unexpected(Token::Ident(field), field_span)),
+            }
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+            }
+        }
+        let end = self.expect_span(Token::RBrace)?;
+        let span = merge_span(start, end);
+        match (key, kind, source) {
+            (Some(key), Some((kind, threshold)), Some((source_field, source)))
+                if (kind == TemporalKind::Confirm) == (source_field == "observe") =>
+            {
+                Ok(TemporalDecl {
+                    key,
+                    kind,
+                    threshold,
+                    source,
+                    per,
+                    span,
+                })
+            }
+            _ => Err(ParserError::Unexpected(Token::RBrace, end)),
+        }
+    }
+
     fn parse_block(&mut self) -> Result<Block, ParserError> {
         let (body, _) = self.parse_block_with_span()?;
         Ok(body)
@@ -1284,9 +1758,16 @@ impl Parser {
     fn parse_type(&mut self) -> Result<Type, ParserError> {
         match self.next() {
             Some(TokenSpan {
-                token: Token::Ident(name),
+                token: Token::Ident(mut name),
                 ..
             }) => {
+                // A type exported by an imported module: `alias.Type`.
+                if let (Some(Token::Dot), Some(Token::Ident(item))) =
+                    (self.peek(), self.peek_next())
+                {
+                    name = format!("{name}.{item}");
+                    self.pos += 2;
+                }
                 if name == "Safe" {
                     self.expect(Token::Less)?;
                     let base = self.parse_type()?;
@@ -1948,6 +2429,11 @@ impl Parser {
                         span,
                     );
                 }
+                Some(Token::Question) => {
+                    let end = self.expect_span(Token::Question)?;
+                    let span = merge_span(expr.span, end);
+                    expr = Expr::new(ExprKind::Try(Box::new(expr)), span);
+                }
                 _ => break,
             }
         }
@@ -2127,6 +2613,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2140,6 +2627,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2164,6 +2652,86 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_pipeline_rich_constraints() {
+        let src = r#"
+        pipeline P {
+            input: i64,
+            constraints: [
+                { metric: "accuracy", ge: 0.9 },
+                { metric: "latency", between: [10, 200], severity: "warn" },
+                { metric: "regime", in: [1, 2, -1] },
+                { metric: "drawdown", lt: "config.max_drawdown" },
+                { any: [{ metric: "a", ne: 0 }, { all: [{ metric: "b", gt: 1 }] }] },
+            ],
+            steps: [],
+        }
+        "#;
+        let program = parse_program(src).unwrap();
+        let Item::Pipeline(pipe) = &program.items[0] else {
+            panic!("expected pipeline");
+        };
+        let c = &pipe.constraints;
+        assert_eq!(c[0].comparator, Comparator::Ge);
+        assert_eq!(c[0].operands, [ConstraintOperand::Number(0.9)]);
+        assert_eq!(c[1].comparator, Comparator::Between);
+        assert_eq!(c[1].severity, Severity::Warn);
+        assert_eq!(
+            c[1].operands,
+            [
+                ConstraintOperand::Number(10.0),
+                ConstraintOperand::Number(200.0)
+            ]
+        );
+        assert_eq!(c[2].operands[2], ConstraintOperand::Number(-1.0));
+        assert_eq!(
+            c[3].operands,
+            [ConstraintOperand::Ref("config.max_drawdown".into())]
+        );
+        assert_eq!(c[4].comparator, Comparator::Any);
+        assert_eq!(c[4].metric, "");
+        assert_eq!(c[4].group[1].group[0].metric, "b");
+
+        for invalid in [
+            r#"{ metric: "a", between: [1] }"#,
+            r#"{ metric: "a", lt: 1, gt: 2 }"#,
+            r#"{ metric: "a", any: [] }"#,
+            r#"{ metric: "a", lt: 1, severity: "fatal" }"#,
+        ] {
+            let src = format!("pipeline P {{ input: i64, constraints: [{invalid}], steps: [] }}");
+            assert!(parse_program(&src).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_pipeline_temporal_state() {
+        let src = r#"
+        pipeline Entry {
+            input: i64,
+            state: [
+                { key: "signal", confirm: 3, observe: "check.passed" },
+                { key: "stop_loss", cooldown: 5, trigger: "exit.stop_loss", per: "symbol" },
+            ],
+            steps: [],
+        }
+        "#;
+        let program = parse_program(src).unwrap();
+        let Item::Pipeline(pipe) = &program.items[0] else {
+            panic!("expected pipeline");
+        };
+        assert_eq!(pipe.temporal.len(), 2);
+        assert_eq!(pipe.temporal[0].key, "signal");
+        assert_eq!(pipe.temporal[0].kind, TemporalKind::Confirm);
+        assert_eq!(pipe.temporal[0].threshold, 3);
+        assert_eq!(pipe.temporal[1].kind, TemporalKind::Cooldown);
+        assert_eq!(pipe.temporal[1].source, "exit.stop_loss");
+        assert_eq!(pipe.temporal[0].per, None);
+        assert_eq!(pipe.temporal[1].per.as_deref(), Some("symbol"));
+
+        let mismatched = r#"pipeline P { input: i64, state: [{ key: "k", confirm: 3, trigger: "a.b" }], steps: [] }"#;
+        assert!(parse_program(mismatched).is_err());
+    }
+
     #[test]
     fn parse_complex_attributes() {
         let src = r#"
@@ -2253,12 +2821,51 @@ mod tests {
         assert_eq!(spec.python.as_deref(), Some("math.sqrt"));
         assert!(spec.effects.is_empty());
     }
+
+    #[test]
+    fn parse_extern_function_declaration() {
+        let src = "extern fn fetch_price(symbol: string): f64;\nfn main() { print(fetch_price(\"BTC\")); }";
+        let program = parse_program(src).unwrap();
+        let Item::Function(func) = &program.items[0] else {
+            panic!("expected function");
+        };
+        assert_eq!(func.name, "fetch_price");
+        assert_eq!(func.params[0].ty, Type::Ident("string".into()));
+        assert_eq!(func.return_type, Some(Type::Ident("f64".into())));
+        assert!(func.body.is_empty());
+        let spec = func.external_spec.as_ref().expect("expected external spec");
+        assert_eq!((spec.python.as_ref(), spec.rust.as_ref()), (None, None));
+
+        assert!(matches!(
+            parse_program("native fn now(): i64;"),
+            Err(ParserError::Unexpected(Token::Ident(keyword), _)) if keyword == "native"
+        ));
+        assert!(parse_program("extern fn now(): i64 { return 0; }").is_err());
+    }
+
+    #[test]
+    fn parse_external_attribute_rust_target() {
+        let src = r#"
+        @external(rust="viper::fetch_market_data", effects=[IO])
+        fn fetch(req: { symbol: string }): f64 { return 0.0; }
+        "#;
+        let program = parse_program(src).unwrap();
+        let Item::Function(func) = &program.items[0] else {
+            panic!("expected function");
+        };
+
+        let spec = func.external_spec.as_ref().expect("expected external spec");
+        assert_eq!(spec.rust.as_deref(), Some("viper::fetch_market_data"));
+        assert_eq!(spec.python, None);
+        assert_eq!(spec.effects, vec!["IO".to_string()]);
+    }
     #[test]
     fn parse_match_expression() {
         let src = "fn main() { match x { 1 => foo(), _ => bar(), }; }";
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2272,6 +2879,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2285,6 +2893,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2314,6 +2923,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2382,6 +2992,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2400,6 +3011,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2418,6 +3030,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2438,6 +3051,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2461,6 +3075,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2486,6 +3101,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2508,6 +3124,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2530,6 +3147,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2544,6 +3162,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2560,6 +3179,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2582,6 +3202,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2595,6 +3216,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2615,6 +3237,7 @@ mod tests {
         let program = parse_program(src).unwrap();
         let func = match &program.items[0] {
             Item::Trait(_) => panic!("expected function"),
+            Item::TypeAlias(_) => panic!("expected function"),
             Item::Enum(_) => panic!("expected function"),
             Item::Pipeline(_) => panic!("expected function"),
             Item::Function(func) => func,
@@ -2624,6 +3247,25 @@ mod tests {
         };
         assert!(matches!(expr.kind, ExprKind::Index { .. }));
     }
+
+    #[test]
+    fn parse_try_operator() {
+        let src = "fn main() { let x = parse(raw)?.value; }";
+        let program = parse_program(src).unwrap();
+        let Item::Function(func) = &program.items[0] else {
+            panic!("expected function");
+        };
+        let Stmt::Let { expr, .. } = &func.body[0] else {
+            panic!("expected let");
+        };
+        let ExprKind::Field { expr: inner, .. } = &expr.kind else {
+            panic!("expected field access");
+        };
+        let ExprKind::Try(call) = &inner.kind else {
+            panic!("expected try");
+        };
+        assert!(matches!(call.kind, ExprKind::Call { .. }));
+    }
     #[test]
     fn parse_program_with_type_declarations() {
         let src = r#"
@@ -2640,7 +3282,98 @@ mod tests {
         "#;
 
         let program = parse_program(src).unwrap();
-        assert_eq!(program.items.len(), 1);
-        assert!(matches!(program.items[0], Item::Pipeline(_)));
+        assert_eq!(program.items.len(), 2);
+        let Item::TypeAlias(alias) = &program.items[0] else {
+            panic!("expected type alias");
+        };
+        assert_eq!(alias.name, "MarketSignal");
+        assert_eq!(
+            alias.def,
+            TypeDef::Alias(Type::Record(vec![
+                ("symbol".into(), Type::Ident("string".into())),
+                ("current_price".into(), Type::Ident("f64".into())),
+            ]))
+        );
+        assert!(matches!(program.items[1], Item::Pipeline(_)));
+    }
+
+    #[test]
+    fn parse_generic_aliases_and_unions() {
+        let src = r#"
+            export type Window<T> = [T; 3];
+            type Signal = | Buy(f64) | Hold | Sell(f64, string);
+            type Id = string;
+        "#;
+
+        let program = parse_program(src).unwrap();
+        let aliases: Vec<&TypeAlias> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::TypeAlias(alias) => alias,
+                _ => panic!("expected type alias"),
+            })
+            .collect();
+        assert!(aliases[0].exported);
+        assert_eq!(aliases[0].generics, ["T"]);
+        assert_eq!(
+            aliases[0].def,
+            TypeDef::Alias(Type::Array {
+                elem: Box::new(Type::Ident("T".into())),
+                len: 3,
+            })
+        );
+        let union = aliases[1].as_enum().expect("union");
+        assert_eq!(union.name, "Signal");
+        let variants: Vec<(&str, usize)> = union
+            .variants
+            .iter()
+            .map(|v| (v.name.as_str(), v.args.len()))
+            .collect();
+        assert_eq!(variants, [("Buy", 1), ("Hold", 0), ("Sell", 2)]);
+        assert_eq!(aliases[2].def, TypeDef::Alias(Type::Ident("string".into())));
+        assert_eq!(
+            aliases[0].expand(&[Type::Ident("f64".into())]),
+            Some(Type::Array {
+                elem: Box::new(Type::Ident("f64".into())),
+                len: 3,
+            })
+        );
+        assert_eq!(aliases[0].expand(&[]), None);
+    }
+
+    #[test]
+    fn parse_imports_and_exports() {
+        let src = r#"
+            import "lib/quotes" as quotes;
+            import "math"
+
+            export fn mid(q: quotes.Quote): f64 { 0.0 }
+            @external(python="feeds.fetch", effects=[IO])
+            export fn fetch(symbol: string): f64 {}
+            export enum Side { Buy, Sell }
+            fn helper() {}
+        "#;
+
+        let program = parse_program(src).unwrap();
+        let imports: Vec<_> = program
+            .imports
+            .iter()
+            .map(|i| (i.path.as_str(), i.alias.as_deref()))
+            .collect();
+        assert_eq!(imports, [("lib/quotes", Some("quotes")), ("math", None)]);
+        let Item::Function(mid) = &program.items[0] else {
+            panic!("expected function");
+        };
+        assert!(mid.exported);
+        assert_eq!(mid.params[0].ty, Type::Ident("quotes.Quote".to_string()));
+        let Item::Function(fetch) = &program.items[1] else {
+            panic!("expected function");
+        };
+        assert!(fetch.exported && fetch.external_spec.is_some());
+        assert!(matches!(&program.items[2], Item::Enum(def) if def.exported));
+        assert!(matches!(&program.items[3], Item::Function(f) if !f.exported));
+
+        assert!(parse_program("export pipeline P { input: i64, steps: [] }").is_err());
     }
 }
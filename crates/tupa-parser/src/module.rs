@@ -0,0 +1,837 @@
+//! Programs spread over several `.tp` files.
+//!
+//! `load_program` parses a root file and, transitively, the files it imports
+//! with `import "path" as alias;`. Paths are relative to the importing file
+//! and `.tp` is implied. The modules are merged into one `Program` that the
+//! type checker and the code generators handle like a single file:
+//!
+//! - the root file's items keep their names;
+//! - items of an imported module are renamed `module.name`, where `module`
+//!   is the file stem (suffixed with a number when two files share it);
+//! - `alias.name`, in expressions and types, refers to an item the imported
+//!   module declares with `export`;
+//! - pipelines belong to the file declaring them and are not imported.
+//!
+//! Spans of each module are offset so that they do not overlap;
+//! `LoadedProgram::locate` maps a span of the merged program back to its
+//! file.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::{
+    parse_program_at, Block, ElseBranch, Expr, ExprKind, FieldAccess, Function, Item, ParserError,
+    Pattern, Program, Span, Stmt, Type, TypeDef,
+};
+
+/// A file of a loaded program.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// Path as reached from the root file, e.g. `examples/modules/math.tp`.
+    pub path: PathBuf,
+    pub source: String,
+    /// Where the file's spans start in the merged program.
+    pub offset: usize,
+}
+
+/// A root file merged with the modules it imports.
+#[derive(Debug, Clone)]
+pub struct LoadedProgram {
+    pub program: Program,
+    /// The root file first, then its imports in the order they were loaded.
+    pub files: Vec<SourceFile>,
+}
+
+impl LoadedProgram {
+    pub fn root(&self) -> &SourceFile {
+        &self.files[0]
+    }
+
+    /// File a span of the merged program points into, and the span within
+    /// that file.
+    pub fn locate(&self, span: Span) -> (&SourceFile, Span) {
+        let file = self
+            .files
+            .iter()
+            .rev()
+            .find(|file| file.offset <= span.start)
+            .unwrap_or(self.root());
+        let span = Span {
+            start: span.start - file.offset,
+            end: span.end.saturating_sub(file.offset),
+        };
+        (file, span)
+    }
+}
+
+/// Where a module error points.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub path: PathBuf,
+    pub source: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Error)]
+pub enum ModuleError {
+    #[error("cannot read {}: {error}", path.display())]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+        /// The import that names the file; `None` for the root file.
+        at: Option<Location>,
+    },
+    #[error("{error}")]
+    Parse { error: ParserError, at: Location },
+    #[error("import cycle: {}", display_chain(chain))]
+    Cycle { chain: Vec<PathBuf>, at: Location },
+    #[error("'{name}' is not exported by {}", module.display())]
+    NotExported {
+        name: String,
+        module: PathBuf,
+        at: Location,
+    },
+    #[error("import alias '{alias}' is already in use")]
+    DuplicateAlias { alias: String, at: Location },
+}
+
+impl ModuleError {
+    /// File and span the error points at, if any.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            ModuleError::Io { at, .. } => at.as_ref(),
+            ModuleError::Parse { at, .. }
+            | ModuleError::Cycle { at, .. }
+            | ModuleError::NotExported { at, .. }
+            | ModuleError::DuplicateAlias { at, .. } => Some(at),
+        }
+    }
+}
+
+fn display_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Loads `root` and every module it imports, directly or not.
+pub fn load_program(root: impl AsRef<Path>) -> Result<LoadedProgram, ModuleError> {
+    let mut loader = Loader::default();
+    loader.load(root.as_ref().to_path_buf(), None)?;
+    loader.merge()
+}
+
+struct Module {
+    /// Prefix of the module's item names in the merged program; empty for
+    /// the root file.
+    name: String,
+    program: Program,
+    /// Import aliases and the modules they name.
+    imports: HashMap<String, usize>,
+    /// Spans of the `import` declarations, by alias.
+    import_spans: HashMap<String, Span>,
+}
+
+#[derive(Default)]
+struct Loader {
+    modules: Vec<Module>,
+    files: Vec<SourceFile>,
+    by_path: HashMap<PathBuf, usize>,
+    /// Modules whose imports are being loaded, outermost first.
+    stack: Vec<usize>,
+    /// Modules in the order their imports finished loading, the root last.
+    order: Vec<usize>,
+    names: HashSet<String>,
+    next_offset: usize,
+}
+
+impl Loader {
+    fn load(&mut self, path: PathBuf, at: Option<Location>) -> Result<usize, ModuleError> {
+        let io_error = |error| ModuleError::Io {
+            path: path.clone(),
+            error,
+            at: at.clone(),
+        };
+        let canonical = fs::canonicalize(&path).map_err(io_error)?;
+        if let Some(&index) = self.by_path.get(&canonical) {
+            if let Some(pos) = self.stack.iter().position(|&i| i == index) {
+                let mut chain: Vec<PathBuf> = self.stack[pos..]
+                    .iter()
+                    .map(|&i| self.files[i].path.clone())
+                    .collect();
+                chain.push(path);
+                return Err(ModuleError::Cycle {
+                    chain,
+                    at: at.expect("only imports close a cycle"),
+                });
+            }
+            return Ok(index);
+        }
+
+        let source = fs::read_to_string(&path).map_err(io_error)?;
+        let offset = self.next_offset;
+        self.next_offset += source.len() + 1;
+        let program = match parse_program_at(&source, offset) {
+            Ok(program) => program,
+            Err(error) => {
+                let at = Location {
+                    path,
+                    source,
+                    span: error.span(),
+                };
+                return Err(ModuleError::Parse { error, at });
+            }
+        };
+
+        let index = self.modules.len();
+        let name = if index == 0 {
+            String::new()
+        } else {
+            self.unique_name(&path)
+        };
+        let imports = program.imports.clone();
+        self.modules.push(Module {
+            name,
+            program,
+            imports: HashMap::new(),
+            import_spans: HashMap::new(),
+        });
+        self.files.push(SourceFile {
+            path: path.clone(),
+            source,
+            offset,
+        });
+        self.by_path.insert(canonical, index);
+
+        self.stack.push(index);
+        for import in imports {
+            let at = self.location(index, import.span);
+            let alias = import
+                .alias
+                .unwrap_or_else(|| file_stem(Path::new(&import.path)));
+            if self.modules[index].imports.contains_key(&alias) {
+                return Err(ModuleError::DuplicateAlias { alias, at });
+            }
+            let target = self.load(resolve_import(&path, &import.path), Some(at))?;
+            let module = &mut self.modules[index];
+            module.imports.insert(alias.clone(), target);
+            module.import_spans.insert(alias, import.span);
+        }
+        self.stack.pop();
+        self.order.push(index);
+        Ok(index)
+    }
+
+    fn unique_name(&mut self, path: &Path) -> String {
+        let stem = file_stem(path);
+        let mut name = stem.clone();
+        let mut n = 2;
+        while !self.names.insert(name.clone()) {
+            name = format!("{stem}_{n}");
+            n += 1;
+        }
+        name
+    }
+
+    /// Location of a span of the merged program in module `index`.
+    fn location(&self, index: usize, span: Span) -> Location {
+        let file = &self.files[index];
+        Location {
+            path: file.path.clone(),
+            source: file.source.clone(),
+            span: Span {
+                start: span.start - file.offset,
+                end: span.end - file.offset,
+            },
+        }
+    }
+
+    fn merge(self) -> Result<LoadedProgram, ModuleError> {
+        let mut own = Vec::new();
+        let mut exports = Vec::new();
+        for module in &self.modules {
+            let mut names = HashMap::new();
+            let mut exported = HashMap::new();
+            for item in &module.program.items {
+                let (name, is_exported) = match item {
+                    Item::Function(f) => (&f.name, f.exported),
+                    Item::Enum(e) => (&e.name, e.exported),
+                    Item::Trait(t) => (&t.name, t.exported),
+                    Item::TypeAlias(t) => (&t.name, t.exported),
+                    Item::Pipeline(_) => continue,
+                };
+                let merged = if module.name.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{name}", module.name)
+                };
+                if is_exported {
+                    exported.insert(name.clone(), merged.clone());
+                }
+                names.insert(name.clone(), merged);
+            }
+            own.push(names);
+            exports.push(exported);
+        }
+
+        // Dependencies come first: the type checker registers enums and
+        // signatures in item order.
+        let mut items = Vec::new();
+        for &index in &self.order {
+            let module = &self.modules[index];
+            let mut resolver = Resolver {
+                loader: &self,
+                index,
+                module,
+                own: &own[index],
+                exports: &exports,
+                scopes: Vec::new(),
+            };
+            for item in &module.program.items {
+                let mut item = item.clone();
+                if index > 0 && matches!(item, Item::Pipeline(_)) {
+                    continue;
+                }
+                resolver.item(&mut item)?;
+                items.push(item);
+            }
+        }
+
+        Ok(LoadedProgram {
+            program: Program {
+                imports: Vec::new(),
+                items,
+            },
+            files: self.files,
+        })
+    }
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Path of `import "spec"` in the file at `importer`.
+fn resolve_import(importer: &Path, spec: &str) -> PathBuf {
+    let mut path = importer.parent().unwrap_or(Path::new("")).join(spec);
+    if path.extension().is_none() {
+        path.set_extension("tp");
+    }
+    path
+}
+
+/// Renames one module's items and references for the merged program.
+struct Resolver<'a> {
+    loader: &'a Loader,
+    index: usize,
+    module: &'a Module,
+    /// Top-level names of the module and their merged names.
+    own: &'a HashMap<String, String>,
+    /// Exported names of every module and their merged names.
+    exports: &'a [HashMap<String, String>],
+    /// Local bindings, which shadow top-level names.
+    scopes: Vec<HashSet<String>>,
+}
+
+impl Resolver<'_> {
+    fn item(&mut self, item: &mut Item) -> Result<(), ModuleError> {
+        match item {
+            Item::Function(func) => self.function(func),
+            Item::Enum(def) => {
+                self.rename(&mut def.name);
+                for variant in &mut def.variants {
+                    for ty in &mut variant.args {
+                        self.ty(ty)?;
+                    }
+                }
+                Ok(())
+            }
+            Item::Trait(def) => {
+                self.rename(&mut def.name);
+                for method in &mut def.methods {
+                    self.function(method)?;
+                }
+                Ok(())
+            }
+            Item::TypeAlias(alias) => {
+                self.rename(&mut alias.name);
+                match &mut alias.def {
+                    TypeDef::Alias(ty) => self.ty(ty),
+                    TypeDef::Union(def) => {
+                        def.name.clone_from(&alias.name);
+                        for variant in &mut def.variants {
+                            for ty in &mut variant.args {
+                                self.ty(ty)?;
+                            }
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            Item::Pipeline(pipeline) => {
+                self.ty(&mut pipeline.input_ty)?;
+                if let Some(ty) = &mut pipeline.output_ty {
+                    self.ty(ty)?;
+                }
+                for step in &mut pipeline.steps {
+                    self.expr(&mut step.body)?;
+                }
+                if let Some(block) = &mut pipeline.validation {
+                    self.block(block)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn function(&mut self, func: &mut Function) -> Result<(), ModuleError> {
+        self.rename(&mut func.name);
+        for param in &mut func.params {
+            self.ty(&mut param.ty)?;
+        }
+        if let Some(ty) = &mut func.return_type {
+            self.ty(ty)?;
+        }
+        self.scopes
+            .push(func.params.iter().map(|p| p.name.clone()).collect());
+        let result = self.stmts(&mut func.body);
+        self.scopes.pop();
+        result
+    }
+
+    fn rename(&self, name: &mut String) {
+        if let Some(merged) = self.own.get(name.as_str()) {
+            *name = merged.clone();
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    fn bind(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+
+    /// Merged name of `alias.name`, or `None` if `alias` is not an import.
+    fn qualified(
+        &self,
+        alias: &str,
+        name: &str,
+        span: Span,
+    ) -> Result<Option<String>, ModuleError> {
+        let Some(&target) = self.module.imports.get(alias) else {
+            return Ok(None);
+        };
+        match self.exports[target].get(name) {
+            Some(merged) => Ok(Some(merged.clone())),
+            None => Err(ModuleError::NotExported {
+                name: name.to_string(),
+                module: self.loader.files[target].path.clone(),
+                at: self.loader.location(self.index, span),
+            }),
+        }
+    }
+
+    fn type_name(&self, name: &mut String) -> Result<(), ModuleError> {
+        if let Some((alias, item)) = name.split_once('.') {
+            // Types carry no span; point at the import instead.
+            let span = self
+                .module
+                .import_spans
+                .get(alias)
+                .copied()
+                .unwrap_or(Span { start: 0, end: 0 });
+            if let Some(merged) = self.qualified(alias, item, span)? {
+                *name = merged;
+            }
+        } else {
+            self.rename(name);
+        }
+        Ok(())
+    }
+
+    fn ty(&mut self, ty: &mut Type) -> Result<(), ModuleError> {
+        match ty {
+            Type::Ident(name) => self.type_name(name),
+            Type::Generic { name, args } => {
+                self.type_name(name)?;
+                args.iter_mut().try_for_each(|arg| self.ty(arg))
+            }
+            Type::Record(fields) => fields.iter_mut().try_for_each(|(_, ty)| self.ty(ty)),
+            Type::Tuple(items) => items.iter_mut().try_for_each(|ty| self.ty(ty)),
+            Type::Safe { base, .. } => self.ty(base),
+            Type::Array { elem, .. } | Type::Slice { elem } => self.ty(elem),
+            Type::Func { params, ret } => {
+                params.iter_mut().try_for_each(|ty| self.ty(ty))?;
+                self.ty(ret)
+            }
+            Type::Tensor(_) | Type::Unit => Ok(()),
+        }
+    }
+
+    fn block(&mut self, block: &mut Block) -> Result<(), ModuleError> {
+        self.scopes.push(HashSet::new());
+        let result = self.stmts(block);
+        self.scopes.pop();
+        result
+    }
+
+    fn stmts(&mut self, stmts: &mut [Stmt]) -> Result<(), ModuleError> {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Let { name, ty, expr } => {
+                    if let Some(ty) = ty {
+                        self.ty(ty)?;
+                    }
+                    self.expr(expr)?;
+                    self.bind(name);
+                }
+                Stmt::Return(expr) => {
+                    if let Some(expr) = expr {
+                        self.expr(expr)?;
+                    }
+                }
+                Stmt::While { condition, body } => {
+                    self.expr(condition)?;
+                    self.block(body)?;
+                }
+                Stmt::For { name, iter, body } => {
+                    self.expr(iter)?;
+                    self.scopes.push(HashSet::from([name.clone()]));
+                    let result = self.block(body);
+                    self.scopes.pop();
+                    result?;
+                }
+                Stmt::Expr(expr) => self.expr(expr)?,
+                Stmt::Lambda { params, body } => self.lambda(params, body)?,
+                Stmt::Break | Stmt::Continue => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn lambda(&mut self, params: &[String], body: &mut Expr) -> Result<(), ModuleError> {
+        self.scopes.push(params.iter().cloned().collect());
+        let result = self.expr(body);
+        self.scopes.pop();
+        result
+    }
+
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), ModuleError> {
+        match &mut expr.kind {
+            ExprKind::Ident(name) => {
+                if !self.is_local(name) {
+                    self.rename(name);
+                }
+            }
+            ExprKind::Field {
+                expr: base,
+                field: FieldAccess::Ident(field),
+            } => {
+                if let ExprKind::Ident(alias) = &base.kind {
+                    if !self.is_local(alias) {
+                        if let Some(merged) = self.qualified(alias, field, expr.span)? {
+                            expr.kind = ExprKind::Ident(merged);
+                            return Ok(());
+                        }
+                    }
+                }
+                self.expr(base)?;
+            }
+            ExprKind::Field { expr, .. }
+            | ExprKind::Await(expr)
+            | ExprKind::Try(expr)
+            | ExprKind::Unary { expr, .. }
+            | ExprKind::Assign { expr, .. } => self.expr(expr)?,
+            ExprKind::Lambda { params, body } => self.lambda(params, body)?,
+            ExprKind::Tuple(items) | ExprKind::ArrayLiteral(items) => {
+                items.iter_mut().try_for_each(|item| self.expr(item))?
+            }
+            ExprKind::RecordLiteral(fields) => fields
+                .iter_mut()
+                .try_for_each(|(_, value)| self.expr(value))?,
+            ExprKind::AssignIndex { expr, index, value } => {
+                self.expr(expr)?;
+                self.expr(index)?;
+                self.expr(value)?;
+            }
+            ExprKind::Call { callee, args } => {
+                self.expr(callee)?;
+                args.iter_mut().try_for_each(|arg| self.expr(arg))?;
+            }
+            ExprKind::Index { expr, index } => {
+                self.expr(expr)?;
+                self.expr(index)?;
+            }
+            ExprKind::Block(block) => self.block(block)?,
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition)?;
+                self.block(then_branch)?;
+                match else_branch {
+                    Some(ElseBranch::Block(block)) => self.block(block)?,
+                    Some(ElseBranch::If(expr)) => self.expr(expr)?,
+                    None => {}
+                }
+            }
+            ExprKind::Match { expr, arms } => {
+                self.expr(expr)?;
+                for arm in arms {
+                    let mut bindings = HashSet::new();
+                    pattern_bindings(&arm.pattern, &mut bindings);
+                    self.scopes.push(bindings);
+                    let result = arm
+                        .guard
+                        .iter_mut()
+                        .try_for_each(|guard| self.expr(guard))
+                        .and_then(|_| self.expr(&mut arm.expr));
+                    self.scopes.pop();
+                    result?;
+                }
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left)?;
+                self.expr(right)?;
+            }
+            ExprKind::Int(_)
+            | ExprKind::Float(_)
+            | ExprKind::Str(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Null => {}
+        }
+        Ok(())
+    }
+}
+
+fn pattern_bindings(pattern: &Pattern, bindings: &mut HashSet<String>) {
+    match pattern {
+        Pattern::Ident(name) => {
+            bindings.insert(name.clone());
+        }
+        Pattern::Tuple(items) | Pattern::Constructor { args: items, .. } => {
+            for item in items {
+                pattern_bindings(item, bindings);
+            }
+        }
+        Pattern::Wildcard | Pattern::Int(_) | Pattern::Str(_) | Pattern::Bool(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` to a fresh directory and returns its path.
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tupa-modules-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn function<'a>(program: &'a Program, name: &str) -> &'a Function {
+        program
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Function(f) if f.name == name => Some(f),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no function {name}"))
+    }
+
+    #[test]
+    fn test_imports_are_merged_under_qualified_names() {
+        let dir = write_files(
+            "merge",
+            &[
+                (
+                    "main.tp",
+                    "import \"lib/quotes\" as q;\n\
+                     fn main() { let spread = 1.0; print(q.mid(spread, 2.0)); }\n\
+                     fn mid(a: f64, b: f64): f64 { a }\n",
+                ),
+                (
+                    "lib/quotes.tp",
+                    "import \"../util\";\n\
+                     export enum Side { Buy, Sell }\n\
+                     export fn mid(bid: f64, ask: f64): f64 { util.half(bid + ask) }\n\
+                     fn side(s: Side): Side { s }\n",
+                ),
+                (
+                    "util.tp",
+                    "export fn half(x: f64): f64 { let half = x / 2.0; half }\n",
+                ),
+            ],
+        );
+        let loaded = load_program(dir.join("main.tp")).unwrap();
+        let program = &loaded.program;
+
+        // Dependencies first, the root file last.
+        let names: Vec<&str> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Function(f) => f.name.as_str(),
+                Item::Enum(e) => e.name.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "util.half",
+                "quotes.Side",
+                "quotes.mid",
+                "quotes.side",
+                "main",
+                "mid"
+            ]
+        );
+        assert_eq!(
+            function(program, "quotes.side").params[0].ty,
+            Type::Ident("quotes.Side".to_string())
+        );
+        // A local named like a top-level item is left alone.
+        let Stmt::Expr(body) = &function(program, "util.half").body[1] else {
+            panic!("expected expression");
+        };
+        assert_eq!(body.kind, ExprKind::Ident("half".to_string()));
+        let Stmt::Expr(print) = &function(program, "main").body[1] else {
+            panic!("expected expression");
+        };
+        let ExprKind::Call { args, .. } = &print.kind else {
+            panic!("expected call");
+        };
+        let ExprKind::Call { callee, .. } = &args[0].kind else {
+            panic!("expected call");
+        };
+        assert_eq!(callee.kind, ExprKind::Ident("quotes.mid".to_string()));
+
+        let (file, span) = loaded.locate(callee.span);
+        assert!(file.path.ends_with("main.tp"));
+        assert_eq!(&file.source[span.start..span.end], "q.mid");
+        let (file, span) = loaded.locate(body.span);
+        assert!(file.path.ends_with("util.tp"));
+        assert_eq!(&file.source[span.start..span.end], "half");
+    }
+
+    #[test]
+    fn test_exported_type_aliases_are_qualified() {
+        let dir = write_files(
+            "aliases",
+            &[
+                (
+                    "main.tp",
+                    "import \"quotes\" as q;\n\
+                     fn main(quote: q.Quote, signal: q.Signal) {}\n",
+                ),
+                (
+                    "quotes.tp",
+                    "export type Quote { bid: f64, ask: Price }\n\
+                     export type Signal = Buy(Quote) | Hold;\n\
+                     type Price = f64;\n",
+                ),
+            ],
+        );
+        let program = load_program(dir.join("main.tp")).unwrap().program;
+
+        let Item::TypeAlias(quote) = &program.items[0] else {
+            panic!("expected type alias");
+        };
+        assert_eq!(quote.name, "quotes.Quote");
+        assert_eq!(
+            quote.def,
+            TypeDef::Alias(Type::Record(vec![
+                ("bid".to_string(), Type::Ident("f64".to_string())),
+                ("ask".to_string(), Type::Ident("quotes.Price".to_string())),
+            ]))
+        );
+        let Item::TypeAlias(signal) = &program.items[1] else {
+            panic!("expected type alias");
+        };
+        let union = signal.as_enum().unwrap();
+        assert_eq!(union.name, "quotes.Signal");
+        assert_eq!(
+            union.variants[0].args,
+            [Type::Ident("quotes.Quote".to_string())]
+        );
+        let params: Vec<&Type> = function(&program, "main")
+            .params
+            .iter()
+            .map(|p| &p.ty)
+            .collect();
+        assert_eq!(
+            params,
+            [
+                &Type::Ident("quotes.Quote".to_string()),
+                &Type::Ident("quotes.Signal".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_exported_items_are_visible() {
+        let dir = write_files(
+            "exports",
+            &[
+                ("main.tp", "import \"lib\";\nfn main() { lib.hidden(); }\n"),
+                ("lib.tp", "fn hidden() {}\n"),
+            ],
+        );
+        let err = load_program(dir.join("main.tp")).unwrap_err();
+        assert!(
+            matches!(&err, ModuleError::NotExported { name, .. } if name == "hidden"),
+            "{err}"
+        );
+        let at = err.location().unwrap();
+        assert!(at.path.ends_with("main.tp"));
+        assert_eq!(&at.source[at.span.start..at.span.end], "lib.hidden");
+    }
+
+    #[test]
+    fn test_import_cycles_are_reported() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("a.tp", "import \"b\";\nfn main() {}\n"),
+                ("b.tp", "import \"c\";\n"),
+                ("c.tp", "import \"b\";\n"),
+            ],
+        );
+        let err = load_program(dir.join("a.tp")).unwrap_err();
+        let ModuleError::Cycle { chain, at } = &err else {
+            panic!("expected a cycle, got {err}");
+        };
+        let files: Vec<_> = chain
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(files, ["b.tp", "c.tp", "b.tp"]);
+        assert!(at.path.ends_with("c.tp"));
+
+        let dir = write_files("missing", &[("a.tp", "import \"nope\";\n")]);
+        let err = load_program(dir.join("a.tp")).unwrap_err();
+        assert!(
+            matches!(&err, ModuleError::Io { path, at: Some(_), .. } if path.ends_with("nope.tp")),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Python package `tupa`: compile Tupã pipelines and run them from Python,
+//! e.g. in a notebook, with the same runtime the production host uses.
+//!
+//! ```python
+//! import tupa
+//!
+//! plan = tupa.compile(open("fraud.tp").read())
+//! plan.run({"amount": 120.0})
+//! plan.run_batch(records)
+//! ```
+
+use once_cell::sync::Lazy;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde_json::Value;
+use tupa_codegen::execution_plan::{codegen_pipeline, ExecutionPlan};
+use tupa_parser::{parse_program, Item, ParserError, Program, Span};
+use tupa_pyffi::serialize::{FromPython, ToPython};
+use tupa_runtime::Runtime;
+use tupa_typecheck::{typecheck_program_with_warnings, TypeError, Warning};
+
+create_exception!(tupa, TupaError, PyException, "Base class of Tupã errors.");
+create_exception!(
+    tupa,
+    CompileError,
+    TupaError,
+    "Source that does not parse or typecheck."
+);
+create_exception!(tupa, RunError, TupaError, "A pipeline run that failed.");
+
+/// Drives the async runtime for every plan; calls block on it with the GIL
+/// released, so Python steps can run on its worker threads.
+static EXECUTOR: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start the Tupã runtime")
+});
+
+/// One problem found in a source file.
+struct Diagnostic {
+    severity: &'static str,
+    code: Option<&'static str>,
+    message: String,
+    span: Option<Span>,
+}
+
+impl Diagnostic {
+    fn parse(error: &ParserError) -> Self {
+        let message = match error {
+            ParserError::Unexpected(token, _) => format!("unexpected token {token:?}"),
+            _ => error.to_string(),
+        };
+        Self {
+            severity: "error",
+            code: None,
+            message,
+            span: Some(error.span()),
+        }
+    }
+
+    fn typecheck(error: &TypeError) -> Self {
+        Self {
+            severity: "error",
+            code: Some(error.code()),
+            message: error.to_string(),
+            span: error.span(),
+        }
+    }
+
+    fn warning(warning: &Warning) -> Self {
+        let message = match warning {
+            Warning::UnusedVar(name) => format!("unused variable '{name}'"),
+        };
+        Self {
+            severity: "warning",
+            code: None,
+            message,
+            span: None,
+        }
+    }
+
+    /// `line:col: message` for exception text.
+    fn render(&self, source: &str) -> String {
+        match self.span {
+            Some(span) => {
+                let (line, col) = line_col(source, span.start);
+                format!("{line}:{col}: {}", self.message)
+            }
+            None => self.message.clone(),
+        }
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("severity", self.severity)?;
+        dict.set_item("code", self.code)?;
+        dict.set_item("message", &self.message)?;
+        let position = self.span.map(|span| line_col(source, span.start));
+        dict.set_item("line", position.map(|(line, _)| line))?;
+        dict.set_item("col", position.map(|(_, col)| col))?;
+        dict.set_item("span", self.span.map(|span| (span.start, span.end)))?;
+        Ok(dict)
+    }
+}
+
+/// 1-based line and column of byte offset `pos`.
+fn line_col(source: &str, pos: usize) -> (usize, usize) {
+    let before = &source[..pos.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+    (line, col)
+}
+
+fn parse(source: &str) -> PyResult<Program> {
+    parse_program(source).map_err(|e| CompileError::new_err(Diagnostic::parse(&e).render(source)))
+}
+
+fn to_value(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    Value::from_python(obj)
+}
+
+/// Parses, typechecks and compiles `pipeline` (default: the first one) of
+/// `source` into a runnable `Plan`.
+#[pyfunction]
+#[pyo3(signature = (source, pipeline = None))]
+fn compile(source: &str, pipeline: Option<&str>) -> PyResult<Plan> {
+    let program = parse(source)?;
+    typecheck_program_with_warnings(&program)
+        .map_err(|e| CompileError::new_err(Diagnostic::typecheck(&e).render(source)))?;
+    let target = program
+        .items
+        .iter()
+        .find_map(|item| match item {
+            Item::Pipeline(p) if pipeline.is_none_or(|name| p.name == name) => Some(p),
+            _ => None,
+        })
+        .ok_or_else(|| match pipeline {
+            Some(name) => CompileError::new_err(format!("Pipeline '{name}' not found")),
+            None => CompileError::new_err("No pipeline found in source"),
+        })?;
+    let plan_json = codegen_pipeline("main", target, &program)
+        .map_err(|e| CompileError::new_err(e.to_string()))?;
+    Plan::from_json(&plan_json)
+}
+
+/// Diagnostics for `source`: a list of dicts with `severity` (`error` or
+/// `warning`), `code`, `message`, 1-based `line`/`col` and byte `span`.
+/// An empty list means the source compiles cleanly.
+#[pyfunction]
+fn check<'py>(py: Python<'py>, source: &str) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    let diagnostics = match parse_program(source) {
+        Err(e) => vec![Diagnostic::parse(&e)],
+        Ok(program) => match typecheck_program_with_warnings(&program) {
+            Ok(warnings) => warnings.iter().map(Diagnostic::warning).collect(),
+            Err(e) => vec![Diagnostic::typecheck(&e)],
+        },
+    };
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.to_dict(py, source))
+        .collect()
+}
+
+/// SHA3-256 fingerprint of `source`'s AST, as printed by `tupa audit`.
+#[pyfunction]
+fn hash_ast(source: &str) -> PyResult<String> {
+    Ok(tupa_audit::hash_ast(&parse(source)?).to_string())
+}
+
+/// A compiled pipeline and the runtime that executes it.
+#[pyclass(module = "tupa")]
+struct Plan {
+    plan: ExecutionPlan,
+    runtime: Runtime,
+}
+
+impl Plan {
+    fn execute(&self, py: Python<'_>, inputs: Vec<Value>) -> Vec<Result<Value, String>> {
+        py.allow_threads(|| {
+            EXECUTOR.block_on(futures::future::join_all(inputs.into_iter().map(
+                |input| async {
+                    self.runtime
+                        .run_pipeline_async(&self.plan, input)
+                        .await
+                        .map_err(|e| e.to_string())
+                },
+            )))
+        })
+    }
+}
+
+#[pymethods]
+impl Plan {
+    /// Loads a plan produced by `to_json` or `tupa codegen --plan-only`.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let plan = serde_json::from_str(json)
+            .map_err(|e| CompileError::new_err(format!("Invalid plan JSON: {e}")))?;
+        Ok(Self {
+            plan,
+            runtime: Runtime::new(),
+        })
+    }
+
+    /// The plan as JSON, runnable by `tupa run --plan` and Rust hosts.
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string_pretty(&self.plan).map_err(|e| TupaError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.plan.name
+    }
+
+    /// Step names, in execution order.
+    #[getter]
+    fn steps(&self) -> Vec<String> {
+        self.plan
+            .steps
+            .iter()
+            .map(|step| step.name.clone())
+            .collect()
+    }
+
+    /// Implements `step` with a Python callable taking the pipeline state
+    /// and returning the step's output. External steps call their
+    /// `@external(python=...)` function without registration.
+    fn register(&self, step: &str, func: PyObject) -> PyResult<()> {
+        let function_ref = self
+            .plan
+            .steps
+            .iter()
+            .find(|candidate| candidate.name == step)
+            .map(|candidate| candidate.function_ref.clone())
+            .ok_or_else(|| TupaError::new_err(format!("Plan has no step '{step}'")))?;
+        self.runtime.register_step(&function_ref, move |input| {
+            Python::with_gil(|py| {
+                let output = func.call1(py, (input.to_python(py)?,))?;
+                to_value(output.bind(py))
+            })
+            .map_err(|e| e.to_string())
+        });
+        Ok(())
+    }
+
+    /// Runs the pipeline on one input (a dict, list or scalar matching the
+    /// pipeline's input type) and returns the final state.
+    fn run(&self, py: Python<'_>, input: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let input = to_value(input)?;
+        match self.execute(py, vec![input]).remove(0) {
+            Ok(output) => output.to_python(py),
+            Err(e) => Err(RunError::new_err(e)),
+        }
+    }
+
+    /// Runs the pipeline on every input concurrently and returns the final
+    /// states in order. A failed run raises `RunError` naming its index, or
+    /// with `return_exceptions=True` takes its place in the list.
+    #[pyo3(signature = (inputs, return_exceptions = false))]
+    fn run_batch(
+        &self,
+        py: Python<'_>,
+        inputs: Vec<Bound<'_, PyAny>>,
+        return_exceptions: bool,
+    ) -> PyResult<Vec<PyObject>> {
+        let inputs = inputs.iter().map(to_value).collect::<PyResult<Vec<_>>>()?;
+        self.execute(py, inputs)
+            .into_iter()
+            .enumerate()
+            .map(|(index, result)| match result {
+                Ok(output) => output.to_python(py),
+                Err(e) if return_exceptions => Ok(RunError::new_err(e).into_value(py).into_py(py)),
+                Err(e) => Err(RunError::new_err(format!("input {index}: {e}"))),
+            })
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<tupa.Plan {} steps=[{}]>",
+            self.plan.name,
+            self.steps().join(", ")
+        )
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "tupa")]
+fn tupa_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_function(wrap_pyfunction!(check, m)?)?;
+    m.add_function(wrap_pyfunction!(hash_ast, m)?)?;
+    m.add_class::<Plan>()?;
+    m.add("TupaError", py.get_type_bound::<TupaError>())?;
+    m.add("CompileError", py.get_type_bound::<CompileError>())?;
+    m.add("RunError", py.get_type_bound::<RunError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::wrap_pymodule;
+
+    const SOURCE: &str = r#"
+@external(python="tupa_py_scoring.double", effects=[])
+fn double(tx: { x: f64 }): f64 { return 0.0; }
+
+pipeline Scoring {
+    input: { x: f64 },
+    steps: [ step("doubled") { double(input) }, step("half") { input.x / 2.0 } ]
+}
+"#;
+
+    #[test]
+    fn test_module_compiles_checks_and_runs_pipelines() {
+        Python::with_gil(|py| {
+            let modules = py.import_bound("sys").unwrap().getattr("modules").unwrap();
+            modules
+                .set_item("tupa", wrap_pymodule!(tupa_module)(py))
+                .unwrap();
+            let scoring = PyModule::from_code_bound(
+                py,
+                "def double(state):\n    return state['x'] * 2\n",
+                "tupa_py_scoring.py",
+                "tupa_py_scoring",
+            )
+            .unwrap();
+            modules.set_item("tupa_py_scoring", scoring).unwrap();
+
+            let globals = PyDict::new_bound(py);
+            globals.set_item("SOURCE", SOURCE).unwrap();
+            let program = parse_program(SOURCE).unwrap();
+            globals
+                .set_item("AST_HASH", tupa_audit::hash_ast(&program).to_string())
+                .unwrap();
+            let script = r#"
+import tupa
+
+plan = tupa.compile(SOURCE)
+assert plan.name == "Scoring" and plan.steps == ["doubled", "half"], plan
+plan.register("half", lambda state: state["x"] / 2)
+out = plan.run({"x": 3.0})
+assert out["doubled"] == 6.0 and out["half"] == 1.5, out
+
+outs = plan.run_batch([{"x": 1.0}, {"x": 2.0}])
+assert [o["doubled"] for o in outs] == [2.0, 4.0], outs
+mixed = plan.run_batch([{"x": 1.0}, {"y": 1}], return_exceptions=True)
+assert isinstance(mixed[1], tupa.RunError), mixed
+try:
+    plan.run_batch([{"y": 1}])
+    raise AssertionError("expected RunError")
+except tupa.RunError as e:
+    assert str(e).startswith("input 0: "), e
+
+assert tupa.Plan.from_json(plan.to_json()).steps == plan.steps
+assert [d for d in tupa.check(SOURCE) if d["severity"] == "error"] == []
+[error] = tupa.check("fn main() {\n  let x: i64 = true;\n}")
+assert error["code"] == "E2001" and error["line"] == 2, error
+assert tupa.hash_ast(SOURCE) == AST_HASH
+try:
+    tupa.compile("fn main() {\n  let x: i64 = true;\n}")
+    raise AssertionError("expected CompileError")
+except tupa.CompileError as e:
+    assert str(e).startswith("2:"), e
+"#;
+            if let Err(e) = py.run_bound(script, Some(&globals), None) {
+                panic!("{e}");
+            }
+        });
+    }
+}
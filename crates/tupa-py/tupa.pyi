@@ -0,0 +1,23 @@
+from typing import Any, Callable, Optional
+
+__version__: str
+
+class TupaError(Exception): ...
+class CompileError(TupaError): ...
+class RunError(TupaError): ...
+
+class Plan:
+    @property
+    def name(self) -> str: ...
+    @property
+    def steps(self) -> list[str]: ...
+    @staticmethod
+    def from_json(json: str) -> Plan: ...
+    def to_json(self) -> str: ...
+    def register(self, step: str, func: Callable[[Any], Any]) -> None: ...
+    def run(self, input: Any) -> Any: ...
+    def run_batch(self, inputs: list[Any], return_exceptions: bool = False) -> list[Any]: ...
+
+def compile(source: str, pipeline: Optional[str] = None) -> Plan: ...
+def check(source: str) -> list[dict[str, Any]]: ...
+def hash_ast(source: str) -> str: ...
@@ -0,0 +1,241 @@
+//! Enforcement of the effects declared by `@external` functions.
+//!
+//! While a guarded call runs, two hooks watch the calling thread:
+//! - a CPython audit hook (`sys.addaudithook`) classifies file, process and
+//!   network events (`open`, `subprocess.Popen`, `socket.connect`, ...);
+//! - a profile hook catches calls to clock and RNG C functions
+//!   (`time.time`, `datetime.now`, `random.random`, `os.urandom`, ...),
+//!   which raise no audit events.
+//!
+//! An event whose effect is not declared raises `PermissionError` inside
+//! Python, blocking the operation, and is reported as a violation even if
+//! the function catches the exception. Reading Python sources while
+//! importing is not I/O. Threads started by the function are not watched.
+
+use std::collections::BTreeSet;
+
+use pyo3::prelude::*;
+use pyo3::types::{PyList, PyModule, PySet};
+
+/// Categories allowed by the declared Tupã effect names (`io`, `time`,
+/// `random`, `external:<name>`); any external call permits network access.
+pub fn allowed_categories(effects: &[String]) -> BTreeSet<&'static str> {
+    effects
+        .iter()
+        .filter_map(|effect| match effect.as_str() {
+            "io" => Some("io"),
+            "time" => Some("time"),
+            "random" => Some("random"),
+            other if other.starts_with("external:") => Some("network"),
+            _ => None,
+        })
+        .collect()
+}
+
+/// An operation the function performed outside its declared effects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Category: `io`, `network`, `time` or `random`.
+    pub effect: String,
+    /// Audit event or C function, e.g. `socket.connect` or `time.time`.
+    pub event: String,
+}
+
+pub(crate) const GUARD_MODULE: &str = r#"
+import _random, datetime, os, sys, threading, time
+
+_local = threading.local()
+
+_SOURCE_SUFFIXES = (".py", ".pyc", ".so", ".pyd", ".pth")
+_IO_EVENTS = {
+    "subprocess.Popen", "os.system", "os.exec", "os.posix_spawn", "os.spawn",
+    "os.fork", "os.forkpty", "os.kill", "os.remove", "os.rename", "os.mkdir",
+    "os.rmdir", "os.truncate", "os.chmod", "os.chown", "os.link", "os.symlink",
+    "os.utime", "os.putenv", "os.unsetenv", "shutil.copyfile", "shutil.copymode",
+    "shutil.copystat", "shutil.copytree", "shutil.move", "shutil.rmtree",
+}
+_NETWORK_PREFIXES = (
+    "socket.", "urllib.", "http.client.", "ftplib.", "smtplib.", "poplib.",
+    "imaplib.", "nntplib.", "telnetlib.",
+)
+_TIME_FUNCS = {
+    id(f): f"time.{f.__name__}"
+    for f in (
+        time.time, time.time_ns, time.monotonic, time.monotonic_ns,
+        time.perf_counter, time.perf_counter_ns, time.process_time,
+        time.process_time_ns, time.thread_time, time.thread_time_ns,
+        time.localtime, time.gmtime, time.ctime, time.sleep,
+    )
+}
+_RANDOM_FUNCS = {
+    id(f): f"os.{f.__name__}"
+    for f in (os.urandom, getattr(os, "getrandom", os.urandom))
+}
+
+def _violate(effect, event):
+    if effect in _local.allowed:
+        return
+    _local.violations.append((effect, event))
+    raise PermissionError(f"undeclared effect {effect}: {event}")
+
+def _audit_effect(event, args):
+    if event == "open":
+        path, mode, flags = args
+        if mode is None:
+            reading = not flags & (os.O_WRONLY | os.O_RDWR | os.O_CREAT)
+        else:
+            reading = "r" in mode and "+" not in mode
+        if reading and isinstance(path, str) and path.endswith(_SOURCE_SUFFIXES):
+            return None
+        return "io"
+    if event in _IO_EVENTS:
+        return "io"
+    if event.startswith(_NETWORK_PREFIXES):
+        return "network"
+    return None
+
+def _audit(event, args):
+    if getattr(_local, "allowed", None) is None:
+        return
+    effect = _audit_effect(event, args)
+    if effect is not None:
+        _violate(effect, event)
+
+def _c_call_effect(func):
+    if id(func) in _TIME_FUNCS:
+        return "time", _TIME_FUNCS[id(func)]
+    if id(func) in _RANDOM_FUNCS:
+        return "random", _RANDOM_FUNCS[id(func)]
+    owner = getattr(func, "__self__", None)
+    if isinstance(owner, _random.Random):
+        return "random", f"random.{func.__name__}"
+    if isinstance(owner, type) and issubclass(owner, datetime.date):
+        if func.__name__ in ("now", "utcnow", "today"):
+            return "time", f"datetime.{owner.__name__}.{func.__name__}"
+    return None
+
+def _profile(frame, event, arg):
+    if event == "c_call":
+        found = _c_call_effect(arg)
+        if found is not None:
+            _violate(*found)
+
+def invoke(func, arg):
+    return func(arg)
+
+def enter(allowed):
+    _local.allowed = frozenset(allowed)
+    _local.violations = []
+    sys.setprofile(_profile)
+
+def leave():
+    sys.setprofile(None)
+    _local.allowed = None
+    return _local.violations
+
+sys.addaudithook(_audit)
+"#;
+
+/// The guard module, created (and its audit hook installed) on first use.
+fn guard_module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    let modules = py.import_bound("sys")?.getattr("modules")?;
+    if let Ok(module) = modules.get_item("_tupa_effects") {
+        return Ok(module.downcast_into::<PyModule>()?);
+    }
+    let module = PyModule::from_code_bound(py, GUARD_MODULE, "_tupa_effects.py", "_tupa_effects")?;
+    modules.set_item("_tupa_effects", &module)?;
+    Ok(module)
+}
+
+/// Calls `func(arg)` from a Python frame, so the profile hook also sees an
+/// external that is itself a C function, such as `time.time`.
+pub fn invoke<'py>(
+    py: Python<'py>,
+    func: &Bound<'py, PyAny>,
+    arg: PyObject,
+) -> PyResult<Bound<'py, PyAny>> {
+    guard_module(py)?.getattr("invoke")?.call1((func, arg))
+}
+
+/// Runs `f` with only the `allowed` categories permitted on this thread and
+/// returns its result with the violations it attempted.
+pub fn guarded<'py, T>(
+    py: Python<'py>,
+    allowed: &BTreeSet<&'static str>,
+    f: impl FnOnce() -> PyResult<T>,
+) -> PyResult<(PyResult<T>, Vec<Violation>)> {
+    let guard = guard_module(py)?;
+    let allowed = PySet::new_bound(py, allowed.iter())?;
+    guard.getattr("enter")?.call1((allowed,))?;
+    let result = f();
+    let violations = guard.getattr("leave")?.call0()?;
+    let violations = violations
+        .downcast::<PyList>()?
+        .iter()
+        .map(|item| {
+            let (effect, event): (String, String) = item.extract()?;
+            Ok(Violation { effect, event })
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok((result, violations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(code: &str, effects: &[&str]) -> (PyResult<()>, Vec<Violation>) {
+        crate::PythonBridge::new();
+        let effects: Vec<String> = effects.iter().map(|e| e.to_string()).collect();
+        Python::with_gil(|py| {
+            guarded(py, &allowed_categories(&effects), || {
+                py.run_bound(code, None, None)
+            })
+            .unwrap()
+        })
+    }
+
+    #[test]
+    fn test_undeclared_effects_are_blocked() {
+        let (result, violations) = run("import time\ntime.time()", &[]);
+        assert!(result.is_err());
+        assert_eq!(
+            violations,
+            [Violation {
+                effect: "time".into(),
+                event: "time.time".into()
+            }]
+        );
+
+        let (result, violations) = run(
+            "import random\ntry:\n    random.random()\nexcept PermissionError:\n    pass",
+            &[],
+        );
+        assert!(result.is_ok());
+        assert_eq!(violations[0].effect, "random");
+
+        let (_, violations) = run("open('/tmp/tupa-effects-test', 'w')", &["time"]);
+        assert_eq!(violations[0].event, "open");
+        assert!(!std::path::Path::new("/tmp/tupa-effects-test").exists());
+
+        let (_, violations) = run(
+            "import socket\nsocket.create_connection(('127.0.0.1', 9))",
+            &["io"],
+        );
+        assert_eq!(violations[0].effect, "network");
+    }
+
+    #[test]
+    fn test_declared_effects_are_allowed() {
+        let (result, violations) = run(
+            "import time, random, os.path\ntime.time(); random.random(); os.path.basename('/a/b')",
+            &["time", "random"],
+        );
+        assert!(result.is_ok());
+        assert!(violations.is_empty());
+        assert_eq!(
+            allowed_categories(&["external:Bybit".into(), "io".into()]),
+            BTreeSet::from(["io", "network"])
+        );
+    }
+}
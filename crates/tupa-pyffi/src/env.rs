@@ -0,0 +1,280 @@
+//! Python environment of the bridge: which packages are importable, which
+//! modules load up front, and a report of the versions in use.
+//!
+//! The in-process interpreter is the libpython `tupa` was built against. A
+//! virtualenv or another interpreter contributes its `site-packages`, and
+//! must therefore run the same Python minor version; worker pools start
+//! that interpreter directly.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use once_cell::sync::Lazy;
+use pyo3::prelude::*;
+use serde_json::{json, Value};
+use std::sync::Mutex;
+
+use crate::serialize::FromPython;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PythonEnv {
+    /// Virtualenv whose packages are importable.
+    pub venv: Option<PathBuf>,
+    /// Interpreter whose packages are importable, overriding the
+    /// virtualenv's.
+    pub interpreter: Option<PathBuf>,
+    /// Entries prepended to `sys.path`, in order.
+    pub paths: Vec<PathBuf>,
+    /// Modules imported when the environment is applied, so a missing
+    /// package fails before the first step and slow imports run once.
+    pub preload: Vec<String>,
+}
+
+impl PythonEnv {
+    /// Reads `TUPA_PYTHON_VENV` (or an activated `VIRTUAL_ENV`),
+    /// `TUPA_PYTHON`, `TUPA_PYTHON_PATH` (separated like `PATH`) and
+    /// `TUPA_PYTHON_PRELOAD` (comma-separated).
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+        Self {
+            venv: var("TUPA_PYTHON_VENV")
+                .or_else(|| var("VIRTUAL_ENV"))
+                .map(PathBuf::from),
+            interpreter: var("TUPA_PYTHON").map(PathBuf::from),
+            paths: var("TUPA_PYTHON_PATH")
+                .map(|paths| std::env::split_paths(&paths).collect())
+                .unwrap_or_default(),
+            preload: var("TUPA_PYTHON_PRELOAD")
+                .map(|modules| {
+                    modules
+                        .to_string_lossy()
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|module| !module.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Resolves relative `paths` against `base`, e.g. the directory of the
+    /// `.tp` file being run.
+    pub fn paths_relative_to(mut self, base: &Path) -> Self {
+        for path in &mut self.paths {
+            if path.is_relative() {
+                *path = base.join(&*path);
+            }
+        }
+        self
+    }
+
+    /// The configured interpreter, or the virtualenv's.
+    pub fn python(&self) -> Option<PathBuf> {
+        if let Some(interpreter) = &self.interpreter {
+            return Some(interpreter.clone());
+        }
+        self.venv.as_ref().map(|venv| {
+            if cfg!(windows) {
+                venv.join("Scripts").join("python.exe")
+            } else {
+                venv.join("bin").join("python")
+            }
+        })
+    }
+}
+
+/// Environment applied by `configure_python`, and the report built from it.
+static CONFIGURED: Lazy<Mutex<(PythonEnv, Option<Value>)>> =
+    Lazy::new(|| Mutex::new((PythonEnv::default(), None)));
+
+/// Applies `env` to the in-process interpreter: adds the interpreter's or
+/// virtualenv's `site-packages`, prepends `paths` to `sys.path` and imports
+/// the `preload` modules.
+pub fn configure_python(env: &PythonEnv) -> Result<(), String> {
+    let site_dirs = match env.python() {
+        Some(python) => probe_site_dirs(&python)?,
+        None => Vec::new(),
+    };
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| -> PyResult<()> {
+        crate::explain::module(py)?;
+        let site = py.import_bound("site")?;
+        for dir in &site_dirs {
+            site.call_method1("addsitedir", (dir,))?;
+        }
+        let sys_path = py.import_bound("sys")?.getattr("path")?;
+        for path in env.paths.iter().rev() {
+            let path = path.to_string_lossy();
+            if !sys_path.contains(path.as_ref())? {
+                sys_path.call_method1("insert", (0, path.as_ref()))?;
+            }
+        }
+        Ok(())
+    })
+    .map_err(|e| e.to_string())?;
+    for module in &env.preload {
+        Python::with_gil(|py| py.import_bound(module.as_str()).map(|_| ()))
+            .map_err(|e| format!("Failed to preload Python module '{module}': {e}"))?;
+    }
+    let mut configured = CONFIGURED.lock().map_err(|e| e.to_string())?;
+    *configured = (env.clone(), None);
+    Ok(())
+}
+
+const PROBE: &str = "import json, site, sys; \
+print(json.dumps({'version': '%d.%d' % sys.version_info[:2], 'site': site.getsitepackages()}))";
+
+/// `site-packages` directories of `python`, which must run the embedded
+/// interpreter's minor version.
+fn probe_site_dirs(python: &Path) -> Result<Vec<String>, String> {
+    let output = Command::new(python)
+        .args(["-c", PROBE])
+        .output()
+        .map_err(|e| format!("Failed to run Python interpreter {}: {e}", python.display()))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Python interpreter {} failed: {}",
+            python.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let probe: Value = serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())?;
+    let version = probe["version"].as_str().unwrap_or_default();
+    let embedded = embedded_version();
+    if version != embedded {
+        return Err(format!(
+            "Python interpreter {} is {version}, but tupa embeds Python {embedded}; \
+             use a worker pool or rebuild with PYO3_PYTHON={}",
+            python.display(),
+            python.display()
+        ));
+    }
+    Ok(probe["site"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|dir| dir.as_str().map(str::to_string))
+        .collect())
+}
+
+fn embedded_version() -> String {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let version = py.version_info();
+        format!("{}.{}", version.major, version.minor)
+    })
+}
+
+pub(crate) const REPORT: &str = r#"
+import importlib.metadata, platform
+
+def report():
+    packages = {}
+    for dist in importlib.metadata.distributions():
+        name = dist.metadata["Name"]
+        if name and name not in packages:
+            packages[name] = dist.version
+    return {
+        "implementation": platform.python_implementation(),
+        "version": platform.python_version(),
+        "packages": packages,
+    }
+"#;
+
+/// Implementation, version and installed packages (`name -> version`, the
+/// first found on `sys.path`) of the interpreter that runs Python calls: the
+/// worker pool's when one is in use, the in-process one otherwise. Machine
+/// paths are left out, so equal environments report equally; the report is
+/// built once per configuration.
+pub fn python_report() -> Result<Value, String> {
+    if let Some(pool) = crate::worker_pool() {
+        return pool.report();
+    }
+    let mut configured = CONFIGURED.lock().map_err(|e| e.to_string())?;
+    if let Some(report) = &configured.1 {
+        return Ok(report.clone());
+    }
+    pyo3::prepare_freethreaded_python();
+    let report = Python::with_gil(|py| -> PyResult<Value> {
+        let module = PyModule::from_code_bound(py, REPORT, "_tupa_env.py", "_tupa_env")?;
+        let report = module.getattr("report")?.call0()?;
+        Value::from_python(&report)
+    })
+    .map_err(|e| e.to_string())?;
+    let report = json!({
+        "implementation": report["implementation"],
+        "version": report["version"],
+        "packages": report["packages"],
+        "preload": configured.0.preload,
+    });
+    configured.1 = Some(report.clone());
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tupa-env-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(format!("{name}.py")),
+            "def double(x):\n    return x * 2\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_configure_adds_paths_and_preloads_modules() {
+        let dir = module_dir("tupa_env_probe");
+        let env = PythonEnv {
+            interpreter: Some("python3".into()),
+            paths: vec![
+                "tupa-env-probe-missing".into(),
+                dir.file_name().unwrap().into(),
+            ],
+            preload: vec!["tupa_env_probe".into()],
+            ..PythonEnv::default()
+        }
+        .paths_relative_to(dir.parent().unwrap());
+        configure_python(&env).unwrap();
+        assert_eq!(
+            crate::call_python_path("tupa_env_probe.double", json!(4)),
+            Ok(json!(8))
+        );
+        let report = python_report().unwrap();
+        assert_eq!(report["implementation"], "CPython");
+        assert!(report["version"].as_str().unwrap().starts_with("3."));
+        assert_eq!(report["preload"], json!(["tupa_env_probe"]));
+        assert!(report["packages"].is_object());
+    }
+
+    #[test]
+    fn test_configure_reports_bad_interpreters_and_modules() {
+        let err = configure_python(&PythonEnv {
+            venv: Some("/nonexistent/venv".into()),
+            ..PythonEnv::default()
+        })
+        .unwrap_err();
+        assert!(
+            err.starts_with("Failed to run Python interpreter /nonexistent/venv/bin/python"),
+            "{err}"
+        );
+        let err = configure_python(&PythonEnv {
+            preload: vec!["no_such_module_for_tupa".into()],
+            ..PythonEnv::default()
+        })
+        .unwrap_err();
+        assert!(
+            err.starts_with("Failed to preload Python module 'no_such_module_for_tupa'"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Explanation events reported by Python steps.
+//!
+//! Python code imports `tupa_explain` and calls `branch(label, taken)`,
+//! `bind(name, value)` and `guard(name, passed, reason)` while it runs. The
+//! calls are recorded only inside `collect`, in-process or in a worker, and
+//! do nothing otherwise. Events are JSON objects tagged by `kind`
+//! (`branch`, `binding`, `guard`), the shape of the runtime's explanation
+//! events.
+
+use std::cell::RefCell;
+
+use pyo3::prelude::*;
+use pyo3::types::PyModule;
+use serde_json::Value;
+
+use crate::serialize::FromPython;
+
+/// Source of the `tupa_explain` module, installed in-process and in every
+/// worker before user modules are imported.
+pub(crate) const EXPLAIN_MODULE: &str = r#"
+import threading
+
+_local = threading.local()
+
+def _plain(value):
+    if hasattr(value, "tolist"):
+        return value.tolist()
+    if value is None or isinstance(value, (bool, int, float, str, list, dict)):
+        return value
+    return repr(value)
+
+def _record(event):
+    events = getattr(_local, "events", None)
+    if events is not None:
+        events.append(event)
+
+def branch(label, taken):
+    """Records that the `if`/`match` labelled `label` took the arm `taken`."""
+    _record({"kind": "branch", "label": str(label), "taken": str(taken)})
+
+def bind(name, value):
+    """Records the value bound to `name`."""
+    _record({"kind": "binding", "name": str(name), "value": _plain(value)})
+
+def guard(name, passed, reason=""):
+    """Records the outcome of the check `name`."""
+    _record({"kind": "guard", "name": str(name), "passed": bool(passed), "reason": str(reason)})
+
+def start():
+    _local.events = []
+
+def finish():
+    events = getattr(_local, "events", None) or []
+    _local.events = None
+    return events
+"#;
+
+thread_local! {
+    static EVENTS: RefCell<Option<Vec<Value>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` and returns the events the Python calls it made reported.
+pub fn collect<R>(f: impl FnOnce() -> R) -> (R, Vec<Value>) {
+    let outer = EVENTS.with(|events| events.replace(Some(Vec::new())));
+    let result = f();
+    let events = EVENTS.with(|events| events.replace(outer));
+    (result, events.unwrap_or_default())
+}
+
+/// Whether Python calls on this thread should report their events.
+pub(crate) fn collecting() -> bool {
+    EVENTS.with(|events| events.borrow().is_some())
+}
+
+pub(crate) fn extend(reported: Vec<Value>) {
+    EVENTS.with(|events| {
+        if let Some(events) = events.borrow_mut().as_mut() {
+            events.extend(reported);
+        }
+    });
+}
+
+/// Installs `tupa_explain` in `sys.modules` of the in-process interpreter.
+pub(crate) fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    let modules = py.import_bound("sys")?.getattr("modules")?;
+    if let Ok(module) = modules.get_item("tupa_explain") {
+        return Ok(module.downcast_into::<PyModule>()?);
+    }
+    let module = PyModule::from_code_bound(py, EXPLAIN_MODULE, "tupa_explain.py", "tupa_explain")?;
+    modules.set_item("tupa_explain", &module)?;
+    Ok(module)
+}
+
+/// Runs the in-process call `f`, recording its events when collecting.
+pub(crate) fn reported<'py, T>(py: Python<'py>, f: impl FnOnce() -> T) -> PyResult<T> {
+    if !collecting() {
+        return Ok(f());
+    }
+    let module = module(py)?;
+    module.getattr("start")?.call0()?;
+    let result = f();
+    let events = Value::from_python(&module.getattr("finish")?.call0()?)?;
+    extend(events.as_array().cloned().unwrap_or_default());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Signature, BRIDGE};
+    use serde_json::json;
+
+    const PROBE: &str = "import tupa_explain
+
+def signal(price):
+    tupa_explain.bind('price', price)
+    tupa_explain.branch('price > 100', 'then' if price > 100 else 'else')
+    tupa_explain.guard('spread', True, 'spread_ok')
+    return price > 100
+";
+
+    #[test]
+    fn test_python_steps_report_events_only_while_collecting() {
+        let mut bridge = BRIDGE.lock().unwrap();
+        Python::with_gil(|py| -> PyResult<()> {
+            module(py)?;
+            let probe = PyModule::from_code_bound(
+                py,
+                PROBE,
+                "tupa_explain_probe.py",
+                "tupa_explain_probe",
+            )?;
+            py.import_bound("sys")?
+                .getattr("modules")?
+                .set_item("tupa_explain_probe", probe)
+        })
+        .unwrap();
+        bridge.resolve("tupa_explain_probe.signal").unwrap();
+
+        let signature = Signature::default();
+        let (result, events) = collect(|| {
+            bridge.call_path_typed("tupa_explain_probe.signal", json!(101.0), &signature)
+        });
+        assert_eq!(result, Ok(json!(true)));
+        assert_eq!(
+            events,
+            [
+                json!({ "kind": "binding", "name": "price", "value": 101.0 }),
+                json!({ "kind": "branch", "label": "price > 100", "taken": "then" }),
+                json!({ "kind": "guard", "name": "spread", "passed": true, "reason": "spread_ok" }),
+            ]
+        );
+
+        // Outside `collect` the calls record nothing.
+        assert_eq!(
+            bridge.call_path_typed("tupa_explain_probe.signal", json!(99.0), &signature),
+            Ok(json!(false))
+        );
+        let (_, events) = collect(|| ());
+        assert!(events.is_empty());
+    }
+}
@@ -3,15 +3,72 @@ use pyo3::prelude::*;
 use pyo3::types::PyModule;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
 
+pub mod effects;
+pub mod env;
+pub mod explain;
 pub mod serialize;
+pub mod tensor;
+pub mod worker;
+pub use env::{configure_python, python_report, PythonEnv};
 use serialize::{FromPython, ToPython};
+pub use tensor::Layout;
+pub use worker::{Codec, WorkerPool, WorkerPoolConfig};
+
+/// What an external's declaration says about a call: where its tensors sit
+/// and, when declared, the Tupã effects it may use.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Signature {
+    pub param: Layout,
+    pub returns: Layout,
+    /// Declared effect names (`io`, `time`, `random`, `external:<name>`),
+    /// enforced while the call runs; `None` leaves the call unchecked.
+    pub effects: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum CallError {
+    #[error("{0}")]
+    Python(String),
+    /// The function attempted an operation outside its declared effects.
+    #[error("undeclared effect {effect}: {event}")]
+    UndeclaredEffect { effect: String, event: String },
+}
+
+impl From<PyErr> for CallError {
+    fn from(err: PyErr) -> Self {
+        CallError::Python(err.to_string())
+    }
+}
 
 pub static BRIDGE: Lazy<Mutex<PythonBridge>> = Lazy::new(|| Mutex::new(PythonBridge::default()));
 
+/// Backend used by the `call_python_*` functions when set; `None` runs
+/// Python in-process through `BRIDGE`.
+static WORKER_POOL: Lazy<RwLock<Option<Arc<WorkerPool>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Routes subsequent Python calls to a pool of worker processes.
+pub fn use_worker_pool(config: WorkerPoolConfig) -> Result<(), String> {
+    let pool = WorkerPool::new(config)?;
+    *WORKER_POOL.write().map_err(|e| e.to_string())? = Some(Arc::new(pool));
+    Ok(())
+}
+
+/// Routes subsequent Python calls back to the in-process interpreter.
+pub fn use_in_process() {
+    if let Ok(mut pool) = WORKER_POOL.write() {
+        *pool = None;
+    }
+}
+
+fn worker_pool() -> Option<Arc<WorkerPool>> {
+    WORKER_POOL.read().ok().and_then(|pool| pool.clone())
+}
+
 pub struct PythonBridge {
     modules: HashMap<String, Py<PyModule>>,
+    functions: HashMap<String, Py<PyAny>>,
 }
 
 impl Default for PythonBridge {
@@ -23,8 +80,11 @@ impl Default for PythonBridge {
 impl PythonBridge {
     pub fn new() -> Self {
         pyo3::prepare_freethreaded_python();
+        // Steps import `tupa_explain`, so it must exist before they load.
+        let _ = Python::with_gil(|py| explain::module(py).map(|_| ()));
         Self {
             modules: HashMap::new(),
+            functions: HashMap::new(),
         }
     }
 
@@ -39,6 +99,122 @@ impl PythonBridge {
         })
     }
 
+    /// Resolves a dotted path such as `models.fraud.v2.score` or
+    /// `models.fraud.Scorer.score` to a callable: the longest importable
+    /// prefix is the module and the rest are attributes (classes, then
+    /// class or static methods). Resolved callables are cached.
+    pub fn resolve(&mut self, path: &str) -> PyResult<()> {
+        if self.functions.contains_key(path) {
+            return Ok(());
+        }
+        let parts: Vec<&str> = path.split('.').collect();
+        if parts.len() < 2 || parts.iter().any(|part| part.is_empty()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid Python path '{path}': expected <module>.<function>"
+            )));
+        }
+        Python::with_gil(|py| {
+            let mut import_error = None;
+            for split in (1..parts.len()).rev() {
+                let module_name = parts[..split].join(".");
+                let module = match self.modules.get(&module_name) {
+                    Some(module) => module.bind(py).clone(),
+                    None => match PyModule::import_bound(py, module_name.as_str()) {
+                        Ok(module) => {
+                            self.modules
+                                .insert(module_name.clone(), module.clone().unbind());
+                            module
+                        }
+                        // Only the prefix itself being absent means the
+                        // path continues with attributes; any other import
+                        // failure is the module's own and is reported.
+                        Err(e) if is_missing_module(py, &e, &module_name) => {
+                            import_error.get_or_insert(e);
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                let mut target = module.into_any();
+                for attr in &parts[split..] {
+                    target = target.getattr(*attr).map_err(|_| {
+                        PyErr::new::<pyo3::exceptions::PyAttributeError, _>(format!(
+                            "'{module_name}' has no attribute path '{}'",
+                            parts[split..].join(".")
+                        ))
+                    })?;
+                }
+                if !target.is_callable() {
+                    return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                        "'{path}' is not callable"
+                    )));
+                }
+                self.functions.insert(path.to_string(), target.unbind());
+                return Ok(());
+            }
+            Err(import_error.unwrap_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyImportError, _>(format!(
+                    "No module found for '{path}'"
+                ))
+            }))
+        })
+    }
+
+    /// Calls a callable previously resolved with `resolve`.
+    pub fn call_path(&self, path: &str, arg: Value) -> PyResult<Value> {
+        Python::with_gil(|py| {
+            let func = self.functions.get(path).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyImportError, _>(format!(
+                    "Function {} not resolved",
+                    path
+                ))
+            })?;
+            let py_arg = arg.to_python(py)?;
+            let result = func.bind(py).call1((py_arg,))?;
+            Value::from_python(&result)
+        })
+    }
+
+    /// Like `call_path`, marshalling tensors as laid out in `signature` and
+    /// enforcing its declared effects.
+    pub fn call_path_typed(
+        &self,
+        path: &str,
+        arg: Value,
+        signature: &Signature,
+    ) -> Result<Value, CallError> {
+        Python::with_gil(|py| {
+            let func = self.functions.get(path).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyImportError, _>(format!(
+                    "Function {} not resolved",
+                    path
+                ))
+            })?;
+            let py_arg = tensor::to_python_with(&arg, &signature.param, py)?;
+            let func = func.bind(py);
+            let result = explain::reported(py, || match &signature.effects {
+                Some(declared) => {
+                    let allowed = effects::allowed_categories(declared);
+                    let (result, violations) =
+                        effects::guarded(py, &allowed, || effects::invoke(py, func, py_arg))?;
+                    if let Some(violation) = violations.into_iter().next() {
+                        return Err(CallError::UndeclaredEffect {
+                            effect: violation.effect,
+                            event: violation.event,
+                        });
+                    }
+                    Ok(result?)
+                }
+                None => Ok(func.call1((py_arg,))?),
+            })??;
+            Ok(tensor::from_python_with(
+                &result,
+                &signature.returns,
+                "return",
+            )?)
+        })
+    }
+
     pub fn call(&self, module_name: &str, func_name: &str, arg: Value) -> PyResult<Value> {
         Python::with_gil(|py| {
             let module = self.modules.get(module_name).ok_or_else(|| {
@@ -55,12 +231,60 @@ impl PythonBridge {
     }
 }
 
+/// Whether `err` is the `ModuleNotFoundError` raised for `name` itself, as
+/// opposed to one raised while `name` imports its own dependencies.
+fn is_missing_module(py: Python<'_>, err: &PyErr, name: &str) -> bool {
+    err.is_instance_of::<pyo3::exceptions::PyModuleNotFoundError>(py)
+        && err
+            .value_bound(py)
+            .getattr("name")
+            .and_then(|missing| missing.extract::<String>())
+            .is_ok_and(|missing| missing == name)
+}
+
 pub fn call_python_function(module: &str, func: &str, arg: Value) -> Result<Value, String> {
+    if let Some(pool) = worker_pool() {
+        return pool.call(&format!("{module}.{func}"), arg);
+    }
     let mut bridge = BRIDGE.lock().map_err(|e| e.to_string())?;
     bridge.ensure_module(module).map_err(|e| e.to_string())?;
     bridge.call(module, func, arg).map_err(|e| e.to_string())
 }
 
+/// Checks that the dotted `path` names an importable callable; see
+/// `PythonBridge::resolve`.
+pub fn resolve_python_path(path: &str) -> Result<(), String> {
+    if let Some(pool) = worker_pool() {
+        return pool.resolve(path);
+    }
+    let mut bridge = BRIDGE.lock().map_err(|e| e.to_string())?;
+    bridge.resolve(path).map_err(|e| e.to_string())
+}
+
+/// Calls the callable at the dotted `path`, e.g. `models.fraud.v2.score`.
+pub fn call_python_path(path: &str, arg: Value) -> Result<Value, String> {
+    call_python_typed(path, arg, &Signature::default()).map_err(|e| e.to_string())
+}
+
+/// Calls the callable at the dotted `path` as declared by `signature`:
+/// tensors pass as NumPy arrays, array results are read as laid out, and
+/// operations outside the declared effects are blocked. Worker pools
+/// exchange tensors as nested lists.
+pub fn call_python_typed(
+    path: &str,
+    arg: Value,
+    signature: &Signature,
+) -> Result<Value, CallError> {
+    if let Some(pool) = worker_pool() {
+        return pool.call_typed(path, arg, signature);
+    }
+    let mut bridge = BRIDGE
+        .lock()
+        .map_err(|e| CallError::Python(e.to_string()))?;
+    bridge.resolve(path)?;
+    bridge.call_path_typed(path, arg, signature)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,4 +298,54 @@ mod tests {
             .unwrap();
         assert_eq!(result, serde_json::json!(4.0));
     }
+
+    #[test]
+    fn test_dotted_paths_and_class_methods() {
+        assert_eq!(
+            call_python_path("os.path.basename", serde_json::json!("/tmp/x.json")),
+            Ok(serde_json::json!("x.json"))
+        );
+        // `int.bit_length` is a method of the `int` class in `builtins`.
+        assert_eq!(
+            call_python_path("builtins.int.bit_length", serde_json::json!(8)),
+            Ok(serde_json::json!(4))
+        );
+        assert_eq!(
+            call_python_path("builtins.str.upper", serde_json::json!("ok")),
+            Ok(serde_json::json!("OK"))
+        );
+        assert!(resolve_python_path("os.path.no_such_function").is_err());
+        assert!(resolve_python_path("no_such_package.mod.f").is_err());
+        assert!(resolve_python_path("math").is_err());
+        assert!(resolve_python_path("math.pi").is_err());
+    }
+
+    #[test]
+    fn test_resolve_reports_submodule_import_failures() {
+        let dir = std::env::temp_dir().join(format!("tupa-pyffi-broken-{}", std::process::id()));
+        let package = dir.join("tupa_broken_pkg");
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(package.join("__init__.py"), "").unwrap();
+        std::fs::write(
+            package.join("needs_dep.py"),
+            "import tupa_missing_dep\n\ndef score(x):\n    return x\n",
+        )
+        .unwrap();
+        std::fs::write(package.join("bad_syntax.py"), "def score(x:\n").unwrap();
+        Python::with_gil(|py| {
+            let sys_path = py.import_bound("sys").unwrap().getattr("path").unwrap();
+            sys_path
+                .call_method1("insert", (0, dir.to_string_lossy().as_ref()))
+                .unwrap();
+        });
+
+        let err = resolve_python_path("tupa_broken_pkg.needs_dep.score").unwrap_err();
+        assert!(
+            err.contains("ModuleNotFoundError: No module named 'tupa_missing_dep'"),
+            "{err}"
+        );
+        let err = resolve_python_path("tupa_broken_pkg.bad_syntax.score").unwrap_err();
+        assert!(err.starts_with("SyntaxError"), "{err}");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
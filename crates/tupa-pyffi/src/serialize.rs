@@ -107,6 +107,9 @@ impl FromPython for Value {
             }
             return Ok(Value::Object(map));
         }
+        if let Some((array, _dtype)) = crate::tensor::read_buffer(obj)? {
+            return Ok(array);
+        }
         Ok(Value::String(obj.to_string()))
     }
 }
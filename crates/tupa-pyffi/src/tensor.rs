@@ -0,0 +1,336 @@
+//! Tensor marshalling between pipeline values and NumPy.
+//!
+//! Values declared as `Tensor` are passed to Python as NumPy arrays built
+//! from one contiguous buffer (nested lists when NumPy is not importable).
+//! On return, NumPy arrays, PyTorch CPU tensors and any other object
+//! exposing the buffer protocol are read in one pass and rebuilt as nested
+//! JSON arrays. A declared dtype must match the buffer's element type;
+//! shapes are checked by the runtime against the declared type.
+
+use std::collections::BTreeMap;
+
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes, PyDict, PyString};
+use serde_json::{Map, Number, Value};
+
+use crate::serialize::{FromPython, ToPython};
+
+/// Where tensors sit inside a call's argument or result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Layout {
+    /// Plain JSON-like value.
+    #[default]
+    Value,
+    Tensor {
+        shape: Option<Vec<Option<usize>>>,
+        /// Tupã dtype name (`f32`, `i64`, `bool`, ...).
+        dtype: Option<String>,
+    },
+    /// Record whose fields have their own layouts; missing fields are plain.
+    Record(BTreeMap<String, Layout>),
+}
+
+/// Converts `value` following `layout`.
+pub fn to_python_with(value: &Value, layout: &Layout, py: Python) -> PyResult<PyObject> {
+    match (layout, value) {
+        (Layout::Tensor { dtype, .. }, Value::Array(_)) => {
+            match to_numpy(py, value, dtype.as_deref().unwrap_or("f64"))? {
+                Some(array) => Ok(array),
+                None => value.to_python(py),
+            }
+        }
+        (Layout::Record(fields), Value::Object(map)) => {
+            let dict = PyDict::new_bound(py);
+            for (key, field) in map {
+                let layout = fields.get(key).unwrap_or(&Layout::Value);
+                dict.set_item(key, to_python_with(field, layout, py)?)?;
+            }
+            Ok(dict.into_py(py))
+        }
+        _ => value.to_python(py),
+    }
+}
+
+/// Converts `obj` following `layout`; `path` names the value in errors.
+pub fn from_python_with(obj: &Bound<'_, PyAny>, layout: &Layout, path: &str) -> PyResult<Value> {
+    match layout {
+        Layout::Tensor { dtype, .. } => match read_buffer(obj)? {
+            Some((value, found)) => {
+                if let Some(expected) = dtype.as_deref() {
+                    if expected != found {
+                        return Err(PyTypeError::new_err(format!(
+                            "{path} expected tensor dtype {expected}, got {found}"
+                        )));
+                    }
+                }
+                Ok(value)
+            }
+            None => Value::from_python(obj),
+        },
+        Layout::Record(fields) => match obj.downcast::<PyDict>() {
+            Ok(dict) => {
+                let mut map = Map::new();
+                for (k, v) in dict {
+                    let key = String::from_python(k.as_any())?;
+                    let value = match fields.get(&key) {
+                        Some(layout) => from_python_with(&v, layout, &format!("{path}.{key}"))?,
+                        None => Value::from_python(&v)?,
+                    };
+                    map.insert(key, value);
+                }
+                Ok(Value::Object(map))
+            }
+            Err(_) => Value::from_python(obj),
+        },
+        Layout::Value => Value::from_python(obj),
+    }
+}
+
+/// Builds a NumPy array from a nested JSON array, or `None` when NumPy is
+/// unavailable or the value is not a rectangular numeric array.
+fn to_numpy(py: Python, value: &Value, dtype: &str) -> PyResult<Option<PyObject>> {
+    let Ok(numpy) = py.import_bound("numpy") else {
+        return Ok(None);
+    };
+    let Some(shape) = rectangular_shape(value) else {
+        return Ok(None);
+    };
+    let mut leaves = Vec::new();
+    flatten(value, &mut leaves);
+    let Some(bytes) = encode(&leaves, dtype) else {
+        return Ok(None);
+    };
+    let buffer = PyByteArray::new_bound(py, &bytes);
+    let array = numpy
+        .call_method1("frombuffer", (buffer, numpy_dtype(dtype)))?
+        .call_method1("reshape", (shape,))?;
+    Ok(Some(array.unbind()))
+}
+
+fn rectangular_shape(value: &Value) -> Option<Vec<usize>> {
+    match value {
+        Value::Array(items) => {
+            let inner = match items.first() {
+                Some(first) => rectangular_shape(first)?,
+                None => Vec::new(),
+            };
+            for item in items {
+                if rectangular_shape(item)? != inner {
+                    return None;
+                }
+            }
+            Some(std::iter::once(items.len()).chain(inner).collect())
+        }
+        Value::Number(_) | Value::Bool(_) => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+fn flatten<'a>(value: &'a Value, out: &mut Vec<&'a Value>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| flatten(item, out)),
+        leaf => out.push(leaf),
+    }
+}
+
+/// Native-endian bytes of `leaves` as `dtype`.
+fn encode(leaves: &[&Value], dtype: &str) -> Option<Vec<u8>> {
+    macro_rules! pack {
+        ($t:ty, $read:expr) => {{
+            let mut bytes = Vec::with_capacity(leaves.len() * std::mem::size_of::<$t>());
+            for leaf in leaves {
+                let v: $t = $read(leaf)?;
+                bytes.extend_from_slice(&v.to_ne_bytes());
+            }
+            Some(bytes)
+        }};
+    }
+    let float = |v: &Value| v.as_f64();
+    let int = |v: &Value| v.as_i64();
+    let uint = |v: &Value| v.as_u64();
+    match dtype {
+        "f32" => pack!(f32, |v| float(v).map(|f| f as f32)),
+        "f64" => pack!(f64, float),
+        "i8" => pack!(i8, |v| int(v).and_then(|i| i8::try_from(i).ok())),
+        "i16" => pack!(i16, |v| int(v).and_then(|i| i16::try_from(i).ok())),
+        "i32" => pack!(i32, |v| int(v).and_then(|i| i32::try_from(i).ok())),
+        "i64" => pack!(i64, int),
+        "u8" => pack!(u8, |v| uint(v).and_then(|i| u8::try_from(i).ok())),
+        "u16" => pack!(u16, |v| uint(v).and_then(|i| u16::try_from(i).ok())),
+        "u32" => pack!(u32, |v| uint(v).and_then(|i| u32::try_from(i).ok())),
+        "u64" => pack!(u64, uint),
+        "bool" => pack!(u8, |v: &Value| v.as_bool().map(u8::from)),
+        _ => None,
+    }
+}
+
+fn numpy_dtype(dtype: &str) -> &'static str {
+    match dtype {
+        "f32" => "float32",
+        "i8" => "int8",
+        "i16" => "int16",
+        "i32" => "int32",
+        "i64" => "int64",
+        "u8" => "uint8",
+        "u16" => "uint16",
+        "u32" => "uint32",
+        "u64" => "uint64",
+        "bool" => "bool",
+        _ => "float64",
+    }
+}
+
+/// Reads a NumPy array, PyTorch CPU tensor or other buffer-protocol object
+/// as nested JSON arrays plus its Tupã dtype. Returns `None` for objects
+/// that are not arrays; `str`, `bytes` and `bytearray` are never arrays.
+pub fn read_buffer(obj: &Bound<'_, PyAny>) -> PyResult<Option<(Value, &'static str)>> {
+    if obj.is_instance_of::<PyString>()
+        || obj.is_instance_of::<PyBytes>()
+        || obj.is_instance_of::<PyByteArray>()
+    {
+        return Ok(None);
+    }
+    let obj = if is_torch_tensor(obj)? {
+        let tensor = obj.call_method0("detach")?;
+        tensor.call_method0("numpy")?
+    } else {
+        obj.clone()
+    };
+    let memoryview = obj.py().import_bound("builtins")?.getattr("memoryview")?;
+    let Ok(view) = memoryview.call1((&obj,)) else {
+        return Ok(None);
+    };
+    let format: String = view.getattr("format")?.extract()?;
+    let itemsize: usize = view.getattr("itemsize")?.extract()?;
+    let Some(dtype) = element_dtype(&format, itemsize) else {
+        return Err(PyTypeError::new_err(format!(
+            "unsupported tensor element format '{format}'"
+        )));
+    };
+
+    macro_rules! read {
+        ($t:ty, $to:expr) => {{
+            let buffer = PyBuffer::<$t>::get_bound(&view)?;
+            let shape = buffer.shape().to_vec();
+            let flat: Vec<Value> = buffer.to_vec(obj.py())?.into_iter().map($to).collect();
+            nest(flat, &shape)
+        }};
+    }
+    let value = match dtype {
+        "f32" => read!(f32, |v: f32| float_value(v as f64)),
+        "f64" => read!(f64, float_value),
+        "i8" => read!(i8, |v: i8| Value::from(v)),
+        "i16" => read!(i16, |v: i16| Value::from(v)),
+        "i32" => read!(i32, |v: i32| Value::from(v)),
+        "i64" => read!(i64, |v: i64| Value::from(v)),
+        "u8" => read!(u8, |v: u8| Value::from(v)),
+        "u16" => read!(u16, |v: u16| Value::from(v)),
+        "u32" => read!(u32, |v: u32| Value::from(v)),
+        "u64" => read!(u64, |v: u64| Value::from(v)),
+        _ => {
+            // `bool` buffers ('?') have no `Element` impl; go through Python.
+            return Ok(Some((
+                Value::from_python(&view.call_method0("tolist")?)?,
+                dtype,
+            )));
+        }
+    };
+    value.map(|value| Some((value, dtype)))
+}
+
+fn is_torch_tensor(obj: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let module = obj
+        .get_type()
+        .getattr("__module__")
+        .and_then(|module| module.extract::<String>())
+        .unwrap_or_default();
+    Ok(module.starts_with("torch") && obj.hasattr("numpy")?)
+}
+
+/// Tupã dtype of a struct-module `format` with `itemsize` bytes.
+fn element_dtype(format: &str, itemsize: usize) -> Option<&'static str> {
+    let code = format.trim_start_matches(['@', '=', '<', '>', '!']);
+    let dtype = match (code, itemsize) {
+        ("f", 4) => "f32",
+        ("d", 8) => "f64",
+        ("?", 1) => "bool",
+        ("b" | "h" | "i" | "l" | "q" | "n", 1) => "i8",
+        ("b" | "h" | "i" | "l" | "q" | "n", 2) => "i16",
+        ("b" | "h" | "i" | "l" | "q" | "n", 4) => "i32",
+        ("b" | "h" | "i" | "l" | "q" | "n", 8) => "i64",
+        ("B" | "H" | "I" | "L" | "Q" | "N", 1) => "u8",
+        ("B" | "H" | "I" | "L" | "Q" | "N", 2) => "u16",
+        ("B" | "H" | "I" | "L" | "Q" | "N", 4) => "u32",
+        ("B" | "H" | "I" | "L" | "Q" | "N", 8) => "u64",
+        _ => return None,
+    };
+    Some(dtype)
+}
+
+fn float_value(v: f64) -> Value {
+    Number::from_f64(v).map_or(Value::Null, Value::Number)
+}
+
+/// Rebuilds nested arrays of `shape` from row-major `flat` values.
+fn nest(flat: Vec<Value>, shape: &[usize]) -> PyResult<Value> {
+    if flat.iter().any(Value::is_null) {
+        return Err(PyValueError::new_err("NaN/Inf encountered in tensor"));
+    }
+    fn build(flat: &mut std::vec::IntoIter<Value>, shape: &[usize]) -> Value {
+        match shape.split_first() {
+            None => flat.next().unwrap_or(Value::Null),
+            Some((&len, rest)) => Value::Array((0..len).map(|_| build(flat, rest)).collect()),
+        }
+    }
+    Ok(build(&mut flat.into_iter(), shape))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval<'py>(py: Python<'py>, code: &str) -> Bound<'py, PyAny> {
+        py.eval_bound(code, None, None).unwrap()
+    }
+
+    #[test]
+    fn test_reads_shaped_buffers_with_dtype() {
+        crate::PythonBridge::new();
+        Python::with_gil(|py| {
+            let matrix = eval(
+                py,
+                "memoryview(__import__('array').array('f', [1, 2, 3, 4, 5, 6])).cast('B').cast('f', [2, 3])",
+            );
+            let (value, dtype) = read_buffer(&matrix).unwrap().unwrap();
+            assert_eq!(dtype, "f32");
+            assert_eq!(value, serde_json::json!([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]));
+
+            let ints = eval(py, "__import__('array').array('q', [7, -8])");
+            let layout = Layout::Tensor {
+                shape: None,
+                dtype: Some("f32".into()),
+            };
+            let err = from_python_with(&ints, &layout, "return").unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("return expected tensor dtype f32, got i64"));
+
+            assert!(read_buffer(&eval(py, "b'raw'")).unwrap().is_none());
+            assert!(read_buffer(&eval(py, "[1, 2]")).unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_packs_declared_dtype() {
+        let value = serde_json::json!([[1, 2], [3, 4]]);
+        assert_eq!(rectangular_shape(&value), Some(vec![2, 2]));
+        assert_eq!(rectangular_shape(&serde_json::json!([[1], [2, 3]])), None);
+        let mut leaves = Vec::new();
+        flatten(&value, &mut leaves);
+        assert_eq!(encode(&leaves, "u8"), Some(vec![1, 2, 3, 4]));
+        assert_eq!(encode(&leaves, "f32").map(|b| b.len()), Some(16));
+        assert_eq!(encode(&[&serde_json::json!(300)], "u8"), None);
+    }
+}
@@ -0,0 +1,716 @@
+//! Out-of-process backend: Python functions run in supervised `python`
+//! subprocesses instead of the host interpreter.
+//!
+//! Each worker reads length-prefixed requests (4-byte big-endian length, then
+//! a JSON or msgpack body) on stdin and answers on stdout. Calls check out an
+//! idle worker, so a pool of `size` workers runs up to `size` calls at once.
+//! A worker that times out or dies is killed and replaced; a call never waits
+//! on a crashed or hung process.
+
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::{effects, env, explain, CallError, PythonEnv, Signature};
+
+/// Wire format between the host and its workers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    #[default]
+    Json,
+    /// Requires the `msgpack` package in the worker interpreter.
+    Msgpack,
+}
+
+impl Codec {
+    fn name(self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            Codec::Msgpack => "msgpack",
+        }
+    }
+
+    fn encode(self, value: &Value) -> Result<Vec<u8>, String> {
+        match self {
+            Codec::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Codec::Msgpack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> Result<Value, String> {
+        match self {
+            Codec::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Codec::Msgpack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerPoolConfig {
+    /// Number of worker processes.
+    pub size: usize,
+    /// Per-call limit; a worker that exceeds it is killed and restarted.
+    pub timeout: Duration,
+    /// Address-space limit applied to each worker (`RLIMIT_AS`), in MiB.
+    pub memory_limit_mb: Option<u64>,
+    /// Interpreter used to start workers, unless `env` names one.
+    pub python: String,
+    pub codec: Codec,
+    /// Interpreter or virtualenv, `sys.path` entries and preloaded modules
+    /// of every worker.
+    pub env: PythonEnv,
+}
+
+impl Default for WorkerPoolConfig {
+    fn default() -> Self {
+        Self {
+            size: 2,
+            timeout: Duration::from_secs(30),
+            memory_limit_mb: None,
+            python: "python3".into(),
+            codec: Codec::Json,
+            env: PythonEnv::default(),
+        }
+    }
+}
+
+const WORKER_SCRIPT: &str = r#"
+import importlib, json, math, struct, sys, types
+
+def resolve(path, cache):
+    if path in cache:
+        return cache[path]
+    parts = path.split(".")
+    if len(parts) < 2 or not all(parts):
+        raise ValueError(f"Invalid Python path '{path}': expected <module>.<function>")
+    error = None
+    for split in range(len(parts) - 1, 0, -1):
+        try:
+            target = importlib.import_module(".".join(parts[:split]))
+        except ModuleNotFoundError as e:
+            if e.name != ".".join(parts[:split]):
+                raise
+            error = error or e
+            continue
+        for attr in parts[split:]:
+            target = getattr(target, attr)
+        if not callable(target):
+            raise TypeError(f"'{path}' is not callable")
+        cache[path] = target
+        return target
+    raise error
+
+def finite(value):
+    if isinstance(value, float) and not math.isfinite(value):
+        raise ValueError("NaN/Inf encountered in float conversion")
+    if isinstance(value, dict):
+        for item in value.values():
+            finite(item)
+    elif isinstance(value, (list, tuple)):
+        for item in value:
+            finite(item)
+    return value
+
+def default(obj):
+    if hasattr(obj, "tolist"):
+        return finite(obj.tolist())
+    raise TypeError(f"Object of type {type(obj).__name__} is not serializable")
+
+def explained(func, arg, effects, explain):
+    if not explain:
+        return call(func, arg, effects)
+    tupa_explain.start()
+    try:
+        response = call(func, arg, effects)
+    except Exception as e:
+        response = {"error": f"{type(e).__name__}: {e}"}
+    response["events"] = tupa_explain.finish()
+    return response
+
+def call(func, arg, effects):
+    if effects is None:
+        return {"ok": func(arg)}
+    enter(effects)
+    try:
+        result = func(arg)
+    except Exception as e:
+        result = e
+    finally:
+        violations = leave()
+    if violations:
+        return {"violation": list(violations[0])}
+    if isinstance(result, Exception):
+        raise result
+    return {"ok": result}
+
+def main(codec, memory_limit_mb, preload, explain_source):
+    global tupa_explain
+    tupa_explain = types.ModuleType("tupa_explain")
+    exec(explain_source, tupa_explain.__dict__)
+    sys.modules["tupa_explain"] = tupa_explain
+    if memory_limit_mb:
+        import resource
+        limit = memory_limit_mb * 1024 * 1024
+        resource.setrlimit(resource.RLIMIT_AS, (limit, limit))
+    for module in filter(None, preload.split(",")):
+        importlib.import_module(module)
+    if codec == "msgpack":
+        import msgpack
+        loads = lambda b: msgpack.unpackb(b, raw=False)
+        dumps = lambda v: msgpack.packb(finite(v), default=default)
+    else:
+        loads = json.loads
+        dumps = lambda v: json.dumps(finite(v), default=default).encode()
+    stdin, stdout = sys.stdin.buffer, sys.stdout.buffer
+    sys.stdout = sys.stderr
+    cache = {}
+    while True:
+        header = stdin.read(4)
+        if len(header) < 4:
+            return
+        request = loads(stdin.read(struct.unpack(">I", header)[0]))
+        try:
+            if request.get("report"):
+                body = dumps({"ok": report()})
+            elif request.get("resolve"):
+                resolve(request["path"], cache)
+                body = dumps({"ok": None})
+            else:
+                func = resolve(request["path"], cache)
+                body = dumps(explained(
+                    func, request["arg"], request.get("effects"), request.get("explain")
+                ))
+        except Exception as e:
+            body = dumps({"error": f"{type(e).__name__}: {e}"})
+        stdout.write(struct.pack(">I", len(body)) + body)
+        stdout.flush()
+
+main(sys.argv[1], int(sys.argv[2]), sys.argv[3], sys.argv[4])
+"#;
+
+/// How long a worker whose output closed gets to exit on its own.
+const EXIT_GRACE: Duration = Duration::from_millis(200);
+
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    responses: Receiver<Vec<u8>>,
+}
+
+impl Worker {
+    fn spawn(config: &WorkerPoolConfig) -> Result<Self, String> {
+        let python = config
+            .env
+            .python()
+            .unwrap_or_else(|| config.python.clone().into());
+        let mut command = Command::new(&python);
+        command
+            .arg("-c")
+            .arg(format!(
+                "{}\n{}\n{WORKER_SCRIPT}",
+                effects::GUARD_MODULE,
+                env::REPORT
+            ))
+            .arg(config.codec.name())
+            .arg(config.memory_limit_mb.unwrap_or(0).to_string())
+            .arg(config.env.preload.join(","))
+            .arg(explain::EXPLAIN_MODULE);
+        if !config.env.paths.is_empty() {
+            let inherited = std::env::var_os("PYTHONPATH").unwrap_or_default();
+            let paths = config
+                .env
+                .paths
+                .iter()
+                .cloned()
+                .chain(std::env::split_paths(&inherited));
+            command.env(
+                "PYTHONPATH",
+                std::env::join_paths(paths).map_err(|e| e.to_string())?,
+            );
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("Failed to start Python worker '{}': {e}", python.display()))?;
+        let stdin = child.stdin.take().expect("worker stdin is piped");
+        let mut stdout = child.stdout.take().expect("worker stdout is piped");
+        let (tx, responses) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(frame) = read_frame(&mut stdout) {
+                if tx.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            child,
+            stdin,
+            responses,
+        })
+    }
+
+    fn call(&mut self, request: &[u8], timeout: Duration) -> Result<Vec<u8>, WorkerFailure> {
+        let len = u32::try_from(request.len()).map_err(|_| WorkerFailure::Exited)?;
+        self.stdin
+            .write_all(&len.to_be_bytes())
+            .and_then(|_| self.stdin.write_all(request))
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| WorkerFailure::Exited)?;
+        self.responses.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => WorkerFailure::TimedOut,
+            RecvTimeoutError::Disconnected => WorkerFailure::Exited,
+        })
+    }
+
+    /// Exit status of a worker whose output closed. A worker that closed it
+    /// but keeps running is killed instead of waited on forever.
+    fn exit_status(&mut self) -> String {
+        let deadline = Instant::now() + EXIT_GRACE;
+        let status = loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => break Ok(status),
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+                Ok(None) => {
+                    let _ = self.child.kill();
+                    break self.child.wait();
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        match status {
+            Ok(status) => status.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+enum WorkerFailure {
+    TimedOut,
+    Exited,
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+    let mut frame = vec![0u8; u32::from_be_bytes(header) as usize];
+    reader.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+/// A fixed-size pool of supervised Python workers.
+pub struct WorkerPool {
+    config: WorkerPoolConfig,
+    /// Idle slots; `None` marks a worker that failed to restart and is
+    /// started again on its next checkout.
+    idle: Mutex<Vec<Option<Worker>>>,
+    available: Condvar,
+    /// Environment report of the workers, built on first request.
+    report: Mutex<Option<Value>>,
+    /// Paths `resolve` has found, so runs resolving their steps again skip
+    /// the round-trip to a worker.
+    resolved: Mutex<HashSet<String>>,
+}
+
+impl WorkerPool {
+    /// Starts `config.size` workers (at least one).
+    pub fn new(config: WorkerPoolConfig) -> Result<Self, String> {
+        let workers = (0..config.size.max(1))
+            .map(|_| Worker::spawn(&config).map(Some))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            config,
+            idle: Mutex::new(workers),
+            available: Condvar::new(),
+            report: Mutex::new(None),
+            resolved: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn config(&self) -> &WorkerPoolConfig {
+        &self.config
+    }
+
+    /// Calls the callable at the dotted `path` in a worker.
+    pub fn call(&self, path: &str, arg: Value) -> Result<Value, String> {
+        self.request(path, json!({ "path": path, "arg": arg }))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Like `call`, enforcing the effects declared in `signature`.
+    pub fn call_typed(
+        &self,
+        path: &str,
+        arg: Value,
+        signature: &Signature,
+    ) -> Result<Value, CallError> {
+        let mut request = json!({ "path": path, "arg": arg });
+        if let Some(declared) = &signature.effects {
+            request["effects"] = json!(effects::allowed_categories(declared));
+        }
+        self.request(path, request)
+    }
+
+    /// Implementation, version, installed packages and preloaded modules of
+    /// the workers' interpreter, in the shape of `python_report`.
+    pub fn report(&self) -> Result<Value, String> {
+        let mut cached = self.report.lock().map_err(|e| e.to_string())?;
+        if let Some(report) = &*cached {
+            return Ok(report.clone());
+        }
+        let report = self
+            .request("the environment report", json!({ "report": true }))
+            .map_err(|e| e.to_string())?;
+        let report = json!({
+            "implementation": report["implementation"],
+            "version": report["version"],
+            "packages": report["packages"],
+            "preload": self.config.env.preload,
+        });
+        *cached = Some(report.clone());
+        Ok(report)
+    }
+
+    /// Checks that the dotted `path` names a callable importable by the
+    /// workers. Resolved paths are cached.
+    pub fn resolve(&self, path: &str) -> Result<(), String> {
+        if self.resolved.lock().unwrap().contains(path) {
+            return Ok(());
+        }
+        self.request(path, json!({ "path": path, "resolve": true }))
+            .map_err(|e| e.to_string())?;
+        self.resolved.lock().unwrap().insert(path.to_string());
+        Ok(())
+    }
+
+    fn request(&self, path: &str, mut request: Value) -> Result<Value, CallError> {
+        if explain::collecting() {
+            request["explain"] = json!(true);
+        }
+        let body = self
+            .config
+            .codec
+            .encode(&request)
+            .map_err(CallError::Python)?;
+        let mut worker = match self.checkout() {
+            Some(worker) => worker,
+            None => match Worker::spawn(&self.config) {
+                Ok(worker) => worker,
+                Err(e) => {
+                    self.checkin(None);
+                    return Err(CallError::Python(e));
+                }
+            },
+        };
+        match worker.call(&body, self.config.timeout) {
+            Ok(frame) => {
+                self.checkin(Some(worker));
+                let response = self
+                    .config
+                    .codec
+                    .decode(&frame)
+                    .map_err(CallError::Python)?;
+                if let Some(events) = response.get("events").and_then(Value::as_array) {
+                    explain::extend(events.clone());
+                }
+                if let Some(error) = response.get("error").and_then(Value::as_str) {
+                    return Err(CallError::Python(error.to_string()));
+                }
+                if let Some([effect, event]) = response
+                    .get("violation")
+                    .and_then(Value::as_array)
+                    .map(Vec::as_slice)
+                {
+                    return Err(CallError::UndeclaredEffect {
+                        effect: effect.as_str().unwrap_or_default().to_string(),
+                        event: event.as_str().unwrap_or_default().to_string(),
+                    });
+                }
+                Ok(response.get("ok").cloned().unwrap_or(Value::Null))
+            }
+            Err(failure) => {
+                let error = match failure {
+                    WorkerFailure::TimedOut => {
+                        drop(worker);
+                        format!(
+                            "Python worker timed out after {}ms calling {path}",
+                            self.config.timeout.as_millis()
+                        )
+                    }
+                    WorkerFailure::Exited => format!(
+                        "Python worker exited ({}) calling {path}",
+                        worker.exit_status()
+                    ),
+                };
+                self.checkin(Worker::spawn(&self.config).ok());
+                Err(CallError::Python(error))
+            }
+        }
+    }
+
+    fn checkout(&self) -> Option<Worker> {
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if let Some(slot) = idle.pop() {
+                return slot;
+            }
+            idle = self.available.wait(idle).unwrap();
+        }
+    }
+
+    fn checkin(&self, worker: Option<Worker>) {
+        self.idle.lock().unwrap().push(worker);
+        self.available.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn pool(timeout: Duration) -> WorkerPool {
+        WorkerPool::new(WorkerPoolConfig {
+            size: 1,
+            timeout,
+            ..WorkerPoolConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pool_calls_dotted_paths() {
+        let pool = pool(Duration::from_secs(10));
+        assert_eq!(
+            pool.call("os.path.basename", json!("/tmp/x.json")),
+            Ok(json!("x.json"))
+        );
+        assert_eq!(
+            pool.call("builtins.str.upper", json!("buy")),
+            Ok(json!("BUY"))
+        );
+        assert!(pool.resolve("os.path.join").is_ok());
+        let err = pool.resolve("no_such_pkg.models.score").unwrap_err();
+        assert!(err.contains("ModuleNotFoundError"), "{err}");
+        let err = pool.call("math.sqrt", json!("x")).unwrap_err();
+        assert!(err.starts_with("TypeError"), "{err}");
+        let err = pool.call("builtins.float", json!("nan")).unwrap_err();
+        assert!(err.contains("NaN/Inf encountered"), "{err}");
+    }
+
+    #[test]
+    fn test_pool_caches_resolved_paths() {
+        let pool = Arc::new(pool(Duration::from_secs(10)));
+        assert!(pool.resolve("math.sqrt").is_ok());
+        // With the only worker checked out, a second round-trip would block.
+        let worker = pool.checkout();
+        let (tx, rx) = mpsc::channel();
+        let resolver = pool.clone();
+        thread::spawn(move || tx.send(resolver.resolve("math.sqrt")).unwrap());
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(Ok(())));
+        pool.checkin(worker);
+        assert!(pool.resolve("no_such_pkg.score").is_err());
+        assert!(pool.resolve("no_such_pkg.score").is_err());
+    }
+
+    #[test]
+    fn test_pool_restarts_workers_after_timeout_and_crash() {
+        let pool = pool(Duration::from_millis(300));
+        let err = pool.call("time.sleep", json!(5)).unwrap_err();
+        assert!(err.contains("timed out after 300ms"), "{err}");
+        assert_eq!(pool.call("math.sqrt", json!(16.0)), Ok(json!(4.0)));
+
+        let err = pool.call("os._exit", json!(3)).unwrap_err();
+        assert!(err.contains("Python worker exited"), "{err}");
+        assert_eq!(pool.call("math.sqrt", json!(16.0)), Ok(json!(4.0)));
+    }
+
+    #[test]
+    fn test_pool_kills_workers_that_close_output_but_keep_running() {
+        let dir = std::env::temp_dir().join(format!("tupa-worker-hang-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("tupa_worker_hang.py"),
+            "import os, time\n\ndef hang(_):\n    os.close(1)\n    time.sleep(60)\n",
+        )
+        .unwrap();
+        let pool = WorkerPool::new(WorkerPoolConfig {
+            size: 1,
+            timeout: Duration::from_secs(30),
+            env: PythonEnv {
+                paths: vec![dir.clone()],
+                ..PythonEnv::default()
+            },
+            ..WorkerPoolConfig::default()
+        })
+        .unwrap();
+        let started = Instant::now();
+        let err = pool.call("tupa_worker_hang.hang", json!(null)).unwrap_err();
+        assert!(err.contains("Python worker exited"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(pool.call("math.sqrt", json!(16.0)), Ok(json!(4.0)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_pool_applies_python_env() {
+        let dir = std::env::temp_dir().join(format!("tupa-worker-env-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("tupa_worker_probe.py"),
+            "def triple(x):\n    return x * 3\n",
+        )
+        .unwrap();
+        let pool = WorkerPool::new(WorkerPoolConfig {
+            size: 1,
+            env: PythonEnv {
+                paths: vec![dir],
+                preload: vec!["tupa_worker_probe".into()],
+                ..PythonEnv::default()
+            },
+            ..WorkerPoolConfig::default()
+        })
+        .unwrap();
+        assert_eq!(
+            pool.call("tupa_worker_probe.triple", json!(2)),
+            Ok(json!(6))
+        );
+    }
+
+    #[test]
+    fn test_pool_reports_submodule_import_failures() {
+        let dir = std::env::temp_dir().join(format!("tupa-worker-broken-{}", std::process::id()));
+        let package = dir.join("tupa_worker_broken");
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(package.join("__init__.py"), "").unwrap();
+        std::fs::write(
+            package.join("needs_dep.py"),
+            "import tupa_missing_dep\n\ndef score(x):\n    return x\n",
+        )
+        .unwrap();
+        let pool = WorkerPool::new(WorkerPoolConfig {
+            size: 1,
+            env: PythonEnv {
+                paths: vec![dir.clone()],
+                ..PythonEnv::default()
+            },
+            ..WorkerPoolConfig::default()
+        })
+        .unwrap();
+        let err = pool
+            .resolve("tupa_worker_broken.needs_dep.score")
+            .unwrap_err();
+        assert!(
+            err.contains("ModuleNotFoundError: No module named 'tupa_missing_dep'"),
+            "{err}"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_pool_returns_reported_explain_events() {
+        let dir = std::env::temp_dir().join(format!("tupa-worker-explain-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("tupa_worker_explain.py"),
+            "import tupa_explain\n\ndef signal(price):\n    \
+             tupa_explain.branch('price > 100', 'then' if price > 100 else 'else')\n    \
+             return price > 100\n",
+        )
+        .unwrap();
+        let pool = WorkerPool::new(WorkerPoolConfig {
+            size: 1,
+            env: PythonEnv {
+                paths: vec![dir.clone()],
+                ..PythonEnv::default()
+            },
+            ..WorkerPoolConfig::default()
+        })
+        .unwrap();
+        let (result, events) =
+            explain::collect(|| pool.call("tupa_worker_explain.signal", json!(99)));
+        assert_eq!(result, Ok(json!(false)));
+        assert_eq!(
+            events,
+            [json!({ "kind": "branch", "label": "price > 100", "taken": "else" })]
+        );
+        assert_eq!(
+            pool.call("tupa_worker_explain.signal", json!(101)),
+            Ok(json!(true))
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_pool_reports_the_workers_environment() {
+        let dir = std::env::temp_dir().join(format!("tupa-worker-report-{}", std::process::id()));
+        let dist = dir.join("tupa_worker_only-1.2.3.dist-info");
+        std::fs::create_dir_all(&dist).unwrap();
+        std::fs::write(
+            dist.join("METADATA"),
+            "Metadata-Version: 2.1\nName: tupa-worker-only\nVersion: 1.2.3\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("tupa_worker_only.py"), "").unwrap();
+        let pool = WorkerPool::new(WorkerPoolConfig {
+            size: 1,
+            env: PythonEnv {
+                paths: vec![dir.clone()],
+                preload: vec!["tupa_worker_only".into()],
+                ..PythonEnv::default()
+            },
+            ..WorkerPoolConfig::default()
+        })
+        .unwrap();
+        let report = pool.report().unwrap();
+        assert_eq!(report["implementation"], "CPython");
+        assert!(report["version"].as_str().unwrap().starts_with("3."));
+        // Installed only where the workers look, not in the host process.
+        assert_eq!(report["packages"]["tupa-worker-only"], "1.2.3");
+        assert_eq!(report["preload"], json!(["tupa_worker_only"]));
+        assert_eq!(pool.report(), Ok(report));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_pool_enforces_declared_effects() {
+        let pool = pool(Duration::from_secs(10));
+        let pure = Signature {
+            effects: Some(vec![]),
+            ..Signature::default()
+        };
+        assert_eq!(
+            pool.call_typed("time.sleep", json!(0), &pure),
+            Err(CallError::UndeclaredEffect {
+                effect: "time".into(),
+                event: "time.sleep".into()
+            })
+        );
+        let timed = Signature {
+            effects: Some(vec!["time".into()]),
+            ..Signature::default()
+        };
+        assert_eq!(
+            pool.call_typed("time.sleep", json!(0), &timed),
+            Ok(Value::Null)
+        );
+        assert_eq!(
+            pool.call_typed("math.sqrt", json!(16.0), &pure),
+            Ok(json!(4.0))
+        );
+    }
+}
@@ -58,12 +58,7 @@ async fn main() -> Result<(), String> {
         seed: None,
         input_schema: TypeSchema {
             kind: "any".to_string(),
-            elem: None,
-            fields: None,
-            len: None,
-            name: None,
-            tensor_shape: None,
-            tensor_dtype: None,
+            ..Default::default()
         },
         output_schema: None,
         steps: vec![
@@ -71,11 +66,15 @@ async fn main() -> Result<(), String> {
                 name: "mnist::load_image".to_string(),
                 function_ref: "mnist::load_image".to_string(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             },
             StepPlan {
                 name: "mnist::validate_shape".to_string(),
                 function_ref: "mnist::validate_shape".to_string(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             },
         ],
         constraints: vec![
@@ -83,10 +82,12 @@ async fn main() -> Result<(), String> {
                 metric: "mnist::validate_shape.valid".to_string(),
                 comparator: "eq".to_string(),
                 threshold: 1.0,
+                ..Default::default()
             }, // true -> 1.0
         ],
         metrics: std::collections::HashMap::new(),
         metric_plans: vec![],
+        temporal: vec![],
     };
 
     println!("Running pipeline...");
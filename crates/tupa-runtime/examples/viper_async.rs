@@ -47,12 +47,7 @@ async fn main() -> Result<(), String> {
         seed: None,
         input_schema: TypeSchema {
             kind: "any".to_string(),
-            elem: None,
-            fields: None,
-            len: None,
-            name: None,
-            tensor_shape: None,
-            tensor_dtype: None,
+            ..Default::default()
         },
         output_schema: None,
         steps: vec![
@@ -60,16 +55,22 @@ async fn main() -> Result<(), String> {
                 name: "viper::fetch_market_data".to_string(),
                 function_ref: "viper::fetch_market_data".to_string(),
                 effects: vec!["io".to_string()],
+                external: None,
+                returns_result: false,
             },
             StepPlan {
                 name: "viper::check_smart_copy_constraints".to_string(),
                 function_ref: "viper::check_smart_copy_constraints".to_string(),
                 effects: vec!["io".to_string()],
+                external: None,
+                returns_result: false,
             },
             StepPlan {
                 name: "viper::validate_entry".to_string(),
                 function_ref: "viper::validate_entry".to_string(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             },
         ],
         constraints: vec![
@@ -77,15 +78,18 @@ async fn main() -> Result<(), String> {
                 metric: "viper::validate_entry.entry_validated".to_string(),
                 comparator: "eq".to_string(),
                 threshold: 1.0,
+                ..Default::default()
             },
             ConstraintPlan {
                 metric: "viper::fetch_market_data.market_data_fetched".to_string(),
                 comparator: "eq".to_string(),
                 threshold: 1.0,
+                ..Default::default()
             }, // true -> 1.0
         ],
         metrics: std::collections::HashMap::new(),
         metric_plans: vec![],
+        temporal: vec![],
     };
 
     // 2. Setup Shared State (DB)
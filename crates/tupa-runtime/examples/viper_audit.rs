@@ -61,12 +61,8 @@ fn main() {
         seed: Some(12345),
         input_schema: TypeSchema {
             kind: "ident".to_string(),
-            elem: None,
-            fields: None,
-            len: None,
             name: Some("TradeSignal".to_string()),
-            tensor_shape: None,
-            tensor_dtype: None,
+            ..Default::default()
         },
         output_schema: None,
         steps: vec![
@@ -74,18 +70,24 @@ fn main() {
                 name: "is_safe_market".to_string(),
                 function_ref: "viper::validate_market_regime".to_string(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             },
             StepPlan {
                 name: "position_size".to_string(),
                 function_ref: "viper::calculate_position_size".to_string(),
                 effects: vec!["wallet".to_string()],
+                external: None,
+                returns_result: false,
             },
         ],
         metric_plans: vec![],
+        temporal: vec![],
         constraints: vec![ConstraintPlan {
             metric: "is_safe_market".to_string(),
             comparator: "eq".to_string(),
             threshold: 1.0, // true
+            ..Default::default()
         }],
         metrics: std::collections::HashMap::new(),
     };
@@ -21,12 +21,7 @@ use tupa_runtime::{register_step, run_backtest};
 fn default_schema() -> TypeSchema {
     TypeSchema {
         kind: "any".to_string(),
-        elem: None,
-        fields: None,
-        len: None,
-        name: None,
-        tensor_shape: None,
-        tensor_dtype: None,
+        ..Default::default()
     }
 }
 
@@ -79,14 +74,18 @@ async fn main() {
             name: "signal".to_string(),
             function_ref: "strategy::sma_cross".to_string(),
             effects: vec!["action".to_string()],
+            external: None,
+            returns_result: false,
         }],
         // Risk Management: Don't trade if signal is weak
         constraints: vec![ConstraintPlan {
             metric: "signal.signal_strength".to_string(),
             comparator: "gt".to_string(),
             threshold: 0.5, // Minimum spread required
+            ..Default::default()
         }],
         metric_plans: vec![],
+        temporal: vec![],
         metrics: std::collections::HashMap::new(),
     };
 
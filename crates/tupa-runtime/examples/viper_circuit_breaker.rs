@@ -23,12 +23,7 @@ use tupa_runtime::{configure_circuit_breaker, register_step, run_pipeline_async}
 fn default_schema() -> TypeSchema {
     TypeSchema {
         kind: "any".to_string(),
-        elem: None,
-        fields: None,
-        len: None,
-        name: None,
-        tensor_shape: None,
-        tensor_dtype: None,
+        ..Default::default()
     }
 }
 
@@ -74,9 +69,12 @@ async fn main() {
             name: "price_data".to_string(),
             function_ref: "exchange::get_price".to_string(),
             effects: vec!["price".to_string()],
+            external: None,
+            returns_result: false,
         }],
         constraints: vec![],
         metric_plans: vec![],
+        temporal: vec![],
         metrics: std::collections::HashMap::new(),
     };
 
@@ -30,12 +30,7 @@ fn step_normalize_candles(input: Value) -> Result<Value, String> {
 fn default_schema() -> TypeSchema {
     TypeSchema {
         kind: "any".to_string(),
-        elem: None,
-        fields: None,
-        len: None,
-        name: None,
-        tensor_shape: None,
-        tensor_dtype: None,
+        ..Default::default()
     }
 }
 
@@ -76,18 +71,24 @@ async fn main() {
                 name: "normalized_data".to_string(),
                 function_ref: "viper::normalize".to_string(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             },
             StepPlan {
                 name: "ai_signal".to_string(),
                 function_ref: "viper_model::predict_signal".to_string(),
                 effects: vec!["signal".to_string()],
+                external: None,
+                returns_result: false,
             },
         ],
         metric_plans: vec![],
+        temporal: vec![],
         constraints: vec![ConstraintPlan {
             metric: "ai_signal.signal_strength".to_string(), // Uses dot notation!
             comparator: "gt".to_string(),
             threshold: 0.6, // Only trade if confidence > 60%
+            ..Default::default()
         }],
         metrics: std::collections::HashMap::new(),
     };
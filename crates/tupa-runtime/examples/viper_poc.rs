@@ -70,12 +70,7 @@ async fn main() -> Result<(), String> {
         seed: None,
         input_schema: TypeSchema {
             kind: "any".to_string(),
-            elem: None,
-            fields: None,
-            len: None,
-            name: None,
-            tensor_shape: None,
-            tensor_dtype: None,
+            ..Default::default()
         },
         output_schema: None,
         steps: vec![
@@ -83,25 +78,33 @@ async fn main() -> Result<(), String> {
                 name: "viper::fetch_data".to_string(),
                 function_ref: "viper::fetch_data".to_string(),
                 effects: vec!["io".to_string()],
+                external: None,
+                returns_result: false,
             },
             StepPlan {
                 name: "viper::analyze".to_string(),
                 function_ref: "viper::analyze".to_string(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             },
             StepPlan {
                 name: "viper::execute".to_string(),
                 function_ref: "viper::execute".to_string(),
                 effects: vec!["io".to_string()],
+                external: None,
+                returns_result: false,
             },
         ],
         constraints: vec![ConstraintPlan {
             metric: "viper::analyze.confidence".to_string(),
             comparator: "gt".to_string(),
             threshold: 0.9,
+            ..Default::default()
         }],
         metrics: std::collections::HashMap::new(),
         metric_plans: vec![],
+        temporal: vec![],
     };
 
     println!("\n--- Pipeline Execution ---");
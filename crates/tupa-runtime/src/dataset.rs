@@ -0,0 +1,238 @@
+//! Pipeline metrics over a labeled dataset, as computed by `tupa validate`.
+//!
+//! Every record runs through the pipeline. Its label (`DatasetOptions::label`)
+//! and the prediction read from the output (`DatasetOptions::prediction`)
+//! feed a confusion matrix, and every numeric output field is aggregated:
+//! - counts: `records`, `errors`, `unlabeled`, `true_positives`,
+//!   `false_positives`, `true_negatives`, `false_negatives`
+//! - rates: `error_rate`, `accuracy`, `precision`, `recall`, `f1`,
+//!   `false_positive_rate`, `false_negative_rate`, `true_negative_rate`,
+//!   `approval_rate` (predicted positive) and `positive_rate` (labeled positive)
+//! - per numeric output field `<path>` (nested keys joined with `_`): `<path>_mean`, `_min`,
+//!   `_max`, `_p50`, `_p90`, `_p95` and `_p99`
+//!
+//! A rate whose denominator is empty, such as `false_positive_rate` without
+//! negative labels, is `null`: it was not measured.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+#[derive(Debug, Clone)]
+pub struct DatasetOptions {
+    /// Record field holding the expected class.
+    pub label: String,
+    /// Dotted path of the predicted class in the pipeline output; defaults
+    /// to the last step's output.
+    pub prediction: Option<String>,
+}
+
+impl Default for DatasetOptions {
+    fn default() -> Self {
+        Self {
+            label: "label".into(),
+            prediction: None,
+        }
+    }
+}
+
+/// Splits a record into the pipeline input and its label: the record's
+/// `input` field when present, otherwise the record without the label.
+pub fn split_record(record: &Value, label: &str) -> (Value, Value) {
+    let expected = record.get(label).cloned().unwrap_or(Value::Null);
+    let input = match record.get("input") {
+        Some(input) => input.clone(),
+        None => {
+            let mut input = record.clone();
+            if let Some(obj) = input.as_object_mut() {
+                obj.remove(label);
+            }
+            input
+        }
+    };
+    (input, expected)
+}
+
+/// Reads a binary class: booleans, numbers (`>= 0.5` is positive) and the
+/// strings `"true"`/`"false"`/`"1"`/`"0"`.
+pub fn classify(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Number(n) => n.as_f64().map(|v| v >= 0.5),
+        Value::String(s) => match s.as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Nearest-rank quantile of an ascending, non-empty slice.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[derive(Default)]
+pub(crate) struct DatasetMetrics {
+    records: usize,
+    errors: usize,
+    unlabeled: usize,
+    tp: usize,
+    fp: usize,
+    tn: usize,
+    fn_: usize,
+    numeric: BTreeMap<String, Vec<f64>>,
+}
+
+impl DatasetMetrics {
+    pub(crate) fn add_error(&mut self) {
+        self.records += 1;
+        self.errors += 1;
+    }
+
+    pub(crate) fn add_output(&mut self, output: &Value, prediction: &Value, label: &Value) {
+        self.records += 1;
+        match (classify(prediction), classify(label)) {
+            (Some(true), Some(true)) => self.tp += 1,
+            (Some(true), Some(false)) => self.fp += 1,
+            (Some(false), Some(false)) => self.tn += 1,
+            (Some(false), Some(true)) => self.fn_ += 1,
+            _ => self.unlabeled += 1,
+        }
+        collect_numeric(output, "", &mut self.numeric);
+    }
+
+    pub(crate) fn finish(self) -> Map<String, Value> {
+        let ratio = |num: usize, den: usize| (den > 0).then(|| num as f64 / den as f64);
+        let (tp, fp, tn, fn_) = (self.tp, self.fp, self.tn, self.fn_);
+        let labeled = tp + fp + tn + fn_;
+        let precision = ratio(tp, tp + fp);
+        let recall = ratio(tp, tp + fn_);
+        let f1 = precision.zip(recall).map(|(precision, recall)| {
+            if precision + recall > 0.0 {
+                2.0 * precision * recall / (precision + recall)
+            } else {
+                0.0
+            }
+        });
+
+        let mut metrics = Map::new();
+        for (name, value) in [
+            ("records", json!(self.records)),
+            ("errors", json!(self.errors)),
+            ("unlabeled", json!(self.unlabeled)),
+            ("true_positives", json!(tp)),
+            ("false_positives", json!(fp)),
+            ("true_negatives", json!(tn)),
+            ("false_negatives", json!(fn_)),
+            ("error_rate", json!(ratio(self.errors, self.records))),
+            ("accuracy", json!(ratio(tp + tn, labeled))),
+            ("precision", json!(precision)),
+            ("recall", json!(recall)),
+            ("f1", json!(f1)),
+            ("false_positive_rate", json!(ratio(fp, fp + tn))),
+            ("false_negative_rate", json!(ratio(fn_, fn_ + tp))),
+            ("true_negative_rate", json!(ratio(tn, tn + fp))),
+            ("approval_rate", json!(ratio(tp + fp, labeled))),
+            ("positive_rate", json!(ratio(tp + fn_, labeled))),
+        ] {
+            metrics.insert(name.to_string(), value);
+        }
+        for (path, mut values) in self.numeric {
+            values.sort_by(f64::total_cmp);
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            metrics.insert(format!("{path}_mean"), json!(mean));
+            metrics.insert(format!("{path}_min"), json!(values[0]));
+            metrics.insert(format!("{path}_max"), json!(values[values.len() - 1]));
+            for (suffix, q) in [("p50", 0.5), ("p90", 0.9), ("p95", 0.95), ("p99", 0.99)] {
+                metrics.insert(format!("{path}_{suffix}"), json!(quantile(&values, q)));
+            }
+        }
+        metrics
+    }
+}
+
+fn collect_numeric(value: &Value, path: &str, out: &mut BTreeMap<String, Vec<f64>>) {
+    match value {
+        Value::Number(n) => {
+            let key = if path.is_empty() { "output" } else { path };
+            out.entry(key.to_string()).or_default().extend(n.as_f64());
+        }
+        Value::Object(obj) => {
+            for (key, field) in obj {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}_{key}")
+                };
+                collect_numeric(field, &path, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_from_confusion_matrix_and_numeric_fields() {
+        let mut metrics = DatasetMetrics::default();
+        for (prediction, label, score) in [
+            (true, true, 0.9),
+            (true, false, 0.7),
+            (false, false, 0.1),
+            (false, false, 0.2),
+            (false, true, 0.4),
+        ] {
+            let output = json!({ "risk": { "score": score } });
+            metrics.add_output(&output, &json!(prediction), &json!(label));
+        }
+        metrics.add_error();
+        let metrics = metrics.finish();
+
+        assert_eq!(metrics["records"], json!(6));
+        assert_eq!(metrics["error_rate"], json!(1.0 / 6.0));
+        assert_eq!(metrics["false_positive_rate"], json!(1.0 / 3.0));
+        assert_eq!(metrics["false_negative_rate"], json!(0.5));
+        assert_eq!(metrics["approval_rate"], json!(0.4));
+        assert_eq!(metrics["accuracy"], json!(0.6));
+        assert_eq!(metrics["risk_score_max"], json!(0.9));
+        assert_eq!(metrics["risk_score_p50"], json!(0.4));
+    }
+
+    #[test]
+    fn test_rates_without_denominator_are_unmeasured() {
+        let mut metrics = DatasetMetrics::default();
+        metrics.add_output(&json!({}), &json!(true), &json!(null));
+        metrics.add_error();
+        let metrics = metrics.finish();
+
+        assert_eq!(metrics["unlabeled"], json!(1));
+        assert_eq!(metrics["error_rate"], json!(0.5));
+        for rate in [
+            "accuracy",
+            "precision",
+            "f1",
+            "false_positive_rate",
+            "approval_rate",
+        ] {
+            assert_eq!(metrics[rate], Value::Null, "{rate}");
+        }
+    }
+
+    #[test]
+    fn test_split_record_and_classify() {
+        let (input, label) = split_record(&json!({ "amount": 10, "is_fraud": 1 }), "is_fraud");
+        assert_eq!(input, json!({ "amount": 10 }));
+        assert_eq!(classify(&label), Some(true));
+
+        let (input, label) = split_record(&json!({ "input": 5, "label": "false" }), "label");
+        assert_eq!(input, json!(5));
+        assert_eq!(classify(&label), Some(false));
+        assert_eq!(classify(&json!("BUY")), None);
+    }
+}
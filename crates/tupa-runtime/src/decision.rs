@@ -0,0 +1,165 @@
+//! The standard `Decision` output of policy pipelines.
+//!
+//! Mirrors the built-in `Decision` type of the typechecker and the
+//! `decide`/`with_gate`/`with_score`/`with_flag` built-ins, so host steps
+//! written in Rust fold component results the same way Tupã code does.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    pub action: String,
+    pub stage: String,
+    pub reason: String,
+    /// Weighted mean of the scored components; gates do not contribute.
+    pub score: f64,
+    pub components: Vec<DecisionComponent>,
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecisionComponent {
+    pub name: String,
+    pub passed: bool,
+    pub score: f64,
+    pub weight: f64,
+    pub reason: String,
+}
+
+impl Decision {
+    /// `decide(action, stage, reason)`: a decision with no components.
+    pub fn new(
+        action: impl Into<String>,
+        stage: impl Into<String>,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self {
+            action: action.into(),
+            stage: stage.into(),
+            reason: reason.into(),
+            score: 0.0,
+            components: Vec::new(),
+            flags: Vec::new(),
+        }
+    }
+
+    /// `with_gate(d, name, check)`: records a `pass`/`fail`/`warn` result.
+    ///
+    /// A failed gate adds `name` to `flags`; the first failed gate also
+    /// becomes the decision's `reason`.
+    pub fn with_gate(
+        mut self,
+        name: impl Into<String>,
+        passed: bool,
+        reason: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        let reason = reason.into();
+        if !passed {
+            if self.components.iter().all(|c| c.passed) {
+                self.reason = reason.clone();
+            }
+            self = self.with_flag(name.clone());
+        }
+        self.components.push(DecisionComponent {
+            name,
+            passed,
+            score: if passed { 1.0 } else { 0.0 },
+            weight: 0.0,
+            reason,
+        });
+        self
+    }
+
+    /// `with_score(d, name, score)`: records a `score`/`weighted` result and
+    /// recomputes `score` as the weighted mean of all scored components.
+    pub fn with_score(
+        mut self,
+        name: impl Into<String>,
+        score: f64,
+        weight: f64,
+        reason: impl Into<String>,
+    ) -> Self {
+        self.components.push(DecisionComponent {
+            name: name.into(),
+            passed: true,
+            score,
+            weight,
+            reason: reason.into(),
+        });
+        let (total, weights) = self
+            .components
+            .iter()
+            .filter(|c| c.weight > 0.0)
+            .fold((0.0, 0.0), |(total, weights), c| {
+                (total + c.score * c.weight, weights + c.weight)
+            });
+        self.score = if weights > 0.0 { total / weights } else { 0.0 };
+        self
+    }
+
+    /// `with_flag(d, flag)`: adds `flag` once.
+    pub fn with_flag(mut self, flag: impl Into<String>) -> Self {
+        let flag = flag.into();
+        if !self.flags.contains(&flag) {
+            self.flags.push(flag);
+        }
+        self
+    }
+
+    /// Whether every gate passed.
+    pub fn passed(&self) -> bool {
+        self.components.iter().all(|c| c.passed)
+    }
+
+    /// Reads a decision from a step output, if it has the `Decision` shape.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let obj = value.as_object()?;
+        let shaped = ["action", "stage", "reason", "score", "components", "flags"]
+            .iter()
+            .all(|key| obj.contains_key(*key));
+        if !shaped {
+            return None;
+        }
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_combinators_fold_components() {
+        let d = Decision::new("BUY", "confirmed", "entry")
+            .with_gate("spread", true, "spread_within_limit")
+            .with_score("trend", 1.0, 30.0, "trend_score_ok")
+            .with_score("volume", 0.0, 10.0, "volume_low")
+            .with_gate("cooldown", false, "stop_loss_cooldown")
+            .with_gate("liquidity", false, "book_too_thin")
+            .with_flag("paper")
+            .with_flag("paper");
+
+        assert_eq!(d.score, 0.75);
+        assert_eq!(d.reason, "stop_loss_cooldown");
+        assert_eq!(d.flags, ["cooldown", "liquidity", "paper"]);
+        assert_eq!(d.components.len(), 5);
+        assert!(!d.passed());
+    }
+
+    #[test]
+    fn test_from_value_requires_decision_shape() {
+        let d = Decision::new("HOLD", "pending", "warming_up");
+        assert_eq!(Decision::from_value(&d.to_value()), Some(d));
+        assert_eq!(
+            Decision::from_value(&json!({ "action": "BUY", "score": 1.0 })),
+            None
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Explanations of why a pipeline produced its output.
+//!
+//! `Runtime::run_pipeline_explained` returns an `Explanation` alongside the
+//! result: one node per step with its outcome, its output and the events that
+//! led to it. Events come from two places:
+//! - step outputs: `Decision` components and the `reason` strings of built-in
+//!   results such as `confirm`, `cooldown` or `check` become `guard`/`reason`
+//!   events;
+//! - step code: Rust steps call `branch`, `bind` and `guard` while they run,
+//!   and Python steps call the same functions of the `tupa_explain` module.
+//!   Outside an explained run these calls do nothing.
+//!
+//! Branches and bindings are what the step code reports; the runtime does
+//! not instrument step bodies itself.
+
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::decision::Decision;
+
+/// Why a step produced its output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExplainEvent {
+    /// An `if`/`match` took the arm `taken`.
+    Branch { label: String, taken: String },
+    /// A bound variable had `value`.
+    Binding { name: String, value: Value },
+    /// A check passed or fired.
+    Guard {
+        name: String,
+        passed: bool,
+        reason: String,
+    },
+    /// A scored `Decision` component.
+    Score {
+        name: String,
+        score: f64,
+        weight: f64,
+        reason: String,
+    },
+    /// A `reason` string found at `path` in the step output.
+    Reason { path: String, reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepExplanation {
+    pub step: String,
+    pub function_ref: String,
+    /// `success`, `failure` or `blocked`, as in the step metrics.
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub events: Vec<ExplainEvent>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    pub pipeline: String,
+    pub steps: Vec<StepExplanation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision: Option<Decision>,
+}
+
+impl Explanation {
+    pub(crate) fn new(pipeline: &str) -> Self {
+        Self {
+            pipeline: pipeline.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn step(&self, name: &str) -> Option<&StepExplanation> {
+        self.steps.iter().find(|step| step.step == name)
+    }
+
+    /// Indented text rendering, as printed by `tupa run --explain`.
+    pub fn render_text(&self) -> String {
+        let mut out = format!("pipeline {}\n", self.pipeline);
+        for step in &self.steps {
+            let _ = writeln!(out, "  step {} [{}]", step.step, step.status);
+            for event in &step.events {
+                let _ = match event {
+                    ExplainEvent::Branch { label, taken } => {
+                        writeln!(out, "    branch {label} -> {taken}")
+                    }
+                    ExplainEvent::Binding { name, value } => {
+                        writeln!(out, "    let {name} = {value}")
+                    }
+                    ExplainEvent::Guard {
+                        name,
+                        passed,
+                        reason,
+                    } => {
+                        let verdict = if *passed { "pass" } else { "FIRED" };
+                        writeln!(out, "    guard {name}: {verdict} ({reason})")
+                    }
+                    ExplainEvent::Score {
+                        name,
+                        score,
+                        weight,
+                        reason,
+                    } => writeln!(out, "    score {name}: {score} x {weight} ({reason})"),
+                    ExplainEvent::Reason { path, reason } => {
+                        writeln!(out, "    reason {path}: {reason}")
+                    }
+                };
+            }
+            if let Some(error) = &step.error {
+                let _ = writeln!(out, "    error: {error}");
+            }
+        }
+        if let Some(decision) = &self.decision {
+            let _ = writeln!(
+                out,
+                "  decision {} [{}]: {} (score {})",
+                decision.action, decision.stage, decision.reason, decision.score
+            );
+        }
+        out
+    }
+}
+
+thread_local! {
+    static THREAD_EVENTS: RefCell<Option<Vec<ExplainEvent>>> = const { RefCell::new(None) };
+}
+
+tokio::task_local! {
+    static TASK_EVENTS: RefCell<Vec<ExplainEvent>>;
+}
+
+/// Records that an `if`/`match` labelled `label` took the arm `taken`.
+pub fn branch(label: impl Into<String>, taken: impl Into<String>) {
+    record(ExplainEvent::Branch {
+        label: label.into(),
+        taken: taken.into(),
+    });
+}
+
+/// Records the value bound to `name`.
+pub fn bind(name: impl Into<String>, value: impl Serialize) {
+    record(ExplainEvent::Binding {
+        name: name.into(),
+        value: serde_json::to_value(value).unwrap_or(Value::Null),
+    });
+}
+
+/// Records the outcome of the check `name`.
+pub fn guard(name: impl Into<String>, passed: bool, reason: impl Into<String>) {
+    record(ExplainEvent::Guard {
+        name: name.into(),
+        passed,
+        reason: reason.into(),
+    });
+}
+
+fn record(event: ExplainEvent) {
+    let mut event = Some(event);
+    let in_task = TASK_EVENTS
+        .try_with(|events| events.borrow_mut().extend(event.take()))
+        .is_ok();
+    if !in_task {
+        THREAD_EVENTS.with(|events| {
+            if let Some(events) = events.borrow_mut().as_mut() {
+                events.extend(event.take());
+            }
+        });
+    }
+}
+
+/// Whether a step running on this thread or task is being explained.
+pub(crate) fn capturing() -> bool {
+    TASK_EVENTS.try_with(|_| ()).is_ok() || THREAD_EVENTS.with(|events| events.borrow().is_some())
+}
+
+/// Records the events a Python step reported through `tupa_explain`.
+pub(crate) fn record_reported(events: Vec<Value>) {
+    for event in events {
+        if let Ok(event) = serde_json::from_value(event) {
+            record(event);
+        }
+    }
+}
+
+/// Runs a synchronous step and returns the events it recorded.
+pub(crate) fn capture<R>(f: impl FnOnce() -> R) -> (R, Vec<ExplainEvent>) {
+    let outer = THREAD_EVENTS.with(|events| events.replace(Some(Vec::new())));
+    let result = f();
+    let events = THREAD_EVENTS.with(|events| events.replace(outer));
+    (result, events.unwrap_or_default())
+}
+
+/// Runs an asynchronous step and returns the events it recorded.
+pub(crate) async fn capture_async<F: Future>(future: F) -> (F::Output, Vec<ExplainEvent>) {
+    TASK_EVENTS
+        .scope(RefCell::new(Vec::new()), async {
+            let result = future.await;
+            (result, TASK_EVENTS.with(|events| events.take()))
+        })
+        .await
+}
+
+/// Events implied by a step output: decision components and built-in reasons.
+pub(crate) fn output_events(output: &Value) -> Vec<ExplainEvent> {
+    let mut events = Vec::new();
+    collect_output_events(output, "", &mut events);
+    events
+}
+
+fn collect_output_events(value: &Value, path: &str, events: &mut Vec<ExplainEvent>) {
+    if let Some(decision) = Decision::from_value(value) {
+        for c in decision.components {
+            events.push(if c.weight > 0.0 {
+                ExplainEvent::Score {
+                    name: c.name,
+                    score: c.score,
+                    weight: c.weight,
+                    reason: c.reason,
+                }
+            } else {
+                ExplainEvent::Guard {
+                    name: c.name,
+                    passed: c.passed,
+                    reason: c.reason,
+                }
+            });
+        }
+        return;
+    }
+    let Some(obj) = value.as_object() else {
+        return;
+    };
+    if let Some(reason) = obj.get("reason").and_then(Value::as_str) {
+        let name = if path.is_empty() { "output" } else { path };
+        let passed = obj
+            .get("passed")
+            .and_then(Value::as_bool)
+            .or_else(|| obj.get("blocked").and_then(Value::as_bool).map(|b| !b));
+        events.push(match passed {
+            Some(passed) => ExplainEvent::Guard {
+                name: name.to_string(),
+                passed,
+                reason: reason.to_string(),
+            },
+            None => ExplainEvent::Reason {
+                path: name.to_string(),
+                reason: reason.to_string(),
+            },
+        });
+    }
+    for (key, field) in obj {
+        let path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{path}.{key}")
+        };
+        collect_output_events(field, &path, events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_capture_collects_host_events_only_while_active() {
+        branch("ignored", "then");
+        let (value, events) = capture(|| {
+            branch("price > threshold", "then");
+            bind("spread", 0.02);
+            7
+        });
+        assert_eq!(value, 7);
+        assert_eq!(
+            events,
+            [
+                ExplainEvent::Branch {
+                    label: "price > threshold".into(),
+                    taken: "then".into()
+                },
+                ExplainEvent::Binding {
+                    name: "spread".into(),
+                    value: json!(0.02)
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_events_read_builtin_reasons_and_decisions() {
+        let events = output_events(&json!({
+            "signal": { "passed": false, "pending": true, "remaining_hits": 2, "reason": "signal_confirmation" },
+            "note": { "reason": "warming_up" }
+        }));
+        assert!(events.contains(&ExplainEvent::Guard {
+            name: "signal".into(),
+            passed: false,
+            reason: "signal_confirmation".into()
+        }));
+        assert!(events.contains(&ExplainEvent::Reason {
+            path: "note".into(),
+            reason: "warming_up".into()
+        }));
+
+        let decision = Decision::new("SELL", "confirmed", "exit")
+            .with_gate("stop_loss", false, "stop_loss_hit")
+            .with_score("trend", 0.5, 2.0, "trend_weak");
+        assert_eq!(output_events(&decision.to_value()).len(), 2);
+    }
+}
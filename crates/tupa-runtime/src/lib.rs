@@ -7,16 +7,50 @@
 //! - **Circuit Breaker**: `CircuitBreaker` struct for failure handling.
 //! - **Backtesting**: `run_backtest` function for historical simulation.
 //! - **Audit Logs**: Structured JSON logging for compliance.
+//! - **Dataset Validation**: `Runtime::validate_dataset` computes confusion-matrix and aggregate metrics over labeled records.
+//! - **Metrics**: `Runtime::render_metrics` exports latencies and outcomes in Prometheus text format.
+//! - **Temporal State**: `StateStore` persists confirmation and cooldown stages across runs.
+//! - **Explanations**: `Runtime::run_pipeline_explained` reports the guards, branches and reasons behind each step.
+//! - **Tracing**: `Runtime::run_pipeline_traced` records OTLP-JSON spans per step and external call.
+//! - **Typed Steps**: `#[tupa_step]` adapts typed Rust functions and writes their `@external(rust=...)` declarations.
+//! - **Linking**: `Runtime::link` resolves every step and checks allowed effects before anything runs.
 //!
 //! See `examples/viper_backtest.rs` and `examples/viper_circuit_breaker.rs` for usage.
 
+// Lets `#[tupa_step]` expansions name `::tupa_runtime` inside this crate.
+extern crate self as tupa_runtime;
+
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tracing::{error, info, instrument, warn};
-use tupa_codegen::execution_plan::{ExecutionPlan, TypeSchema};
+use tupa_codegen::execution_plan::{
+    ConstraintOperand, ConstraintPlan, ConstraintSeverity, ExecutionPlan, ExternalSignature,
+    StepPlan, TypeSchema,
+};
+pub use tupa_macros::{tupa_step, TupaType};
+pub use tupa_pyffi::PythonEnv;
+use tupa_pyffi::{CallError, Layout, Signature};
+
+pub mod dataset;
+pub mod decision;
+pub mod explain;
+pub mod link;
+pub mod metrics;
+pub mod state;
+pub mod step;
+pub mod tensor;
+pub mod trace;
+
+use dataset::{DatasetMetrics, DatasetOptions};
+use decision::Decision;
+use explain::{ExplainEvent, Explanation, StepExplanation};
+use link::{LinkError, LinkedPlan};
+use metrics::MetricsRegistry;
+use state::{InMemoryStateStore, StateStore};
+use trace::{ActiveSpan, PipelineTrace, SpanScope, TraceContext, TraceRecorder};
 
 pub type RuntimeResult<T> = Result<T, RuntimeError>;
 
@@ -34,6 +68,30 @@ pub enum RuntimeError {
     ValidationError(String),
     #[error("Circuit breaker open: {0}")]
     CircuitBreakerOpen(String),
+    #[error("State store error: {0}")]
+    StateError(String),
+    #[error("Trace error: {0}")]
+    Trace(String),
+    /// An external call's argument or return value does not match the
+    /// declared Tupã type; `detail` starts with the offending path.
+    #[error("External call {function} type mismatch: {detail}")]
+    ExternalTypeMismatch { function: String, detail: String },
+    #[error("Python environment error: {0}")]
+    PythonEnv(String),
+    /// A step declares an effect outside `Runtime::allow_effects`.
+    #[error("Step '{step}' declares effect {effect}, which the runtime does not allow")]
+    EffectNotAllowed { step: String, effect: String },
+    /// An external attempted an operation outside its declared effects,
+    /// e.g. `time` for `time.time`; the operation was blocked.
+    #[error("External call {function} used undeclared effect {effect}: {event}")]
+    EffectViolation {
+        function: String,
+        effect: String,
+        event: String,
+    },
+    /// A step declared to return `Result<T, E>` returned `Err(error)`.
+    #[error("Step '{step}' failed: {error}")]
+    StepFailed { step: String, error: Value },
 }
 
 // --- Circuit Breaker ---
@@ -60,6 +118,25 @@ enum BreakerState {
     HalfOpen, // Testing recovery
 }
 
+impl BreakerState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+
+    /// Value of the breaker state gauge.
+    fn gauge(&self) -> f64 {
+        match self {
+            BreakerState::Closed => 0.0,
+            BreakerState::Open => 1.0,
+            BreakerState::HalfOpen => 2.0,
+        }
+    }
+}
+
 impl CircuitBreaker {
     pub fn new(failure_threshold: usize, reset_timeout: Duration) -> Self {
         Self {
@@ -104,14 +181,22 @@ impl CircuitBreaker {
 }
 
 // --- Runtime Architecture ---
-type StepFunction = Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync>;
+/// Shared so a step runs without holding the runtime's lock, and steps of
+/// overlapping runs can run concurrently.
+type StepFunction = Arc<dyn Fn(Value) -> Result<Value, String> + Send + Sync>;
 type AsyncStepFunction =
     Box<dyn Fn(Value) -> futures::future::BoxFuture<'static, Result<Value, String>> + Send + Sync>;
 
 struct RuntimeState {
     steps: HashMap<String, StepFunction>,
     async_steps: HashMap<String, AsyncStepFunction>,
+    /// Signatures of `register_rust_fn` functions, whose steps are under
+    /// `rust:<name>`.
+    rust_signatures: HashMap<String, ExternalSignature>,
+    /// Effects steps may declare; `None` allows any.
+    allowed_effects: Option<BTreeSet<String>>,
     circuit_breaker: CircuitBreaker,
+    state_store: Arc<dyn StateStore>,
 }
 
 impl RuntimeState {
@@ -119,14 +204,26 @@ impl RuntimeState {
         Self {
             steps: HashMap::new(),
             async_steps: HashMap::new(),
+            rust_signatures: HashMap::new(),
+            allowed_effects: None,
             circuit_breaker: CircuitBreaker::new(5, Duration::from_secs(30)),
+            state_store: Arc::new(InMemoryStateStore::new()),
         }
     }
 }
 
+/// Temporal state loaded for one run, with the locks on its store keys.
+struct TemporalRun {
+    keys: Vec<String>,
+    current: Value,
+    _guards: Vec<tokio::sync::OwnedMutexGuard<()>>,
+}
+
 #[derive(Clone)]
 pub struct Runtime {
     state: Arc<Mutex<RuntimeState>>,
+    metrics: Arc<MetricsRegistry>,
+    temporal_locks: Arc<state::KeyLocks>,
 }
 
 impl Default for Runtime {
@@ -139,15 +236,28 @@ impl Runtime {
     pub fn new() -> Self {
         Self {
             state: Arc::new(Mutex::new(RuntimeState::new())),
+            metrics: Arc::new(MetricsRegistry::new()),
+            temporal_locks: Arc::new(state::KeyLocks::default()),
         }
     }
 
+    /// Metrics recorded by this runtime: pipeline and step latencies and
+    /// outcomes, constraint results, breaker transitions and Python calls.
+    pub fn metrics(&self) -> &MetricsRegistry {
+        &self.metrics
+    }
+
+    /// Renders `metrics()` in the Prometheus text exposition format.
+    pub fn render_metrics(&self) -> String {
+        self.metrics.render_prometheus()
+    }
+
     pub fn register_step<F>(&self, name: &str, func: F)
     where
         F: Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
     {
         let mut state = self.state.lock().unwrap();
-        state.steps.insert(name.to_string(), Box::new(func));
+        state.steps.insert(name.to_string(), Arc::new(func));
     }
 
     pub fn register_async_step<F>(&self, name: &str, func: F)
@@ -161,32 +271,418 @@ impl Runtime {
         state.async_steps.insert(name.to_string(), Box::new(func));
     }
 
+    /// Implements `@external(rust="name")` functions. `signature` is what
+    /// the host declares, e.g. with `ExternalSignature::from_declaration`;
+    /// `resolve_plan` checks it against the `.tp` declaration.
+    pub fn register_rust_fn<F>(&self, name: &str, signature: ExternalSignature, func: F)
+    where
+        F: Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        let mut state = self.state.lock().unwrap();
+        state.steps.insert(format!("rust:{name}"), Arc::new(func));
+        state.rust_signatures.insert(name.to_string(), signature);
+    }
+
+    /// Registers a `#[tupa_step]` function under its `@external(rust=...)`
+    /// name, with the signature of its declaration.
+    pub fn register_tupa_step<S: step::TupaStep + 'static>(&self) {
+        self.register_rust_fn(S::NAME, S::signature(), S::call);
+    }
+
     pub fn configure_circuit_breaker(&self, threshold: usize, timeout: Duration) {
         let mut state = self.state.lock().unwrap();
         state.circuit_breaker = CircuitBreaker::new(threshold, timeout);
     }
 
+    /// Restricts the effects steps may declare to `effects`, named as in
+    /// plans (`io`, `time`, `random`, `external:<name>`); runtimes start
+    /// allowing any. Linking and every run check it.
+    pub fn allow_effects<I, S>(&self, effects: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut state = self.state.lock().unwrap();
+        state.allowed_effects = Some(effects.into_iter().map(Into::into).collect());
+    }
+
+    /// Resolves every step of `plan` and checks its declared effects, for
+    /// `run_linked`. Fails with one error per problem, in step order.
+    pub fn link(&self, plan: &ExecutionPlan) -> Result<LinkedPlan, Vec<LinkError>> {
+        let errors: Vec<LinkError> = plan
+            .steps
+            .iter()
+            .flat_map(|step| self.link_step(step))
+            .collect();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(LinkedPlan {
+            plan: Arc::new(plan.clone()),
+            python_env: self.python_env(plan),
+        })
+    }
+
+    /// Checks that every step of `plan` resolves: to a registered (async)
+    /// step, to a Rust function registered with a matching signature, or to
+    /// an importable Python callable, and declares only allowed effects.
+    /// Every unlinked run checks this before its first step; `link` reports
+    /// every failure instead of the first.
+    pub fn resolve_plan(&self, plan: &ExecutionPlan) -> RuntimeResult<()> {
+        for step in &plan.steps {
+            if let Some(failure) = self.link_step(step).into_iter().next() {
+                return Err(failure.error);
+            }
+        }
+        Ok(())
+    }
+
+    fn link_step(&self, step: &StepPlan) -> Vec<LinkError> {
+        self.resolve_step(step)
+            .err()
+            .into_iter()
+            .chain(self.check_effects(step))
+            .map(|error| LinkError {
+                step: step.name.clone(),
+                error,
+            })
+            .collect()
+    }
+
+    /// Effects `step` declares outside `allow_effects`.
+    fn check_effects(&self, step: &StepPlan) -> Vec<RuntimeError> {
+        let guard = self.state.lock().unwrap();
+        let Some(allowed) = &guard.allowed_effects else {
+            return Vec::new();
+        };
+        step.effects
+            .iter()
+            .chain(step.external.iter().flat_map(|external| &external.effects))
+            .filter(|effect| *effect != "pure" && !allowed.contains(*effect))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|effect| RuntimeError::EffectNotAllowed {
+                step: step.name.clone(),
+                effect: effect.clone(),
+            })
+            .collect()
+    }
+
+    fn resolve_step(&self, step: &StepPlan) -> RuntimeResult<()> {
+        if let Some(name) = step.function_ref.strip_prefix("rust:") {
+            return self.check_rust_fn(step, name);
+        }
+        let registered = {
+            let guard = self.state.lock().unwrap();
+            guard.steps.contains_key(&step.function_ref)
+                || guard.async_steps.contains_key(&step.function_ref)
+        };
+        if registered {
+            return Ok(());
+        }
+        let resolved = match python_path(&step.function_ref) {
+            Some(path) => tupa_pyffi::resolve_python_path(&path),
+            None => Err("not registered".to_string()),
+        };
+        resolved.map_err(|e| {
+            RuntimeError::FunctionNotFound(format!(
+                "step '{}' ({}): {e}",
+                step.name, step.function_ref
+            ))
+        })
+    }
+
+    fn check_rust_fn(&self, step: &StepPlan, name: &str) -> RuntimeResult<()> {
+        let guard = self.state.lock().unwrap();
+        let Some(registered) = guard.rust_signatures.get(name) else {
+            return Err(RuntimeError::FunctionNotFound(format!(
+                "step '{}' ({}): not registered",
+                step.name, step.function_ref
+            )));
+        };
+        let Some(declared) = &step.external else {
+            return Ok(());
+        };
+        let sides = [
+            ("argument", &registered.param, &declared.param),
+            ("return", &registered.returns, &declared.returns),
+        ];
+        for (side, host, plan) in sides {
+            let mismatch = match (host, plan) {
+                (Some(host), Some(plan)) => schema_mismatch(host, plan, side),
+                (None, None) => None,
+                (Some(_), None) => Some(format!("{side} is registered but not declared")),
+                (None, Some(_)) => Some(format!("{side} is declared but not registered")),
+            };
+            if let Some(detail) = mismatch {
+                return Err(RuntimeError::ExternalTypeMismatch {
+                    function: declared.function.clone(),
+                    detail: format!("rust function {name} does not match: {detail}"),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `env` (virtualenv or interpreter packages, `sys.path` entries
+    /// and preloaded modules) to the Python interpreter that runs `py:`
+    /// steps. The interpreter is shared by every runtime in the process.
+    pub fn configure_python(&self, env: &PythonEnv) -> RuntimeResult<()> {
+        tupa_pyffi::configure_python(env).map_err(RuntimeError::PythonEnv)
+    }
+
+    /// Python and package versions behind `plan`'s Python steps, as
+    /// reported by `tupa_pyffi::python_report`, with their hash; `None` when
+    /// no step calls Python.
+    pub fn python_env(&self, plan: &ExecutionPlan) -> Option<(Value, tupa_audit::Hash)> {
+        let uses_python = {
+            let guard = self.state.lock().unwrap();
+            plan.steps.iter().any(|step| {
+                !guard.steps.contains_key(&step.function_ref)
+                    && !guard.async_steps.contains_key(&step.function_ref)
+                    && python_path(&step.function_ref).is_some()
+            })
+        };
+        if !uses_python {
+            return None;
+        }
+        let report = tupa_pyffi::python_report().ok()?;
+        let hash = tupa_audit::hash_json(&report);
+        Some((report, hash))
+    }
+
+    /// Replaces the store used for pipelines with temporal state; runtimes
+    /// start with an `InMemoryStateStore`.
+    pub fn set_state_store(&self, store: Arc<dyn StateStore>) {
+        let mut state = self.state.lock().unwrap();
+        state.state_store = store;
+    }
+
     #[instrument(skip(self, plan), fields(pipeline = plan.name))]
     pub async fn run_pipeline_async(
         &self,
         plan: &ExecutionPlan,
         input: Value,
+    ) -> RuntimeResult<Value> {
+        self.run_measured(plan, input, None, None, None).await
+    }
+
+    /// Runs a plan from `link` like `run_pipeline_async`, without resolving
+    /// its steps again; their effects are checked against the current
+    /// `allow_effects`.
+    #[instrument(skip(self, linked), fields(pipeline = linked.plan.name))]
+    pub async fn run_linked(&self, linked: &LinkedPlan, input: Value) -> RuntimeResult<Value> {
+        self.run_measured(&linked.plan, input, Some(linked), None, None)
+            .await
+    }
+
+    /// Runs the pipeline like `run_pipeline_async` and returns an
+    /// `Explanation` of every step that ran, even when the run fails.
+    pub async fn run_pipeline_explained(
+        &self,
+        plan: &ExecutionPlan,
+        input: Value,
+    ) -> (RuntimeResult<Value>, Explanation) {
+        let mut explanation = Explanation::new(&plan.name);
+        let result = self
+            .run_measured(plan, input, None, None, Some(&mut explanation))
+            .await;
+        if let Ok(output) = &result {
+            explanation.decision = final_decision(plan, output);
+        }
+        (result, explanation)
+    }
+
+    /// Runs the pipeline like `run_pipeline_async` and returns the spans it
+    /// recorded alongside the result, even when the run fails.
+    ///
+    /// With a `parent` context (e.g. from `TraceContext::parse_traceparent`)
+    /// the root span joins the caller's trace; otherwise a new trace starts.
+    pub async fn run_pipeline_traced(
+        &self,
+        plan: &ExecutionPlan,
+        input: Value,
+        parent: Option<TraceContext>,
+    ) -> (RuntimeResult<Value>, PipelineTrace) {
+        let context = parent.unwrap_or_else(TraceContext::new_root);
+        let recorder = TraceRecorder::new(context.trace_id.clone());
+        let mut root = recorder.start(
+            format!("pipeline {}", plan.name),
+            context.parent_span_id.as_deref(),
+        );
+        root.set_attribute("tupa.plan.name", json!(plan.name));
+        root.set_attribute("tupa.plan.version", json!(plan.version));
+        let plan_json = serde_json::to_value(plan).unwrap_or(Value::Null);
+        root.set_attribute(
+            "tupa.plan.hash",
+            json!(tupa_audit::hash_json(&plan_json).to_string()),
+        );
+        if let Some(seed) = plan.seed {
+            root.set_attribute("tupa.plan.seed", json!(seed));
+        }
+        if let Some((report, hash)) = self.python_env(plan) {
+            root.set_attribute("tupa.python.version", report["version"].clone());
+            root.set_attribute("tupa.python.env_hash", json!(hash.to_string()));
+        }
+
+        let scope = SpanScope {
+            recorder: recorder.clone(),
+            parent: root.span_id().to_string(),
+        };
+        let result = self
+            .run_measured(plan, input, None, Some(&scope), None)
+            .await;
+        if let Ok(output) = &result {
+            let report = self.evaluate_constraints(plan, output);
+            for constraint in report["constraints"].as_array().into_iter().flatten() {
+                root.add_event(
+                    "constraint",
+                    [
+                        "metric",
+                        "comparator",
+                        "threshold",
+                        "value",
+                        "pass",
+                        "status",
+                    ]
+                    .iter()
+                    .filter(|key| !constraint[**key].is_null())
+                    .map(|key| (format!("tupa.constraint.{key}"), constraint[*key].clone()))
+                    .collect(),
+                );
+            }
+            root.set_attribute("tupa.constraints.success", report["success"].clone());
+            if let Some(decision) = final_decision(plan, output) {
+                root.set_attribute("tupa.decision.action", json!(decision.action));
+                root.set_attribute("tupa.decision.stage", json!(decision.stage));
+                root.set_attribute("tupa.decision.reason", json!(decision.reason));
+            }
+        }
+        root.set_result(&result);
+        let root_id = root.span_id().to_string();
+        recorder.finish(root);
+
+        let trace = PipelineTrace {
+            trace_id: context.trace_id,
+            sampled: context.sampled,
+            spans: recorder.into_spans(&root_id),
+        };
+        (result, trace)
+    }
+
+    async fn run_measured(
+        &self,
+        plan: &ExecutionPlan,
+        input: Value,
+        linked: Option<&LinkedPlan>,
+        scope: Option<&SpanScope>,
+        explain: Option<&mut Explanation>,
+    ) -> RuntimeResult<Value> {
+        let started = Instant::now();
+        let result = self
+            .execute_pipeline(plan, input, linked, scope, explain)
+            .await;
+        let labels = [
+            ("pipeline", plan.name.as_str()),
+            ("status", if result.is_ok() { "success" } else { "failure" }),
+        ];
+        self.metrics
+            .inc_counter(metrics::PIPELINE_RUNS, "Pipeline runs by outcome.", &labels);
+        self.metrics.observe(
+            metrics::PIPELINE_DURATION,
+            "Pipeline run latency in seconds.",
+            &labels[..1],
+            started.elapsed().as_secs_f64(),
+        );
+        result
+    }
+
+    async fn execute_pipeline(
+        &self,
+        plan: &ExecutionPlan,
+        input: Value,
+        linked: Option<&LinkedPlan>,
+        scope: Option<&SpanScope>,
+        mut explain: Option<&mut Explanation>,
     ) -> RuntimeResult<Value> {
         info!(target: "audit", event = "pipeline_start", plan = plan.name);
+        let explaining = explain.is_some();
         validate_value_against_schema(&input, &plan.input_schema, "input")?;
+        // Linked plans only check effects again, which `allow_effects` may
+        // have narrowed since linking. Under `--explain` a step that cannot
+        // run still shows up as a failed node, so the tree points at it.
+        for step in &plan.steps {
+            let failure = match linked {
+                Some(_) => self.check_effects(step).into_iter().next(),
+                None => self
+                    .link_step(step)
+                    .into_iter()
+                    .next()
+                    .map(|failure| failure.error),
+            };
+            if let Some(error) = failure {
+                if let Some(explanation) = explain.as_deref_mut() {
+                    explanation.steps.push(explain_step(
+                        step,
+                        "failure",
+                        Err(&error.to_string()),
+                        Vec::new(),
+                    ));
+                }
+                return Err(error);
+            }
+        }
+        let python_env = match linked {
+            Some(linked) => linked.python_env.clone(),
+            None => self.python_env(plan),
+        };
+        if let Some((report, hash)) = python_env {
+            info!(
+                target: "audit",
+                event = "python_env",
+                implementation = report["implementation"].as_str().unwrap_or_default(),
+                version = report["version"].as_str().unwrap_or_default(),
+                packages = %report["packages"],
+                env_hash = %hash
+            );
+        }
+        // External steps are `f(input)` calls: they receive the pipeline
+        // input, not the state the previous steps extended.
+        let pipeline_input = input.clone();
+        let temporal = self.load_temporal(plan, &input).await?;
         let mut state = input;
+        if let Some(temporal) = &temporal {
+            insert_state_field(&mut state, "state", temporal.current.clone());
+        }
 
         for step in &plan.steps {
+            let span = scope.map(|scope| {
+                let mut span = scope.start(format!("step {}", step.name));
+                span.set_attribute("tupa.step.name", json!(step.name));
+                span.set_attribute("tupa.step.function_ref", json!(step.function_ref));
+                span.set_attribute("tupa.step.effects", json!(step.effects));
+                span
+            });
+            let step_scope = scope.zip(span.as_ref()).map(|(s, span)| s.child(span));
+
             // Check circuit breaker
-            {
-                let mut guard = self.state.lock().unwrap();
-                if !guard.circuit_breaker.allow_request() {
-                    warn!(target: "audit", event = "circuit_breaker_block", step = step.name);
-                    return Err(RuntimeError::CircuitBreakerOpen(format!(
-                        "Circuit breaker open for step {}",
-                        step.name
-                    )));
+            if !self.with_breaker(CircuitBreaker::allow_request) {
+                warn!(target: "audit", event = "circuit_breaker_block", step = step.name);
+                self.record_step(plan, &step.name, "blocked", None);
+                let err = RuntimeError::CircuitBreakerOpen(format!(
+                    "Circuit breaker open for step {}",
+                    step.name
+                ));
+                if let Some(explanation) = explain.as_deref_mut() {
+                    explanation.steps.push(explain_step(
+                        step,
+                        "blocked",
+                        Err(&err.to_string()),
+                        Vec::new(),
+                    ));
                 }
+                finish_span(scope, span, &Err::<(), _>(&err));
+                return Err(err);
             }
 
             // Check if async step exists
@@ -195,45 +691,97 @@ impl Runtime {
                 guard.async_steps.contains_key(&step.function_ref)
             };
 
-            let result = if is_async {
-                self.call_async_step_function(&step.function_ref, state.clone())
-                    .await
+            let step_started = Instant::now();
+            // Typed failure of the external call, reported instead of a
+            // plain `StepError`.
+            let argument = if step.external.is_some() {
+                pipeline_input.clone()
+            } else {
+                state.clone()
+            };
+            let mut call_error = check_external(step, "argument", &argument).err();
+            let (result, events) = if let Some(err) = &call_error {
+                (Err(err.to_string()), Vec::new())
+            } else if is_async {
+                let call = self.call_async_step_function(
+                    &step.function_ref,
+                    argument,
+                    step_scope.as_ref(),
+                );
+                if explaining {
+                    explain::capture_async(call).await
+                } else {
+                    (call.await, Vec::new())
+                }
             } else {
                 let func_name = step.function_ref.clone();
-                let input_clone = state.clone();
+                let signature = external_layouts(step);
+                let input_clone = argument;
                 let runtime = self.clone(); // Clone runtime for closure
-                tokio::task::spawn_blocking(move || {
-                    runtime.call_step_function(&func_name, input_clone)
+                let blocking_scope = step_scope.clone();
+                match tokio::task::spawn_blocking(move || {
+                    let call = || {
+                        runtime.call_step_function(
+                            &func_name,
+                            input_clone,
+                            &signature,
+                            blocking_scope.as_ref(),
+                        )
+                    };
+                    if explaining {
+                        explain::capture(call)
+                    } else {
+                        (call(), Vec::new())
+                    }
                 })
                 .await
-                .map_err(|e| RuntimeError::AsyncError(e.to_string()))?
+                {
+                    Ok((Err(RuntimeError::StepError(e)), events)) => (Err(e), events),
+                    Ok((Err(err), events)) => {
+                        let message = err.to_string();
+                        call_error = Some(err);
+                        (Err(message), events)
+                    }
+                    Ok((Ok(output), events)) => (Ok(output), events),
+                    Err(e) => {
+                        let err = RuntimeError::AsyncError(e.to_string());
+                        finish_span(scope, span, &Err::<(), _>(&err));
+                        return Err(err);
+                    }
+                }
             };
 
+            let result = result.and_then(|output| {
+                match check_external(step, "return", &output).and_then(|()| ok_value(step, output))
+                {
+                    Ok(output) => Ok(output),
+                    Err(err) => {
+                        let message = err.to_string();
+                        call_error = Some(err);
+                        Err(message)
+                    }
+                }
+            });
+
+            let elapsed = step_started.elapsed();
+            finish_span(scope, span, &result);
+            if let Some(explanation) = explain.as_deref_mut() {
+                let status = if result.is_ok() { "success" } else { "failure" };
+                let outcome = result.as_ref().map_err(String::as_str);
+                explanation
+                    .steps
+                    .push(explain_step(step, status, outcome, events));
+            }
             match result {
                 Ok(output) => {
-                    {
-                        let mut guard = self.state.lock().unwrap();
-                        guard.circuit_breaker.record_success();
-                    }
-
-                    if let Some(obj) = state.as_object_mut() {
-                        obj.insert(step.name.clone(), output);
-                    } else {
-                        // State is primitive. Upgrade to object to store result.
-                        // We preserve the original primitive value as "input".
-                        let old_state = state.clone();
-                        state = json!({
-                            "input": old_state,
-                            step.name.clone(): output
-                        });
-                    }
+                    self.with_breaker(CircuitBreaker::record_success);
+                    self.record_step(plan, &step.name, "success", Some(elapsed));
+                    insert_state_field(&mut state, &step.name, output);
                 }
                 Err(e) => {
-                    {
-                        let mut guard = self.state.lock().unwrap();
-                        guard.circuit_breaker.record_failure();
-                    }
-                    return Err(RuntimeError::StepError(e));
+                    self.with_breaker(CircuitBreaker::record_failure);
+                    self.record_step(plan, &step.name, "failure", Some(elapsed));
+                    return Err(call_error.unwrap_or(RuntimeError::StepError(e)));
                 }
             }
         }
@@ -241,6 +789,21 @@ impl Runtime {
         if let Some(output_schema) = &plan.output_schema {
             validate_value_against_schema(&state, output_schema, "output")?;
         }
+        if let Some(temporal) = temporal {
+            let next = self.commit_temporal(plan, &temporal, &state)?;
+            insert_state_field(&mut state, "state", next);
+        }
+        if let Some(decision) = final_decision(plan, &state) {
+            info!(
+                target: "audit",
+                event = "pipeline_decision",
+                action = %decision.action,
+                stage = %decision.stage,
+                reason = %decision.reason,
+                score = decision.score,
+                flags = ?decision.flags
+            );
+        }
         info!(target: "audit", event = "pipeline_complete", result = ?state);
         Ok(state)
     }
@@ -249,7 +812,8 @@ impl Runtime {
     ///
     /// This method iterates over the `dataset`, running the pipeline for each entry.
     /// It tracks the Portfolio PnL based on "action" (BUY/SELL) and "close" price fields,
-    /// and validates risk constraints for each step.
+    /// and validates risk constraints for each step: failed `block` constraints skip the
+    /// trade, failed `warn` constraints are only counted in `warnings`.
     ///
     /// # Arguments
     ///
@@ -270,12 +834,15 @@ impl Runtime {
         let mut results = Vec::new();
         let mut portfolio_value = 10000.0; // Starting capital
         let mut position = 0.0; // Current holdings
+        let mut warnings = 0; // Failed `warn` constraints; these never block trades
 
         for (i, input) in dataset.iter().enumerate() {
             let output = self.run_pipeline_async(plan, input.clone()).await?;
 
             // Evaluate constraints (risk check)
-            let constraint_report = evaluate_constraints(plan, &output);
+            let constraint_report = self.evaluate_constraints(plan, &output);
+
+            warnings += constraint_report["warnings"].as_array().map_or(0, Vec::len);
 
             // Simple PnL logic (can be made configurable)
             let price = input.get("close").and_then(|v| v.as_f64()).unwrap_or(0.0);
@@ -286,19 +853,15 @@ impl Runtime {
 
             if constraint_report["success"].as_bool().unwrap_or(false) {
                 match action {
-                    "BUY" => {
-                        if portfolio_value > price {
-                            position += 1.0;
-                            portfolio_value -= price;
-                            info!(target: "audit", event = "trade_executed", type = "BUY", price = price, index = i);
-                        }
+                    "BUY" if portfolio_value > price => {
+                        position += 1.0;
+                        portfolio_value -= price;
+                        info!(target: "audit", event = "trade_executed", type = "BUY", price = price, index = i);
                     }
-                    "SELL" => {
-                        if position > 0.0 {
-                            position -= 1.0;
-                            portfolio_value += price;
-                            info!(target: "audit", event = "trade_executed", type = "SELL", price = price, index = i);
-                        }
+                    "SELL" if position > 0.0 => {
+                        position -= 1.0;
+                        portfolio_value += price;
+                        info!(target: "audit", event = "trade_executed", type = "SELL", price = price, index = i);
                     }
                     _ => {}
                 }
@@ -327,104 +890,576 @@ impl Runtime {
         Ok(json!({
             "final_pnl": final_pnl,
             "trades": results.len(),
+            "warnings": warnings,
             "history": results
         }))
     }
 
-    #[instrument(skip(self, input), fields(step = name))]
-    fn call_step_function(&self, name: &str, input: Value) -> Result<Value, String> {
-        let guard = self.state.lock().unwrap();
-        if let Some(func) = guard.steps.get(name) {
+    /// Runs the pipeline over every record of a labeled `dataset` and checks
+    /// the resulting metrics (see `dataset`) against the plan's constraints.
+    ///
+    /// Records that fail count towards `errors`. Constraint metrics the
+    /// dataset does not produce, or rates without a denominator, are listed
+    /// under `unmeasured` and fail the report; the validation block's
+    /// literals never stand in for them. Failed records, or no record with a
+    /// usable label, are listed under `problems` and fail it too.
+    pub async fn validate_dataset(
+        &self,
+        plan: &ExecutionPlan,
+        dataset: Vec<Value>,
+        options: &DatasetOptions,
+    ) -> RuntimeResult<Value> {
+        if dataset.is_empty() {
+            return Err(RuntimeError::ValidationError("dataset is empty".into()));
+        }
+        self.resolve_plan(plan)?;
+        info!(target: "audit", event = "validate_start", dataset_size = dataset.len());
+
+        let prediction_path = options
+            .prediction
+            .clone()
+            .or_else(|| plan.steps.last().map(|step| step.name.clone()));
+        let mut metrics = DatasetMetrics::default();
+        let mut errors = Vec::new();
+        for (index, record) in dataset.iter().enumerate() {
+            let (input, label) = dataset::split_record(record, &options.label);
+            match self.run_pipeline_async(plan, input).await {
+                Ok(output) => {
+                    let prediction = prediction_path
+                        .as_deref()
+                        .and_then(|path| {
+                            path.split('.')
+                                .try_fold(&output, |value, segment| value.get(segment))
+                        })
+                        .unwrap_or(&Value::Null);
+                    metrics.add_output(&output, prediction, &label);
+                }
+                Err(e) => {
+                    metrics.add_error();
+                    errors.push(json!({ "index": index, "error": e.to_string() }));
+                }
+            }
+        }
+
+        let metrics = Value::Object(metrics.finish());
+        // Records that failed to run, or a label no record carries, make
+        // every rate meaningless, whatever the constraints say.
+        let mut problems = Vec::new();
+        if !errors.is_empty() {
+            problems.push(format!(
+                "{} of {} records failed to run",
+                errors.len(),
+                dataset.len()
+            ));
+        }
+        if metrics["unlabeled"] == json!(dataset.len()) {
+            problems.push(format!(
+                "no record has a usable '{}' label and prediction",
+                options.label
+            ));
+        }
+        let mut unmeasured = Vec::new();
+        collect_unmeasured(&plan.constraints, &metrics, &mut unmeasured);
+        let mut report = self.evaluate_constraints(plan, &metrics);
+        report["pipeline"] = json!(plan.name);
+        report["records"] = json!(dataset.len());
+        report["metrics"] = metrics;
+        if !unmeasured.is_empty() || !problems.is_empty() {
+            report["success"] = json!(false);
+        }
+        report["unmeasured"] = json!(unmeasured);
+        report["problems"] = json!(problems);
+        report["errors"] = json!(errors);
+        info!(target: "audit", event = "validate_complete", success = %report["success"]);
+        Ok(report)
+    }
+
+    /// Evaluates `plan`'s constraints against `state` and records the
+    /// outcome of each one in `metrics()`.
+    ///
+    /// A constraint whose metric resolves nowhere is `unmeasured` and fails
+    /// the report whatever its severity, so `run_backtest` blocks the trade.
+    pub fn evaluate_constraints(&self, plan: &ExecutionPlan, state: &Value) -> Value {
+        let report = evaluate_constraints(plan, state);
+        if let Some(results) = report["constraints"].as_array() {
+            for result in results {
+                let outcome = result["status"].as_str().unwrap_or("fail");
+                self.metrics.inc_counter(
+                    metrics::CONSTRAINT_EVALUATIONS,
+                    "Constraint evaluations by outcome.",
+                    &[
+                        ("pipeline", plan.name.as_str()),
+                        ("metric", result["metric"].as_str().unwrap_or_default()),
+                        ("result", outcome),
+                    ],
+                );
+            }
+        }
+        report
+    }
+
+    /// Loads the temporal state of `plan` for a run on `input`, or `None`
+    /// when the plan declares none. Holds the keys' locks until the returned
+    /// run is committed or dropped.
+    async fn load_temporal(
+        &self,
+        plan: &ExecutionPlan,
+        input: &Value,
+    ) -> RuntimeResult<Option<TemporalRun>> {
+        if plan.temporal.is_empty() {
+            return Ok(None);
+        }
+        let keys = plan
+            .temporal
+            .iter()
+            .map(|t| state::store_key(&plan.name, t, input))
+            .collect::<RuntimeResult<Vec<_>>>()?;
+        let guards = self.temporal_locks.acquire(&keys).await;
+        let store = self.state.lock().unwrap().state_store.clone();
+        let mut loaded = store.load(&keys)?;
+        let current = plan
+            .temporal
+            .iter()
+            .zip(&keys)
+            .map(|(t, key)| {
+                let value = loaded.remove(key).unwrap_or_else(|| state::initial(t));
+                (t.key.clone(), value)
+            })
+            .collect();
+        Ok(Some(TemporalRun {
+            keys,
+            current: Value::Object(current),
+            _guards: guards,
+        }))
+    }
+
+    /// Advances every temporal key from the run's results, commits them in
+    /// one write and audits each stage transition. Returns the new state.
+    fn commit_temporal(
+        &self,
+        plan: &ExecutionPlan,
+        temporal: &TemporalRun,
+        run_state: &Value,
+    ) -> RuntimeResult<Value> {
+        let mut next = serde_json::Map::new();
+        let mut updates = HashMap::new();
+        let mut transitions = Vec::new();
+        for (decl, key) in plan.temporal.iter().zip(&temporal.keys) {
+            let before = &temporal.current[&decl.key];
+            let after = state::advance(decl, before, state::observed(run_state, &decl.source));
+            let from = before["stage"].as_str().unwrap_or("idle");
+            let to = after["stage"].as_str().unwrap_or("idle");
+            if from != to {
+                transitions.push(state::Transition {
+                    key: decl.key.clone(),
+                    from: from.to_string(),
+                    to: to.to_string(),
+                });
+            }
+            updates.insert(key.clone(), after.clone());
+            next.insert(decl.key.clone(), after);
+        }
+
+        let store = self.state.lock().unwrap().state_store.clone();
+        store.commit(&updates)?;
+        for transition in transitions {
+            info!(
+                target: "audit",
+                event = "temporal_transition",
+                pipeline = plan.name,
+                key = transition.key,
+                from = transition.from,
+                to = transition.to
+            );
+        }
+        Ok(Value::Object(next))
+    }
+
+    /// Runs `f` on the circuit breaker and records any state transition.
+    fn with_breaker<R>(&self, f: impl FnOnce(&mut CircuitBreaker) -> R) -> R {
+        let mut guard = self.state.lock().unwrap();
+        let before = guard.circuit_breaker.state.clone();
+        let result = f(&mut guard.circuit_breaker);
+        let after = guard.circuit_breaker.state.clone();
+        drop(guard);
+        if before != after {
+            self.metrics.inc_counter(
+                metrics::BREAKER_TRANSITIONS,
+                "Circuit breaker state transitions.",
+                &[("from", before.as_str()), ("to", after.as_str())],
+            );
+            self.metrics.set_gauge(
+                metrics::BREAKER_STATE,
+                "Circuit breaker state (0 closed, 1 open, 2 half-open).",
+                &[],
+                after.gauge(),
+            );
+        }
+        result
+    }
+
+    fn record_step(
+        &self,
+        plan: &ExecutionPlan,
+        step: &str,
+        status: &str,
+        elapsed: Option<Duration>,
+    ) {
+        let labels = [
+            ("pipeline", plan.name.as_str()),
+            ("step", step),
+            ("status", status),
+        ];
+        self.metrics
+            .inc_counter(metrics::STEP_RUNS, "Step runs by outcome.", &labels);
+        if let Some(elapsed) = elapsed {
+            self.metrics.observe(
+                metrics::STEP_DURATION,
+                "Step latency in seconds.",
+                &labels[..2],
+                elapsed.as_secs_f64(),
+            );
+        }
+    }
+
+    fn call_python(
+        &self,
+        path: &str,
+        input: Value,
+        signature: &Signature,
+        scope: Option<&SpanScope>,
+    ) -> RuntimeResult<Value> {
+        let span = scope.map(|scope| {
+            let (module, func) = path.rsplit_once('.').unwrap_or(("", path));
+            let mut span = scope.start(format!("python {path}"));
+            span.set_attribute("tupa.python.module", json!(module));
+            span.set_attribute("tupa.python.function", json!(func));
+            span
+        });
+        let started = Instant::now();
+        let result = if explain::capturing() {
+            let (result, events) = tupa_pyffi::explain::collect(|| {
+                tupa_pyffi::call_python_typed(path, input, signature)
+            });
+            explain::record_reported(events);
+            result
+        } else {
+            tupa_pyffi::call_python_typed(path, input, signature)
+        };
+        let result = result.map_err(|e| match e {
+            CallError::Python(message) => RuntimeError::StepError(message),
+            CallError::UndeclaredEffect { effect, event } => {
+                warn!(
+                    target: "audit",
+                    event = "effect_violation",
+                    function = path,
+                    effect = %effect,
+                    operation = %event
+                );
+                RuntimeError::EffectViolation {
+                    function: path.to_string(),
+                    effect,
+                    event,
+                }
+            }
+        });
+        finish_span(scope, span, &result);
+        self.metrics.observe(
+            metrics::PYTHON_CALL_DURATION,
+            "Python call latency in seconds.",
+            &[
+                ("function", path),
+                ("status", if result.is_ok() { "success" } else { "failure" }),
+            ],
+            started.elapsed().as_secs_f64(),
+        );
+        result
+    }
+
+    #[instrument(skip(self, input, signature, scope), fields(step = name))]
+    fn call_step_function(
+        &self,
+        name: &str,
+        input: Value,
+        signature: &Signature,
+        scope: Option<&SpanScope>,
+    ) -> RuntimeResult<Value> {
+        let func = self.state.lock().unwrap().steps.get(name).cloned();
+        if let Some(func) = func {
             let result = func(input);
             match &result {
                 Ok(v) => info!(target: "audit", event = "step_success", output = ?v),
                 Err(e) => error!(target: "audit", event = "step_failure", error = %e),
             }
-            return result;
+            return result.map_err(RuntimeError::StepError);
         }
 
-        // Support "py:module.func" format from codegen
-        if let Some(stripped) = name.strip_prefix("py:") {
-            let parts: Vec<&str> = stripped.split('.').collect();
-            if parts.len() == 2 {
-                drop(guard);
-                let result = tupa_pyffi::call_python_function(parts[0], parts[1], input);
-                match &result {
-                    Ok(v) => {
-                        info!(target: "audit", event = "step_success", type = "python", output = ?v)
-                    }
-                    Err(e) => {
-                        error!(target: "audit", event = "step_failure", type = "python", error = %e)
-                    }
+        if let Some(path) = python_path(name) {
+            let result = self.call_python(&path, input, signature, scope);
+            match &result {
+                Ok(v) => {
+                    info!(target: "audit", event = "step_success", type = "python", output = ?v)
                 }
-                return result;
-            }
-        }
-
-        // Support "module::func" legacy format
-        if name.contains("::") {
-            let parts: Vec<&str> = name.split("::").collect();
-            if parts.len() == 2 {
-                drop(guard);
-                let result = tupa_pyffi::call_python_function(parts[0], parts[1], input);
-                match &result {
-                    Ok(v) => {
-                        info!(target: "audit", event = "step_success", type = "python", output = ?v)
-                    }
-                    Err(e) => {
-                        error!(target: "audit", event = "step_failure", type = "python", error = %e)
-                    }
+                Err(e) => {
+                    error!(target: "audit", event = "step_failure", type = "python", error = %e)
                 }
-                return result;
             }
+            return result;
         }
 
-        Err(format!("Function {} not found", name))
+        Err(RuntimeError::StepError(format!(
+            "Function {} not found",
+            name
+        )))
     }
 
-    async fn call_async_step_function(&self, name: &str, input: Value) -> Result<Value, String> {
+    async fn call_async_step_function(
+        &self,
+        name: &str,
+        input: Value,
+        scope: Option<&SpanScope>,
+    ) -> Result<Value, String> {
         let future_opt = {
             let guard = self.state.lock().unwrap();
             guard.async_steps.get(name).map(|f| f(input))
         };
 
         if let Some(fut) = future_opt {
-            return fut.await;
+            let span = scope.map(|scope| scope.start(format!("async {name}")));
+            let result = fut.await;
+            finish_span(scope, span, &result);
+            return result;
         }
 
         Err(format!("Async function {} not found", name))
     }
 }
 
-lazy_static::lazy_static! {
-    pub static ref GLOBAL_RUNTIME: Runtime = Runtime::new();
+/// Checks `value`, the `side` (`argument` or `return`) of an external call,
+/// against the signature declared for `step`.
+fn check_external(step: &StepPlan, side: &str, value: &Value) -> RuntimeResult<()> {
+    let Some(external) = &step.external else {
+        return Ok(());
+    };
+    let schema = match side {
+        "argument" => external.param.as_ref(),
+        _ => external.returns.as_ref(),
+    };
+    let Some(schema) = schema else {
+        return Ok(());
+    };
+    validate_value_against_schema(value, schema, side).map_err(|e| {
+        let detail = match e {
+            RuntimeError::ValidationError(detail) => detail,
+            other => other.to_string(),
+        };
+        RuntimeError::ExternalTypeMismatch {
+            function: external.function.clone(),
+            detail,
+        }
+    })
 }
 
-// --- Global API Delegates ---
+/// The value a step contributes to the state. When the step returns
+/// `Result<T, E>`, `{"Ok": v}` contributes `v` and `{"Err": e}` fails the
+/// step with `e`; other values pass through.
+fn ok_value(step: &StepPlan, output: Value) -> RuntimeResult<Value> {
+    let returns_result = step.returns_result
+        || step
+            .external
+            .as_ref()
+            .and_then(|external| external.returns.as_ref())
+            .is_some_and(is_result_schema);
+    match output {
+        Value::Object(map) if returns_result && map.len() == 1 => {
+            let (variant, payload) = map.into_iter().next().expect("one entry");
+            if variant == "Err" {
+                return Err(RuntimeError::StepFailed {
+                    step: step.name.clone(),
+                    error: payload,
+                });
+            }
+            Ok(payload)
+        }
+        other => Ok(other),
+    }
+}
 
-pub fn register_step<F>(name: &str, func: F)
-where
-    F: Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
-{
-    GLOBAL_RUNTIME.register_step(name, func)
+/// Whether `schema` describes `Result<T, E>`: an enum with `Ok(T)` and `Err(E)`.
+fn is_result_schema(schema: &TypeSchema) -> bool {
+    let Some(variants) = schema.variants.as_deref() else {
+        return false;
+    };
+    schema.kind == "enum"
+        && schema.name.as_deref() == Some("Result")
+        && variants.len() == 2
+        && variants
+            .iter()
+            .all(|v| (v.name == "Ok" || v.name == "Err") && v.payload.len() == 1)
 }
 
-pub fn register_async_step<F>(name: &str, func: F)
-where
-    F: Fn(Value) -> futures::future::BoxFuture<'static, Result<Value, String>>
-        + Send
-        + Sync
-        + 'static,
-{
-    GLOBAL_RUNTIME.register_async_step(name, func)
+/// Tensor layouts and declared effects of the external call made by `step`,
+/// if any.
+fn external_layouts(step: &StepPlan) -> Signature {
+    let layout = |schema: Option<&TypeSchema>| schema.map_or(Layout::Value, tensor::layout_of);
+    step.external
+        .as_ref()
+        .map(|external| Signature {
+            param: layout(external.param.as_ref()),
+            returns: layout(external.returns.as_ref()),
+            effects: Some(external.effects.clone()),
+        })
+        .unwrap_or_default()
 }
 
-pub fn configure_circuit_breaker(threshold: usize, timeout: Duration) {
-    GLOBAL_RUNTIME.configure_circuit_breaker(threshold, timeout)
+/// Dotted Python path of a step `function_ref`: `py:pkg.mod.func` from
+/// codegen, or the legacy `pkg::mod::func` form.
+fn python_path(function_ref: &str) -> Option<String> {
+    let path = match function_ref.strip_prefix("py:") {
+        Some(path) => path.to_string(),
+        None if function_ref.starts_with("rust:") => return None,
+        None if function_ref.contains("::") => function_ref.replace("::", "."),
+        None => return None,
+    };
+    let valid = path.contains('.') && path.split('.').all(|part| !part.is_empty());
+    valid.then_some(path)
 }
 
-pub async fn run_pipeline_async(plan: &ExecutionPlan, input: Value) -> RuntimeResult<Value> {
-    GLOBAL_RUNTIME.run_pipeline_async(plan, input).await
+/// Where `registered`, a host's schema, first differs from `declared`, the
+/// plan's, starting at `path`; `None` when they agree.
+fn schema_mismatch(registered: &TypeSchema, declared: &TypeSchema, path: &str) -> Option<String> {
+    if registered.kind != declared.kind {
+        return Some(format!(
+            "{path} is registered as {}, declared as {}",
+            registered.kind, declared.kind
+        ));
+    }
+    if let (Some(registered), Some(declared)) = (&registered.elem, &declared.elem) {
+        if let Some(mismatch) = schema_mismatch(registered, declared, &format!("{path}[]")) {
+            return Some(mismatch);
+        }
+    }
+    if let (Some(registered), Some(declared)) = (&registered.fields, &declared.fields) {
+        let mut names: Vec<&String> = registered.keys().chain(declared.keys()).collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let field = format!("{path}.{name}");
+            match (registered.get(name), declared.get(name)) {
+                (Some(registered), Some(declared)) => {
+                    if let Some(mismatch) = schema_mismatch(registered, declared, &field) {
+                        return Some(mismatch);
+                    }
+                }
+                (Some(_), None) => return Some(format!("{field} is registered but not declared")),
+                _ => return Some(format!("{field} is declared but not registered")),
+            }
+        }
+    }
+    if let (Some(registered), Some(declared)) = (&registered.items, &declared.items) {
+        for (i, (registered, declared)) in registered.iter().zip(declared).enumerate() {
+            if let Some(mismatch) = schema_mismatch(registered, declared, &format!("{path}.{i}")) {
+                return Some(mismatch);
+            }
+        }
+    }
+    (registered != declared).then(|| format!("{path} is registered and declared differently"))
+}
+
+/// Stores `value` under `name` in the pipeline state.
+fn insert_state_field(state: &mut Value, name: &str, value: Value) {
+    if let Some(obj) = state.as_object_mut() {
+        obj.insert(name.to_string(), value);
+    } else {
+        // State is primitive. Upgrade to object to store result.
+        // We preserve the original primitive value as "input".
+        let old_state = state.take();
+        *state = json!({
+            "input": old_state,
+            name: value
+        });
+    }
+}
+
+/// Explanation node for `step`: events the host recorded, then those implied
+/// by its output.
+fn explain_step(
+    step: &StepPlan,
+    status: &str,
+    outcome: Result<&Value, &str>,
+    mut events: Vec<ExplainEvent>,
+) -> StepExplanation {
+    if let Ok(output) = outcome {
+        events.extend(explain::output_events(output));
+    }
+    StepExplanation {
+        step: step.name.clone(),
+        function_ref: step.function_ref.clone(),
+        status: status.to_string(),
+        output: outcome.ok().cloned(),
+        error: outcome.err().map(str::to_string),
+        events,
+    }
+}
+
+/// The `Decision` produced by the last step, or the state itself when it
+/// already is one.
+fn final_decision(plan: &ExecutionPlan, state: &Value) -> Option<Decision> {
+    plan.steps
+        .last()
+        .and_then(|step| state.get(&step.name))
+        .and_then(Decision::from_value)
+        .or_else(|| Decision::from_value(state))
+}
+
+/// Sets the span status from `result` and records it, when tracing.
+fn finish_span<T, E: std::fmt::Display>(
+    scope: Option<&SpanScope>,
+    span: Option<ActiveSpan>,
+    result: &Result<T, E>,
+) {
+    if let (Some(scope), Some(mut span)) = (scope, span) {
+        span.set_result(result);
+        scope.recorder.finish(span);
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref GLOBAL_RUNTIME: Runtime = Runtime::new();
+}
+
+// --- Global API Delegates ---
+
+pub fn register_step<F>(name: &str, func: F)
+where
+    F: Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
+{
+    GLOBAL_RUNTIME.register_step(name, func)
+}
+
+pub fn register_async_step<F>(name: &str, func: F)
+where
+    F: Fn(Value) -> futures::future::BoxFuture<'static, Result<Value, String>>
+        + Send
+        + Sync
+        + 'static,
+{
+    GLOBAL_RUNTIME.register_async_step(name, func)
+}
+
+pub fn register_rust_fn<F>(name: &str, signature: ExternalSignature, func: F)
+where
+    F: Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
+{
+    GLOBAL_RUNTIME.register_rust_fn(name, signature, func)
+}
+
+pub fn configure_circuit_breaker(threshold: usize, timeout: Duration) {
+    GLOBAL_RUNTIME.configure_circuit_breaker(threshold, timeout)
+}
+
+pub async fn run_pipeline_async(plan: &ExecutionPlan, input: Value) -> RuntimeResult<Value> {
+    GLOBAL_RUNTIME.run_pipeline_async(plan, input).await
 }
 
 pub fn run_pipeline(plan: &ExecutionPlan, input: Value) -> RuntimeResult<Value> {
@@ -439,13 +1474,35 @@ pub async fn run_backtest(plan: &ExecutionPlan, dataset: Vec<Value>) -> RuntimeR
     GLOBAL_RUNTIME.run_backtest(plan, dataset).await
 }
 
+/// Renders the global runtime's metrics in the Prometheus text format.
+pub fn render_metrics() -> String {
+    GLOBAL_RUNTIME.render_metrics()
+}
+
 fn validate_value_against_schema(
     value: &Value,
     schema: &TypeSchema,
     path: &str,
 ) -> RuntimeResult<()> {
+    if value.is_null() && schema.nullable == Some(true) {
+        return Ok(());
+    }
+    // `Safe` constraints need no check here: JSON holds no NaN or Inf, the
+    // Python bridge rejects them, and content constraints are proven by the
+    // typechecker.
+    validate_kind(value, schema, path)
+}
+
+fn validate_kind(value: &Value, schema: &TypeSchema, path: &str) -> RuntimeResult<()> {
     match schema.kind.as_str() {
-        "any" | "unknown" | "ident" | "tensor" => Ok(()),
+        "any" | "unknown" | "ident" => Ok(()),
+        "tensor" => tensor::validate_json_tensor(
+            value,
+            schema.tensor_shape.as_deref(),
+            schema.tensor_dtype.as_deref(),
+            path,
+        )
+        .map_err(|e| RuntimeError::ValidationError(e.to_string())),
         "i64" => {
             if value.as_i64().is_some() {
                 Ok(())
@@ -497,11 +1554,14 @@ fn validate_value_against_schema(
             })?;
             if let Some(fields) = &schema.fields {
                 for (field_name, field_schema) in fields {
-                    let field_value = obj.get(field_name).ok_or_else(|| {
-                        RuntimeError::ValidationError(format!(
+                    let Some(field_value) = obj.get(field_name) else {
+                        if field_schema.nullable == Some(true) {
+                            continue;
+                        }
+                        return Err(RuntimeError::ValidationError(format!(
                             "{path}.{field_name} missing required field"
-                        ))
-                    })?;
+                        )));
+                    };
                     validate_value_against_schema(
                         field_value,
                         field_schema,
@@ -530,6 +1590,75 @@ fn validate_value_against_schema(
             }
             Ok(())
         }
+        "tuple" => {
+            let items = value.as_array().ok_or_else(|| {
+                RuntimeError::ValidationError(format!("{path} expected tuple, got {value}"))
+            })?;
+            let Some(item_schemas) = &schema.items else {
+                return Ok(());
+            };
+            if items.len() != item_schemas.len() {
+                return Err(RuntimeError::ValidationError(format!(
+                    "{path} expected tuple of {} items, got {}",
+                    item_schemas.len(),
+                    items.len()
+                )));
+            }
+            for (index, (item, item_schema)) in items.iter().zip(item_schemas).enumerate() {
+                validate_value_against_schema(item, item_schema, &format!("{path}[{index}]"))?;
+            }
+            Ok(())
+        }
+        "enum" => {
+            let Some(variants) = &schema.variants else {
+                return Ok(());
+            };
+            let enum_name = schema.name.as_deref().unwrap_or("enum");
+            // Unit variants are plain strings, payload variants single-key objects.
+            let (variant_name, payload) = match value {
+                Value::String(name) => (name.as_str(), None),
+                Value::Object(obj) if obj.len() == 1 => {
+                    let (name, payload) = obj.iter().next().unwrap();
+                    (name.as_str(), Some(payload))
+                }
+                _ => {
+                    return Err(RuntimeError::ValidationError(format!(
+                        "{path} expected {enum_name} variant, got {value}"
+                    )))
+                }
+            };
+            let variant = variants
+                .iter()
+                .find(|v| v.name == variant_name)
+                .ok_or_else(|| {
+                    RuntimeError::ValidationError(format!(
+                        "{path} unknown {enum_name} variant '{variant_name}'"
+                    ))
+                })?;
+            let variant_path = format!("{path}.{variant_name}");
+            match (variant.payload.as_slice(), payload) {
+                ([], None) => Ok(()),
+                ([single], Some(payload)) => {
+                    validate_value_against_schema(payload, single, &variant_path)
+                }
+                (many, Some(Value::Array(items)))
+                    if many.len() > 1 && items.len() == many.len() =>
+                {
+                    for (index, (item, item_schema)) in items.iter().zip(many).enumerate() {
+                        validate_value_against_schema(
+                            item,
+                            item_schema,
+                            &format!("{variant_path}[{index}]"),
+                        )?;
+                    }
+                    Ok(())
+                }
+                (expected, _) => Err(RuntimeError::ValidationError(format!(
+                    "{path} {enum_name}::{variant_name} expects {} payload value(s), got {value}",
+                    expected.len()
+                ))),
+            }
+        }
         _ => Ok(()),
     }
 }
@@ -558,45 +1687,155 @@ fn get_metric_value(state: &Value, path: &str) -> Option<f64> {
     None
 }
 
+/// Constraint metrics missing from computed `metrics`, or `null` there.
+fn collect_unmeasured(constraints: &[ConstraintPlan], metrics: &Value, out: &mut Vec<String>) {
+    for constraint in constraints {
+        if constraint.group.is_empty() {
+            let missing = metrics.get(&constraint.metric).is_none_or(Value::is_null);
+            if missing && !out.contains(&constraint.metric) {
+                out.push(constraint.metric.clone());
+            }
+        } else {
+            collect_unmeasured(&constraint.group, metrics, out);
+        }
+    }
+}
+
+/// Resolves a referenced threshold: a metric or input path, falling back to
+/// the validation block's literals. The gated metric itself never falls
+/// back; it must be measured.
+fn resolve_operand(
+    plan: &ExecutionPlan,
+    state: &Value,
+    operand: &ConstraintOperand,
+) -> Option<f64> {
+    match operand {
+        ConstraintOperand::Number(value) => Some(*value),
+        ConstraintOperand::Ref(path) => {
+            get_metric_value(state, path).or_else(|| plan.metrics.get(path).copied())
+        }
+    }
+}
+
+/// Result of one constraint; `any`/`all` groups nest their members' results.
+fn evaluate_constraint(plan: &ExecutionPlan, constraint: &ConstraintPlan, state: &Value) -> Value {
+    let mut measured = true;
+    let (metric, value, threshold, pass, members) = if constraint.group.is_empty() {
+        let resolved = get_metric_value(state, &constraint.metric);
+        measured = resolved.is_some();
+        let value = resolved.unwrap_or(f64::NAN);
+        let operands: Option<Vec<f64>> = if constraint.operands.is_empty() {
+            Some(vec![constraint.threshold])
+        } else {
+            constraint
+                .operands
+                .iter()
+                .map(|operand| resolve_operand(plan, state, operand))
+                .collect()
+        };
+        // An unresolved referenced threshold fails the constraint.
+        let operands = operands.unwrap_or_default();
+        let equals = |t: &f64| (value - t).abs() < f64::EPSILON;
+        let pass = match (constraint.comparator.as_str(), operands.as_slice()) {
+            ("gt", [t]) => value > *t,
+            ("lt", [t]) => value < *t,
+            ("eq", [t]) => equals(t),
+            ("ne", [t]) => !equals(t),
+            ("ge", [t]) => value >= *t,
+            ("le", [t]) => value <= *t,
+            ("between", [low, high]) => *low <= value && value <= *high,
+            ("in", members) => members.iter().any(equals),
+            _ => false,
+        };
+        let threshold = match constraint.comparator.as_str() {
+            "between" | "in" => json!(operands),
+            _ => json!(operands.first()),
+        };
+        (
+            constraint.metric.clone(),
+            json!(resolved),
+            threshold,
+            pass && measured,
+            None,
+        )
+    } else {
+        let members: Vec<Value> = constraint
+            .group
+            .iter()
+            .map(|member| evaluate_constraint(plan, member, state))
+            .collect();
+        let passed = |result: &Value| result["pass"].as_bool().unwrap_or(false);
+        let pass = match constraint.comparator.as_str() {
+            "any" => members.iter().any(passed),
+            "all" => members.iter().all(passed),
+            _ => false,
+        };
+        let names: Vec<&str> = members
+            .iter()
+            .filter_map(|member| member["metric"].as_str())
+            .collect();
+        let metric = format!("{}({})", constraint.comparator, names.join(", "));
+        (metric, Value::Null, Value::Null, pass, Some(members))
+    };
+
+    // A metric nothing computed fails the gate whatever its severity.
+    let status = match (pass, constraint.severity) {
+        _ if !measured => "unmeasured",
+        (true, _) => "pass",
+        (false, ConstraintSeverity::Block) => "fail",
+        (false, ConstraintSeverity::Warn) => "warn",
+    };
+    let mut result = json!({
+        "metric": metric,
+        "comparator": constraint.comparator,
+        "threshold": threshold,
+        "value": value,
+        "pass": pass,
+        "status": status,
+        "severity": constraint.severity,
+    });
+    if !constraint.operands.is_empty() {
+        result["operands"] = json!(constraint.operands);
+    }
+    if let Some(members) = members {
+        result["constraints"] = json!(members);
+    }
+    result
+}
+
 pub fn evaluate_constraints(plan: &ExecutionPlan, state: &Value) -> Value {
     let mut report = json!({
         "success": true,
         "metrics": {},
-        "constraints": []
+        "constraints": [],
+        "warnings": []
     });
 
     for constraint in &plan.constraints {
-        let metric_val = get_metric_value(state, &constraint.metric).unwrap_or(0.0);
-
-        let pass = match constraint.comparator.as_str() {
-            "gt" => metric_val > constraint.threshold,
-            "lt" => metric_val < constraint.threshold,
-            "eq" => (metric_val - constraint.threshold).abs() < f64::EPSILON,
-            "ge" => metric_val >= constraint.threshold,
-            "le" => metric_val <= constraint.threshold,
-            _ => false,
-        };
-
-        if !pass {
-            report["success"] = json!(false);
-            warn!(target: "audit", event = "constraint_violation", metric = %constraint.metric, value = metric_val, threshold = constraint.threshold);
+        let constraint_result = evaluate_constraint(plan, constraint, state);
+        if !constraint_result["pass"].as_bool().unwrap_or(false) {
+            if constraint.severity.is_block() || constraint_result["status"] == "unmeasured" {
+                report["success"] = json!(false);
+                warn!(target: "audit", event = "constraint_violation", metric = %constraint_result["metric"], value = %constraint_result["value"], threshold = %constraint_result["threshold"]);
+            } else {
+                warn!(target: "audit", event = "constraint_warning", metric = %constraint_result["metric"], value = %constraint_result["value"], threshold = %constraint_result["threshold"]);
+                report["warnings"]
+                    .as_array_mut()
+                    .unwrap()
+                    .push(constraint_result["metric"].clone());
+            }
         }
 
-        let constraint_result = json!({
-            "metric": constraint.metric,
-            "comparator": constraint.comparator,
-            "threshold": constraint.threshold,
-            "value": metric_val,
-            "pass": pass,
-            "status": if pass { "pass" } else { "fail" }
-        });
-
         report["constraints"]
             .as_array_mut()
             .unwrap()
             .push(constraint_result);
     }
 
+    if let Some(decision) = final_decision(plan, state) {
+        report["decision"] = decision.to_value();
+    }
+
     if !report["success"].as_bool().unwrap() {
         warn!(target: "audit", event = "constraints_fail", report = ?report);
     }
@@ -657,12 +1896,7 @@ mod tests {
             seed: None,
             input_schema: TypeSchema {
                 kind: "any".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
+                ..Default::default()
             },
             output_schema: None,
             steps: vec![],
@@ -671,25 +1905,30 @@ mod tests {
                     metric: "score".into(),
                     comparator: "gt".into(),
                     threshold: 0.5,
+                    ..Default::default()
                 },
                 ConstraintPlan {
                     metric: "nested.val".into(),
                     comparator: "eq".into(),
                     threshold: 10.0,
+                    ..Default::default()
                 },
                 ConstraintPlan {
                     metric: "min_ok".into(),
                     comparator: "ge".into(),
                     threshold: 1.0,
+                    ..Default::default()
                 },
                 ConstraintPlan {
                     metric: "max_ok".into(),
                     comparator: "le".into(),
                     threshold: 2.0,
+                    ..Default::default()
                 },
             ],
             metrics: HashMap::new(),
             metric_plans: vec![],
+            temporal: vec![],
         };
 
         let state_pass = json!({
@@ -793,26 +2032,25 @@ mod tests {
             seed: None,
             input_schema: TypeSchema {
                 kind: "object".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
+                ..Default::default()
             },
             output_schema: None,
             steps: vec![StepPlan {
                 name: "result".into(),
                 function_ref: "double".into(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             }],
             constraints: vec![ConstraintPlan {
                 metric: "result".into(),
                 comparator: "gt".into(),
                 threshold: 10.0,
+                ..Default::default()
             }],
             metrics: HashMap::new(),
             metric_plans: vec![],
+            temporal: vec![],
         };
 
         // Test pipeline execution
@@ -847,22 +2085,20 @@ mod tests {
             seed: None,
             input_schema: TypeSchema {
                 kind: "object".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
+                ..Default::default()
             },
             output_schema: None,
             steps: vec![StepPlan {
                 name: "action".into(),
                 function_ref: "strategy".into(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             }],
             constraints: vec![], // No constraints, so always success
             metrics: HashMap::new(),
             metric_plans: vec![],
+            temporal: vec![],
         };
 
         let backtest_result = runtime
@@ -897,22 +2133,20 @@ mod tests {
             seed: None,
             input_schema: TypeSchema {
                 kind: "number".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
+                ..Default::default()
             },
             output_schema: None,
             steps: vec![StepPlan {
                 name: "root".into(),
                 function_ref: "py:math.sqrt".into(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             }],
             constraints: vec![],
             metrics: HashMap::new(),
             metric_plans: vec![],
+            temporal: vec![],
         };
 
         let input = json!(16.0);
@@ -935,6 +2169,361 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_external_calls_are_checked_against_declared_types() {
+        let runtime = Runtime::new();
+        let plan_with = |returns: &str| -> ExecutionPlan {
+            serde_json::from_value(json!({
+                "name": "external", "version": "1.0", "seed": null,
+                "input_schema": { "kind": "object" }, "output_schema": null,
+                "constraints": [], "metrics": {}, "metric_plans": [],
+                "steps": [{
+                    "name": "size", "function_ref": "py:builtins.len", "effects": [],
+                    "external": {
+                        "function": "size",
+                        "param": { "kind": "object", "fields": { "items": { "kind": "slice" } } },
+                        "returns": { "kind": returns }
+                    }
+                }]
+            }))
+            .unwrap()
+        };
+
+        let output = runtime
+            .run_pipeline_async(&plan_with("i64"), json!({ "items": [1, 2] }))
+            .await
+            .unwrap();
+        assert_eq!(output["size"], json!(1));
+
+        let err = runtime
+            .run_pipeline_async(&plan_with("i64"), json!({ "count": 2 }))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::ExternalTypeMismatch { function, detail }
+                if function == "size" && detail == "argument.items missing required field"),
+            "{err}"
+        );
+
+        let err = runtime
+            .run_pipeline_async(&plan_with("string"), json!({ "items": [] }))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::ExternalTypeMismatch { detail, .. }
+                if detail.starts_with("return expected string")),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_undeclared_external_effects_are_blocked() {
+        let runtime = Runtime::new();
+        let plan_with = |effects: Value| -> ExecutionPlan {
+            serde_json::from_value(json!({
+                "name": "effects", "version": "1.0", "seed": null,
+                "input_schema": { "kind": "slice" }, "output_schema": null,
+                "constraints": [], "metrics": {}, "metric_plans": [],
+                "steps": [{
+                    "name": "now", "function_ref": "py:time.time", "effects": [],
+                    "external": {
+                        "function": "now", "param": null, "returns": null,
+                        "effects": effects
+                    }
+                }]
+            }))
+            .unwrap()
+        };
+
+        let err = runtime
+            .run_pipeline_async(&plan_with(json!([])), json!([1, 2, 3]))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::EffectViolation { function, effect, .. }
+                if function == "time.time" && effect == "time"),
+            "{err}"
+        );
+
+        let result = runtime
+            .run_pipeline_async(&plan_with(json!(["time"])), json!([1, 2, 3]))
+            .await;
+        assert!(
+            !matches!(result, Err(RuntimeError::EffectViolation { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rust_externals_must_be_registered_with_matching_signature() {
+        let declaration = "fn fetch(req: { symbol: string }): f64";
+        let plan: ExecutionPlan = serde_json::from_value(json!({
+            "name": "rust", "version": "1.0", "seed": null,
+            "input_schema": { "kind": "object" }, "output_schema": null,
+            "constraints": [], "metrics": {}, "metric_plans": [],
+            "steps": [{
+                "name": "price", "function_ref": "rust:viper::fetch_market_data",
+                "effects": [],
+                "external": ExternalSignature::from_declaration(declaration).unwrap()
+            }]
+        }))
+        .unwrap();
+
+        let runtime = Runtime::new();
+        let err = runtime.resolve_plan(&plan).unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::FunctionNotFound(detail)
+                if detail == "step 'price' (rust:viper::fetch_market_data): not registered"),
+            "{err}"
+        );
+
+        runtime.register_rust_fn(
+            "viper::fetch_market_data",
+            ExternalSignature::from_declaration("fn fetch(req: { symbol: i64 }): f64").unwrap(),
+            |_| Ok(json!(1.5)),
+        );
+        let err = runtime.resolve_plan(&plan).unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::ExternalTypeMismatch { function, detail }
+                if function == "fetch" && detail == "rust function viper::fetch_market_data \
+                does not match: argument.symbol is registered as i64, declared as string"),
+            "{err}"
+        );
+
+        runtime.register_rust_fn(
+            "viper::fetch_market_data",
+            ExternalSignature::from_declaration(declaration).unwrap(),
+            |input| Ok(json!(input["symbol"].as_str().unwrap().len() as f64)),
+        );
+        let output = runtime
+            .run_pipeline_async(&plan, json!({ "symbol": "BTCUSDT" }))
+            .await
+            .unwrap();
+        assert_eq!(output["price"], json!(7.0));
+    }
+
+    #[tokio::test]
+    async fn test_chained_externals_receive_the_pipeline_input() {
+        let declaration = "fn double(x: f64): f64";
+        let step = |name: &str| {
+            json!({
+                "name": name, "function_ref": "rust:math::double", "effects": [],
+                "external": ExternalSignature::from_declaration(declaration).unwrap()
+            })
+        };
+        let plan: ExecutionPlan = serde_json::from_value(json!({
+            "name": "chain", "version": "1.0", "seed": null,
+            "input_schema": { "kind": "f64" }, "output_schema": null,
+            "constraints": [], "metrics": {}, "metric_plans": [],
+            "steps": [step("a"), step("b")]
+        }))
+        .unwrap();
+
+        let runtime = Runtime::new();
+        runtime.register_rust_fn(
+            "math::double",
+            ExternalSignature::from_declaration(declaration).unwrap(),
+            |x| Ok(json!(x.as_f64().unwrap() * 2.0)),
+        );
+        let output = runtime.run_pipeline_async(&plan, json!(2.0)).await.unwrap();
+        assert_eq!(output, json!({ "input": 2.0, "a": 4.0, "b": 4.0 }));
+    }
+
+    #[tokio::test]
+    async fn test_err_results_fail_the_step() {
+        let declaration = "fn fetch(req: { symbol: string }): Result<f64, string>";
+        let plan: ExecutionPlan = serde_json::from_value(json!({
+            "name": "quote", "version": "1.0", "seed": null,
+            "input_schema": { "kind": "object" }, "output_schema": null,
+            "constraints": [], "metrics": {}, "metric_plans": [],
+            "steps": [{
+                "name": "price", "function_ref": "rust:quotes::fetch", "effects": [],
+                "external": ExternalSignature::from_declaration(declaration).unwrap()
+            }]
+        }))
+        .unwrap();
+
+        let runtime = Runtime::new();
+        runtime.register_rust_fn(
+            "quotes::fetch",
+            ExternalSignature::from_declaration(declaration).unwrap(),
+            |input| match input["symbol"].as_str() {
+                Some("BTCUSDT") => Ok(json!({ "Ok": 42000.0 })),
+                _ => Ok(json!({ "Err": "unknown symbol" })),
+            },
+        );
+
+        let output = runtime
+            .run_pipeline_async(&plan, json!({ "symbol": "BTCUSDT" }))
+            .await
+            .unwrap();
+        assert_eq!(output["price"], json!(42000.0));
+
+        let err = runtime
+            .run_pipeline_async(&plan, json!({ "symbol": "DOGE" }))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::StepFailed { step, error }
+                if step == "price" && error == "unknown symbol"),
+            "{err}"
+        );
+        assert_eq!(err.to_string(), "Step 'price' failed: \"unknown symbol\"");
+
+        // Host-registered steps are marked by codegen instead of a signature.
+        let plan: ExecutionPlan = serde_json::from_value(json!({
+            "name": "quote", "version": "1.0", "seed": null,
+            "input_schema": { "kind": "object" }, "output_schema": null,
+            "constraints": [], "metrics": {}, "metric_plans": [],
+            "steps": [{
+                "name": "price", "function_ref": "main::step_price", "effects": [],
+                "returns_result": true
+            }]
+        }))
+        .unwrap();
+        runtime.register_step("main::step_price", |_| Ok(json!({ "Err": "stale quote" })));
+        let err = runtime
+            .run_pipeline_async(&plan, json!({ "symbol": "BTCUSDT" }))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::StepFailed { error, .. } if error == "stale quote"),
+            "{err}"
+        );
+        runtime.register_step("main::step_price", |_| Ok(json!({ "Ok": 41000.0 })));
+        let output = runtime
+            .run_pipeline_async(&plan, json!({ "symbol": "BTCUSDT" }))
+            .await
+            .unwrap();
+        assert_eq!(output["price"], json!(41000.0));
+    }
+
+    #[test]
+    fn test_python_env_is_reported_for_python_steps() {
+        let runtime = Runtime::new();
+        runtime.register_step("host::score", Ok);
+        let plan_with = |function_ref: &str| -> ExecutionPlan {
+            serde_json::from_value(json!({
+                "name": "env", "version": "1.0", "seed": null,
+                "input_schema": { "kind": "f64" }, "output_schema": null,
+                "constraints": [], "metrics": {}, "metric_plans": [],
+                "steps": [{ "name": "score", "function_ref": function_ref, "effects": [] }]
+            }))
+            .unwrap()
+        };
+
+        assert!(runtime.python_env(&plan_with("host::score")).is_none());
+        let (report, hash) = runtime.python_env(&plan_with("py:math.sqrt")).unwrap();
+        assert!(report["version"].as_str().unwrap().starts_with("3."));
+        assert_eq!(hash, tupa_audit::hash_json(&report));
+        assert_eq!(
+            runtime.python_env(&plan_with("py:math.floor")).unwrap().1,
+            hash
+        );
+    }
+
+    #[tokio::test]
+    async fn test_link_reports_every_unresolved_step_before_running() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let runtime = Runtime::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        runtime.register_step("host::send", move |input| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(input)
+        });
+        runtime.allow_effects(["io"]);
+        let plan_with = |steps: Value| -> ExecutionPlan {
+            serde_json::from_value(json!({
+                "name": "link", "version": "1.0", "seed": null,
+                "input_schema": { "kind": "object" }, "output_schema": null,
+                "constraints": [], "metrics": {}, "metric_plans": [],
+                "steps": steps
+            }))
+            .unwrap()
+        };
+        let send = json!({ "name": "send", "function_ref": "host::send", "effects": ["io"] });
+
+        let plan = plan_with(json!([
+            send,
+            { "name": "missing", "function_ref": "missing_fn", "effects": [] },
+            { "name": "clock", "function_ref": "py:time.time", "effects": ["time", "io"] },
+            { "name": "model", "function_ref": "py:no_such_pkg.score", "effects": [] }
+        ]));
+        let errors = runtime.link(&plan).err().unwrap();
+        let failures: Vec<(&str, String)> = errors
+            .iter()
+            .map(|e| (e.step.as_str(), e.to_string()))
+            .collect();
+        assert_eq!(failures.len(), 3, "{failures:?}");
+        assert_eq!(failures[0].0, "missing");
+        assert_eq!(
+            failures[1],
+            (
+                "clock",
+                "Step 'clock' declares effect time, which the runtime does not allow".to_string()
+            )
+        );
+        assert_eq!(failures[2].0, "model");
+        assert!(runtime.run_pipeline_async(&plan, json!({})).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let linked = runtime
+            .link(&plan_with(json!([send])))
+            .unwrap_or_else(|errors| panic!("{errors:?}"));
+        assert_eq!(linked.plan().steps.len(), 1);
+        let output = runtime
+            .run_linked(&linked, json!({ "x": 1 }))
+            .await
+            .unwrap();
+        assert_eq!(output["send"], json!({ "x": 1 }));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Effects disallowed after linking fail linked runs too.
+        runtime.allow_effects(["time"]);
+        let err = runtime
+            .run_linked(&linked, json!({ "x": 2 }))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::EffectNotAllowed { step, effect } if step == "send" && effect == "io"),
+            "{err}"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_resolve_plan_checks_every_step() {
+        let runtime = Runtime::new();
+        runtime.register_step("host::score", Ok);
+        let plan_with = |function_ref: &str| -> ExecutionPlan {
+            serde_json::from_value(json!({
+                "name": "resolve", "version": "1.0", "seed": null,
+                "input_schema": { "kind": "object" }, "output_schema": null,
+                "constraints": [], "metrics": {}, "metric_plans": [],
+                "steps": [
+                    { "name": "score", "function_ref": "host::score", "effects": [] },
+                    { "name": "extra", "function_ref": function_ref, "effects": [] }
+                ]
+            }))
+            .unwrap()
+        };
+
+        assert!(runtime
+            .resolve_plan(&plan_with("py:os.path.basename"))
+            .is_ok());
+        assert!(runtime
+            .resolve_plan(&plan_with("builtins::str::upper"))
+            .is_ok());
+        let err = runtime
+            .resolve_plan(&plan_with("py:no_such_pkg.models.score"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("step 'extra'"), "{err}");
+        assert!(runtime.resolve_plan(&plan_with("missing_fn")).is_err());
+    }
+
     #[tokio::test]
     async fn test_runtime_validates_structured_input_schema() {
         use tupa_codegen::execution_plan::StepPlan;
@@ -954,41 +2543,31 @@ mod tests {
                         "reason".into(),
                         TypeSchema {
                             kind: "string".into(),
-                            elem: None,
-                            fields: None,
-                            len: None,
-                            name: None,
-                            tensor_shape: None,
-                            tensor_dtype: None,
+                            ..Default::default()
                         },
                     ),
                     (
                         "score".into(),
                         TypeSchema {
                             kind: "f64".into(),
-                            elem: None,
-                            fields: None,
-                            len: None,
-                            name: None,
-                            tensor_shape: None,
-                            tensor_dtype: None,
+                            ..Default::default()
                         },
                     ),
                 ])),
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
+                ..Default::default()
             },
             output_schema: None,
             steps: vec![StepPlan {
                 name: "result".into(),
                 function_ref: "noop".into(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             }],
             constraints: vec![],
             metrics: HashMap::new(),
             metric_plans: vec![],
+            temporal: vec![],
         };
 
         let err = runtime
@@ -1011,12 +2590,7 @@ mod tests {
             seed: None,
             input_schema: TypeSchema {
                 kind: "object".into(),
-                elem: None,
-                fields: None,
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
+                ..Default::default()
             },
             output_schema: Some(TypeSchema {
                 kind: "object".into(),
@@ -1025,27 +2599,22 @@ mod tests {
                     "result".into(),
                     TypeSchema {
                         kind: "string".into(),
-                        elem: None,
-                        fields: None,
-                        len: None,
-                        name: None,
-                        tensor_shape: None,
-                        tensor_dtype: None,
+                        ..Default::default()
                     },
                 )])),
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
+                ..Default::default()
             }),
             steps: vec![StepPlan {
                 name: "result".into(),
                 function_ref: "emit_score".into(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             }],
             constraints: vec![],
             metrics: HashMap::new(),
             metric_plans: vec![],
+            temporal: vec![],
         };
 
         let err = runtime
@@ -1074,12 +2643,7 @@ mod tests {
                         "symbol".into(),
                         TypeSchema {
                             kind: "string".into(),
-                            elem: None,
-                            fields: None,
-                            len: None,
-                            name: None,
-                            tensor_shape: None,
-                            tensor_dtype: None,
+                            ..Default::default()
                         },
                     ),
                     (
@@ -1096,41 +2660,30 @@ mod tests {
                                         "max_spread_pct".into(),
                                         TypeSchema {
                                             kind: "f64".into(),
-                                            elem: None,
-                                            fields: None,
-                                            len: None,
-                                            name: None,
-                                            tensor_shape: None,
-                                            tensor_dtype: None,
+                                            ..Default::default()
                                         },
                                     )])),
-                                    len: None,
-                                    name: None,
-                                    tensor_shape: None,
-                                    tensor_dtype: None,
+                                    ..Default::default()
                                 },
                             )])),
-                            len: None,
-                            name: None,
-                            tensor_shape: None,
-                            tensor_dtype: None,
+                            ..Default::default()
                         },
                     ),
                 ])),
-                len: None,
-                name: None,
-                tensor_shape: None,
-                tensor_dtype: None,
+                ..Default::default()
             },
             output_schema: None,
             steps: vec![StepPlan {
                 name: "result".into(),
                 function_ref: "noop".into(),
                 effects: vec![],
+                external: None,
+                returns_result: false,
             }],
             constraints: vec![],
             metrics: HashMap::new(),
             metric_plans: vec![],
+            temporal: vec![],
         };
 
         let result = runtime
@@ -1154,4 +2707,993 @@ mod tests {
             json!(0.001)
         );
     }
+
+    #[tokio::test]
+    async fn test_runtime_validates_enum_tuple_and_optional_schema() {
+        use tupa_codegen::execution_plan::StepPlan;
+
+        let runtime = Runtime::new();
+        runtime.register_step("noop", Ok);
+
+        let input_schema: TypeSchema = serde_json::from_value(json!({
+            "kind": "object",
+            "elem": null,
+            "len": null,
+            "name": null,
+            "tensor_shape": null,
+            "tensor_dtype": null,
+            "fields": {
+                "tx": {
+                    "kind": "enum",
+                    "elem": null,
+                    "len": null,
+                    "name": "Transaction",
+                    "tensor_shape": null,
+                    "tensor_dtype": null,
+                    "variants": [
+                        { "name": "Refund", "payload": [] },
+                        {
+                            "name": "Purchase",
+                            "payload": [
+                                { "kind": "f64", "elem": null, "len": null, "name": null,
+                                  "tensor_shape": null, "tensor_dtype": null,
+                                  "constraints": ["nan", "inf"] },
+                                { "kind": "string", "elem": null, "len": null, "name": null,
+                                  "tensor_shape": null, "tensor_dtype": null }
+                            ]
+                        }
+                    ]
+                },
+                "pair": {
+                    "kind": "tuple",
+                    "elem": null,
+                    "len": null,
+                    "name": null,
+                    "tensor_shape": null,
+                    "tensor_dtype": null,
+                    "items": [
+                        { "kind": "i64", "elem": null, "len": null, "name": null,
+                          "tensor_shape": null, "tensor_dtype": null },
+                        { "kind": "bool", "elem": null, "len": null, "name": null,
+                          "tensor_shape": null, "tensor_dtype": null }
+                    ]
+                },
+                "note": {
+                    "kind": "string",
+                    "elem": null,
+                    "len": null,
+                    "name": null,
+                    "tensor_shape": null,
+                    "tensor_dtype": null,
+                    "nullable": true
+                }
+            }
+        }))
+        .unwrap();
+        let plan = ExecutionPlan {
+            name: "enum_input".into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema,
+            output_schema: None,
+            steps: vec![StepPlan {
+                name: "result".into(),
+                function_ref: "noop".into(),
+                effects: vec![],
+                external: None,
+                returns_result: false,
+            }],
+            constraints: vec![],
+            metrics: HashMap::new(),
+            metric_plans: vec![],
+            temporal: vec![],
+        };
+
+        runtime
+            .run_pipeline_async(
+                &plan,
+                json!({ "tx": { "Purchase": [12.5, "C123"] }, "pair": [1, true] }),
+            )
+            .await
+            .expect("payload variant and missing optional field should pass");
+        runtime
+            .run_pipeline_async(
+                &plan,
+                json!({ "tx": "Refund", "pair": [1, false], "note": null }),
+            )
+            .await
+            .expect("unit variant and null optional field should pass");
+
+        for (input, expected) in [
+            (
+                json!({ "tx": { "amount": 100 }, "pair": [1, true] }),
+                "input.tx unknown Transaction variant 'amount'",
+            ),
+            (
+                json!({ "tx": { "Purchase": 12.5 }, "pair": [1, true] }),
+                "input.tx Transaction::Purchase expects 2 payload value(s)",
+            ),
+            (
+                json!({ "tx": { "Purchase": ["12.5", "C123"] }, "pair": [1, true] }),
+                "input.tx.Purchase[0] expected f64",
+            ),
+            (
+                json!({ "tx": "Refund", "pair": [1] }),
+                "input.pair expected tuple of 2 items, got 1",
+            ),
+            (
+                json!({ "tx": "Refund", "pair": [1, true], "note": 3 }),
+                "input.note expected string",
+            ),
+        ] {
+            let err = runtime
+                .run_pipeline_async(&plan, input)
+                .await
+                .expect_err("malformed input should fail");
+            assert!(
+                err.to_string().contains(expected),
+                "unexpected error: {err}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runtime_validates_tensor_input_shape_and_dtype() {
+        use tupa_codegen::execution_plan::StepPlan;
+
+        let runtime = Runtime::new();
+        runtime.register_step("noop", Ok);
+
+        let plan = ExecutionPlan {
+            name: "tensor_input".into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema: TypeSchema {
+                kind: "object".into(),
+                elem: None,
+                fields: Some(HashMap::from([(
+                    "image".into(),
+                    TypeSchema {
+                        kind: "tensor".into(),
+                        tensor_shape: Some(vec![None, Some(2)]),
+                        tensor_dtype: Some("f32".into()),
+                        ..Default::default()
+                    },
+                )])),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![StepPlan {
+                name: "result".into(),
+                function_ref: "noop".into(),
+                effects: vec![],
+                external: None,
+                returns_result: false,
+            }],
+            constraints: vec![],
+            metrics: HashMap::new(),
+            metric_plans: vec![],
+            temporal: vec![],
+        };
+
+        runtime
+            .run_pipeline_async(
+                &plan,
+                json!({ "image": [[0.1, 0.2], [0.3, 0.4], [0.5, 0.6]] }),
+            )
+            .await
+            .expect("dynamic batch dimension should pass");
+
+        let err = runtime
+            .run_pipeline_async(&plan, json!({ "image": [[0.1, 0.2, 0.3]] }))
+            .await
+            .expect_err("wrong inner dimension should fail");
+        assert_eq!(
+            err.to_string(),
+            "Schema mismatch: input.image expected tensor shape [?, 2], got [1, 3]"
+        );
+
+        let err = runtime
+            .run_pipeline_async(&plan, json!({ "image": [[0.1, true]] }))
+            .await
+            .expect_err("non-numeric element should fail");
+        assert_eq!(
+            err.to_string(),
+            "Schema mismatch: input.image[0][1] expected f32 tensor element, got true"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_runtime_records_metrics() {
+        use tupa_codegen::execution_plan::StepPlan;
+
+        let runtime = Runtime::new();
+        runtime.configure_circuit_breaker(1, Duration::from_secs(60));
+        runtime.register_step("ok", |_| Ok(json!(0.5)));
+        runtime.register_step("boom", |_| Err("boom".into()));
+
+        let plan = |name: &str, function_ref: &str| ExecutionPlan {
+            name: name.into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema: TypeSchema {
+                kind: "any".into(),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![StepPlan {
+                name: "score".into(),
+                function_ref: function_ref.into(),
+                effects: vec![],
+                external: None,
+                returns_result: false,
+            }],
+            constraints: vec![ConstraintPlan {
+                metric: "score".into(),
+                comparator: "lt".into(),
+                threshold: 0.1,
+                ..Default::default()
+            }],
+            metrics: HashMap::new(),
+            metric_plans: vec![],
+            temporal: vec![],
+        };
+
+        let good = plan("good", "ok");
+        let output = runtime.run_pipeline_async(&good, json!({})).await.unwrap();
+        runtime.evaluate_constraints(&good, &output);
+        let bad = plan("bad", "boom");
+        assert!(runtime.run_pipeline_async(&bad, json!({})).await.is_err());
+        assert!(runtime.run_pipeline_async(&bad, json!({})).await.is_err());
+
+        let m = runtime.metrics();
+        let runs = |pipeline, status| {
+            m.value(
+                metrics::PIPELINE_RUNS,
+                &[("pipeline", pipeline), ("status", status)],
+            )
+        };
+        assert_eq!(runs("good", "success"), Some(1.0));
+        assert_eq!(runs("bad", "failure"), Some(2.0));
+        assert_eq!(
+            m.value(
+                metrics::STEP_RUNS,
+                &[
+                    ("pipeline", "bad"),
+                    ("step", "score"),
+                    ("status", "blocked")
+                ]
+            ),
+            Some(1.0)
+        );
+        assert_eq!(
+            m.value(
+                metrics::STEP_DURATION,
+                &[("pipeline", "good"), ("step", "score")]
+            ),
+            Some(1.0)
+        );
+        assert_eq!(
+            m.value(
+                metrics::CONSTRAINT_EVALUATIONS,
+                &[
+                    ("pipeline", "good"),
+                    ("metric", "score"),
+                    ("result", "fail")
+                ]
+            ),
+            Some(1.0)
+        );
+        assert_eq!(
+            m.value(
+                metrics::BREAKER_TRANSITIONS,
+                &[("from", "closed"), ("to", "open")]
+            ),
+            Some(1.0)
+        );
+        assert_eq!(m.value(metrics::BREAKER_STATE, &[]), Some(1.0));
+
+        let text = runtime.render_metrics();
+        assert!(text.contains("# TYPE tupa_pipeline_duration_seconds histogram\n"));
+        assert!(text.contains("tupa_pipeline_runs_total{pipeline=\"bad\",status=\"failure\"} 2\n"));
+    }
+
+    #[tokio::test]
+    async fn test_runtime_traced_run_joins_caller_trace() {
+        use tupa_codegen::execution_plan::StepPlan;
+
+        let runtime = Runtime::new();
+        runtime.register_step("score_fn", |_| Ok(json!(0.05)));
+        runtime.register_async_step("enrich_fn", |_| Box::pin(async { Ok(json!("ok")) }));
+        let plan = ExecutionPlan {
+            name: "traced".into(),
+            version: "1.0".into(),
+            seed: Some(7),
+            input_schema: TypeSchema {
+                kind: "any".into(),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![
+                StepPlan {
+                    name: "enrich".into(),
+                    function_ref: "enrich_fn".into(),
+                    effects: vec!["IO".into()],
+                    external: None,
+                    returns_result: false,
+                },
+                StepPlan {
+                    name: "score".into(),
+                    function_ref: "score_fn".into(),
+                    effects: vec![],
+                    external: None,
+                    returns_result: false,
+                },
+            ],
+            constraints: vec![ConstraintPlan {
+                metric: "score".into(),
+                comparator: "lt".into(),
+                threshold: 0.1,
+                ..Default::default()
+            }],
+            metrics: HashMap::new(),
+            metric_plans: vec![],
+            temporal: vec![],
+        };
+
+        let parent = TraceContext::parse_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .unwrap();
+        let (result, trace) = runtime
+            .run_pipeline_traced(&plan, json!({}), Some(parent))
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+
+        let names: Vec<&str> = trace.spans.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "pipeline traced",
+                "step enrich",
+                "async enrich_fn",
+                "step score"
+            ]
+        );
+        let root = trace.root_span().unwrap();
+        assert_eq!(root.parent_span_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_eq!(
+            root.attribute("tupa.plan.hash")
+                .and_then(Value::as_str)
+                .map(str::len),
+            Some(64)
+        );
+        assert_eq!(
+            root.attribute("tupa.constraints.success"),
+            Some(&json!(true))
+        );
+        assert_eq!(root.events.len(), 1);
+        assert_eq!(
+            trace.spans[1].parent_span_id.as_deref(),
+            Some(root.span_id.as_str())
+        );
+        assert_eq!(
+            trace.spans[2].parent_span_id.as_deref(),
+            Some(trace.spans[1].span_id.as_str())
+        );
+        assert_eq!(
+            trace.spans[1].attribute("tupa.step.effects"),
+            Some(&json!(["IO"]))
+        );
+        assert_eq!(trace.spans[3].status, trace::SpanStatus::Ok);
+    }
+
+    #[test]
+    fn test_evaluate_rich_constraints() {
+        let leaf =
+            |metric: &str, comparator: &str, operands: Vec<ConstraintOperand>| ConstraintPlan {
+                metric: metric.into(),
+                comparator: comparator.into(),
+                operands,
+                ..Default::default()
+            };
+        let plan = ExecutionPlan {
+            name: "risk".into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema: TypeSchema {
+                kind: "any".into(),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![],
+            constraints: vec![
+                leaf(
+                    "spread",
+                    "between",
+                    vec![
+                        ConstraintOperand::Number(0.0),
+                        ConstraintOperand::Number(0.5),
+                    ],
+                ),
+                leaf(
+                    "drawdown",
+                    "lt",
+                    vec![ConstraintOperand::Ref("config.max_drawdown".into())],
+                ),
+                leaf(
+                    "regime",
+                    "in",
+                    vec![
+                        ConstraintOperand::Number(1.0),
+                        ConstraintOperand::Number(2.0),
+                    ],
+                ),
+                ConstraintPlan {
+                    comparator: "any".into(),
+                    group: vec![
+                        ConstraintPlan {
+                            metric: "volume".into(),
+                            comparator: "ne".into(),
+                            threshold: 0.0,
+                            ..Default::default()
+                        },
+                        leaf(
+                            "liquidity",
+                            "ge",
+                            vec![ConstraintOperand::Ref("min_liquidity".into())],
+                        ),
+                    ],
+                    ..Default::default()
+                },
+                ConstraintPlan {
+                    metric: "latency".into(),
+                    comparator: "le".into(),
+                    threshold: 100.0,
+                    severity: ConstraintSeverity::Warn,
+                    ..Default::default()
+                },
+            ],
+            metrics: HashMap::from([("min_liquidity".to_string(), 5.0)]),
+            metric_plans: vec![],
+            temporal: vec![],
+        };
+
+        let state = json!({
+            "config": { "max_drawdown": 0.2 },
+            "spread": 0.3, "drawdown": 0.1, "regime": 2, "volume": 0, "liquidity": 7,
+            "latency": 250
+        });
+        let report = evaluate_constraints(&plan, &state);
+        assert_eq!(report["success"], json!(true), "{report}");
+        assert_eq!(report["warnings"], json!(["latency"]));
+        assert_eq!(report["constraints"][1]["threshold"], json!(0.2));
+        assert_eq!(report["constraints"][3]["metric"], "any(volume, liquidity)");
+        assert_eq!(report["constraints"][4]["status"], "warn");
+
+        let failing = [
+            ("spread", json!(0.6)),
+            ("drawdown", json!(0.3)),
+            ("regime", json!(3)),
+            ("liquidity", json!(4)),
+        ];
+        for (field, value) in failing {
+            let mut state = state.clone();
+            state[field] = value;
+            let report = evaluate_constraints(&plan, &state);
+            assert_eq!(report["success"], json!(false), "{field}: {report}");
+        }
+
+        // An unresolvable reference fails the constraint.
+        let mut state = state.clone();
+        state["config"] = json!({});
+        assert_eq!(evaluate_constraints(&plan, &state)["success"], json!(false));
+    }
+
+    #[tokio::test]
+    async fn test_unmeasured_metrics_fail_runs_and_block_backtest_trades() {
+        let runtime = Runtime::new();
+        runtime.register_step("signal_fn", |_| Ok(json!("BUY")));
+        let plan: ExecutionPlan = serde_json::from_value(json!({
+            "name": "signal", "version": "1.0", "seed": null,
+            "input_schema": { "kind": "object" }, "output_schema": null,
+            "constraints": [{
+                "metric": "latency_ms", "comparator": "lt", "threshold": 50.0,
+                "severity": "warn"
+            }],
+            // A validation block literal never stands in for the gated metric.
+            "metrics": { "latency_ms": 10.0 }, "metric_plans": [],
+            "steps": [{ "name": "action", "function_ref": "signal_fn", "effects": [] }]
+        }))
+        .unwrap();
+
+        let output = runtime
+            .run_pipeline_async(&plan, json!({ "close": 10.0 }))
+            .await
+            .unwrap();
+        let report = runtime.evaluate_constraints(&plan, &output);
+        assert_eq!(report["success"], json!(false), "{report}");
+        assert_eq!(report["constraints"][0]["status"], "unmeasured");
+        assert_eq!(report["warnings"], json!([]));
+
+        // Referenced thresholds may come from the literals.
+        let mut referenced = plan.clone();
+        referenced.metrics = HashMap::from([("max_latency".to_string(), 50.0)]);
+        referenced.constraints[0].operands = vec![ConstraintOperand::Ref("max_latency".into())];
+        let report = evaluate_constraints(&referenced, &json!({ "latency_ms": 20.0 }));
+        assert_eq!(report["constraints"][0]["status"], "pass", "{report}");
+        assert_eq!(report["constraints"][0]["threshold"], json!(50.0));
+
+        let backtest = runtime
+            .run_backtest(
+                &plan,
+                vec![json!({ "close": 10.0 }), json!({ "close": 20.0 })],
+            )
+            .await
+            .unwrap();
+        // Buying at 10.0 would be worth 10.0 at the last close.
+        assert_eq!(backtest["final_pnl"], json!(0.0));
+        assert_eq!(
+            backtest["history"][0]["constraints"]["constraints"][0]["status"],
+            "unmeasured"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_runtime_validates_labeled_dataset() {
+        use tupa_codegen::execution_plan::StepPlan;
+
+        let runtime = Runtime::new();
+        runtime.register_step("flag_fn", |input| {
+            let amount = input["amount"].as_f64().ok_or("missing amount")?;
+            Ok(json!({ "flagged": amount > 100.0, "risk": amount / 1000.0 }))
+        });
+        let mut plan = ExecutionPlan {
+            name: "fraud".into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema: TypeSchema {
+                kind: "any".into(),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![StepPlan {
+                name: "flag".into(),
+                function_ref: "flag_fn".into(),
+                effects: vec![],
+                external: None,
+                returns_result: false,
+            }],
+            constraints: vec![
+                ConstraintPlan {
+                    metric: "false_positive_rate".into(),
+                    comparator: "le".into(),
+                    threshold: 0.5,
+                    ..Default::default()
+                },
+                ConstraintPlan {
+                    metric: "flag_risk_p95".into(),
+                    comparator: "lt".into(),
+                    threshold: 1.0,
+                    ..Default::default()
+                },
+                ConstraintPlan {
+                    metric: "approval_rate".into(),
+                    comparator: "ge".into(),
+                    threshold: 0.9,
+                    severity: ConstraintSeverity::Warn,
+                    ..Default::default()
+                },
+                ConstraintPlan {
+                    metric: "avg_score".into(),
+                    comparator: "ge".into(),
+                    threshold: 650.0,
+                    ..Default::default()
+                },
+            ],
+            metrics: HashMap::from([("avg_score".to_string(), 700.0)]),
+            metric_plans: vec![],
+            temporal: vec![],
+        };
+        let dataset = vec![
+            json!({ "amount": 500.0, "label": true }),
+            json!({ "amount": 150.0, "label": false }),
+            json!({ "amount": 20.0, "label": false }),
+            json!({ "amount": 30.0, "label": false }),
+            json!({ "label": true }),
+        ];
+
+        let options = DatasetOptions {
+            prediction: Some("flag.flagged".into()),
+            ..DatasetOptions::default()
+        };
+        let report = runtime
+            .validate_dataset(&plan, dataset.clone(), &options)
+            .await
+            .unwrap();
+        assert_eq!(report["records"], json!(5));
+        assert_eq!(report["metrics"]["errors"], json!(1));
+        assert_eq!(report["metrics"]["false_positive_rate"], json!(1.0 / 3.0));
+        assert_eq!(report["metrics"]["flag_risk_max"], json!(0.5));
+        assert_eq!(report["warnings"], json!(["approval_rate"]));
+        assert_eq!(report["errors"][0]["index"], json!(4));
+
+        // `avg_score` is not computed from the dataset: its literal in the
+        // validation block does not stand in for a measurement.
+        assert_eq!(report["success"], json!(false), "{report}");
+        assert_eq!(report["unmeasured"], json!(["avg_score"]));
+        let avg_score = &report["constraints"][3];
+        assert_eq!(avg_score["status"], "unmeasured");
+        assert_eq!(avg_score["value"], Value::Null);
+
+        plan.constraints.pop();
+        let report = runtime
+            .validate_dataset(&plan, dataset.clone(), &options)
+            .await
+            .unwrap();
+        assert_eq!(report["success"], json!(false), "{report}");
+        assert_eq!(report["problems"], json!(["1 of 5 records failed to run"]));
+
+        let labeled = dataset[..4].to_vec();
+        let report = runtime
+            .validate_dataset(&plan, labeled.clone(), &options)
+            .await
+            .unwrap();
+        assert_eq!(report["success"], json!(true), "{report}");
+
+        // A label no record carries leaves every rate unmeasured.
+        let wrong_label = DatasetOptions {
+            label: "is_fraud".into(),
+            ..options.clone()
+        };
+        let report = runtime
+            .validate_dataset(&plan, labeled, &wrong_label)
+            .await
+            .unwrap();
+        assert_eq!(report["success"], json!(false), "{report}");
+        assert_eq!(report["metrics"]["false_positive_rate"], Value::Null);
+        assert_eq!(
+            report["unmeasured"],
+            json!(["false_positive_rate", "approval_rate"])
+        );
+        assert_eq!(
+            report["problems"],
+            json!(["no record has a usable 'is_fraud' label and prediction"])
+        );
+
+        assert!(runtime
+            .validate_dataset(&plan, vec![], &options)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_evaluate_constraints_reports_final_decision() {
+        use tupa_codegen::execution_plan::StepPlan;
+
+        let plan = ExecutionPlan {
+            name: "entry".into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema: TypeSchema {
+                kind: "any".into(),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![StepPlan {
+                name: "decide".into(),
+                function_ref: "decide_fn".into(),
+                effects: vec![],
+                external: None,
+                returns_result: false,
+            }],
+            constraints: vec![ConstraintPlan {
+                metric: "decide.score".into(),
+                comparator: "ge".into(),
+                threshold: 0.5,
+                ..Default::default()
+            }],
+            metrics: HashMap::new(),
+            metric_plans: vec![],
+            temporal: vec![],
+        };
+        let decision = Decision::new("BUY", "confirmed", "entry")
+            .with_gate("spread", false, "spread_above_limit")
+            .with_score("trend", 0.8, 1.0, "trend_score_ok");
+        let state = json!({ "symbol": "BTC", "decide": decision.to_value() });
+
+        let report = evaluate_constraints(&plan, &state);
+        assert!(report["success"].as_bool().unwrap());
+        assert_eq!(report["decision"]["reason"], json!("spread_above_limit"));
+        assert_eq!(report["decision"]["flags"], json!(["spread"]));
+
+        let report = evaluate_constraints(&plan, &json!({ "decide": { "action": "BUY" } }));
+        assert!(report.get("decision").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_runtime_advances_and_commits_temporal_state() {
+        use tupa_codegen::execution_plan::{StepPlan, TemporalPlan};
+
+        let runtime = Runtime::new();
+        let store = Arc::new(state::InMemoryStateStore::new());
+        runtime.set_state_store(store.clone());
+        runtime.register_step("check_fn", |input| {
+            Ok(json!({ "passed": input["input"].as_bool().unwrap_or(false) }))
+        });
+        let plan = ExecutionPlan {
+            name: "entry".into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema: TypeSchema {
+                kind: "bool".into(),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![StepPlan {
+                name: "check".into(),
+                function_ref: "check_fn".into(),
+                effects: vec![],
+                external: None,
+                returns_result: false,
+            }],
+            constraints: vec![],
+            metrics: HashMap::new(),
+            metric_plans: vec![],
+            temporal: vec![TemporalPlan {
+                key: "signal".into(),
+                kind: "confirm".into(),
+                threshold: 2,
+                source: "check.passed".into(),
+                per: None,
+            }],
+        };
+
+        let mut stages = Vec::new();
+        for observed in [true, true, false] {
+            let output = runtime
+                .run_pipeline_async(&plan, json!(observed))
+                .await
+                .unwrap();
+            stages.push(output["state"]["signal"]["stage"].clone());
+        }
+        assert_eq!(
+            stages,
+            [json!("pending"), json!("confirmed"), json!("degrading")]
+        );
+        let stored = store.load(&["entry/signal".into()]).unwrap();
+        assert_eq!(
+            stored["entry/signal"],
+            json!({ "stage": "degrading", "consecutive_hits": 0 })
+        );
+
+        // A failed run commits nothing.
+        runtime.register_step("check_fn", |_| Err("boom".into()));
+        assert!(runtime
+            .run_pipeline_async(&plan, json!(true))
+            .await
+            .is_err());
+        assert_eq!(
+            store.load(&["entry/signal".into()]).unwrap()["entry/signal"]["stage"],
+            json!("degrading")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_runs_keep_temporal_state_per_entity() {
+        use tupa_codegen::execution_plan::{StepPlan, TemporalPlan};
+
+        let runtime = Runtime::new();
+        let store = Arc::new(state::InMemoryStateStore::new());
+        runtime.set_state_store(store.clone());
+        // Yields between load and commit so unserialized runs would interleave.
+        runtime.register_async_step("check_fn", |_| {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                Ok(json!({ "passed": true }))
+            })
+        });
+        let plan = ExecutionPlan {
+            name: "entry".into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema: TypeSchema {
+                kind: "any".into(),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![StepPlan {
+                name: "check".into(),
+                function_ref: "check_fn".into(),
+                effects: vec![],
+                external: None,
+                returns_result: false,
+            }],
+            constraints: vec![],
+            metrics: HashMap::new(),
+            metric_plans: vec![],
+            temporal: vec![TemporalPlan {
+                key: "signal".into(),
+                kind: "confirm".into(),
+                threshold: 100,
+                source: "check.passed".into(),
+                per: Some("symbol".into()),
+            }],
+        };
+
+        let runs = (0..40).map(|i| {
+            let runtime = runtime.clone();
+            let plan = plan.clone();
+            let symbol = if i % 4 == 0 { "ETH" } else { "BTC" };
+            tokio::spawn(async move {
+                runtime
+                    .run_pipeline_async(&plan, json!({ "symbol": symbol }))
+                    .await
+            })
+        });
+        for run in futures::future::join_all(runs).await {
+            run.unwrap().unwrap();
+        }
+        let stored = store
+            .load(&["entry/signal/BTC".into(), "entry/signal/ETH".into()])
+            .unwrap();
+        assert_eq!(stored["entry/signal/BTC"]["consecutive_hits"], json!(30));
+        assert_eq!(stored["entry/signal/ETH"]["consecutive_hits"], json!(10));
+
+        let missing = runtime.run_pipeline_async(&plan, json!({})).await;
+        assert!(matches!(missing, Err(RuntimeError::StateError(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_sync_steps_of_overlapping_runs_run_concurrently() {
+        use std::sync::Condvar;
+
+        let runtime = Runtime::new();
+        // Each call waits for the other to start, which only happens when
+        // neither holds the runtime's lock while it runs.
+        let entered = Arc::new((Mutex::new(0), Condvar::new()));
+        let rendezvous = entered.clone();
+        runtime.register_step("host::meet", move |input| {
+            let (count, arrived) = &*rendezvous;
+            let mut count = count.lock().unwrap();
+            *count += 1;
+            arrived.notify_all();
+            let (count, timeout) = arrived
+                .wait_timeout_while(count, Duration::from_secs(5), |count| *count < 2)
+                .unwrap();
+            if timeout.timed_out() {
+                return Err(format!("only {count} step running"));
+            }
+            Ok(input)
+        });
+        let plan: ExecutionPlan = serde_json::from_value(json!({
+            "name": "meet", "version": "1.0", "seed": null,
+            "input_schema": { "kind": "object" }, "output_schema": null,
+            "constraints": [], "metrics": {}, "metric_plans": [],
+            "steps": [{ "name": "meet", "function_ref": "host::meet", "effects": [] }]
+        }))
+        .unwrap();
+
+        let runs: Vec<_> = (0..2)
+            .map(|i| {
+                let runtime = runtime.clone();
+                let plan = plan.clone();
+                tokio::spawn(
+                    async move { runtime.run_pipeline_async(&plan, json!({ "i": i })).await },
+                )
+            })
+            .collect();
+        for run in runs {
+            run.await.unwrap().unwrap();
+        }
+        assert_eq!(*entered.0.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_explains_branches_guards_and_decision() {
+        use tupa_codegen::execution_plan::StepPlan;
+
+        let runtime = Runtime::new();
+        runtime.register_step("signal_fn", |input| {
+            let price = input["price"].as_f64().unwrap_or(0.0);
+            explain::bind("price", price);
+            let above = price > 100.0;
+            explain::branch("price > 100.0", if above { "then" } else { "else" });
+            Ok(json!({ "passed": above, "reason": "price_above_threshold" }))
+        });
+        runtime.register_async_step("decide_fn", |input| {
+            Box::pin(async move {
+                let passed = input["signal"]["passed"].as_bool().unwrap_or(false);
+                explain::guard("signal", passed, "signal_confirmation");
+                let decision = Decision::new("SELL", "confirmed", "exit").with_gate(
+                    "stop_loss",
+                    false,
+                    "stop_loss_hit",
+                );
+                Ok(decision.to_value())
+            })
+        });
+        let plan = ExecutionPlan {
+            name: "exit".into(),
+            version: "1.0".into(),
+            seed: None,
+            input_schema: TypeSchema {
+                kind: "object".into(),
+                ..Default::default()
+            },
+            output_schema: None,
+            steps: vec![
+                StepPlan {
+                    name: "signal".into(),
+                    function_ref: "signal_fn".into(),
+                    effects: vec![],
+                    external: None,
+                    returns_result: false,
+                },
+                StepPlan {
+                    name: "decision".into(),
+                    function_ref: "decide_fn".into(),
+                    effects: vec![],
+                    external: None,
+                    returns_result: false,
+                },
+            ],
+            constraints: vec![],
+            metrics: HashMap::new(),
+            metric_plans: vec![],
+            temporal: vec![],
+        };
+
+        let (result, explanation) = runtime
+            .run_pipeline_explained(&plan, json!({ "price": 101.0 }))
+            .await;
+        assert!(result.is_ok(), "{result:?}");
+        let signal = explanation.step("signal").unwrap();
+        assert_eq!(signal.status, "success");
+        assert_eq!(
+            signal.events,
+            [
+                ExplainEvent::Binding {
+                    name: "price".into(),
+                    value: json!(101.0)
+                },
+                ExplainEvent::Branch {
+                    label: "price > 100.0".into(),
+                    taken: "then".into()
+                },
+                ExplainEvent::Guard {
+                    name: "output".into(),
+                    passed: true,
+                    reason: "price_above_threshold".into()
+                },
+            ]
+        );
+        let decision = explanation.step("decision").unwrap();
+        assert_eq!(decision.events.len(), 2);
+        assert_eq!(
+            explanation.decision.as_ref().unwrap().reason,
+            "stop_loss_hit"
+        );
+
+        let text = explanation.render_text();
+        assert!(text.contains("branch price > 100.0 -> then"), "{text}");
+        assert!(
+            text.contains("guard stop_loss: FIRED (stop_loss_hit)"),
+            "{text}"
+        );
+        assert!(
+            text.contains("decision SELL [confirmed]: stop_loss_hit"),
+            "{text}"
+        );
+
+        // A failing step is explained with its error.
+        runtime.register_step("signal_fn", |_| Err("feed down".into()));
+        let (result, explanation) = runtime
+            .run_pipeline_explained(&plan, json!({ "price": 99.0 }))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(explanation.steps.len(), 1);
+        assert_eq!(explanation.steps[0].error.as_deref(), Some("feed down"));
+    }
 }
@@ -0,0 +1,48 @@
+//! Plans linked against a runtime with `Runtime::link`.
+//!
+//! Linking resolves every step's `function_ref` (registered sync and async
+//! steps, Rust externals and Python callables) and checks each step's
+//! declared effects against `Runtime::allow_effects`, reporting every
+//! failure at once instead of stopping at the first step that cannot run.
+//! `Runtime::run_linked` then skips resolving steps on every run, checking
+//! only their effects again.
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde_json::Value;
+use tupa_codegen::execution_plan::ExecutionPlan;
+
+use crate::RuntimeError;
+
+/// A plan whose steps all resolved; cheap to clone and to run.
+///
+/// Steps registered on the runtime after linking are not re-checked; effects
+/// are, on every run.
+#[derive(Clone)]
+pub struct LinkedPlan {
+    pub(crate) plan: Arc<ExecutionPlan>,
+    /// Python report and hash, when a step calls Python.
+    pub(crate) python_env: Option<(Value, tupa_audit::Hash)>,
+}
+
+impl LinkedPlan {
+    pub fn plan(&self) -> &ExecutionPlan {
+        &self.plan
+    }
+}
+
+/// Why one step of a plan failed to link.
+#[derive(Debug)]
+pub struct LinkError {
+    pub step: String,
+    pub error: RuntimeError,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for LinkError {}
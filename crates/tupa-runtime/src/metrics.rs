@@ -0,0 +1,233 @@
+//! Operational metrics for the runtime.
+//!
+//! `MetricsRegistry` keeps counters, gauges and histograms keyed by name and
+//! label set, and renders them in the Prometheus text exposition format
+//! (version 0.0.4). Every `Runtime` owns one; see `Runtime::metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Histogram bucket upper bounds, in seconds.
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub const PIPELINE_DURATION: &str = "tupa_pipeline_duration_seconds";
+pub const PIPELINE_RUNS: &str = "tupa_pipeline_runs_total";
+pub const STEP_DURATION: &str = "tupa_step_duration_seconds";
+pub const STEP_RUNS: &str = "tupa_step_runs_total";
+pub const CONSTRAINT_EVALUATIONS: &str = "tupa_constraint_evaluations_total";
+pub const BREAKER_TRANSITIONS: &str = "tupa_circuit_breaker_transitions_total";
+pub const BREAKER_STATE: &str = "tupa_circuit_breaker_state";
+pub const PYTHON_CALL_DURATION: &str = "tupa_python_call_duration_seconds";
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Default)]
+pub struct MetricsRegistry {
+    families: Mutex<BTreeMap<String, Family>>,
+}
+
+struct Family {
+    help: String,
+    series: BTreeMap<Labels, Series>,
+}
+
+enum Series {
+    Counter(f64),
+    Gauge(f64),
+    Histogram(Histogram),
+}
+
+struct Histogram {
+    /// Observations per bucket (not cumulative); the last slot is `+Inf`.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds one to the counter `name` for `labels`.
+    pub fn inc_counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) {
+        self.update(name, help, labels, |series| match series {
+            Some(Series::Counter(value)) => *value += 1.0,
+            _ => *series = Some(Series::Counter(1.0)),
+        });
+    }
+
+    /// Sets the gauge `name` for `labels`.
+    pub fn set_gauge(&self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, help, labels, |series| {
+            *series = Some(Series::Gauge(value));
+        });
+    }
+
+    /// Records `value` in the histogram `name` for `labels`, using `DEFAULT_BUCKETS`.
+    pub fn observe(&self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, help, labels, |series| {
+            if !matches!(series, Some(Series::Histogram(_))) {
+                *series = Some(Series::Histogram(Histogram {
+                    buckets: vec![0; DEFAULT_BUCKETS.len() + 1],
+                    sum: 0.0,
+                    count: 0,
+                }));
+            }
+            if let Some(Series::Histogram(histogram)) = series {
+                let slot = DEFAULT_BUCKETS
+                    .iter()
+                    .position(|bound| value <= *bound)
+                    .unwrap_or(DEFAULT_BUCKETS.len());
+                histogram.buckets[slot] += 1;
+                histogram.sum += value;
+                histogram.count += 1;
+            }
+        });
+    }
+
+    /// Current value of a counter or gauge, or the observation count of a histogram.
+    pub fn value(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let families = self.families.lock().unwrap();
+        match families.get(name)?.series.get(&owned_labels(labels))? {
+            Series::Counter(value) | Series::Gauge(value) => Some(*value),
+            Series::Histogram(histogram) => Some(histogram.count as f64),
+        }
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let kind = match family.series.values().next() {
+                Some(Series::Counter(_)) => "counter",
+                Some(Series::Gauge(_)) => "gauge",
+                Some(Series::Histogram(_)) => "histogram",
+                None => continue,
+            };
+            let _ = writeln!(out, "# HELP {name} {}", escape_help(&family.help));
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, series) in &family.series {
+                match series {
+                    Series::Counter(value) | Series::Gauge(value) => {
+                        let _ = writeln!(out, "{name}{} {value}", format_labels(labels, None));
+                    }
+                    Series::Histogram(histogram) => {
+                        let mut cumulative = 0;
+                        for (slot, count) in histogram.buckets.iter().enumerate() {
+                            cumulative += count;
+                            let le = DEFAULT_BUCKETS
+                                .get(slot)
+                                .map_or_else(|| "+Inf".to_string(), |bound| bound.to_string());
+                            let _ = writeln!(
+                                out,
+                                "{name}_bucket{} {cumulative}",
+                                format_labels(labels, Some(&le))
+                            );
+                        }
+                        let labels = format_labels(labels, None);
+                        let _ = writeln!(out, "{name}_sum{labels} {}", histogram.sum);
+                        let _ = writeln!(out, "{name}_count{labels} {}", histogram.count);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn update(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        apply: impl FnOnce(&mut Option<Series>),
+    ) {
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            series: BTreeMap::new(),
+        });
+        let key = owned_labels(labels);
+        let mut series = family.series.remove(&key);
+        apply(&mut series);
+        if let Some(series) = series {
+            family.series.insert(key, series);
+        }
+    }
+}
+
+fn owned_labels(labels: &[(&str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let mut parts: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{k}=\"{}\"", escape_label(v)))
+        .collect();
+    if let Some(le) = le {
+        parts.push(format!("le=\"{le}\""));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", parts.join(","))
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_help(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_gauges_render_with_labels() {
+        let registry = MetricsRegistry::new();
+        registry.inc_counter("runs_total", "Runs.", &[("pipeline", "a\"b")]);
+        registry.inc_counter("runs_total", "Runs.", &[("pipeline", "a\"b")]);
+        registry.set_gauge("state", "State.", &[], 2.0);
+
+        assert_eq!(
+            registry.value("runs_total", &[("pipeline", "a\"b")]),
+            Some(2.0)
+        );
+        let text = registry.render_prometheus();
+        assert!(text.contains("# HELP runs_total Runs.\n# TYPE runs_total counter\n"));
+        assert!(text.contains("runs_total{pipeline=\"a\\\"b\"} 2\n"));
+        assert!(text.contains("# TYPE state gauge\nstate 2\n"));
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let registry = MetricsRegistry::new();
+        registry.observe("latency_seconds", "Latency.", &[("step", "s")], 0.002);
+        registry.observe("latency_seconds", "Latency.", &[("step", "s")], 0.2);
+        registry.observe("latency_seconds", "Latency.", &[("step", "s")], 60.0);
+
+        let text = registry.render_prometheus();
+        assert!(text.contains("# TYPE latency_seconds histogram\n"));
+        assert!(text.contains("latency_seconds_bucket{step=\"s\",le=\"0.001\"} 0\n"));
+        assert!(text.contains("latency_seconds_bucket{step=\"s\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("latency_seconds_bucket{step=\"s\",le=\"0.25\"} 2\n"));
+        assert!(text.contains("latency_seconds_bucket{step=\"s\",le=\"+Inf\"} 3\n"));
+        assert!(text.contains("latency_seconds_sum{step=\"s\"} 60.202\n"));
+        assert!(text.contains("latency_seconds_count{step=\"s\"} 3\n"));
+    }
+}
@@ -0,0 +1,306 @@
+//! Keyed temporal state that persists across pipeline runs.
+//!
+//! Pipelines declare temporal state in their `state:` section (see
+//! `TemporalPlan`). Before a run the runtime loads each key from the
+//! configured `StateStore` and exposes it to steps as `state.<key>`; after a
+//! successful run it advances every key from the step results and commits
+//! all of them at once. A key declared with `per` keeps one state for each
+//! value of that input field, e.g. one per symbol.
+//!
+//! Runs of one `Runtime` that share a key are serialized from load to
+//! commit, so concurrent runs never lose each other's updates. Runtimes in
+//! other processes sharing a store are not coordinated.
+//!
+//! Stages:
+//! - `confirm`: `idle` → `pending` → `confirmed` after `threshold`
+//!   consecutive observations, then `degrading` on the first miss and `idle`
+//!   on the next.
+//! - `cooldown`: `active` for `threshold` evaluations after a trigger, then
+//!   `idle`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Map, Value};
+use tokio::sync::OwnedMutexGuard;
+use tupa_codegen::execution_plan::TemporalPlan;
+
+use crate::{RuntimeError, RuntimeResult};
+
+/// Persistence for temporal state, keyed by `<pipeline>/<key>`, or
+/// `<pipeline>/<key>/<entity>` for keys declared with `per`.
+pub trait StateStore: Send + Sync {
+    /// Stored values for `keys`; keys never committed are left out.
+    fn load(&self, keys: &[String]) -> RuntimeResult<HashMap<String, Value>>;
+    /// Writes every update or none of them.
+    fn commit(&self, updates: &HashMap<String, Value>) -> RuntimeResult<()>;
+}
+
+/// Process-local store; the default for a new `Runtime`.
+#[derive(Default)]
+pub struct InMemoryStateStore {
+    values: Mutex<HashMap<String, Value>>,
+}
+
+impl InMemoryStateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StateStore for InMemoryStateStore {
+    fn load(&self, keys: &[String]) -> RuntimeResult<HashMap<String, Value>> {
+        let values = self.values.lock().unwrap();
+        Ok(keys
+            .iter()
+            .filter_map(|key| values.get(key).map(|v| (key.clone(), v.clone())))
+            .collect())
+    }
+
+    fn commit(&self, updates: &HashMap<String, Value>) -> RuntimeResult<()> {
+        let mut values = self.values.lock().unwrap();
+        values.extend(updates.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Ok(())
+    }
+}
+
+/// Stores all keys in one JSON object file. Commits write a temporary file
+/// next to it and rename it into place, so readers never see a partial run.
+pub struct FileStateStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileStateStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read_all(&self) -> RuntimeResult<Map<String, Value>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+            Err(e) => return Err(self.error(e)),
+        };
+        match serde_json::from_str(&content).map_err(|e| self.error(e))? {
+            Value::Object(values) => Ok(values),
+            _ => Err(self.error("expected a JSON object")),
+        }
+    }
+
+    fn error(&self, e: impl std::fmt::Display) -> RuntimeError {
+        RuntimeError::StateError(format!("{}: {e}", self.path.display()))
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn load(&self, keys: &[String]) -> RuntimeResult<HashMap<String, Value>> {
+        let _guard = self.lock.lock().unwrap();
+        let values = self.read_all()?;
+        Ok(keys
+            .iter()
+            .filter_map(|key| values.get(key).map(|v| (key.clone(), v.clone())))
+            .collect())
+    }
+
+    fn commit(&self, updates: &HashMap<String, Value>) -> RuntimeResult<()> {
+        let _guard = self.lock.lock().unwrap();
+        let mut values = self.read_all()?;
+        values.extend(updates.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let content =
+            serde_json::to_string_pretty(&Value::Object(values)).map_err(|e| self.error(e))?;
+        std::fs::write(&tmp, content).map_err(|e| self.error(e))?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| self.error(e))
+    }
+}
+
+/// A stage change of one temporal key during a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub key: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Store key of `temporal` for a run of `pipeline` on `input`.
+pub fn store_key(pipeline: &str, temporal: &TemporalPlan, input: &Value) -> RuntimeResult<String> {
+    let Some(per) = &temporal.per else {
+        return Ok(format!("{pipeline}/{}", temporal.key));
+    };
+    let entity = match per
+        .split('.')
+        .try_fold(input, |value, segment| value.get(segment))
+    {
+        Some(Value::String(entity)) => entity.clone(),
+        Some(entity @ (Value::Number(_) | Value::Bool(_))) => entity.to_string(),
+        _ => {
+            return Err(RuntimeError::StateError(format!(
+                "temporal state '{}': input has no '{per}' to key it by",
+                temporal.key
+            )))
+        }
+    };
+    Ok(format!("{pipeline}/{}/{entity}", temporal.key))
+}
+
+/// Per-key locks that serialize runs sharing temporal state.
+#[derive(Default)]
+pub(crate) struct KeyLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl KeyLocks {
+    /// Waits until no other run holds any of `keys`. Keys are taken in
+    /// sorted order, so runs sharing several keys cannot deadlock.
+    pub(crate) async fn acquire(&self, keys: &[String]) -> Vec<OwnedMutexGuard<()>> {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        keys.dedup();
+        let locks: Vec<_> = {
+            let mut locks = self.locks.lock().unwrap();
+            // Drop locks no run holds or waits for.
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            keys.into_iter()
+                .map(|key| locks.entry(key).or_default().clone())
+                .collect()
+        };
+        let mut guards = Vec::with_capacity(locks.len());
+        for lock in locks {
+            guards.push(lock.lock_owned().await);
+        }
+        guards
+    }
+}
+
+/// State of a key that was never committed.
+pub fn initial(temporal: &TemporalPlan) -> Value {
+    match temporal.kind.as_str() {
+        "cooldown" => json!({ "stage": "idle", "remaining_ticks": 0 }),
+        _ => json!({ "stage": "idle", "consecutive_hits": 0 }),
+    }
+}
+
+/// Advances `previous` by one evaluation in which the `source` flag was `observed`.
+pub fn advance(temporal: &TemporalPlan, previous: &Value, observed: bool) -> Value {
+    let stage = previous["stage"].as_str().unwrap_or("idle");
+    match temporal.kind.as_str() {
+        "cooldown" => {
+            let remaining = if observed {
+                temporal.threshold
+            } else {
+                (previous["remaining_ticks"].as_i64().unwrap_or(0) - 1).max(0)
+            };
+            let stage = if remaining > 0 { "active" } else { "idle" };
+            json!({ "stage": stage, "remaining_ticks": remaining })
+        }
+        _ => {
+            let (hits, stage) = if observed {
+                let hits = previous["consecutive_hits"].as_i64().unwrap_or(0) + 1;
+                let stage = if hits >= temporal.threshold {
+                    "confirmed"
+                } else {
+                    "pending"
+                };
+                (hits, stage)
+            } else if stage == "confirmed" {
+                (0, "degrading")
+            } else {
+                (0, "idle")
+            };
+            json!({ "stage": stage, "consecutive_hits": hits })
+        }
+    }
+}
+
+/// Reads the boolean at dotted `path` in the pipeline state; missing is `false`.
+pub(crate) fn observed(state: &Value, path: &str) -> bool {
+    path.split('.')
+        .try_fold(state, |value, segment| value.get(segment))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(kind: &str, threshold: i64) -> TemporalPlan {
+        TemporalPlan {
+            key: "k".into(),
+            kind: kind.into(),
+            threshold,
+            source: "check.passed".into(),
+            per: None,
+        }
+    }
+
+    #[test]
+    fn test_confirm_stages() {
+        let confirm = plan("confirm", 2);
+        let mut state = initial(&confirm);
+        let mut stages = Vec::new();
+        for observed in [true, true, true, false, false] {
+            state = advance(&confirm, &state, observed);
+            stages.push(state["stage"].as_str().unwrap().to_string());
+        }
+        assert_eq!(
+            stages,
+            ["pending", "confirmed", "confirmed", "degrading", "idle"]
+        );
+    }
+
+    #[test]
+    fn test_cooldown_counts_down_per_evaluation() {
+        let cooldown = plan("cooldown", 2);
+        let state = advance(&cooldown, &initial(&cooldown), true);
+        assert_eq!(state, json!({ "stage": "active", "remaining_ticks": 2 }));
+        let state = advance(&cooldown, &state, false);
+        assert_eq!(state, json!({ "stage": "active", "remaining_ticks": 1 }));
+        let state = advance(&cooldown, &state, false);
+        assert_eq!(state, json!({ "stage": "idle", "remaining_ticks": 0 }));
+    }
+
+    #[test]
+    fn test_store_keys_per_entity() {
+        let shared = plan("confirm", 2);
+        let input = json!({ "order": { "symbol": "BTC", "venue": 3 } });
+        assert_eq!(store_key("p", &shared, &input).unwrap(), "p/k");
+
+        let per = |path: &str| TemporalPlan {
+            per: Some(path.into()),
+            ..plan("confirm", 2)
+        };
+        assert_eq!(
+            store_key("p", &per("order.symbol"), &input).unwrap(),
+            "p/k/BTC"
+        );
+        assert_eq!(
+            store_key("p", &per("order.venue"), &input).unwrap(),
+            "p/k/3"
+        );
+        assert!(matches!(
+            store_key("p", &per("order.side"), &input),
+            Err(RuntimeError::StateError(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_store_commits_and_reloads() {
+        let path = std::env::temp_dir().join(format!("tupa-state-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = FileStateStore::new(&path);
+        assert!(store.load(&["p/k".into()]).unwrap().is_empty());
+
+        let updates = HashMap::from([("p/k".to_string(), json!({ "stage": "pending" }))]);
+        store.commit(&updates).unwrap();
+        let reloaded = FileStateStore::new(&path).load(&["p/k".into()]).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(reloaded, updates);
+    }
+}
@@ -0,0 +1,219 @@
+//! Typed Rust steps, as generated by `#[tupa_step]`.
+//!
+//! A `TupaStep` adapts a plain Rust function to the runtime's JSON steps
+//! and describes it as an `@external(rust=...)` declaration: policies
+//! include `declaration()`, and `Runtime::register_tupa_step` registers the
+//! function with the matching `signature()`. Argument and return types
+//! implement `TupaType`, which maps them to Tupã types; structs derive it.
+//!
+//! ```ignore
+//! use tupa_runtime::{tupa_step, TupaType};
+//!
+//! #[derive(serde::Deserialize, TupaType)]
+//! struct MarketRequest { symbol: String }
+//!
+//! #[tupa_step(name = "viper::fetch_market_data", effects = [IO])]
+//! fn fetch_market_data(req: MarketRequest) -> Result<f64, String> { ... }
+//!
+//! runtime.register_tupa_step::<fetch_market_data>();
+//! ```
+
+use serde_json::Value;
+use tupa_codegen::execution_plan::ExternalSignature;
+
+/// A Rust type with a Tupã counterpart.
+pub trait TupaType {
+    /// The Tupã type, as written in source, e.g. `{ symbol: string }`.
+    fn tupa_type() -> String;
+}
+
+/// A typed Rust function registrable as an `@external(rust=...)` step.
+pub trait TupaStep {
+    /// Path in `@external(rust="...")`.
+    const NAME: &'static str;
+    /// Name of the declared Tupã function.
+    const FUNCTION: &'static str;
+    /// Name of its parameter.
+    const PARAM: &'static str;
+    /// Effects as written in `@external(effects=[...])`.
+    const EFFECTS: &'static [&'static str];
+    type Param: TupaType;
+    type Output: TupaType;
+
+    /// Calls the function on the JSON pipeline state.
+    fn call(input: Value) -> Result<Value, String>;
+
+    /// The `@external(rust=...)` declaration to include in a policy.
+    fn declaration() -> String {
+        format!(
+            "@external(rust=\"{}\", effects=[{}])\nfn {}({}: {}): {} {{}}\n",
+            Self::NAME,
+            Self::EFFECTS.join(", "),
+            Self::FUNCTION,
+            Self::PARAM,
+            Self::Param::tupa_type(),
+            Self::Output::tupa_type(),
+        )
+    }
+
+    /// Signature of `declaration()`, with the schemas codegen derives from
+    /// it.
+    fn signature() -> ExternalSignature {
+        ExternalSignature::from_declaration(&Self::declaration())
+            .unwrap_or_else(|e| panic!("invalid declaration for {}: {e}", Self::NAME))
+    }
+}
+
+macro_rules! tupa_types {
+    ($name:literal: $($ty:ty),*) => {
+        $(impl TupaType for $ty {
+            fn tupa_type() -> String {
+                $name.to_string()
+            }
+        })*
+    };
+}
+
+tupa_types!("i64": i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+tupa_types!("f64": f32, f64);
+tupa_types!("bool": bool);
+tupa_types!("string": String);
+
+impl<T: TupaType> TupaType for Vec<T> {
+    fn tupa_type() -> String {
+        format!("[{}]", T::tupa_type())
+    }
+}
+
+impl<T: TupaType, const N: usize> TupaType for [T; N] {
+    fn tupa_type() -> String {
+        format!("[{}; {N}]", T::tupa_type())
+    }
+}
+
+impl<T: TupaType> TupaType for Option<T> {
+    fn tupa_type() -> String {
+        format!("Option<{}>", T::tupa_type())
+    }
+}
+
+impl<T: TupaType> TupaType for Box<T> {
+    fn tupa_type() -> String {
+        T::tupa_type()
+    }
+}
+
+macro_rules! tupa_tuples {
+    ($(($($ty:ident),+)),*) => {
+        $(impl<$($ty: TupaType),+> TupaType for ($($ty,)+) {
+            fn tupa_type() -> String {
+                let items: Vec<String> = vec![$($ty::tupa_type()),+];
+                format!("({})", items.join(", "))
+            }
+        })*
+    };
+}
+
+tupa_tuples!((A, B), (A, B, C), (A, B, C, D));
+
+#[doc(hidden)]
+pub mod __private {
+    pub use serde_json::{from_value, to_value, Value};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tupa_step, Runtime, RuntimeError, TupaType};
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use tupa_codegen::execution_plan::ExecutionPlan;
+
+    #[derive(Deserialize, TupaType)]
+    struct MarketRequest {
+        symbol: String,
+        #[serde(rename = "window")]
+        lookback: u32,
+    }
+
+    #[derive(Serialize, TupaType)]
+    struct Quote {
+        price: f64,
+        history: Vec<f64>,
+    }
+
+    /// Quotes `req.symbol`; fails for an empty symbol.
+    #[tupa_step(name = "viper::fetch_market_data", effects = [IO, ExternalCall("Bybit")])]
+    fn fetch_market_data(req: MarketRequest) -> Result<Quote, String> {
+        if req.symbol.is_empty() {
+            return Err("empty symbol".into());
+        }
+        Ok(Quote {
+            price: req.symbol.len() as f64,
+            history: vec![1.0; req.lookback as usize],
+        })
+    }
+
+    #[tupa_step]
+    fn double(x: f64) -> f64 {
+        x * 2.0
+    }
+
+    #[test]
+    fn test_step_declares_its_signature() {
+        assert_eq!(
+            fetch_market_data::declaration(),
+            "@external(rust=\"viper::fetch_market_data\", effects=[IO, ExternalCall(\"Bybit\")])\n\
+             fn fetch_market_data(req: { symbol: string, window: i64 }): \
+             { price: f64, history: [f64] } {}\n"
+        );
+        let signature = fetch_market_data::signature();
+        let param = signature.param.unwrap();
+        assert_eq!(param.fields.as_ref().unwrap()["window"].kind, "i64");
+        assert_eq!(
+            signature.returns.unwrap().fields.unwrap()["history"].kind,
+            "slice"
+        );
+        assert_eq!(signature.effects, ["io", "external:Bybit"]);
+        assert_eq!(
+            <double as TupaStep>::NAME,
+            concat!(module_path!(), "::double")
+        );
+        assert_eq!(double(2.0), 4.0);
+    }
+
+    #[tokio::test]
+    async fn test_registered_step_runs_against_its_declaration() {
+        let step: ExecutionPlan = serde_json::from_value(json!({
+            "name": "market", "version": "1.0", "seed": null,
+            "input_schema": { "kind": "object" }, "output_schema": null,
+            "constraints": [], "metrics": {}, "metric_plans": [],
+            "steps": [{
+                "name": "quote", "function_ref": "rust:viper::fetch_market_data",
+                "effects": [],
+                "external": fetch_market_data::signature()
+            }]
+        }))
+        .unwrap();
+        let runtime = Runtime::new();
+        runtime.register_tupa_step::<fetch_market_data>();
+
+        let output = runtime
+            .run_pipeline_async(&step, json!({ "symbol": "BTC", "window": 2 }))
+            .await
+            .unwrap();
+        assert_eq!(
+            output["quote"],
+            json!({ "price": 3.0, "history": [1.0, 1.0] })
+        );
+
+        let err = runtime
+            .run_pipeline_async(&step, json!({ "symbol": "", "window": 0 }))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, RuntimeError::StepError(e) if e == "empty symbol"),
+            "{err}"
+        );
+    }
+}
@@ -1,76 +1,267 @@
-use tupa_parser::TensorType;
-use ndarray::ArrayD;
-use thiserror::Error;
+//! Tensor values at the pipeline boundary.
+//!
+//! Tensors travel as nested JSON arrays. The schema carries the expected
+//! shape (`None` for a dynamic dimension) and element dtype; errors name the
+//! offending path and the shape that was found.
+
+use std::collections::BTreeMap;
+
 use serde_json::Value;
+use thiserror::Error;
+use tupa_codegen::execution_plan::TypeSchema;
+use tupa_pyffi::Layout;
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, PartialEq)]
 pub enum Error {
-    #[error("shape mismatch: expected {expected:?}, actual {actual:?}")]
+    #[error(
+        "{path} expected tensor shape {}, got {}",
+        format_shape(expected),
+        format_dims(actual)
+    )]
     ShapeMismatch {
+        path: String,
         expected: Vec<Option<usize>>,
         actual: Vec<usize>,
     },
-    #[error("invalid tensor format in JSON")]
-    InvalidJsonFormat,
+    #[error(
+        "{path} ragged tensor: shape {}, expected {} like its siblings",
+        format_dims(actual),
+        format_dims(expected)
+    )]
+    Ragged {
+        path: String,
+        expected: Vec<usize>,
+        actual: Vec<usize>,
+    },
+    #[error("{path} expected tensor element, got {value}")]
+    InvalidElement { path: String, value: Value },
+    #[error("{path} expected {dtype} tensor element, got {value}")]
+    DtypeMismatch {
+        path: String,
+        dtype: String,
+        value: Value,
+    },
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-pub fn validate_shape(
-    actual: &[usize],
-    expected: &TensorType,
+/// Validates `value` as a tensor with the given shape and dtype.
+///
+/// A missing shape accepts any rank; a missing or unknown dtype only checks
+/// that elements are numbers or booleans.
+pub fn validate_json_tensor(
+    value: &Value,
+    shape: Option<&[Option<usize>]>,
+    dtype: Option<&str>,
+    path: &str,
 ) -> Result<()> {
-    if actual.len() != expected.shape.len() {
-        return Err(Error::ShapeMismatch {
-            expected: expected.shape.clone(),
-            actual: actual.to_vec(),
-        });
+    let actual = json_shape(value, path)?;
+    if let Some(expected) = shape {
+        validate_shape(&actual, expected, path)?;
     }
-    
-    for (exp_dim, act_dim) in expected.shape.iter().zip(actual.iter()) {
-        if let Some(exp_size) = exp_dim {
-            if *exp_size != *act_dim {
-                return Err(Error::ShapeMismatch {
-                    expected: expected.shape.clone(),
-                    actual: actual.to_vec(),
-                });
+    validate_elements(value, dtype.map(|d| d.trim_matches('"')), path)
+}
+
+/// Returns the shape of a nested JSON array, rejecting ragged arrays.
+pub fn json_shape(value: &Value, path: &str) -> Result<Vec<usize>> {
+    match value {
+        Value::Array(items) => {
+            let Some(first) = items.first() else {
+                return Ok(vec![0]);
+            };
+            let inner = json_shape(first, &format!("{path}[0]"))?;
+            for (index, item) in items.iter().enumerate().skip(1) {
+                let item_path = format!("{path}[{index}]");
+                let item_shape = json_shape(item, &item_path)?;
+                if item_shape != inner {
+                    return Err(Error::Ragged {
+                        path: item_path,
+                        expected: inner,
+                        actual: item_shape,
+                    });
+                }
             }
+            let mut shape = vec![items.len()];
+            shape.extend(inner);
+            Ok(shape)
         }
+        Value::Number(_) | Value::Bool(_) => Ok(Vec::new()),
+        _ => Err(Error::InvalidElement {
+            path: path.to_string(),
+            value: value.clone(),
+        }),
     }
-    
-    Ok(())
 }
 
-pub fn validate_tensor_shape<A>(
-    actual: &ArrayD<A>,
-    expected: &TensorType,
-) -> Result<()> {
-    validate_shape(actual.shape(), expected)
+/// Checks `actual` against `expected`, where `None` matches any size.
+///
+/// An empty array carries no inner dimensions, so a trailing `0` matches
+/// any remaining expected dimensions.
+pub fn validate_shape(actual: &[usize], expected: &[Option<usize>], path: &str) -> Result<()> {
+    let rank_ok = if actual.last() == Some(&0) {
+        actual.len() <= expected.len()
+    } else {
+        actual.len() == expected.len()
+    };
+    let dims_ok = expected
+        .iter()
+        .zip(actual)
+        .all(|(exp, act)| exp.is_none_or(|exp| exp == *act));
+    if rank_ok && dims_ok {
+        Ok(())
+    } else {
+        Err(Error::ShapeMismatch {
+            path: path.to_string(),
+            expected: expected.to_vec(),
+            actual: actual.to_vec(),
+        })
+    }
 }
 
-pub fn get_json_shape(v: &Value) -> Result<Vec<usize>> {
-    match v {
-        Value::Array(arr) => {
-            if arr.is_empty() {
-                return Ok(vec![0]);
+/// Where the tensors of a `schema` value sit, for marshalling them to
+/// Python as arrays.
+pub fn layout_of(schema: &TypeSchema) -> Layout {
+    match schema.kind.as_str() {
+        "tensor" => Layout::Tensor {
+            shape: schema.tensor_shape.clone(),
+            dtype: schema
+                .tensor_dtype
+                .as_deref()
+                .map(|dtype| dtype.trim_matches('"').to_string()),
+        },
+        "object" => {
+            let fields: BTreeMap<String, Layout> = schema
+                .fields
+                .iter()
+                .flatten()
+                .map(|(name, field)| (name.clone(), layout_of(field)))
+                .filter(|(_, layout)| *layout != Layout::Value)
+                .collect();
+            if fields.is_empty() {
+                Layout::Value
+            } else {
+                Layout::Record(fields)
             }
-            let sub_shape = get_json_shape(&arr[0])?;
-            for item in arr.iter().skip(1) {
-                let item_shape = get_json_shape(item)?;
-                if item_shape != sub_shape {
-                    return Err(Error::InvalidJsonFormat);
-                }
-            }
-            let mut shape = vec![arr.len()];
-            shape.extend(sub_shape);
-            Ok(shape)
         }
-        Value::Number(_) | Value::Bool(_) => Ok(vec![]),
-        _ => Err(Error::InvalidJsonFormat),
+        _ => Layout::Value,
+    }
+}
+
+fn validate_elements(value: &Value, dtype: Option<&str>, path: &str) -> Result<()> {
+    if let Value::Array(items) = value {
+        for (index, item) in items.iter().enumerate() {
+            validate_elements(item, dtype, &format!("{path}[{index}]"))?;
+        }
+        return Ok(());
+    }
+    let ok = match dtype {
+        Some("f16" | "f32" | "f64") => value.is_number(),
+        Some("i8") => in_range(value, i8::MIN.into(), i8::MAX.into()),
+        Some("i16") => in_range(value, i16::MIN.into(), i16::MAX.into()),
+        Some("i32") => in_range(value, i32::MIN.into(), i32::MAX.into()),
+        Some("i64") => value.is_i64(),
+        Some("u8") => in_range(value, 0, u8::MAX.into()),
+        Some("u16") => in_range(value, 0, u16::MAX.into()),
+        Some("u32") => in_range(value, 0, u32::MAX.into()),
+        Some("u64") => value.is_u64(),
+        Some("bool") => value.is_boolean(),
+        _ => true,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(Error::DtypeMismatch {
+            path: path.to_string(),
+            dtype: dtype.unwrap_or_default().to_string(),
+            value: value.clone(),
+        })
     }
 }
 
-pub fn validate_json_tensor(v: &Value, expected: &TensorType) -> Result<()> {
-    let shape = get_json_shape(v)?;
-    validate_shape(&shape, expected)
+fn in_range(value: &Value, min: i64, max: i64) -> bool {
+    value.as_i64().is_some_and(|n| (min..=max).contains(&n))
+}
+
+fn format_shape(shape: &[Option<usize>]) -> String {
+    let dims: Vec<String> = shape
+        .iter()
+        .map(|dim| dim.map_or_else(|| "?".to_string(), |n| n.to_string()))
+        .collect();
+    format!("[{}]", dims.join(", "))
+}
+
+fn format_dims(shape: &[usize]) -> String {
+    let dims: Vec<String> = shape.iter().map(usize::to_string).collect();
+    format!("[{}]", dims.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_dynamic_dimensions_match_any_size() {
+        let value = json!([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        assert!(validate_json_tensor(&value, Some(&[None, Some(3)]), Some("f32"), "x").is_ok());
+        assert!(validate_json_tensor(&json!([]), Some(&[None, Some(3)]), Some("f32"), "x").is_ok());
+    }
+
+    #[test]
+    fn test_shape_mismatch_reports_path_and_shapes() {
+        let value = json!([[1, 2], [3, 4]]);
+        let err = validate_json_tensor(&value, Some(&[Some(2), Some(3)]), None, "input.image")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "input.image expected tensor shape [2, 3], got [2, 2]"
+        );
+
+        let err = validate_json_tensor(&value, Some(&[None]), None, "x").unwrap_err();
+        assert_eq!(err.to_string(), "x expected tensor shape [?], got [2, 2]");
+    }
+
+    #[test]
+    fn test_ragged_and_dtype_errors_name_the_element() {
+        let err = json_shape(&json!([[1, 2], [3]]), "input.t").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "input.t[1] ragged tensor: shape [1], expected [2] like its siblings"
+        );
+
+        let err =
+            validate_json_tensor(&json!([[1, 2], [3, 300]]), None, Some("u8"), "t").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "t[1][1] expected u8 tensor element, got 300"
+        );
+
+        let err = validate_json_tensor(&json!([1, "a"]), None, None, "t").unwrap_err();
+        assert_eq!(err.to_string(), "t[1] expected tensor element, got \"a\"");
+    }
+
+    #[test]
+    fn test_layout_marks_tensor_fields_only() {
+        let schema: TypeSchema = serde_json::from_value(json!({
+            "kind": "object",
+            "fields": {
+                "image": { "kind": "tensor", "tensor_shape": [1, 28, 28], "tensor_dtype": "f32" },
+                "label": { "kind": "string" }
+            }
+        }))
+        .unwrap();
+        let Layout::Record(fields) = layout_of(&schema) else {
+            panic!("expected record layout");
+        };
+        assert_eq!(fields.len(), 1);
+        assert_eq!(
+            fields["image"],
+            Layout::Tensor {
+                shape: Some(vec![Some(1), Some(28), Some(28)]),
+                dtype: Some("f32".into())
+            }
+        );
+        let plain: TypeSchema = serde_json::from_value(json!({ "kind": "f64" })).unwrap();
+        assert_eq!(layout_of(&plain), Layout::Value);
+    }
 }
@@ -0,0 +1,503 @@
+//! OTLP-JSON traces for pipeline runs.
+//!
+//! `Runtime::run_pipeline_traced` records one root span per run, one span per
+//! step and one per Python or async external call. The result is a
+//! `PipelineTrace` that renders as an OTLP `ExportTraceServiceRequest` in its
+//! JSON encoding and can be handed to a `TraceExporter`. A W3C `traceparent`
+//! from the caller makes the run a child of the host's trace.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::{RuntimeError, RuntimeResult};
+
+/// W3C trace context received from the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits.
+    pub trace_id: String,
+    /// Span of the caller the run is attached to; 16 lowercase hex digits.
+    pub parent_span_id: Option<String>,
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Starts a new trace with no remote parent.
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: random_hex(16),
+            parent_span_id: None,
+            sampled: true,
+        }
+    }
+
+    /// Parses a `traceparent` header (`00-<trace-id>-<parent-id>-<flags>`).
+    pub fn parse_traceparent(header: &str) -> RuntimeResult<Self> {
+        let invalid = || RuntimeError::Trace(format!("invalid traceparent '{header}'"));
+        let parts: Vec<&str> = header.trim().split('-').collect();
+        let [version, trace_id, parent_id, flags] = parts.as_slice() else {
+            return Err(invalid());
+        };
+        let is_hex = |s: &str, len: usize| {
+            s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        if !is_hex(version, 2)
+            || *version == "ff"
+            || !is_hex(trace_id, 32)
+            || !is_hex(parent_id, 16)
+            || !is_hex(flags, 2)
+            || trace_id.bytes().all(|b| b == b'0')
+            || parent_id.bytes().all(|b| b == b'0')
+        {
+            return Err(invalid());
+        }
+        let flags = u8::from_str_radix(flags, 16).map_err(|_| invalid())?;
+        Ok(Self {
+            trace_id: trace_id.to_string(),
+            parent_span_id: Some(parent_id.to_string()),
+            sampled: flags & 1 == 1,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpanStatus {
+    Unset,
+    Ok,
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanEvent {
+    pub name: String,
+    pub time_unix_nano: u64,
+    pub attributes: Vec<(String, Value)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanData {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start_unix_nano: u64,
+    pub end_unix_nano: u64,
+    pub attributes: Vec<(String, Value)>,
+    pub events: Vec<SpanEvent>,
+    pub status: SpanStatus,
+}
+
+impl SpanData {
+    pub fn attribute(&self, key: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find_map(|(k, v)| (k == key).then_some(v))
+    }
+}
+
+/// Spans recorded for one pipeline run; the root span comes first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineTrace {
+    pub trace_id: String,
+    pub sampled: bool,
+    pub spans: Vec<SpanData>,
+}
+
+impl PipelineTrace {
+    pub fn root_span(&self) -> Option<&SpanData> {
+        self.spans.first()
+    }
+
+    /// `traceparent` pointing at the root span, for propagating further.
+    pub fn traceparent(&self) -> Option<String> {
+        let flags = if self.sampled { "01" } else { "00" };
+        self.root_span()
+            .map(|root| format!("00-{}-{}-{flags}", self.trace_id, root.span_id))
+    }
+
+    /// Renders the trace as an OTLP `ExportTraceServiceRequest` (JSON encoding).
+    pub fn to_otlp_json(&self) -> Value {
+        let spans: Vec<Value> = self.spans.iter().map(span_to_otlp).collect();
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": attributes_to_otlp(&[
+                        ("service.name".to_string(), json!("tupa-runtime")),
+                    ]),
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "tupa-runtime", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+}
+
+/// Destination for finished traces.
+pub trait TraceExporter {
+    fn export(&self, trace: &PipelineTrace) -> RuntimeResult<()>;
+}
+
+/// Appends each trace as one line of OTLP-JSON, the layout used by the
+/// OpenTelemetry Collector's file exporter and receiver.
+pub struct FileExporter {
+    path: PathBuf,
+}
+
+impl FileExporter {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl TraceExporter for FileExporter {
+    fn export(&self, trace: &PipelineTrace) -> RuntimeResult<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| RuntimeError::Trace(format!("{}: {e}", self.path.display())))?;
+        writeln!(file, "{}", trace.to_otlp_json())
+            .map_err(|e| RuntimeError::Trace(format!("{}: {e}", self.path.display())))
+    }
+}
+
+/// Posts traces to an OTLP/HTTP endpoint such as a local collector at
+/// `http://localhost:4318/v1/traces`. Only plain `http://` is supported.
+pub struct HttpExporter {
+    endpoint: String,
+    timeout: Duration,
+}
+
+impl HttpExporter {
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl TraceExporter for HttpExporter {
+    fn export(&self, trace: &PipelineTrace) -> RuntimeResult<()> {
+        let error = |msg: String| RuntimeError::Trace(format!("{}: {msg}", self.endpoint));
+        let rest = self
+            .endpoint
+            .strip_prefix("http://")
+            .ok_or_else(|| error("only http:// endpoints are supported".into()))?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/v1/traces"),
+        };
+        let address = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+
+        let body = trace.to_otlp_json().to_string();
+        let mut stream = TcpStream::connect(&address).map_err(|e| error(e.to_string()))?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|_| stream.set_write_timeout(Some(self.timeout)))
+            .map_err(|e| error(e.to_string()))?;
+        write!(
+            stream,
+            "POST {path} HTTP/1.1\r\nHost: {authority}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .map_err(|e| error(e.to_string()))?;
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .map_err(|e| error(e.to_string()))?;
+        let status = response
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| error("malformed HTTP response".into()))?;
+        if (200..300).contains(&status) {
+            Ok(())
+        } else {
+            Err(error(format!("collector answered HTTP {status}")))
+        }
+    }
+}
+
+/// Collects spans of one run; shared by the steps it executes.
+#[derive(Clone)]
+pub(crate) struct TraceRecorder {
+    trace_id: String,
+    spans: Arc<Mutex<Vec<SpanData>>>,
+}
+
+impl TraceRecorder {
+    pub(crate) fn new(trace_id: String) -> Self {
+        Self {
+            trace_id,
+            spans: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub(crate) fn start(&self, name: impl Into<String>, parent: Option<&str>) -> ActiveSpan {
+        ActiveSpan {
+            data: SpanData {
+                trace_id: self.trace_id.clone(),
+                span_id: random_hex(8),
+                parent_span_id: parent.map(str::to_string),
+                name: name.into(),
+                start_unix_nano: now_unix_nano(),
+                end_unix_nano: 0,
+                attributes: Vec::new(),
+                events: Vec::new(),
+                status: SpanStatus::Unset,
+            },
+        }
+    }
+
+    pub(crate) fn finish(&self, mut span: ActiveSpan) {
+        span.data.end_unix_nano = now_unix_nano();
+        self.spans.lock().unwrap().push(span.data);
+    }
+
+    /// Finished spans, with `root_id` first and the rest in start order.
+    pub(crate) fn into_spans(self, root_id: &str) -> Vec<SpanData> {
+        let mut spans = std::mem::take(&mut *self.spans.lock().unwrap());
+        spans.sort_by_key(|span| (span.span_id != root_id, span.start_unix_nano));
+        spans
+    }
+}
+
+/// Where new spans of a run attach: the recorder and the current parent span.
+#[derive(Clone)]
+pub(crate) struct SpanScope {
+    pub(crate) recorder: TraceRecorder,
+    pub(crate) parent: String,
+}
+
+impl SpanScope {
+    pub(crate) fn start(&self, name: impl Into<String>) -> ActiveSpan {
+        self.recorder.start(name, Some(&self.parent))
+    }
+
+    pub(crate) fn child(&self, span: &ActiveSpan) -> SpanScope {
+        SpanScope {
+            recorder: self.recorder.clone(),
+            parent: span.span_id().to_string(),
+        }
+    }
+}
+
+pub(crate) struct ActiveSpan {
+    data: SpanData,
+}
+
+impl ActiveSpan {
+    pub(crate) fn span_id(&self) -> &str {
+        &self.data.span_id
+    }
+
+    pub(crate) fn set_attribute(&mut self, key: &str, value: Value) {
+        self.data.attributes.push((key.to_string(), value));
+    }
+
+    pub(crate) fn add_event(&mut self, name: &str, attributes: Vec<(String, Value)>) {
+        self.data.events.push(SpanEvent {
+            name: name.to_string(),
+            time_unix_nano: now_unix_nano(),
+            attributes,
+        });
+    }
+
+    pub(crate) fn set_result<T, E: std::fmt::Display>(&mut self, result: &Result<T, E>) {
+        self.data.status = match result {
+            Ok(_) => SpanStatus::Ok,
+            Err(e) => SpanStatus::Error(e.to_string()),
+        };
+    }
+}
+
+fn span_to_otlp(span: &SpanData) -> Value {
+    let mut out = json!({
+        "traceId": span.trace_id,
+        "spanId": span.span_id,
+        "name": span.name,
+        "kind": 1,
+        "startTimeUnixNano": span.start_unix_nano.to_string(),
+        "endTimeUnixNano": span.end_unix_nano.to_string(),
+        "attributes": attributes_to_otlp(&span.attributes),
+        "events": span.events.iter().map(|event| json!({
+            "timeUnixNano": event.time_unix_nano.to_string(),
+            "name": event.name,
+            "attributes": attributes_to_otlp(&event.attributes),
+        })).collect::<Vec<_>>(),
+        "status": match &span.status {
+            SpanStatus::Unset => json!({}),
+            SpanStatus::Ok => json!({ "code": 1 }),
+            SpanStatus::Error(message) => json!({ "code": 2, "message": message }),
+        },
+    });
+    if let Some(parent) = &span.parent_span_id {
+        out["parentSpanId"] = json!(parent);
+    }
+    out
+}
+
+fn attributes_to_otlp(attributes: &[(String, Value)]) -> Value {
+    Value::Array(
+        attributes
+            .iter()
+            .map(|(key, value)| json!({ "key": key, "value": any_value(value) }))
+            .collect(),
+    )
+}
+
+/// Maps JSON to an OTLP `AnyValue`; 64-bit integers are strings in OTLP-JSON.
+fn any_value(value: &Value) -> Value {
+    match value {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => json!({ "intValue": i.to_string() }),
+            None => json!({ "doubleValue": n.as_f64() }),
+        },
+        Value::String(s) => json!({ "stringValue": s }),
+        Value::Array(items) => {
+            json!({ "arrayValue": { "values": items.iter().map(any_value).collect::<Vec<_>>() } })
+        }
+        Value::Null | Value::Object(_) => json!({ "stringValue": value.to_string() }),
+    }
+}
+
+fn now_unix_nano() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Random lowercase hex id of `bytes` bytes, never all zeros.
+fn random_hex(bytes: usize) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut out = String::with_capacity(bytes * 2);
+    while out.len() < bytes * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u64(now_unix_nano());
+        out.push_str(&format!("{:016x}", hasher.finish() | 1));
+    }
+    out.truncate(bytes * 2);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_traceparent() {
+        let ctx = TraceContext::parse_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .unwrap();
+        assert_eq!(ctx.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(ctx.parent_span_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert!(ctx.sampled);
+
+        for bad in [
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert!(TraceContext::parse_traceparent(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_otlp_json_encoding() {
+        let recorder = TraceRecorder::new("4bf92f3577b34da6a3ce929d0e0e4736".into());
+        let mut root = recorder.start("pipeline p", Some("00f067aa0ba902b7"));
+        root.set_attribute("tupa.plan.name", json!("p"));
+        root.set_attribute("tupa.step.effects", json!(["IO"]));
+        root.set_result::<(), String>(&Err("boom".into()));
+        let root_id = root.span_id().to_string();
+        recorder.finish(root);
+        let trace = PipelineTrace {
+            trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".into(),
+            sampled: true,
+            spans: recorder.into_spans(&root_id),
+        };
+
+        let otlp = trace.to_otlp_json();
+        let span = &otlp["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], json!("4bf92f3577b34da6a3ce929d0e0e4736"));
+        assert_eq!(span["parentSpanId"], json!("00f067aa0ba902b7"));
+        assert_eq!(span["spanId"].as_str().unwrap().len(), 16);
+        assert!(span["startTimeUnixNano"].is_string());
+        assert_eq!(
+            span["attributes"][0],
+            json!({ "key": "tupa.plan.name", "value": { "stringValue": "p" } })
+        );
+        assert_eq!(
+            span["attributes"][1]["value"],
+            json!({ "arrayValue": { "values": [{ "stringValue": "IO" }] } })
+        );
+        assert_eq!(span["status"], json!({ "code": 2, "message": "boom" }));
+        assert_eq!(
+            trace.traceparent().unwrap(),
+            format!("00-4bf92f3577b34da6a3ce929d0e0e4736-{root_id}-01")
+        );
+    }
+
+    #[test]
+    fn test_http_exporter_posts_to_collector() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let collector = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let len: usize = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    if body.len() >= len {
+                        break;
+                    }
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let trace = PipelineTrace {
+            trace_id: random_hex(16),
+            sampled: true,
+            spans: Vec::new(),
+        };
+        HttpExporter::new(endpoint).export(&trace).unwrap();
+        let request = collector.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        assert!(request.contains("\"resourceSpans\""));
+    }
+}
@@ -0,0 +1,163 @@
+// Generated by `tupa codegen --format rust-bindings`. Do not edit.
+
+use serde::{Deserialize, Serialize};
+use tupa_codegen::execution_plan::ExecutionPlan;
+use tupa_runtime::{Runtime, RuntimeError, RuntimeResult};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredDecisionInputSignal {
+    pub spread_pct: f64,
+    pub trend_score: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredDecisionInputGuards {
+    pub cooldown_active: bool,
+    pub remaining_ticks: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredDecisionInput {
+    pub symbol: String,
+    pub signal: StructuredDecisionInputSignal,
+    pub guards: StructuredDecisionInputGuards,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredDecisionDecisionComponentsItem {
+    pub name: String,
+    pub passed: bool,
+    pub score: f64,
+    pub weight: f64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredDecisionDecision {
+    pub action: String,
+    pub stage: String,
+    pub reason: String,
+    pub score: f64,
+    pub components: Vec<StructuredDecisionDecisionComponentsItem>,
+    pub flags: Vec<String>,
+}
+
+/// Step results of pipeline `StructuredDecision`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredDecisionSteps {
+    pub decision: StructuredDecisionDecision,
+}
+
+/// Final state of pipeline `StructuredDecision`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredDecisionOutput {
+    #[serde(flatten)]
+    pub input: StructuredDecisionInput,
+    #[serde(flatten)]
+    pub steps: StructuredDecisionSteps,
+}
+
+const STRUCTURED_DECISION_PLAN: &str = r#"{
+  "name": "StructuredDecision",
+  "version": "0.8.1",
+  "seed": 42,
+  "input_schema": {
+    "kind": "object",
+    "elem": null,
+    "fields": {
+      "guards": {
+        "kind": "object",
+        "elem": null,
+        "fields": {
+          "remaining_ticks": {
+            "kind": "i64",
+            "elem": null,
+            "len": null,
+            "name": null,
+            "tensor_shape": null,
+            "tensor_dtype": null
+          },
+          "cooldown_active": {
+            "kind": "bool",
+            "elem": null,
+            "len": null,
+            "name": null,
+            "tensor_shape": null,
+            "tensor_dtype": null
+          }
+        },
+        "len": null,
+        "name": null,
+        "tensor_shape": null,
+        "tensor_dtype": null
+      },
+      "symbol": {
+        "kind": "string",
+        "elem": null,
+        "len": null,
+        "name": null,
+        "tensor_shape": null,
+        "tensor_dtype": null
+      },
+      "signal": {
+        "kind": "object",
+        "elem": null,
+        "fields": {
+          "spread_pct": {
+            "kind": "f64",
+            "elem": null,
+            "len": null,
+            "name": null,
+            "tensor_shape": null,
+            "tensor_dtype": null
+          },
+          "trend_score": {
+            "kind": "f64",
+            "elem": null,
+            "len": null,
+            "name": null,
+            "tensor_shape": null,
+            "tensor_dtype": null
+          }
+        },
+        "len": null,
+        "name": null,
+        "tensor_shape": null,
+        "tensor_dtype": null
+      }
+    },
+    "len": null,
+    "name": null,
+    "tensor_shape": null,
+    "tensor_dtype": null
+  },
+  "output_schema": null,
+  "steps": [
+    {
+      "name": "decision",
+      "function_ref": "main::step_decision",
+      "effects": []
+    }
+  ],
+  "constraints": [],
+  "metrics": {},
+  "metric_plans": []
+}"#;
+
+/// Execution plan for pipeline `StructuredDecision`.
+pub fn structured_decision_plan() -> ExecutionPlan {
+    serde_json::from_str(STRUCTURED_DECISION_PLAN).expect("embedded plan for StructuredDecision is valid")
+}
+
+/// Runs pipeline `StructuredDecision` with typed input and output.
+pub async fn run_structured_decision(
+    runtime: &Runtime,
+    input: StructuredDecisionInput,
+) -> RuntimeResult<StructuredDecisionOutput> {
+    let plan = structured_decision_plan();
+    let input = serde_json::to_value(input)
+        .map_err(|e| RuntimeError::ValidationError(format!("input {e}")))?;
+    let output = runtime.run_pipeline_async(&plan, input).await?;
+    serde_json::from_value(output)
+        .map_err(|e| RuntimeError::ValidationError(format!("output {e}")))
+}
@@ -0,0 +1,83 @@
+//! Compiles the Rust bindings `tupa codegen --format rust-bindings` emits for
+//! `examples/pipeline/structured_decision.tp` against this crate, and runs the
+//! pipeline through them.
+//!
+//! After changing the generator, refresh the copy with
+//! `tupa codegen --format rust-bindings examples/pipeline/structured_decision.tp
+//! > crates/tupa-runtime/tests/bindings/structured_decision.rs`.
+
+use serde_json::Value;
+use tupa_codegen::rust_bindings::generate_rust_bindings;
+use tupa_runtime::decision::Decision;
+use tupa_runtime::Runtime;
+
+mod bindings {
+    include!("bindings/structured_decision.rs");
+}
+
+use bindings::*;
+
+const GENERATED: &str = include_str!("bindings/structured_decision.rs");
+
+/// `code` with each embedded plan replaced by a marker, and the plans parsed:
+/// their object keys come out in no fixed order.
+fn split_plans(code: &str) -> (String, Vec<Value>) {
+    let mut text = String::new();
+    let mut plans = Vec::new();
+    let mut rest = code;
+    while let Some(start) = rest.find("= r#\"") {
+        let body = &rest[start + 5..];
+        let end = body.find("\"#;").expect("plan literal is closed");
+        text.push_str(&rest[..start]);
+        text.push_str("= <plan>;");
+        plans.push(serde_json::from_str(&body[..end]).expect("plan literal is JSON"));
+        rest = &body[end + 3..];
+    }
+    text.push_str(rest);
+    (text, plans)
+}
+
+#[test]
+fn test_checked_in_bindings_match_the_generator() {
+    let source = std::fs::read_to_string(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../examples/pipeline/structured_decision.tp"
+    ))
+    .unwrap();
+    let program = tupa_parser::parse_program(&source).unwrap();
+    let code = generate_rust_bindings("main", &program).unwrap();
+    assert_eq!(split_plans(&code), split_plans(GENERATED));
+}
+
+#[tokio::test]
+async fn test_generated_bindings_run_the_pipeline() {
+    let runtime = Runtime::new();
+    runtime.register_step("main::step_decision", |input| {
+        let spread = input["signal"]["spread_pct"].as_f64().unwrap_or(0.0);
+        let decision = Decision::new("ENTER_LONG", "confirmed", "entry_signal")
+            .with_gate("spread", spread <= 0.2, "spread_within_limit")
+            .with_score("trend", 0.8, 70.0, "trend_score");
+        Ok(decision.to_value())
+    });
+
+    let input = StructuredDecisionInput {
+        symbol: "BTCUSDT".into(),
+        signal: StructuredDecisionInputSignal {
+            spread_pct: 0.1,
+            trend_score: 0.8,
+        },
+        guards: StructuredDecisionInputGuards {
+            cooldown_active: false,
+            remaining_ticks: 0,
+        },
+    };
+    let output = run_structured_decision(&runtime, input.clone())
+        .await
+        .unwrap();
+    assert_eq!(output.input, input);
+    let decision = output.steps.decision;
+    assert_eq!(decision.action, "ENTER_LONG");
+    assert_eq!(decision.components.len(), 2);
+    assert!(decision.components[0].passed);
+    assert_eq!(structured_decision_plan().name, "StructuredDecision");
+}
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
+use std::sync::OnceLock;
 
 use thiserror::Error;
 use tupa_effects::EffectSet;
 use tupa_lexer::Span;
 use tupa_parser::{
-    BinaryOp, Expr, ExprKind, Function, Item, MatchArm, Pattern, Program, Stmt, TensorType, Type,
-    UnaryOp,
+    BinaryOp, EnumDef, EnumVariant, Expr, ExprKind, Function, Item, MatchArm, Pattern, Program,
+    Stmt, TemporalDecl, TemporalKind, TensorType, Type, TypeAlias, TypeDef, UnaryOp,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -40,9 +41,58 @@ pub enum Ty {
         args: Vec<TypeSig>,
     },
     Trait(String),
+    /// A `type` alias, by name. Only error messages use it; checking works
+    /// on the type the alias stands for.
+    Alias(String),
     Unknown,
 }
 
+/// Renders the type as written in Tupã source, e.g. `Result<f64, string>`.
+impl std::fmt::Display for Ty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn list<T: std::fmt::Display>(items: &[T]) -> String {
+            items
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        match self {
+            Ty::I64 => write!(f, "i64"),
+            Ty::F64 => write!(f, "f64"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::String => write!(f, "string"),
+            Ty::Null => write!(f, "null"),
+            Ty::Unit => write!(f, "()"),
+            Ty::Tensor(tensor) => {
+                let shape: Vec<String> = tensor
+                    .shape
+                    .iter()
+                    .map(|dim| dim.map_or("?".to_string(), |dim| dim.to_string()))
+                    .collect();
+                write!(f, "Tensor<{}, [{}]>", tensor.dtype, shape.join(", "))
+            }
+            Ty::Tuple(items) => write!(f, "({})", list(items)),
+            Ty::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{name}: {ty}"))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Ty::Array { elem, len } => write!(f, "[{elem}; {len}]"),
+            Ty::Slice { elem } => write!(f, "[{elem}]"),
+            Ty::Func { params, ret } | Ty::Closure { params, ret, .. } => {
+                write!(f, "fn({}) -> {ret}", list(params))
+            }
+            Ty::Enum { name, args } if args.is_empty() => write!(f, "{name}"),
+            Ty::Enum { name, args } => write!(f, "{name}<{}>", list(args)),
+            Ty::Trait(name) | Ty::Alias(name) => write!(f, "{name}"),
+            Ty::Unknown => write!(f, "_"),
+        }
+    }
+}
+
 pub fn analyze_effects(
     expr: &tupa_parser::Expr,
     external_effects: &HashMap<String, EffectSet>,
@@ -149,7 +199,7 @@ pub fn analyze_effects(
                 let acc = fold(expr, external_effects).union(&fold(index, external_effects));
                 acc.union(&fold(value, external_effects))
             }
-            Await(inner) => fold(inner, external_effects),
+            Await(inner) | Try(inner) => fold(inner, external_effects),
         }
     }
     fold(expr, external_effects)
@@ -158,6 +208,8 @@ pub fn analyze_effects(
 pub enum TypeError {
     #[error("unknown type '{name}'{suggestion}")]
     UnknownType { name: String, suggestion: String },
+    #[error("recursive type alias '{name}': an alias cannot contain itself")]
+    RecursiveAlias { name: String },
     #[error("invalid type arity for '{name}': expected {expected}, got {found}")]
     InvalidTypeArity {
         name: String,
@@ -178,7 +230,7 @@ pub enum TypeError {
     },
     #[error("unknown enum variant '{name}'")]
     UnknownVariant { name: String, span: Option<Span> },
-    #[error("type mismatch: expected {expected:?}, got {found:?}")]
+    #[error("type mismatch: expected {expected}, got {found}")]
     Mismatch {
         expected: Ty,
         found: Ty,
@@ -190,22 +242,22 @@ pub enum TypeError {
         found: usize,
         span: Option<Span>,
     },
-    #[error("invalid operand types for {op:?}: {left:?}, {right:?}")]
+    #[error("invalid operand types for {op:?}: {left}, {right}")]
     InvalidBinary {
         op: BinaryOp,
         left: Ty,
         right: Ty,
         span: Option<Span>,
     },
-    #[error("invalid operand type for {op:?}: {found:?}")]
+    #[error("invalid operand type for {op:?}: {found}")]
     InvalidUnary {
         op: UnaryOp,
         found: Ty,
         span: Option<Span>,
     },
-    #[error("invalid call target: {found:?}")]
+    #[error("invalid call target: {found}")]
     InvalidCallTarget { found: Ty, span: Option<Span> },
-    #[error("return type mismatch: expected {expected:?}, got {found:?}")]
+    #[error("return type mismatch: expected {expected}, got {found}")]
     ReturnMismatch {
         expected: Ty,
         found: Ty,
@@ -213,7 +265,7 @@ pub enum TypeError {
     },
     #[error("expected function body to return a value")]
     MissingReturn { span: Option<Span> },
-    #[error("invalid constraint '{constraint}' for base type {base:?}")]
+    #[error("invalid constraint '{constraint}' for base type {base}")]
     InvalidConstraint {
         constraint: String,
         base: Ty,
@@ -238,12 +290,135 @@ pub enum TypeError {
     },
     #[error("undefined metric in pipeline constraints: '{metric}' not found in validation block")]
     UndefinedMetric { metric: String, span: Span },
-    #[error("unknown field '{field}' on type {base:?}")]
+    #[error("unknown field '{field}' on type {base}")]
     UnknownField {
         field: String,
         base: Ty,
         span: Option<Span>,
     },
+    #[error("cannot use '?' on {found} in a function returning {ret}")]
+    InvalidTry {
+        found: Ty,
+        ret: Ty,
+        span: Option<Span>,
+    },
+    #[error("invalid temporal state '{key}': {reason}")]
+    InvalidTemporalState {
+        key: String,
+        reason: String,
+        span: Span,
+    },
+}
+
+impl TypeError {
+    /// Source span of the error, when known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            TypeError::UnknownType { .. }
+            | TypeError::RecursiveAlias { .. }
+            | TypeError::InvalidTypeArity { .. } => None,
+            TypeError::UnknownVar { span, .. }
+            | TypeError::UnknownFunction { span, .. }
+            | TypeError::UnknownVariant { span, .. }
+            | TypeError::Mismatch { span, .. }
+            | TypeError::ArityMismatch { span, .. }
+            | TypeError::InvalidBinary { span, .. }
+            | TypeError::InvalidUnary { span, .. }
+            | TypeError::InvalidCallTarget { span, .. }
+            | TypeError::UnknownField { span, .. }
+            | TypeError::InvalidTry { span, .. }
+            | TypeError::ReturnMismatch { span, .. }
+            | TypeError::MissingReturn { span }
+            | TypeError::InvalidConstraint { span, .. }
+            | TypeError::UnprovenConstraint { span, .. }
+            | TypeError::BreakOutsideLoop { span }
+            | TypeError::ContinueOutsideLoop { span }
+            | TypeError::NonExhaustiveMatch { span } => *span,
+            TypeError::ImpureInDeterministic { span, .. }
+            | TypeError::UndefinedMetric { span, .. }
+            | TypeError::InvalidTemporalState { span, .. } => Some(*span),
+        }
+    }
+
+    /// Diagnostic code reported by `tupa check`, e.g. `E2001`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::UnknownType { .. } => "E1001",
+            TypeError::UnknownVar { .. } => "E1002",
+            TypeError::UnknownFunction { .. } => "E1003",
+            TypeError::UnknownVariant { .. } => "E1004",
+            TypeError::RecursiveAlias { .. } => "E1005",
+            TypeError::Mismatch { .. } => "E2001",
+            TypeError::ArityMismatch { .. } => "E2002",
+            TypeError::InvalidTypeArity { .. } => "E2002",
+            TypeError::InvalidBinary { .. } => "E2003",
+            TypeError::InvalidUnary { .. } => "E2004",
+            TypeError::InvalidCallTarget { .. } => "E2005",
+            TypeError::ReturnMismatch { .. } => "E2006",
+            TypeError::MissingReturn { .. } => "E2007",
+            TypeError::UnknownField { .. } => "E2008",
+            TypeError::InvalidConstraint { .. } => "E3001",
+            TypeError::UnprovenConstraint { .. } => "E3002",
+            TypeError::BreakOutsideLoop { .. } => "E4001",
+            TypeError::ContinueOutsideLoop { .. } => "E4002",
+            TypeError::NonExhaustiveMatch { .. } => "E5001",
+            TypeError::ImpureInDeterministic { .. } => "E2005",
+            TypeError::UndefinedMetric { .. } => "E2006",
+            TypeError::InvalidTemporalState { .. } => "E2009",
+            TypeError::InvalidTry { .. } => "E2010",
+        }
+    }
+
+    /// Prints the types `aliases` declare by name, so a mismatch against a
+    /// large record reads `MarketSignal`.
+    fn with_alias_names(mut self, aliases: &[(String, Ty)]) -> Self {
+        match &mut self {
+            TypeError::Mismatch {
+                expected: a,
+                found: b,
+                ..
+            }
+            | TypeError::ReturnMismatch {
+                expected: a,
+                found: b,
+                ..
+            }
+            | TypeError::InvalidBinary {
+                left: a, right: b, ..
+            }
+            | TypeError::InvalidTry {
+                found: a, ret: b, ..
+            } => {
+                name_aliases(a, aliases);
+                name_aliases(b, aliases);
+            }
+            TypeError::InvalidUnary { found: ty, .. }
+            | TypeError::InvalidCallTarget { found: ty, .. }
+            | TypeError::InvalidConstraint { base: ty, .. }
+            | TypeError::UnknownField { base: ty, .. } => name_aliases(ty, aliases),
+            _ => {}
+        }
+        self
+    }
+}
+
+fn name_aliases(ty: &mut Ty, aliases: &[(String, Ty)]) {
+    if let Some((name, _)) = aliases.iter().find(|(_, aliased)| aliased == ty) {
+        *ty = Ty::Alias(name.clone());
+        return;
+    }
+    match ty {
+        Ty::Tuple(items) => items.iter_mut().for_each(|ty| name_aliases(ty, aliases)),
+        Ty::Record(fields) => fields
+            .iter_mut()
+            .for_each(|(_, ty)| name_aliases(ty, aliases)),
+        Ty::Array { elem, .. } | Ty::Slice { elem } => name_aliases(elem, aliases),
+        Ty::Func { params, ret } | Ty::Closure { params, ret, .. } => {
+            params.iter_mut().for_each(|ty| name_aliases(ty, aliases));
+            name_aliases(ret, aliases);
+        }
+        _ => {}
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -263,6 +438,29 @@ pub struct TypeSig {
     constraints: Option<Vec<String>>,
 }
 
+impl std::fmt::Display for TypeSig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.constraints {
+            Some(constraints) if !constraints.is_empty() => {
+                write!(f, "Safe<{}", self.ty)?;
+                constraints.iter().try_for_each(|c| write!(f, ", !{c}"))?;
+                write!(f, ">")
+            }
+            _ => write!(f, "{}", self.ty),
+        }
+    }
+}
+
+impl TypeSig {
+    pub fn ty(&self) -> &Ty {
+        &self.ty
+    }
+
+    pub fn constraints(&self) -> Option<&[String]> {
+        self.constraints.as_deref()
+    }
+}
+
 #[allow(clippy::result_large_err)]
 pub fn typecheck_program(program: &Program) -> Result<(), TypeError> {
     let _ = typecheck_program_with_warnings(program)?;
@@ -360,7 +558,7 @@ fn collect_vars(expr: &Expr, vars: &mut std::collections::HashSet<String>) {
         ExprKind::Field { expr, .. } => {
             collect_vars(expr, vars);
         }
-        ExprKind::Await(expr) => {
+        ExprKind::Await(expr) | ExprKind::Try(expr) => {
             collect_vars(expr, vars);
         }
     }
@@ -648,7 +846,7 @@ fn infer_lambda_param_types(
                 )?;
             }
         }
-        ExprKind::Await(expr) => {
+        ExprKind::Await(expr) | ExprKind::Try(expr) => {
             infer_lambda_param_types(
                 expr,
                 env,
@@ -1029,6 +1227,15 @@ fn eval_f64_const(expr: &Expr) -> Option<f64> {
     }
 }
 
+/// Effects declared by an `@external(effects=[...])` annotation.
+pub fn external_spec_effects(spec: &tupa_parser::ExternalSpec) -> EffectSet {
+    let mut effects = EffectSet::default();
+    for eff in &spec.effects {
+        effects.insert(effect_from_external_spec(eff));
+    }
+    effects
+}
+
 fn effect_from_external_spec(eff: &str) -> tupa_effects::Effect {
     match eff {
         "IO" => tupa_effects::Effect::IO,
@@ -1049,9 +1256,18 @@ fn effect_from_external_spec(eff: &str) -> tupa_effects::Effect {
     }
 }
 #[allow(clippy::result_large_err)]
-pub fn typecheck_program_with_warnings(program: &Program) -> Result<Vec<Warning>, TypeError> {
+fn collect_symbols(program: &Program) -> Result<ProgramSymbols, TypeError> {
     let mut functions = builtin_functions();
-    let mut enums = HashMap::new();
+    let mut enums: HashMap<String, EnumInfo> = builtin_enums()
+        .iter()
+        .map(|def| {
+            let info = EnumInfo {
+                builtin: true,
+                ..EnumInfo::from_enum(def)
+            };
+            (def.name.clone(), info)
+        })
+        .collect();
     let mut traits = HashMap::new();
     for item in &program.items {
         match item {
@@ -1068,12 +1284,11 @@ pub fn typecheck_program_with_warnings(program: &Program) -> Result<Vec<Warning>
                         constraints: None,
                     },
                 };
-                let mut effects = EffectSet::default();
-                if let Some(spec) = &func.external_spec {
-                    for eff in &spec.effects {
-                        effects.insert(effect_from_external_spec(eff));
-                    }
-                }
+                let effects = func
+                    .external_spec
+                    .as_ref()
+                    .map(external_spec_effects)
+                    .unwrap_or_default();
                 functions.insert(
                     func.name.clone(),
                     FuncSig {
@@ -1087,47 +1302,117 @@ pub fn typecheck_program_with_warnings(program: &Program) -> Result<Vec<Warning>
                 // Pipelines don't contribute to function or enum registries
             }
             Item::Enum(enum_def) => {
-                let mut variants = HashMap::new();
-                for variant in &enum_def.variants {
-                    variants.insert(variant.name.clone(), variant.args.clone());
-                }
-                enums.insert(
-                    enum_def.name.clone(),
-                    EnumInfo {
-                        params: enum_def.generics.clone(),
-                        variants,
-                    },
-                );
+                enums.insert(enum_def.name.clone(), EnumInfo::from_enum(enum_def));
+            }
+            Item::TypeAlias(alias) => {
+                let info = match &alias.def {
+                    TypeDef::Union(def) => EnumInfo::from_enum(def),
+                    TypeDef::Alias(ty) => {
+                        // Checked against the types declared so far, so an
+                        // alias cannot refer to itself.
+                        if ty.mentions(&alias.name) {
+                            return Err(TypeError::RecursiveAlias {
+                                name: alias.name.clone(),
+                            });
+                        }
+                        let params = alias
+                            .generics
+                            .iter()
+                            .map(|param| {
+                                let sig = TypeSig {
+                                    ty: Ty::Unknown,
+                                    constraints: None,
+                                };
+                                (param.clone(), sig)
+                            })
+                            .collect();
+                        type_sig_from_ast_with_generics(ty, &enums, &traits, &params)?;
+                        EnumInfo {
+                            params: alias.generics.clone(),
+                            variants: HashMap::new(),
+                            alias: Some(alias.clone()),
+                            builtin: false,
+                        }
+                    }
+                };
+                enums.insert(alias.name.clone(), info);
             }
             Item::Trait(trait_def) => {
                 traits.insert(trait_def.name.clone(), trait_def.methods.clone());
             }
         }
     }
-    let mut warnings = Vec::new();
-    for item in &program.items {
-        match item {
-            Item::Function(func) => {
-                warnings.extend(typecheck_function(func, &functions, &enums, &traits)?)
-            }
-            Item::Pipeline(pipeline) => {
-                typecheck_pipeline(pipeline, &functions, &enums, &traits)?;
+    Ok(ProgramSymbols {
+        functions,
+        enums,
+        traits,
+    })
+}
+
+#[allow(clippy::result_large_err)]
+pub fn typecheck_program_with_warnings(program: &Program) -> Result<Vec<Warning>, TypeError> {
+    let ProgramSymbols {
+        functions,
+        enums,
+        traits,
+    } = collect_symbols(program)?;
+    let check = || {
+        let mut warnings = Vec::new();
+        for item in &program.items {
+            match item {
+                Item::Function(func) => {
+                    warnings.extend(typecheck_function(func, &functions, &enums, &traits)?)
+                }
+                Item::Pipeline(pipeline) => {
+                    typecheck_pipeline(pipeline, &functions, &enums, &traits)?;
+                }
+                Item::Enum(_) => {} // enums don't need typechecking beyond declaration
+                Item::Trait(_) => {} // traits don't need typechecking beyond declaration
+                Item::TypeAlias(_) => {} // checked when declared
             }
-            Item::Enum(_) => {} // enums don't need typechecking beyond declaration
-            Item::Trait(_) => {} // traits don't need typechecking beyond declaration
         }
-    }
-    Ok(warnings)
+        Ok(warnings)
+    };
+    check().map_err(|e: TypeError| e.with_alias_names(&alias_types(program, &enums, &traits)))
+}
+
+/// Compound types declared with `type`, by name. Aliases of primitives are
+/// left out, or every `f64` would print as `Price`; so are generic aliases.
+fn alias_types(
+    program: &Program,
+    enums: &HashMap<String, EnumInfo>,
+    traits: &HashMap<String, Vec<Function>>,
+) -> Vec<(String, Ty)> {
+    program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::TypeAlias(alias) if alias.generics.is_empty() => {
+                let ty = type_from_ast(&Type::Ident(alias.name.clone()), enums, traits).ok()?;
+                let compound = matches!(
+                    ty,
+                    Ty::Tuple(_) | Ty::Record(_) | Ty::Array { .. } | Ty::Slice { .. }
+                );
+                compound.then(|| (alias.name.clone(), ty))
+            }
+            _ => None,
+        })
+        .collect()
 }
 
+/// Result type of each step, by name, in declaration order.
+type StepTypes = Vec<(String, Ty)>;
+
 #[allow(clippy::result_large_err)]
 fn typecheck_pipeline(
     pipeline: &tupa_parser::PipelineDecl,
     functions: &HashMap<String, FuncSig>,
     enums: &HashMap<String, EnumInfo>,
     traits: &HashMap<String, Vec<Function>>,
-) -> Result<(), TypeError> {
+) -> Result<(StepTypes, Vec<String>), TypeError> {
     let input_sig = type_sig_from_ast(&pipeline.input_ty, enums, traits)?;
+    let mut step_types = Vec::with_capacity(pipeline.steps.len());
+    let mut fallible = Vec::new();
     for step in &pipeline.steps {
         let mut env = TypeEnv::default();
         env.insert_var(
@@ -1135,11 +1420,14 @@ fn typecheck_pipeline(
             input_sig.ty.clone(),
             input_sig.constraints.clone(),
         );
+        if !pipeline.temporal.is_empty() {
+            env.insert_var("state".into(), temporal_state_ty(&pipeline.temporal), None);
+        }
         let expected_return = ExpectedReturn {
             ty: Ty::Unknown,
             constraints: None,
         };
-        let _ty = type_of_expr(
+        let ty = type_of_expr(
             &step.body,
             &mut env,
             functions,
@@ -1147,6 +1435,15 @@ fn typecheck_pipeline(
             traits,
             &expected_return,
         )?;
+        // The runtime fails the run on `Err`, so later steps see the `Ok` value.
+        let ty = match try_variants(&ty, enums) {
+            Some(("Ok", _)) => {
+                fallible.push(step.name.clone());
+                type_of_try(&ty, &ty, enums, traits, Some(step.body.span))?
+            }
+            _ => ty,
+        };
+        step_types.push((step.name.clone(), ty));
     }
     if let Some(block) = &pipeline.validation {
         let mut env = TypeEnv::default();
@@ -1159,9 +1456,132 @@ fn typecheck_pipeline(
     // Validators
     validate_determinism(pipeline, functions)?;
     validate_constraints(pipeline)?;
+    validate_temporal(pipeline, &input_sig.ty, &step_types)?;
+    Ok((step_types, fallible))
+}
+
+/// Type of the `state` variable available to steps of a pipeline that
+/// declares temporal state: one record per key, as loaded before the run.
+pub fn temporal_state_ty(decls: &[TemporalDecl]) -> Ty {
+    Ty::Record(
+        decls
+            .iter()
+            .map(|decl| {
+                let counter = match decl.kind {
+                    TemporalKind::Confirm => "consecutive_hits",
+                    TemporalKind::Cooldown => "remaining_ticks",
+                };
+                (
+                    decl.key.clone(),
+                    Ty::Record(vec![
+                        ("stage".into(), Ty::String),
+                        (counter.into(), Ty::I64),
+                    ]),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Checks that temporal keys are unique, thresholds are positive, each
+/// `observe`/`trigger` path names a boolean step result and each `per` path
+/// names a string, integer or boolean input field.
+#[allow(clippy::result_large_err)]
+fn validate_temporal(
+    pipeline: &tupa_parser::PipelineDecl,
+    input: &Ty,
+    step_types: &[(String, Ty)],
+) -> Result<(), TypeError> {
+    let mut seen = std::collections::HashSet::new();
+    for decl in &pipeline.temporal {
+        let invalid = |reason: String| TypeError::InvalidTemporalState {
+            key: decl.key.clone(),
+            reason,
+            span: decl.span,
+        };
+        if !seen.insert(decl.key.as_str()) {
+            return Err(invalid("duplicate key".into()));
+        }
+        if decl.threshold <= 0 {
+            return Err(invalid(format!(
+                "threshold must be positive, got {}",
+                decl.threshold
+            )));
+        }
+        let mut segments = decl.source.split('.');
+        let step = segments.next().unwrap_or_default();
+        let Some((_, ty)) = step_types.iter().find(|(name, _)| name == step) else {
+            return Err(invalid(format!("'{}' does not name a step", decl.source)));
+        };
+        let ty = field_path_ty(ty, segments)
+            .map_err(|segment| invalid(format!("'{}' has no field '{segment}'", decl.source)))?;
+        if ty != Ty::Bool && ty != Ty::Unknown {
+            return Err(invalid(format!("'{}' must be bool, got {ty}", decl.source)));
+        }
+        if let Some(per) = &decl.per {
+            let ty = field_path_ty(input, per.split('.'))
+                .map_err(|segment| invalid(format!("input has no field '{segment}'")))?;
+            if !matches!(ty, Ty::String | Ty::I64 | Ty::Bool | Ty::Unknown) {
+                return Err(invalid(format!(
+                    "'{per}' must be a string, integer or bool, got {ty}"
+                )));
+            }
+        }
+    }
     Ok(())
 }
 
+/// Type of the field at `segments` inside `ty`, or the first missing segment.
+fn field_path_ty<'a>(ty: &Ty, segments: impl IntoIterator<Item = &'a str>) -> Result<Ty, &'a str> {
+    segments
+        .into_iter()
+        .try_fold(ty.clone(), |ty, segment| match ty {
+            Ty::Record(fields) => fields
+                .into_iter()
+                .find_map(|(name, ty)| (name == segment).then_some(ty))
+                .ok_or(segment),
+            _ => Err(segment),
+        })
+}
+
+/// Resolved types of a pipeline's input, declared output and step results.
+///
+/// Steps are listed in declaration order. Used by generators that need to
+/// describe the pipeline contract to host code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineTypes {
+    pub input: Ty,
+    pub output: Option<Ty>,
+    pub steps: Vec<(String, Ty)>,
+    /// Steps whose expression returns `Result`; `steps` lists their `Ok` type.
+    pub fallible: Vec<String>,
+}
+
+#[allow(clippy::result_large_err)]
+pub fn pipeline_types(
+    program: &Program,
+    pipeline: &tupa_parser::PipelineDecl,
+) -> Result<PipelineTypes, TypeError> {
+    let ProgramSymbols {
+        functions,
+        enums,
+        traits,
+    } = collect_symbols(program)?;
+    let (steps, fallible) = typecheck_pipeline(pipeline, &functions, &enums, &traits)?;
+    let input = type_from_ast(&pipeline.input_ty, &enums, &traits)?;
+    let output = pipeline
+        .output_ty
+        .as_ref()
+        .map(|ty| type_from_ast(ty, &enums, &traits))
+        .transpose()?;
+    Ok(PipelineTypes {
+        input,
+        output,
+        steps,
+        fallible,
+    })
+}
+
 #[allow(clippy::result_large_err)]
 pub fn validate_determinism(
     pipeline: &tupa_parser::PipelineDecl,
@@ -1219,13 +1639,40 @@ pub fn validate_constraints(pipeline: &tupa_parser::PipelineDecl) -> Result<(),
             _ => {}
         }
     }
-    for c in &pipeline.constraints {
-        if !vars.contains(&c.metric) {
+    let input_fields: Vec<&str> = match &pipeline.input_ty {
+        Type::Record(fields) => fields.iter().map(|(name, _)| name.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    check_constraint_refs(&pipeline.constraints, &vars, &input_fields)
+}
+
+/// Every metric must be a validation binding; thresholds may also reference
+/// an input field such as `config.max_drawdown`.
+#[allow(clippy::result_large_err)]
+fn check_constraint_refs(
+    constraints: &[tupa_parser::Constraint],
+    vars: &std::collections::HashSet<String>,
+    input_fields: &[&str],
+) -> Result<(), TypeError> {
+    for c in constraints {
+        if c.group.is_empty() && !vars.contains(&c.metric) {
             return Err(TypeError::UndefinedMetric {
                 metric: c.metric.clone(),
                 span: c.span,
             });
         }
+        for operand in &c.operands {
+            if let tupa_parser::ConstraintOperand::Ref(path) = operand {
+                let root = path.split('.').next().unwrap_or_default();
+                if !vars.contains(path) && !vars.contains(root) && !input_fields.contains(&root) {
+                    return Err(TypeError::UndefinedMetric {
+                        metric: path.clone(),
+                        span: c.span,
+                    });
+                }
+            }
+        }
+        check_constraint_refs(&c.group, vars, input_fields)?;
     }
     Ok(())
 }
@@ -1269,7 +1716,7 @@ fn typecheck_function(
     if expected_return.ty != Ty::Unit && !block_returns(&func.body) {
         if let Some(Stmt::Expr(expr)) = func.body.last() {
             let found = type_of_expr(expr, &mut env, functions, enums, traits, &expected_return)?;
-            if found != expected_return.ty {
+            if join_ty(&found, &expected_return.ty).is_none() {
                 if found == Ty::Unit {
                     return Err(TypeError::MissingReturn {
                         span: Some(expr.span),
@@ -1323,7 +1770,7 @@ fn typecheck_stmt(
                     }
                     _ => (type_from_ast(ty, enums, traits)?, None),
                 };
-                if declared != expr_ty {
+                if join_ty(&declared, &expr_ty).is_none() {
                     return Err(TypeError::Mismatch {
                         expected: declared,
                         found: expr_ty,
@@ -1361,7 +1808,7 @@ fn typecheck_stmt(
             } else {
                 Ty::Unit
             };
-            if found != expected_return.ty {
+            if join_ty(&found, &expected_return.ty).is_none() {
                 return Err(TypeError::ReturnMismatch {
                     expected: expected_return.ty.clone(),
                     found,
@@ -1524,6 +1971,21 @@ fn type_of_expr(
                     ret: Box::new(Ty::String),
                 });
             }
+            // Unit variants such as `None`; arguments are inferred from use.
+            if let Some((enum_name, info, variant_types)) = find_enum_variant(enums, name) {
+                if variant_types.is_empty() {
+                    return Ok(Ty::Enum {
+                        name: enum_name.clone(),
+                        args: vec![
+                            TypeSig {
+                                ty: Ty::Unknown,
+                                constraints: None,
+                            };
+                            info.params.len()
+                        ],
+                    });
+                }
+            }
             Err(TypeError::UnknownVar {
                 name: name.clone(),
                 suggestion: suggestion_message(best_suggestion(
@@ -1730,7 +2192,7 @@ fn type_of_expr(
                     for (arg, expected) in args.iter().zip(params.iter()) {
                         let found =
                             type_of_expr(arg, env, functions, enums, traits, expected_return)?;
-                        if &found != expected && *expected != Ty::Unknown {
+                        if join_ty(&found, expected).is_none() && *expected != Ty::Unknown {
                             // Special case: allow Func with Unknown params to match Func with known params
                             let types_match = match (&found, expected) {
                                 (
@@ -1828,6 +2290,10 @@ fn type_of_expr(
             }
         }
         ExprKind::Await(expr) => type_of_expr(expr, env, functions, enums, traits, expected_return),
+        ExprKind::Try(expr) => {
+            let found = type_of_expr(expr, env, functions, enums, traits, expected_return)?;
+            type_of_try(&found, &expected_return.ty, enums, traits, span)
+        }
         ExprKind::Block(stmts) => {
             type_of_block_expr(stmts, env, functions, enums, traits, expected_return)
         }
@@ -1875,14 +2341,11 @@ fn type_of_expr(
                 },
                 None => return Ok(Ty::Unit),
             };
-            if then_ty != else_ty {
-                return Err(TypeError::Mismatch {
-                    expected: then_ty,
-                    found: else_ty,
-                    span,
-                });
-            }
-            Ok(then_ty)
+            join_ty(&then_ty, &else_ty).ok_or(TypeError::Mismatch {
+                expected: then_ty,
+                found: else_ty,
+                span,
+            })
         }
         ExprKind::Match { expr, arms } => {
             let scrutinee_ty = type_of_expr(expr, env, functions, enums, traits, expected_return)?;
@@ -1920,17 +2383,19 @@ fn type_of_expr(
                     expected_return,
                 )?;
                 env.merge_used(&inner);
-                match &expected_arm_ty {
-                    Some(expected) if *expected != arm_ty => {
-                        return Err(TypeError::Mismatch {
-                            expected: expected.clone(),
-                            found: arm_ty,
-                            span: Some(arm.expr.span),
-                        });
-                    }
-                    None => expected_arm_ty = Some(arm_ty),
-                    _ => {}
-                }
+                expected_arm_ty = match expected_arm_ty {
+                    Some(expected) => match join_ty(&expected, &arm_ty) {
+                        Some(joined) => Some(joined),
+                        None => {
+                            return Err(TypeError::Mismatch {
+                                expected,
+                                found: arm_ty,
+                                span: Some(arm.expr.span),
+                            });
+                        }
+                    },
+                    None => Some(arm_ty),
+                };
             }
             if !is_match_exhaustive(&scrutinee_ty, arms, enums) {
                 return Err(TypeError::NonExhaustiveMatch {
@@ -2039,12 +2504,16 @@ fn find_enum_variant<'a>(
     enums: &'a HashMap<String, EnumInfo>,
     variant: &str,
 ) -> Option<(&'a String, &'a EnumInfo, &'a Vec<Type>)> {
+    let mut builtin = None;
     for (enum_name, info) in enums {
         if let Some(types) = info.variants.get(variant) {
-            return Some((enum_name, info, types));
+            if !info.builtin {
+                return Some((enum_name, info, types));
+            }
+            builtin = Some((enum_name, info, types));
         }
     }
-    None
+    builtin
 }
 
 #[allow(clippy::result_large_err)]
@@ -2402,6 +2871,7 @@ fn typecheck_pattern(
 ) -> Result<(), TypeError> {
     match pattern {
         Pattern::Wildcard => Ok(()),
+        Pattern::Ident(_) if is_unit_variant_pattern(pattern, scrutinee, enums) => Ok(()),
         Pattern::Ident(name) => {
             let constraints = scrutinee_constraints.cloned();
             env.insert_var(name.clone(), scrutinee.clone(), constraints);
@@ -2550,6 +3020,9 @@ fn type_from_ast(
     enums: &HashMap<String, EnumInfo>,
     traits: &HashMap<String, Vec<Function>>,
 ) -> Result<Ty, TypeError> {
+    if let Some(expanded) = expand_alias(ty, enums) {
+        return type_from_ast(&expanded, enums, traits);
+    }
     match ty {
         Type::Ident(name) => match name.as_str() {
             "i64" => Ok(Ty::I64),
@@ -2571,6 +3044,8 @@ fn type_from_ast(
                             found: 0,
                         })
                     }
+                } else if let Some(builtin) = builtin_type(name) {
+                    type_from_ast(&builtin, enums, traits)
                 } else {
                     let mut candidates = vec![
                         "i64".to_string(),
@@ -2578,6 +3053,7 @@ fn type_from_ast(
                         "bool".to_string(),
                         "string".to_string(),
                         "null".to_string(),
+                        "Decision".to_string(),
                     ];
                     candidates.extend(enums.keys().cloned());
                     let suggestion = suggestion_message(best_suggestion(name, candidates));
@@ -2675,6 +3151,9 @@ fn type_sig_from_ast(
             })
         }
         _ => {
+            if let Some(expanded) = expand_alias(ty, enums) {
+                return type_sig_from_ast(&expanded, enums, traits);
+            }
             validate_safe_annotations_in_type(ty, enums, traits)?;
             Ok(TypeSig {
                 ty: type_from_ast(ty, enums, traits)?,
@@ -2699,6 +3178,9 @@ fn type_from_ast_with_generics(
             type_from_ast(ty, enums, traits)
         }
         Type::Generic { name, args } => {
+            if let Some(expanded) = expand_alias(ty, enums) {
+                return type_from_ast_with_generics(&expanded, enums, traits, generics);
+            }
             let Some(info) = enums.get(name) else {
                 let mut candidates = vec![
                     "i64".to_string(),
@@ -2794,6 +3276,9 @@ fn type_sig_from_ast_with_generics(
             })
         }
         _ => {
+            if let Some(expanded) = expand_alias(ty, enums) {
+                return type_sig_from_ast_with_generics(&expanded, enums, traits, generics);
+            }
             validate_safe_annotations_in_type_with_generics(ty, enums, traits, generics)?;
             Ok(TypeSig {
                 ty: type_from_ast_with_generics(ty, enums, traits, generics)?,
@@ -2878,6 +3363,111 @@ fn resolve_variant_types(
         .collect()
 }
 
+/// The success and failure variants `?` looks for when `ty` is an
+/// `Option` (`Some`/`None`) or `Result` (`Ok`/`Err`) with that shape.
+fn try_variants(
+    ty: &Ty,
+    enums: &HashMap<String, EnumInfo>,
+) -> Option<(&'static str, &'static str)> {
+    let Ty::Enum { name, .. } = ty else {
+        return None;
+    };
+    let variants = match name.as_str() {
+        "Option" => ("Some", "None"),
+        "Result" => ("Ok", "Err"),
+        _ => return None,
+    };
+    let info = enums.get(name)?;
+    let success = info.variants.get(variants.0)?;
+    (success.len() == 1 && info.variants.contains_key(variants.1)).then_some(variants)
+}
+
+/// Type of `expr?`, where `expr` has type `found`: the `Some`/`Ok` payload.
+/// The enclosing function returns `ret`, which must be the same enum with
+/// a compatible failure variant so the failure can be returned as is.
+#[allow(clippy::result_large_err)]
+fn type_of_try(
+    found: &Ty,
+    ret: &Ty,
+    enums: &HashMap<String, EnumInfo>,
+    traits: &HashMap<String, Vec<Function>>,
+    span: Option<Span>,
+) -> Result<Ty, TypeError> {
+    let invalid = || TypeError::InvalidTry {
+        found: found.clone(),
+        ret: ret.clone(),
+        span,
+    };
+    let (
+        Some((success, failure)),
+        Ty::Enum { name, args },
+        Ty::Enum {
+            name: ret_name,
+            args: ret_args,
+        },
+    ) = (try_variants(found, enums), found, ret)
+    else {
+        return Err(invalid());
+    };
+    if name != ret_name {
+        return Err(invalid());
+    }
+    let info = &enums[name];
+    let resolve = |args: &[TypeSig], variant: &str| {
+        resolve_variant_types(name, info, args, &info.variants[variant], enums, traits)
+    };
+    let compatible = resolve(args, failure)?
+        .iter()
+        .zip(resolve(ret_args, failure)?.iter())
+        .all(|(found, ret)| join_sig(found, ret).is_some());
+    if !compatible {
+        return Err(invalid());
+    }
+    Ok(resolve(args, success)?.remove(0).ty)
+}
+
+/// A type covering both `a` and `b`: the type itself when they are equal,
+/// or an enum whose arguments join. Constructors leave the arguments they
+/// cannot infer `Unknown`, e.g. `E` in `Ok(1)` or `T` in `None`.
+fn join_ty(a: &Ty, b: &Ty) -> Option<Ty> {
+    if a == b {
+        return Some(a.clone());
+    }
+    match (a, b) {
+        (
+            Ty::Enum { name, args },
+            Ty::Enum {
+                name: other_name,
+                args: other_args,
+            },
+        ) if name == other_name && args.len() == other_args.len() => Some(Ty::Enum {
+            name: name.clone(),
+            args: args
+                .iter()
+                .zip(other_args)
+                .map(|(a, b)| join_sig(a, b))
+                .collect::<Option<_>>()?,
+        }),
+        _ => None,
+    }
+}
+
+fn join_sig(a: &TypeSig, b: &TypeSig) -> Option<TypeSig> {
+    if a.ty == Ty::Unknown {
+        return Some(b.clone());
+    }
+    if b.ty == Ty::Unknown {
+        return Some(a.clone());
+    }
+    if a.constraints != b.constraints {
+        return None;
+    }
+    Some(TypeSig {
+        ty: join_ty(&a.ty, &b.ty)?,
+        constraints: a.constraints.clone(),
+    })
+}
+
 #[allow(clippy::result_large_err)]
 fn validate_safe_annotations_in_type(
     ty: &Type,
@@ -2922,8 +3512,28 @@ fn validate_safe_annotations_in_type(
     }
 }
 
-fn is_catch_all_pattern(p: &Pattern) -> bool {
-    matches!(p, Pattern::Wildcard | Pattern::Ident(_))
+fn is_catch_all_pattern(p: &Pattern, scrutinee: &Ty, enums: &HashMap<String, EnumInfo>) -> bool {
+    match p {
+        Pattern::Wildcard => true,
+        Pattern::Ident(_) => !is_unit_variant_pattern(p, scrutinee, enums),
+        _ => false,
+    }
+}
+
+/// Whether `pattern` names a unit variant of the enum `scrutinee`, as in
+/// `None => ...`, rather than binding a variable.
+fn is_unit_variant_pattern(
+    pattern: &Pattern,
+    scrutinee: &Ty,
+    enums: &HashMap<String, EnumInfo>,
+) -> bool {
+    let (Pattern::Ident(variant), Ty::Enum { name, .. }) = (pattern, scrutinee) else {
+        return false;
+    };
+    enums
+        .get(name)
+        .and_then(|info| info.variants.get(variant))
+        .is_some_and(|args| args.is_empty())
 }
 
 fn is_match_exhaustive(
@@ -2933,7 +3543,7 @@ fn is_match_exhaustive(
 ) -> bool {
     if arms
         .iter()
-        .any(|arm| is_catch_all_pattern(&arm.pattern) && arm.guard.is_none())
+        .any(|arm| is_catch_all_pattern(&arm.pattern, scrutinee, enums) && arm.guard.is_none())
     {
         return true;
     }
@@ -2964,7 +3574,7 @@ fn is_match_exhaustive(
                 covered.insert(variant, false);
             }
             for arm in arms {
-                if let Pattern::Constructor { name, .. } = &arm.pattern {
+                if let Pattern::Constructor { name, .. } | Pattern::Ident(name) = &arm.pattern {
                     if let Some(entry) = covered.get_mut(name) {
                         *entry = true;
                     }
@@ -3170,7 +3780,97 @@ fn builtin_weighted_function() -> FuncSig {
     }
 }
 
+/// Declared shape of a built-in named type: `Decision`, the standard output
+/// of a policy pipeline, and `DecisionComponent`, one folded check or score.
+///
+/// User enums with the same name take precedence.
+pub fn builtin_type(name: &str) -> Option<Type> {
+    let ident = |name: &str| Type::Ident(name.to_string());
+    match name {
+        "Decision" => Some(Type::Record(vec![
+            ("action".into(), ident("string")),
+            ("stage".into(), ident("string")),
+            ("reason".into(), ident("string")),
+            ("score".into(), ident("f64")),
+            (
+                "components".into(),
+                Type::Slice {
+                    elem: Box::new(ident("DecisionComponent")),
+                },
+            ),
+            (
+                "flags".into(),
+                Type::Slice {
+                    elem: Box::new(ident("string")),
+                },
+            ),
+        ])),
+        "DecisionComponent" => Some(Type::Record(vec![
+            ("name".into(), ident("string")),
+            ("passed".into(), ident("bool")),
+            ("score".into(), ident("f64")),
+            ("weight".into(), ident("f64")),
+            ("reason".into(), ident("string")),
+        ])),
+        _ => None,
+    }
+}
+
+fn builtin_decision_type() -> TypeSig {
+    let decision = builtin_type("Decision").expect("Decision is a built-in type");
+    TypeSig {
+        ty: type_from_ast(&decision, &HashMap::new(), &HashMap::new())
+            .expect("built-in types resolve"),
+        constraints: None,
+    }
+}
+
+fn builtin_decision_function(params: Vec<Ty>) -> FuncSig {
+    FuncSig {
+        params: params
+            .into_iter()
+            .map(|ty| TypeSig {
+                ty,
+                constraints: None,
+            })
+            .collect(),
+        ret: builtin_decision_type(),
+        effects: EffectSet::default(),
+    }
+}
+
+/// Enums every program can use without declaring them: `Option<T>`, with
+/// `Some(T)` and `None`, and `Result<T, E>`, with `Ok(T)` and `Err(E)`.
+///
+/// Declarations with the same name take precedence.
+pub fn builtin_enums() -> &'static [EnumDef] {
+    static ENUMS: OnceLock<Vec<EnumDef>> = OnceLock::new();
+    ENUMS.get_or_init(|| {
+        let def = |name: &str, generics: &[&str], variants: &[(&str, Option<&str>)]| EnumDef {
+            name: name.into(),
+            generics: generics.iter().map(|g| g.to_string()).collect(),
+            variants: variants
+                .iter()
+                .map(|(variant, arg)| EnumVariant {
+                    name: variant.to_string(),
+                    args: arg.iter().map(|a| Type::Ident(a.to_string())).collect(),
+                })
+                .collect(),
+            exported: false,
+        };
+        vec![
+            def("Option", &["T"], &[("Some", Some("T")), ("None", None)]),
+            def(
+                "Result",
+                &["T", "E"],
+                &[("Ok", Some("T")), ("Err", Some("E"))],
+            ),
+        ]
+    })
+}
+
 fn builtin_functions() -> HashMap<String, FuncSig> {
+    let decision = builtin_decision_type().ty;
     HashMap::from([
         ("pass".into(), builtin_reason_function(true, "pass")),
         ("fail".into(), builtin_reason_function(false, "fail")),
@@ -3179,13 +3879,73 @@ fn builtin_functions() -> HashMap<String, FuncSig> {
         ("weighted".into(), builtin_weighted_function()),
         ("confirm".into(), builtin_confirm_function()),
         ("cooldown".into(), builtin_cooldown_function()),
+        (
+            "decide".into(),
+            builtin_decision_function(vec![Ty::String, Ty::String, Ty::String]),
+        ),
+        (
+            "with_gate".into(),
+            builtin_decision_function(vec![
+                decision.clone(),
+                Ty::String,
+                builtin_reason_return(true, "pass").ty,
+            ]),
+        ),
+        (
+            "with_score".into(),
+            builtin_decision_function(vec![
+                decision.clone(),
+                Ty::String,
+                builtin_score_return().ty,
+            ]),
+        ),
+        (
+            "with_flag".into(),
+            builtin_decision_function(vec![decision, Ty::String]),
+        ),
     ])
 }
 
+struct ProgramSymbols {
+    functions: HashMap<String, FuncSig>,
+    enums: HashMap<String, EnumInfo>,
+    traits: HashMap<String, Vec<Function>>,
+}
+
 #[derive(Debug, Clone)]
 struct EnumInfo {
     params: Vec<String>,
     variants: HashMap<String, Vec<Type>>,
+    /// Set for a `type` alias, which shares the namespace of enums.
+    alias: Option<TypeAlias>,
+    /// One of [`builtin_enums`]; declared enums win variant name clashes.
+    builtin: bool,
+}
+
+impl EnumInfo {
+    fn from_enum(def: &EnumDef) -> Self {
+        Self {
+            params: def.generics.clone(),
+            variants: def
+                .variants
+                .iter()
+                .map(|variant| (variant.name.clone(), variant.args.clone()))
+                .collect(),
+            alias: None,
+            builtin: false,
+        }
+    }
+}
+
+/// The type `ty` stands for if it names a `type` alias with as many
+/// arguments as parameters.
+fn expand_alias(ty: &Type, enums: &HashMap<String, EnumInfo>) -> Option<Type> {
+    let (name, args) = match ty {
+        Type::Ident(name) => (name, &[][..]),
+        Type::Generic { name, args } => (name, args.as_slice()),
+        _ => return None,
+    };
+    enums.get(name)?.alias.as_ref()?.expand(args)
 }
 
 impl TypeEnv {
@@ -3843,6 +4603,124 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn typecheck_decision_combinators_fold_components() {
+        let program = parse_program(
+            r#"fn decide_entry(spread_ok: bool, trend: f64): Decision {
+  let gate = pass("spread_within_limit");
+  if !spread_ok { gate = fail("spread_above_limit"); }
+  let d = decide("BUY", "confirmed", "entry");
+  let d = with_gate(d, "spread", gate);
+  let d = with_score(d, "trend", weighted(trend, 100.0, "trend_score"));
+  return with_flag(d, "paper");
+}
+fn first_reason(d: Decision): string { return d.components[0].reason; }"#,
+        )
+        .unwrap();
+        let result = typecheck_program(&program);
+        assert!(result.is_ok(), "{result:?}");
+    }
+
+    #[test]
+    fn typecheck_decision_combinator_rejects_loose_record() {
+        let program =
+            parse_program("fn main() { let d = with_flag({ action: \"BUY\" }, \"paper\"); }")
+                .unwrap();
+        assert!(matches!(
+            typecheck_program(&program),
+            Err(TypeError::Mismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn typecheck_temporal_state_is_visible_to_steps() {
+        let program = parse_program(
+            r#"fn check(x: i64): { passed: bool } { return { passed: x > 0 }; }
+fn hits(s: { signal: { stage: string, consecutive_hits: i64 } }): i64 { return s.signal.consecutive_hits; }
+pipeline P {
+  input: i64,
+  state: [{ key: "signal", confirm: 2, observe: "check.passed" }],
+  steps: [
+    step("check") { check(input) },
+    step("hits") { hits(state) }
+  ],
+}"#,
+        )
+        .unwrap();
+        let result = typecheck_program(&program);
+        assert!(result.is_ok(), "{result:?}");
+    }
+
+    #[test]
+    fn typecheck_temporal_state_rejects_non_bool_source() {
+        let program = parse_program(
+            r#"fn check(x: i64): { score: f64 } { return { score: 1.0 }; }
+pipeline P {
+  input: i64,
+  state: [{ key: "signal", confirm: 2, observe: "check.score" }],
+  steps: [ step("check") { check(input) } ],
+}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            typecheck_program(&program),
+            Err(TypeError::InvalidTemporalState { key, .. }) if key == "signal"
+        ));
+    }
+
+    #[test]
+    fn typecheck_temporal_state_per_names_an_input_field() {
+        let pipeline = |per: &str| {
+            format!(
+                r#"fn check(x: {{ symbol: string, price: f64 }}): {{ passed: bool }} {{ return {{ passed: x.price > 1.0 }}; }}
+pipeline P {{
+  input: {{ symbol: string, price: f64 }},
+  state: [{{ key: "signal", confirm: 2, observe: "check.passed", per: "{per}" }}],
+  steps: [ step("check") {{ check(input) }} ],
+}}"#
+            )
+        };
+        let ok = parse_program(&pipeline("symbol")).unwrap();
+        let result = typecheck_program(&ok);
+        assert!(result.is_ok(), "{result:?}");
+
+        let missing = parse_program(&pipeline("venue")).unwrap();
+        assert!(matches!(
+            typecheck_program(&missing),
+            Err(TypeError::InvalidTemporalState { reason, .. }) if reason == "input has no field 'venue'"
+        ));
+        let float = parse_program(&pipeline("price")).unwrap();
+        assert!(matches!(
+            typecheck_program(&float),
+            Err(TypeError::InvalidTemporalState { reason, .. }) if reason.starts_with("'price' must be")
+        ));
+    }
+
+    #[test]
+    fn typecheck_constraint_refs_resolve_to_metrics_or_input() {
+        let pipeline = |threshold: &str| {
+            format!(
+                r#"pipeline P {{
+  input: {{ config: {{ max_drawdown: f64 }} }},
+  constraints: [
+    {{ any: [{{ metric: "drawdown", lt: {threshold} }}, {{ metric: "spread", between: [0, "max_spread"] }}] }}
+  ],
+  steps: [],
+  validation: {{ let drawdown = 0.1; let spread = 0.2; let max_spread = 0.5; }}
+}}"#
+            )
+        };
+        let ok = parse_program(&pipeline("\"config.max_drawdown\"")).unwrap();
+        let result = typecheck_program(&ok);
+        assert!(result.is_ok(), "{result:?}");
+
+        let unknown = parse_program(&pipeline("\"limits.max_drawdown\"")).unwrap();
+        assert!(matches!(
+            typecheck_program(&unknown),
+            Err(TypeError::UndefinedMetric { metric, .. }) if metric == "limits.max_drawdown"
+        ));
+    }
+
     #[test]
     fn typecheck_cooldown_builtin_assignment() {
         let program = parse_program(
@@ -4120,4 +4998,196 @@ mod tests {
             res => panic!("Expected ExternalCall error, got {:?}", res),
         }
     }
+
+    #[test]
+    fn pipeline_types_reports_step_result_types() {
+        let src = r#"
+            fn score(x: i64): f64 { return 1.5; }
+
+            pipeline Scoring {
+                input: { amount: i64 },
+                steps: [
+                    step("score") { score(input.amount) },
+                    step("check") { pass("ok") }
+                ],
+                output: { score: f64 }
+            }
+        "#;
+        let program = parse_program(src).unwrap();
+        let Item::Pipeline(pipeline) = &program.items[1] else {
+            panic!("expected pipeline");
+        };
+        let types = pipeline_types(&program, pipeline).unwrap();
+        assert_eq!(types.input, Ty::Record(vec![("amount".into(), Ty::I64)]));
+        assert_eq!(
+            types.output,
+            Some(Ty::Record(vec![("score".into(), Ty::F64)]))
+        );
+        assert_eq!(types.steps[0], ("score".into(), Ty::F64));
+        assert!(matches!(&types.steps[1].1, Ty::Record(fields) if fields.len() == 3));
+    }
+
+    #[test]
+    fn type_aliases_resolve_records_generics_and_unions() {
+        let src = r#"
+            type Quote { bid: f64, ask: f64 }
+            type Pair<T> = (T, T);
+            type Signal = Buy(f64) | Hold;
+
+            fn spread(q: Quote): f64 { return q.ask - q.bid; }
+            fn same(p: Pair<i64>): (i64, i64) { return p; }
+            fn decide(q: Quote): Signal { return Buy(spread(q)); }
+            fn size(s: Signal): f64 { return match s { Buy(x) => x, _ => 0.0 }; }
+            fn main() {
+                let spread_now = spread({ bid: 1.0, ask: 1.5 });
+                let sized = size(decide({ bid: 1.0, ask: spread_now }));
+                print(sized);
+                print(same((1, 2)));
+            }
+        "#;
+        typecheck_program(&parse_program(src).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn type_alias_errors_name_the_alias() {
+        let src = r#"
+            type Quote { bid: f64, ask: f64 }
+            fn main() { let q: Quote = { bid: 1.0 }; print(q); }
+        "#;
+        let err = typecheck_program(&parse_program(src).unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "type mismatch: expected Quote, got { bid: f64 }"
+        );
+    }
+
+    #[test]
+    fn self_referencing_alias_is_a_recursive_alias_error() {
+        for src in [
+            "type Chain { next: Chain }",
+            "type Tree = [Tree];",
+            "type Wrap<T> = Option<Wrap<T>>;",
+        ] {
+            let err = typecheck_program(&parse_program(src).unwrap()).unwrap_err();
+            assert_eq!(err.code(), "E1005", "{src}");
+            assert!(err.to_string().starts_with("recursive type alias"), "{src}");
+        }
+
+        // A later alias is still an unknown type, not a recursive one.
+        let forward = parse_program("type A { b: B } type B { a: i64 }").unwrap();
+        assert!(matches!(
+            typecheck_program(&forward),
+            Err(TypeError::UnknownType { name, .. }) if name == "B"
+        ));
+    }
+
+    #[test]
+    fn builtin_option_and_result_match_exhaustively() {
+        let src = r#"
+            fn parse(raw: string): Result<f64, string> {
+                if raw == "" { return Err("empty"); }
+                return Ok(1.0);
+            }
+            fn first(x: i64, empty: bool): Option<i64> {
+                if empty { return None; }
+                return Some(x);
+            }
+            fn main() {
+                let price = match parse("1.5") { Ok(v) => v, Err(_) => 0.0 };
+                let head = match first(1, false) { None => 0, Some(x) => x };
+                print(price);
+                print(head);
+            }
+        "#;
+        assert!(typecheck_program(&parse_program(src).unwrap()).is_ok());
+
+        let missing = r#"
+            fn main() {
+                let r: Result<i64, string> = Ok(1);
+                let x = match r { Ok(v) => v };
+                print(x);
+            }
+        "#;
+        assert!(matches!(
+            typecheck_program(&parse_program(missing).unwrap()),
+            Err(TypeError::NonExhaustiveMatch { .. })
+        ));
+
+        let unit_arm =
+            "fn main() { let o: Option<i64> = None; let x = match o { None => 0 }; print(x); }";
+        assert!(matches!(
+            typecheck_program(&parse_program(unit_arm).unwrap()),
+            Err(TypeError::NonExhaustiveMatch { .. })
+        ));
+
+        // Declared enums win variant names shared with the built-ins.
+        let shadowed = "enum Health { Ok, Down } fn check(): Health { return Ok; }";
+        assert!(typecheck_program(&parse_program(shadowed).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn try_operator_propagates_failures() {
+        let src = r#"
+            fn parse(raw: string): Result<f64, string> {
+                if raw == "" { return Err("empty"); }
+                return Ok(2.0);
+            }
+            fn double(raw: string): Result<f64, string> {
+                let v = parse(raw)?;
+                return Ok(v * 2.0);
+            }
+            fn first(x: i64, empty: bool): Option<i64> {
+                if empty { return None; }
+                return Some(x);
+            }
+            fn next(x: i64): Option<i64> { return Some(first(x, false)? + 1); }
+        "#;
+        assert!(typecheck_program(&parse_program(src).unwrap()).is_ok());
+
+        let unit_return = r#"
+            fn parse(raw: string): Result<f64, string> { return Ok(1.0); }
+            fn main() { let v = parse("1")?; print(v); }
+        "#;
+        let err = typecheck_program(&parse_program(unit_return).unwrap()).unwrap_err();
+        assert_eq!(err.code(), "E2010");
+        assert_eq!(
+            err.to_string(),
+            "cannot use '?' on Result<f64, string> in a function returning ()"
+        );
+
+        let other_error = r#"
+            fn parse(raw: string): Result<f64, string> { return Ok(1.0); }
+            fn run(raw: string): Result<f64, i64> { let v = parse(raw)?; return Ok(v); }
+        "#;
+        assert!(matches!(
+            typecheck_program(&parse_program(other_error).unwrap()),
+            Err(TypeError::InvalidTry { .. })
+        ));
+
+        let not_enum = "fn run(x: i64): Option<i64> { return Some(x?); }";
+        assert!(matches!(
+            typecheck_program(&parse_program(not_enum).unwrap()),
+            Err(TypeError::InvalidTry { .. })
+        ));
+    }
+
+    #[test]
+    fn result_steps_expose_the_ok_value() {
+        let src = r#"
+            @external(python = "quotes.fetch")
+            fn fetch(symbol: string): Result<f64, string> {}
+            pipeline Quote {
+                input: string,
+                steps: [ step("price") { fetch(input) } ],
+                output: { price: f64 }
+            }
+        "#;
+        let program = parse_program(src).unwrap();
+        let Item::Pipeline(pipeline) = &program.items[1] else {
+            panic!("expected pipeline");
+        };
+        let types = pipeline_types(&program, pipeline).unwrap();
+        assert_eq!(types.steps, vec![("price".to_string(), Ty::F64)]);
+        assert_eq!(types.fallible, ["price"]);
+    }
 }
@@ -1,7 +1,8 @@
 #!/bin/bash
 set -e
 
-export PYTHONPATH=$PYTHONPATH:$(pwd)/examples
+# Python modules live next to validation_demos.tp.
+export TUPA_PYTHON_PATH=.
 
 echo "Running MnistClassifier..."
 cargo run -q -p tupa-cli -- run --pipeline MnistClassifier --input examples/input_mnist.json examples/validation_demos.tp